@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use anyhow::{Context, Result};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use lazy_static::lazy_static;
+use crate::types::{Config, DownloadSpeedSet, PingDelaySet};
+
+// 合并得到的网段不短于该前缀，避免把稀疏的结果合并成过大的网段
+const MIN_PREFIX_V4: u8 = 16;
+const MIN_PREFIX_V6: u8 = 32;
+
+// 单个已测速 IP
+#[derive(Debug, Clone, Copy, Default)]
+struct Host {
+    passed: bool,
+    latency_ms: f64,
+    speed: f64, // 字节/秒，未下载测速时为 0
+}
+
+// 输出的一个网段
+struct Subnet {
+    net: IpNet,
+    passed: usize,
+    tested: usize,
+    latency_ms: f64,      // 通过的 IP 的平均延迟
+    speed_mb: Option<f64>, // 通过且下载测速的 IP 的平均速度
+}
+
+impl Subnet {
+    fn pass_rate(&self) -> f64 {
+        self.passed as f64 / self.tested as f64
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref HOSTS: Mutex<BTreeMap<IpAddr, Host>> = Mutex::new(BTreeMap::new());
+}
+
+// 每轮测速开始时清空记录，未指定 [-aggregate] 时不记录
+pub fn start(config: &Config) {
+    ENABLED.store(!config.aggregate.is_empty(), Ordering::Relaxed);
+    HOSTS.lock().unwrap().clear();
+}
+
+// 延迟测速失败的 IP 同样计入测试数
+pub fn record_failed(ip: IpAddr) {
+    if ENABLED.load(Ordering::Relaxed) {
+        HOSTS.lock().unwrap().entry(ip).or_default();
+    }
+}
+
+// 记录延迟测速有响应的 IP
+pub fn record_responded(data: &PingDelaySet) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut hosts = HOSTS.lock().unwrap();
+    for ip_data in data {
+        hosts.entry(ip_data.ping_data.ip).or_default();
+    }
+}
+
+// 满足延迟、丢包率、抖动条件的 IP 视为通过
+pub fn record_qualified(data: &PingDelaySet) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut hosts = HOSTS.lock().unwrap();
+    for ip_data in data {
+        let host = hosts.entry(ip_data.ping_data.ip).or_default();
+        host.passed = true;
+        host.latency_ms = ip_data.ping_data.delay.as_secs_f64() * 1000.0;
+    }
+}
+
+// 自顶向下遍历前缀树：只在分叉处或叶子判断，通过率达到 ratio 时输出该节点的网段，否则继续拆分
+// hosts 按地址排序，bits 为地址位数
+fn split(hosts: &[(u128, Host)], base: u128, len: u8, bits: u8, min_prefix: u8, ratio: f64, out: &mut Vec<(u128, u8, Vec<Host>)>) {
+    if hosts.iter().all(|(_, h)| !h.passed) {
+        return;
+    }
+    if len < bits {
+        let half = 1u128 << (bits - len - 1);
+        let mid = hosts.partition_point(|(addr, _)| *addr < base + half);
+        let (left, right) = hosts.split_at(mid);
+        let passed = hosts.iter().filter(|(_, h)| h.passed).count();
+        let branching = !left.is_empty() && !right.is_empty();
+        if !branching || len < min_prefix || (passed as f64) < ratio * hosts.len() as f64 {
+            split(left, base, len + 1, bits, min_prefix, ratio, out);
+            split(right, base + half, len + 1, bits, min_prefix, ratio, out);
+            return;
+        }
+    }
+    out.push((base, len, hosts.iter().map(|(_, h)| *h).collect()));
+}
+
+fn aggregate(hosts: &BTreeMap<IpAddr, Host>, ratio: f64) -> Vec<Subnet> {
+    let v4: Vec<(u128, Host)> = hosts.iter()
+        .filter_map(|(ip, h)| match ip { IpAddr::V4(ip) => Some((u32::from(*ip) as u128, *h)), _ => None })
+        .collect();
+    let v6: Vec<(u128, Host)> = hosts.iter()
+        .filter_map(|(ip, h)| match ip { IpAddr::V6(ip) => Some((u128::from(*ip), *h)), _ => None })
+        .collect();
+
+    let mut nodes = Vec::new();
+    let mut subnets = Vec::new();
+    split(&v4, 0, 0, 32, MIN_PREFIX_V4, ratio, &mut nodes);
+    for (base, len, members) in nodes.drain(..) {
+        let net = Ipv4Net::new(Ipv4Addr::from(base as u32), len).unwrap().trunc();
+        subnets.push(summarize(IpNet::V4(net), &members));
+    }
+    split(&v6, 0, 0, 128, MIN_PREFIX_V6, ratio, &mut nodes);
+    for (base, len, members) in nodes {
+        let net = Ipv6Net::new(Ipv6Addr::from(base), len).unwrap().trunc();
+        subnets.push(summarize(IpNet::V6(net), &members));
+    }
+
+    // 通过数多的在前，其次为通过率高、延迟低
+    subnets.sort_by(|a, b| {
+        b.passed.cmp(&a.passed)
+            .then(b.pass_rate().total_cmp(&a.pass_rate()))
+            .then(a.latency_ms.total_cmp(&b.latency_ms))
+    });
+    subnets
+}
+
+fn summarize(net: IpNet, members: &[Host]) -> Subnet {
+    let passed: Vec<&Host> = members.iter().filter(|h| h.passed).collect();
+    let speeds: Vec<f64> = passed.iter().map(|h| h.speed).filter(|&s| s > 0.0).collect();
+    Subnet {
+        net,
+        passed: passed.len(),
+        tested: members.len(),
+        latency_ms: passed.iter().map(|h| h.latency_ms).sum::<f64>() / passed.len() as f64,
+        speed_mb: (!speeds.is_empty()).then(|| speeds.iter().sum::<f64>() / speeds.len() as f64 / 1024.0 / 1024.0),
+    }
+}
+
+// 按 [-aggregate] 把通过的 IP 合并为网段并写入文件；下载测速未达到 [-sl] 的 IP 不算通过
+pub fn write(config: &Config, speed_data: &DownloadSpeedSet) -> Result<()> {
+    if config.aggregate.is_empty() {
+        return Ok(());
+    }
+    let mut hosts = HOSTS.lock().unwrap().clone();
+    for ip_data in speed_data {
+        if let Some(host) = hosts.get_mut(&ip_data.ping_data.ip) {
+            host.speed = ip_data.download_speed;
+            host.passed &= ip_data.meets_speed_filter(config);
+        }
+    }
+    let subnets = aggregate(&hosts, config.aggregate_ratio);
+
+    let mut writer = csv::Writer::from_path(&config.aggregate)
+        .with_context(|| format!("无法写入 {}", config.aggregate))?;
+    if config.english_header {
+        writer.write_record(["subnet", "passed", "tested", "pass_rate", "latency_ms", "download_speed_mb"])?;
+    } else {
+        writer.write_record(["网段", "通过数", "测试数", "通过率", "平均延迟", "平均下载速度 (MB/s)"])?;
+    }
+    for subnet in &subnets {
+        writer.write_record([
+            subnet.net.to_string(),
+            subnet.passed.to_string(),
+            subnet.tested.to_string(),
+            format!("{:.2}", subnet.pass_rate()),
+            format!("{:.2}", subnet.latency_ms),
+            subnet.speed_mb.map(|s| format!("{:.2}", s)).unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+
+    match subnets.first() {
+        Some(best) => println!(
+            "已写入 {} 个网段到 {}，通过最多：{} ({} / {} 个 IP 通过)",
+            subnets.len(), config.aggregate, best.net, best.passed, best.tested
+        ),
+        None => println!("\n[信息] 没有通过的 IP，{} 为空。", config.aggregate),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(entries: &[(&str, bool, f64)]) -> BTreeMap<IpAddr, Host> {
+        entries.iter()
+            .map(|&(ip, passed, latency_ms)| (ip.parse().unwrap(), Host { passed, latency_ms, speed: 0.0 }))
+            .collect()
+    }
+
+    fn nets(subnets: &[Subnet]) -> Vec<String> {
+        subnets.iter().map(|s| s.net.to_string()).collect()
+    }
+
+    #[test]
+    fn neighbours_merge_at_branching_prefix() {
+        let subnets = aggregate(&hosts(&[("1.1.1.1", true, 10.0), ("1.1.1.2", true, 20.0)]), 1.0);
+        assert_eq!(nets(&subnets), ["1.1.1.0/30"]);
+        assert_eq!((subnets[0].passed, subnets[0].tested), (2, 2));
+        assert_eq!(subnets[0].latency_ms, 15.0);
+        assert_eq!(subnets[0].speed_mb, None);
+    }
+
+    #[test]
+    fn low_pass_rate_splits_subnet() {
+        let data = hosts(&[("1.1.1.1", true, 10.0), ("1.1.1.2", false, 0.0)]);
+        assert_eq!(nets(&aggregate(&data, 0.6)), ["1.1.1.1/32"]);
+        let merged = aggregate(&data, 0.5);
+        assert_eq!(nets(&merged), ["1.1.1.0/30"]);
+        assert_eq!((merged[0].passed, merged[0].tested), (1, 2));
+    }
+
+    #[test]
+    fn min_prefix_limits_merging() {
+        let data = hosts(&[("1.0.0.1", true, 10.0), ("2.0.0.1", true, 10.0), ("2001:db8::1", true, 10.0), ("2400:cb00::1", true, 10.0)]);
+        let mut found = nets(&aggregate(&data, 1.0));
+        found.sort();
+        assert_eq!(found, ["1.0.0.1/32", "2.0.0.1/32", "2001:db8::1/128", "2400:cb00::1/128"]);
+    }
+
+    #[test]
+    fn subnets_sorted_by_passed_count() {
+        let data = hosts(&[("1.1.1.1", true, 10.0), ("1.1.1.2", true, 10.0), ("8.8.8.8", true, 1.0)]);
+        assert_eq!(nets(&aggregate(&data, 1.0)), ["1.1.1.0/30", "8.8.8.8/32"]);
+    }
+
+    #[test]
+    fn failed_hosts_only_produce_nothing() {
+        assert!(aggregate(&hosts(&[("1.1.1.1", false, 0.0)]), 0.5).is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+
+// 收到中断信号后等待进行中请求结束的最长时间
+pub const GRACE_PERIOD: Duration = Duration::from_secs(3);
+// 被中断时的退出码 (128 + SIGINT)
+pub const EXIT_INTERRUPTED: i32 = 130;
+// 第一次中断后在此时间内收到的信号被忽略
+const REPEAT_WINDOW: Duration = Duration::from_millis(500);
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+// 单次测速时处理 Ctrl-C / SIGTERM：第一次停止新的测速并写入已有结果，第二次立即退出
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            signal().await;
+            if trigger() {
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+// 请求中断，信号与 [-tui] 中的 Ctrl-C / q / Esc 共用：第一次停止新的测速并写入已有结果，
+// 返回 true 时为再次中断，调用方应立即以 EXIT_INTERRUPTED 退出
+pub fn trigger() -> bool {
+    static FIRST: OnceLock<Instant> = OnceLock::new();
+    let mut first = false;
+    let since = FIRST.get_or_init(|| {
+        first = true;
+        Instant::now()
+    });
+    if !first {
+        // timeout 等工具会向进程与所在进程组各发送一次信号，紧随其后的重复信号不视为再次中断
+        return since.elapsed() >= REPEAT_WINDOW;
+    }
+    println!(
+        "\n[信息] 收到中断信号，不再开始新的测速，进行中的请求最多再等待 {} 秒，随后写入已完成的部分结果；再次中断立即退出",
+        GRACE_PERIOD.as_secs()
+    );
+    CANCELLED.store(true, Ordering::Relaxed);
+    NOTIFY.notify_waiters();
+    false
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        },
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+// 是否已收到中断信号，各阶段据此停止开始新的测速
+pub fn requested() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+// 收到中断信号后再过 GRACE_PERIOD 完成，未收到信号时一直等待
+pub async fn grace_expired() {
+    let notified = NOTIFY.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+    if !requested() {
+        notified.await;
+    }
+    tokio::time::sleep(GRACE_PERIOD).await;
+}
+
+// 中断后等待进行中的任务，超过 GRACE_PERIOD 仍未结束的任务被取消
+pub async fn drain<T: 'static>(tasks: &mut JoinSet<T>) {
+    let expired = tokio::select! {
+        _ = async { while tasks.join_next().await.is_some() {} } => false,
+        _ = grace_expired() => true,
+    };
+    if expired {
+        tasks.abort_all();
+    }
+}
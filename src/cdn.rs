@@ -0,0 +1,214 @@
+use hyper::header::HeaderMap;
+
+// 从响应头识别 CDN 节点 (数据中心) 代码
+pub trait ColoDetector: Sync {
+    // [-cdn-provider] 使用的名称
+    fn name(&self) -> &'static str;
+    // 响应不是来自该 CDN 或没有节点信息时返回 None
+    fn detect(&self, headers: &HeaderMap) -> Option<String>;
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim)
+}
+
+// 开头的 3 个大写字母，如 "NRT57-P3" -> "NRT"
+fn leading_iata(s: &str) -> Option<String> {
+    let code = s.get(..3)?;
+    code.bytes().all(|b| b.is_ascii_uppercase()).then(|| code.to_string())
+}
+
+// CF-RAY: 8a1b2c3d4e5f6789-LAX
+pub struct Cloudflare;
+
+impl ColoDetector for Cloudflare {
+    fn name(&self) -> &'static str {
+        "cloudflare"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        if !header(headers, "Server").is_some_and(|v| v.eq_ignore_ascii_case("cloudflare")) {
+            return None;
+        }
+        let ray = header(headers, "CF-RAY")?;
+        leading_iata(ray.rsplit('-').next()?)
+    }
+}
+
+// x-amz-cf-pop: NRT57-P3
+pub struct CloudFront;
+
+impl ColoDetector for CloudFront {
+    fn name(&self) -> &'static str {
+        "cloudfront"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        leading_iata(header(headers, "x-amz-cf-pop")?)
+    }
+}
+
+// x-served-by: cache-iad-kiad7000025-IAD, cache-nrt-rjtf7700071-NRT
+// 启用源站屏蔽时有多个节点，最后一个是直接响应客户端的边缘节点
+pub struct Fastly;
+
+impl ColoDetector for Fastly {
+    fn name(&self) -> &'static str {
+        "fastly"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        let edge = header(headers, "x-served-by")?.rsplit(',').next()?.trim();
+        if !edge.starts_with("cache-") {
+            return None;
+        }
+        leading_iata(edge.rsplit('-').next()?)
+    }
+}
+
+// x-id: fr5-hw-edge-gc17，节点代码为第一段，不是机场三字码
+pub struct GCore;
+
+impl ColoDetector for GCore {
+    fn name(&self) -> &'static str {
+        "gcore"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        let id = header(headers, "x-id")?;
+        if !id.rsplit('-').next()?.starts_with("gc") {
+            return None;
+        }
+        id.split('-').next().filter(|s| !s.is_empty()).map(str::to_ascii_uppercase)
+    }
+}
+
+// Server: BunnyCDN-DE1-1026，节点代码为国家二字码加序号
+pub struct Bunny;
+
+impl ColoDetector for Bunny {
+    fn name(&self) -> &'static str {
+        "bunny"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        let server = header(headers, "Server")?;
+        let rest = server.strip_prefix("BunnyCDN-")?;
+        rest.split('-').next().filter(|s| !s.is_empty()).map(str::to_string)
+    }
+}
+
+// x-77-pop: frankfurtDE
+pub struct Cdn77;
+
+impl ColoDetector for Cdn77 {
+    fn name(&self) -> &'static str {
+        "cdn77"
+    }
+
+    fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        header(headers, "x-77-pop").filter(|s| !s.is_empty()).map(str::to_string)
+    }
+}
+
+// 自动识别时按此顺序依次尝试
+pub const DETECTORS: &[&dyn ColoDetector] = &[&Cloudflare, &CloudFront, &Fastly, &Bunny, &Cdn77, &GCore];
+
+// [-cdn-provider] 的取值：auto (默认) 或某个 CDN
+#[derive(Clone, Copy, Default)]
+pub struct Provider(Option<&'static dyn ColoDetector>);
+
+impl Provider {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.is_empty() || name.eq_ignore_ascii_case("auto") {
+            return Some(Self::default());
+        }
+        DETECTORS.iter()
+            .find(|d| d.name().eq_ignore_ascii_case(name))
+            .map(|&d| Self(Some(d)))
+    }
+
+    pub fn detect(&self, headers: &HeaderMap) -> Option<String> {
+        match self.0 {
+            Some(detector) => detector.detect(headers),
+            None => DETECTORS.iter().find_map(|d| d.detect(headers)),
+        }
+    }
+}
+
+pub fn names() -> String {
+    DETECTORS.iter().map(|d| d.name()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{HeaderName, HeaderValue};
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        pairs.iter()
+            .map(|(k, v)| (HeaderName::from_bytes(k.as_bytes()).unwrap(), HeaderValue::from_str(v).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn cloudflare() {
+        let h = headers(&[("Server", "cloudflare"), ("CF-RAY", "8a1b2c3d4e5f6789-LAX")]);
+        assert_eq!(Cloudflare.detect(&h).as_deref(), Some("LAX"));
+        // 其他服务器转发的 CF-RAY 不算
+        assert_eq!(Cloudflare.detect(&headers(&[("Server", "nginx"), ("CF-RAY", "8a1b2c3d4e5f6789-LAX")])), None);
+    }
+
+    #[test]
+    fn cloudfront() {
+        assert_eq!(CloudFront.detect(&headers(&[("x-amz-cf-pop", "NRT57-P3")])).as_deref(), Some("NRT"));
+        assert_eq!(CloudFront.detect(&headers(&[("x-amz-cf-pop", "nrt57")])), None);
+    }
+
+    #[test]
+    fn fastly() {
+        let h = headers(&[("x-served-by", "cache-iad-kiad7000025-IAD, cache-nrt-rjtf7700071-NRT")]);
+        assert_eq!(Fastly.detect(&h).as_deref(), Some("NRT"));
+        assert_eq!(Fastly.detect(&headers(&[("x-served-by", "web-01")])), None);
+    }
+
+    #[test]
+    fn gcore() {
+        assert_eq!(GCore.detect(&headers(&[("x-id", "fr5-hw-edge-gc17")])).as_deref(), Some("FR5"));
+        assert_eq!(GCore.detect(&headers(&[("x-id", "abc-123")])), None);
+    }
+
+    #[test]
+    fn bunny() {
+        assert_eq!(Bunny.detect(&headers(&[("Server", "BunnyCDN-DE1-1026")])).as_deref(), Some("DE1"));
+        assert_eq!(Bunny.detect(&headers(&[("Server", "cloudflare")])), None);
+    }
+
+    #[test]
+    fn cdn77() {
+        assert_eq!(Cdn77.detect(&headers(&[("x-77-pop", "frankfurtDE")])).as_deref(), Some("frankfurtDE"));
+        assert_eq!(Cdn77.detect(&headers(&[])), None);
+    }
+
+    #[test]
+    fn provider_parse() {
+        for detector in DETECTORS {
+            let provider = Provider::parse(&detector.name().to_uppercase()).unwrap();
+            assert_eq!(provider.0.map(|d| d.name()), Some(detector.name()));
+        }
+        assert!(Provider::parse("").unwrap().0.is_none());
+        assert!(Provider::parse(" Auto ").unwrap().0.is_none());
+        assert!(Provider::parse("akamai").is_none());
+        assert_eq!(names(), "cloudflare,cloudfront,fastly,bunny,cdn77,gcore");
+    }
+
+    #[test]
+    fn provider_detect() {
+        let h = headers(&[("x-amz-cf-pop", "SIN2-C1")]);
+        // auto 依次尝试各 CDN，指定 CDN 时只认该 CDN 的响应头
+        assert_eq!(Provider::default().detect(&h).as_deref(), Some("SIN"));
+        assert_eq!(Provider::parse("cloudfront").unwrap().detect(&h).as_deref(), Some("SIN"));
+        assert_eq!(Provider::parse("cloudflare").unwrap().detect(&h), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use crate::ip::{self, IpStream};
+use crate::types::{Config, PingData};
+
+const VERSION: u32 = 1;
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// 检查点文件内容：候选流由随机种子与参数确定，只需记录扫描到的位置与已完成的结果
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckpointFile {
+    version: u32,
+    seed: u64,
+    total: u64,    // 候选总数，用于确认参数未变
+    position: u64, // 此前的候选均已测速完成
+    results: Vec<PingData>,
+}
+
+#[derive(Debug)]
+struct State {
+    path: String,
+    seed: u64,
+    total: u64,
+    skipped: u64,                      // 从检查点跳过的候选数量
+    resumed: Vec<PingData>,            // 从检查点读取的结果
+    in_flight: BTreeSet<u64>,          // 正在测速的候选位置
+    results: BTreeMap<u64, PingData>,  // 本次完成的结果，按位置记录
+    last_save: Instant,
+}
+
+// 延迟测速检查点 [-checkpoint]/[-resume]；未启用时所有操作为空
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    state: Option<Arc<Mutex<State>>>,
+}
+
+fn load(path: &str) -> Option<CheckpointFile> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let file: CheckpointFile = serde_json::from_str(&content).ok()?;
+    (file.version == VERSION).then_some(file)
+}
+
+fn save(path: &str, file: &CheckpointFile) -> Result<()> {
+    // 先写临时文件再重命名，写入时中断也不会损坏已有的检查点
+    let tmp = format!("{}.tmp", path);
+    std::fs::write(&tmp, serde_json::to_vec(file)?).with_context(|| format!("无法写入 {}", tmp))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("无法写入 {}", path))?;
+    Ok(())
+}
+
+impl Checkpoint {
+    // 生成候选流；[-resume] 时读取检查点，使用相同的随机种子并跳过已测速的部分
+    pub async fn start(config: &Config) -> std::io::Result<(Self, IpStream)> {
+        if config.checkpoint.is_empty() {
+            return Ok((Self::default(), ip::ip_stream(config).await?));
+        }
+
+        let saved = if config.resume { load(&config.checkpoint) } else { None };
+        if config.resume && saved.is_none() {
+            println!("[信息] 未找到可用的检查点 {}，从头开始测速", config.checkpoint);
+        }
+        let saved = saved.filter(|file| {
+            let matched = config.seed.is_none_or(|seed| seed == file.seed);
+            if !matched {
+                println!("[提示] 检查点的随机种子与 [-seed] 不一致，从头开始测速");
+            }
+            matched
+        });
+
+        // 未指定 [-seed] 时随机生成，写入检查点以便恢复时得到相同的候选
+        let seed = saved.as_ref().map(|file| file.seed)
+            .or(config.seed)
+            .unwrap_or_else(rand::random);
+        let mut stream_config = config.clone();
+        stream_config.seed = Some(seed);
+        let mut ips = ip::ip_stream(&stream_config).await?;
+        let total = ips.total() as u64;
+
+        let mut resumed = Vec::new();
+        let mut skipped = 0;
+        if let Some(file) = saved {
+            if file.total == total {
+                ips.skip_to(u128::from(file.position));
+                skipped = file.position;
+                println!(
+                    "[信息] 从检查点继续：已测速 {} 个，已有结果 {} 个，剩余 {} 个",
+                    file.position, file.results.len(), ips.total()
+                );
+                resumed = file.results;
+            } else {
+                println!("[提示] 检查点与当前 IP 段或参数不一致，从头开始测速");
+            }
+        }
+
+        let state = State {
+            path: config.checkpoint.clone(),
+            seed,
+            total,
+            skipped,
+            resumed,
+            in_flight: BTreeSet::new(),
+            results: BTreeMap::new(),
+            last_save: Instant::now(),
+        };
+        Ok((Self { state: Some(Arc::new(Mutex::new(state))) }, ips))
+    }
+
+    // 从检查点读取的结果
+    pub fn resumed(&self) -> Vec<PingData> {
+        match &self.state {
+            Some(state) => state.lock().unwrap().resumed.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    // 从检查点跳过的候选数量
+    pub fn skipped(&self) -> usize {
+        match &self.state {
+            Some(state) => state.lock().unwrap().skipped as usize,
+            None => 0,
+        }
+    }
+
+    // 开始测速位置为 position 的候选（[`IpStream::position`] 减 1）
+    pub fn begin(&self, position: u128) {
+        if let Some(state) = &self.state {
+            state.lock().unwrap().in_flight.insert(position as u64);
+        }
+    }
+
+    pub fn complete(&self, position: u128, result: Option<&PingData>) {
+        if let Some(state) = &self.state {
+            let mut state = state.lock().unwrap();
+            state.in_flight.remove(&(position as u64));
+            if let Some(ping) = result {
+                state.results.insert(position as u64, ping.clone());
+            }
+        }
+    }
+
+    // 距上次保存超过间隔时写入检查点，scanned 为当前已生成的候选数量
+    pub fn save_if_due(&self, scanned: u128) {
+        let Some(state) = &self.state else { return };
+        let mut state = state.lock().unwrap();
+        if state.last_save.elapsed() >= SAVE_INTERVAL {
+            write(&mut state, scanned);
+        }
+    }
+
+    // 延迟测速结束时保存，之后的阶段中断时不必重新延迟测速
+    pub fn save(&self, scanned: u128) {
+        if let Some(state) = &self.state {
+            write(&mut state.lock().unwrap(), scanned);
+        }
+    }
+
+    // 等待剩余任务完成后保存；任务可能在速率限制中排队较久，等待期间同样定期保存
+    pub async fn join_all<T: 'static>(&self, tasks: &mut JoinSet<T>, scanned: u128) {
+        loop {
+            match tokio::time::timeout(SAVE_INTERVAL, tasks.join_next()).await {
+                Ok(None) => break,
+                _ => self.save_if_due(scanned),
+            }
+        }
+        self.save(scanned);
+    }
+}
+
+fn write(state: &mut State, scanned: u128) {
+    // 最早仍在测速的位置之前的候选均已完成，之后完成的结果恢复时会重新测速
+    let position = state.in_flight.first().copied().unwrap_or(scanned as u64);
+    let mut results = state.resumed.clone();
+    results.extend(state.results.range(..position).map(|(_, ping)| ping.clone()));
+    let file = CheckpointFile {
+        version: VERSION,
+        seed: state.seed,
+        total: state.total,
+        position,
+        results,
+    };
+    if let Err(e) = save(&state.path, &file) {
+        println!("[错误] 保存检查点失败：{:#}", e);
+    }
+    state.last_save = Instant::now();
+}
+
+// 完整测速流程结束后删除检查点
+pub fn remove(config: &Config) {
+    if !config.checkpoint.is_empty() {
+        std::fs::remove_file(&config.checkpoint).ok();
+    }
+}
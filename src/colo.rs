@@ -0,0 +1,426 @@
+use std::collections::HashSet;
+
+// 数据中心位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub colo: &'static str,
+    pub city: &'static str,
+    pub country: &'static str,   // ISO 3166-1 二字码
+    pub continent: &'static str, // 大洲代码：AF AS EU NA OC SA
+}
+
+const CONTINENTS: &[&str] = &["AF", "AS", "EU", "NA", "OC", "SA"];
+
+// IATA 三字码 -> 城市 / 国家 / 大洲，按三字码排序以便二分查找
+const COLO_TABLE: &[(&str, &str, &str, &str)] = &[
+    ("ABJ", "Abidjan", "CI", "AF"),
+    ("ABV", "Abuja", "NG", "AF"),
+    ("ACC", "Accra", "GH", "AF"),
+    ("ADB", "Izmir", "TR", "AS"),
+    ("ADD", "Addis Ababa", "ET", "AF"),
+    ("ADL", "Adelaide", "AU", "OC"),
+    ("AKL", "Auckland", "NZ", "OC"),
+    ("ALA", "Almaty", "KZ", "AS"),
+    ("ALG", "Algiers", "DZ", "AF"),
+    ("AMD", "Ahmedabad", "IN", "AS"),
+    ("AMM", "Amman", "JO", "AS"),
+    ("AMS", "Amsterdam", "NL", "EU"),
+    ("ANC", "Anchorage", "US", "NA"),
+    ("ARI", "Arica", "CL", "SA"),
+    ("ARN", "Stockholm", "SE", "EU"),
+    ("ASU", "Asuncion", "PY", "SA"),
+    ("ATH", "Athens", "GR", "EU"),
+    ("ATL", "Atlanta", "US", "NA"),
+    ("AUH", "Abu Dhabi", "AE", "AS"),
+    ("AUS", "Austin", "US", "NA"),
+    ("BAH", "Manama", "BH", "AS"),
+    ("BCN", "Barcelona", "ES", "EU"),
+    ("BEG", "Belgrade", "RS", "EU"),
+    ("BEL", "Belem", "BR", "SA"),
+    ("BEY", "Beirut", "LB", "AS"),
+    ("BGW", "Baghdad", "IQ", "AS"),
+    ("BKK", "Bangkok", "TH", "AS"),
+    ("BLR", "Bangalore", "IN", "AS"),
+    ("BNA", "Nashville", "US", "NA"),
+    ("BNE", "Brisbane", "AU", "OC"),
+    ("BOD", "Bordeaux", "FR", "EU"),
+    ("BOG", "Bogota", "CO", "SA"),
+    ("BOM", "Mumbai", "IN", "AS"),
+    ("BOS", "Boston", "US", "NA"),
+    ("BRU", "Brussels", "BE", "EU"),
+    ("BSB", "Brasilia", "BR", "SA"),
+    ("BSR", "Basra", "IQ", "AS"),
+    ("BTS", "Bratislava", "SK", "EU"),
+    ("BUD", "Budapest", "HU", "EU"),
+    ("CAI", "Cairo", "EG", "AF"),
+    ("CAN", "Guangzhou", "CN", "AS"),
+    ("CBR", "Canberra", "AU", "OC"),
+    ("CCS", "Caracas", "VE", "SA"),
+    ("CCU", "Kolkata", "IN", "AS"),
+    ("CDG", "Paris", "FR", "EU"),
+    ("CEB", "Cebu", "PH", "AS"),
+    ("CGK", "Jakarta", "ID", "AS"),
+    ("CGP", "Chittagong", "BD", "AS"),
+    ("CHC", "Christchurch", "NZ", "OC"),
+    ("CKG", "Chongqing", "CN", "AS"),
+    ("CLT", "Charlotte", "US", "NA"),
+    ("CMB", "Colombo", "LK", "AS"),
+    ("CMH", "Columbus", "US", "NA"),
+    ("CMN", "Casablanca", "MA", "AF"),
+    ("CNF", "Belo Horizonte", "BR", "SA"),
+    ("CNX", "Chiang Mai", "TH", "AS"),
+    ("COK", "Kochi", "IN", "AS"),
+    ("COR", "Cordoba", "AR", "SA"),
+    ("CPH", "Copenhagen", "DK", "EU"),
+    ("CPT", "Cape Town", "ZA", "AF"),
+    ("CTU", "Chengdu", "CN", "AS"),
+    ("CWB", "Curitiba", "BR", "SA"),
+    ("DAC", "Dhaka", "BD", "AS"),
+    ("DAR", "Dar es Salaam", "TZ", "AF"),
+    ("DEL", "New Delhi", "IN", "AS"),
+    ("DEN", "Denver", "US", "NA"),
+    ("DFW", "Dallas", "US", "NA"),
+    ("DKR", "Dakar", "SN", "AF"),
+    ("DLA", "Douala", "CM", "AF"),
+    ("DME", "Moscow", "RU", "EU"),
+    ("DMM", "Dammam", "SA", "AS"),
+    ("DOH", "Doha", "QA", "AS"),
+    ("DPS", "Denpasar", "ID", "AS"),
+    ("DTW", "Detroit", "US", "NA"),
+    ("DUB", "Dublin", "IE", "EU"),
+    ("DUR", "Durban", "ZA", "AF"),
+    ("DUS", "Dusseldorf", "DE", "EU"),
+    ("DXB", "Dubai", "AE", "AS"),
+    ("EBB", "Kampala", "UG", "AF"),
+    ("EBL", "Erbil", "IQ", "AS"),
+    ("EDI", "Edinburgh", "GB", "EU"),
+    ("EVN", "Yerevan", "AM", "AS"),
+    ("EWR", "Newark", "US", "NA"),
+    ("EZE", "Buenos Aires", "AR", "SA"),
+    ("FCO", "Rome", "IT", "EU"),
+    ("FIH", "Kinshasa", "CD", "AF"),
+    ("FLN", "Florianopolis", "BR", "SA"),
+    ("FOR", "Fortaleza", "BR", "SA"),
+    ("FRA", "Frankfurt", "DE", "EU"),
+    ("FRU", "Bishkek", "KG", "AS"),
+    ("FUK", "Fukuoka", "JP", "AS"),
+    ("GBE", "Gaborone", "BW", "AF"),
+    ("GDL", "Guadalajara", "MX", "NA"),
+    ("GEO", "Georgetown", "GY", "SA"),
+    ("GIG", "Rio de Janeiro", "BR", "SA"),
+    ("GOT", "Gothenburg", "SE", "EU"),
+    ("GRU", "Sao Paulo", "BR", "SA"),
+    ("GUA", "Guatemala City", "GT", "NA"),
+    ("GUM", "Hagatna", "GU", "OC"),
+    ("GVA", "Geneva", "CH", "EU"),
+    ("GYD", "Baku", "AZ", "AS"),
+    ("GYE", "Guayaquil", "EC", "SA"),
+    ("HAM", "Hamburg", "DE", "EU"),
+    ("HAN", "Hanoi", "VN", "AS"),
+    ("HBA", "Hobart", "AU", "OC"),
+    ("HEL", "Helsinki", "FI", "EU"),
+    ("HFA", "Haifa", "IL", "AS"),
+    ("HGH", "Hangzhou", "CN", "AS"),
+    ("HKG", "Hong Kong", "HK", "AS"),
+    ("HNL", "Honolulu", "US", "NA"),
+    ("HRE", "Harare", "ZW", "AF"),
+    ("HYD", "Hyderabad", "IN", "AS"),
+    ("IAD", "Ashburn", "US", "NA"),
+    ("IAH", "Houston", "US", "NA"),
+    ("ICN", "Seoul", "KR", "AS"),
+    ("IND", "Indianapolis", "US", "NA"),
+    ("ISB", "Islamabad", "PK", "AS"),
+    ("IST", "Istanbul", "TR", "AS"),
+    ("IXC", "Chandigarh", "IN", "AS"),
+    ("JAX", "Jacksonville", "US", "NA"),
+    ("JED", "Jeddah", "SA", "AS"),
+    ("JHB", "Johor Bahru", "MY", "AS"),
+    ("JIB", "Djibouti", "DJ", "AF"),
+    ("JNB", "Johannesburg", "ZA", "AF"),
+    ("KBP", "Kyiv", "UA", "EU"),
+    ("KEF", "Reykjavik", "IS", "EU"),
+    ("KGL", "Kigali", "RW", "AF"),
+    ("KHH", "Kaohsiung", "TW", "AS"),
+    ("KHI", "Karachi", "PK", "AS"),
+    ("KIN", "Kingston", "JM", "NA"),
+    ("KIV", "Chisinau", "MD", "EU"),
+    ("KIX", "Osaka", "JP", "AS"),
+    ("KJA", "Krasnoyarsk", "RU", "AS"),
+    ("KTM", "Kathmandu", "NP", "AS"),
+    ("KUL", "Kuala Lumpur", "MY", "AS"),
+    ("KWI", "Kuwait City", "KW", "AS"),
+    ("KZN", "Kazan", "RU", "EU"),
+    ("LAD", "Luanda", "AO", "AF"),
+    ("LAS", "Las Vegas", "US", "NA"),
+    ("LAX", "Los Angeles", "US", "NA"),
+    ("LCA", "Larnaca", "CY", "EU"),
+    ("LED", "Saint Petersburg", "RU", "EU"),
+    ("LHE", "Lahore", "PK", "AS"),
+    ("LHR", "London", "GB", "EU"),
+    ("LIM", "Lima", "PE", "SA"),
+    ("LIS", "Lisbon", "PT", "EU"),
+    ("LJU", "Ljubljana", "SI", "EU"),
+    ("LOS", "Lagos", "NG", "AF"),
+    ("LPB", "La Paz", "BO", "SA"),
+    ("LUN", "Lusaka", "ZM", "AF"),
+    ("LUX", "Luxembourg", "LU", "EU"),
+    ("LYS", "Lyon", "FR", "EU"),
+    ("MAA", "Chennai", "IN", "AS"),
+    ("MAD", "Madrid", "ES", "EU"),
+    ("MAN", "Manchester", "GB", "EU"),
+    ("MAO", "Manaus", "BR", "SA"),
+    ("MBA", "Mombasa", "KE", "AF"),
+    ("MCI", "Kansas City", "US", "NA"),
+    ("MCO", "Orlando", "US", "NA"),
+    ("MCT", "Muscat", "OM", "AS"),
+    ("MDE", "Medellin", "CO", "SA"),
+    ("MEL", "Melbourne", "AU", "OC"),
+    ("MEM", "Memphis", "US", "NA"),
+    ("MEX", "Mexico City", "MX", "NA"),
+    ("MFM", "Macau", "MO", "AS"),
+    ("MIA", "Miami", "US", "NA"),
+    ("MLA", "Valletta", "MT", "EU"),
+    ("MNL", "Manila", "PH", "AS"),
+    ("MPM", "Maputo", "MZ", "AF"),
+    ("MRS", "Marseille", "FR", "EU"),
+    ("MRU", "Port Louis", "MU", "AF"),
+    ("MSP", "Minneapolis", "US", "NA"),
+    ("MSQ", "Minsk", "BY", "EU"),
+    ("MUC", "Munich", "DE", "EU"),
+    ("MVD", "Montevideo", "UY", "SA"),
+    ("MXP", "Milan", "IT", "EU"),
+    ("NAG", "Nagpur", "IN", "AS"),
+    ("NBO", "Nairobi", "KE", "AF"),
+    ("NOU", "Noumea", "NC", "OC"),
+    ("NQN", "Neuquen", "AR", "SA"),
+    ("NQZ", "Astana", "KZ", "AS"),
+    ("NRT", "Tokyo", "JP", "AS"),
+    ("OKA", "Naha", "JP", "AS"),
+    ("OKC", "Oklahoma City", "US", "NA"),
+    ("OMA", "Omaha", "US", "NA"),
+    ("ORD", "Chicago", "US", "NA"),
+    ("ORK", "Cork", "IE", "EU"),
+    ("OSL", "Oslo", "NO", "EU"),
+    ("OTP", "Bucharest", "RO", "EU"),
+    ("OUA", "Ouagadougou", "BF", "AF"),
+    ("PBM", "Paramaribo", "SR", "SA"),
+    ("PDX", "Portland", "US", "NA"),
+    ("PEK", "Beijing", "CN", "AS"),
+    ("PER", "Perth", "AU", "OC"),
+    ("PHL", "Philadelphia", "US", "NA"),
+    ("PHX", "Phoenix", "US", "NA"),
+    ("PIT", "Pittsburgh", "US", "NA"),
+    ("PMO", "Palermo", "IT", "EU"),
+    ("PNH", "Phnom Penh", "KH", "AS"),
+    ("POA", "Porto Alegre", "BR", "SA"),
+    ("POS", "Port of Spain", "TT", "NA"),
+    ("PPT", "Papeete", "PF", "OC"),
+    ("PRG", "Prague", "CZ", "EU"),
+    ("PTY", "Panama City", "PA", "NA"),
+    ("QRO", "Queretaro", "MX", "NA"),
+    ("QWJ", "Americana", "BR", "SA"),
+    ("RDU", "Durham", "US", "NA"),
+    ("REC", "Recife", "BR", "SA"),
+    ("RGN", "Yangon", "MM", "AS"),
+    ("RIX", "Riga", "LV", "EU"),
+    ("RUH", "Riyadh", "SA", "AS"),
+    ("RUN", "Saint-Denis", "RE", "AF"),
+    ("SAN", "San Diego", "US", "NA"),
+    ("SAT", "San Antonio", "US", "NA"),
+    ("SCL", "Santiago", "CL", "SA"),
+    ("SDQ", "Santo Domingo", "DO", "NA"),
+    ("SEA", "Seattle", "US", "NA"),
+    ("SFO", "San Francisco", "US", "NA"),
+    ("SGN", "Ho Chi Minh City", "VN", "AS"),
+    ("SHA", "Shanghai", "CN", "AS"),
+    ("SIN", "Singapore", "SG", "AS"),
+    ("SJC", "San Jose", "US", "NA"),
+    ("SJJ", "Sarajevo", "BA", "EU"),
+    ("SJO", "San Jose", "CR", "NA"),
+    ("SKG", "Thessaloniki", "GR", "EU"),
+    ("SKP", "Skopje", "MK", "EU"),
+    ("SLC", "Salt Lake City", "US", "NA"),
+    ("SMF", "Sacramento", "US", "NA"),
+    ("SOF", "Sofia", "BG", "EU"),
+    ("SSA", "Salvador", "BR", "SA"),
+    ("STL", "St. Louis", "US", "NA"),
+    ("STR", "Stuttgart", "DE", "EU"),
+    ("SUB", "Surabaya", "ID", "AS"),
+    ("SUV", "Suva", "FJ", "OC"),
+    ("SVX", "Yekaterinburg", "RU", "EU"),
+    ("SYD", "Sydney", "AU", "OC"),
+    ("SZX", "Shenzhen", "CN", "AS"),
+    ("TAS", "Tashkent", "UZ", "AS"),
+    ("TBS", "Tbilisi", "GE", "AS"),
+    ("TGU", "Tegucigalpa", "HN", "NA"),
+    ("TIA", "Tirana", "AL", "EU"),
+    ("TLL", "Tallinn", "EE", "EU"),
+    ("TLV", "Tel Aviv", "IL", "AS"),
+    ("TNR", "Antananarivo", "MG", "AF"),
+    ("TPA", "Tampa", "US", "NA"),
+    ("TPE", "Taipei", "TW", "AS"),
+    ("TSN", "Tianjin", "CN", "AS"),
+    ("TUN", "Tunis", "TN", "AF"),
+    ("TXL", "Berlin", "DE", "EU"),
+    ("UIO", "Quito", "EC", "SA"),
+    ("ULN", "Ulaanbaatar", "MN", "AS"),
+    ("VIE", "Vienna", "AT", "EU"),
+    ("VNO", "Vilnius", "LT", "EU"),
+    ("VTE", "Vientiane", "LA", "AS"),
+    ("WAW", "Warsaw", "PL", "EU"),
+    ("WDH", "Windhoek", "NA", "AF"),
+    ("WUH", "Wuhan", "CN", "AS"),
+    ("XIY", "Xi'an", "CN", "AS"),
+    ("YOW", "Ottawa", "CA", "NA"),
+    ("YUL", "Montreal", "CA", "NA"),
+    ("YVR", "Vancouver", "CA", "NA"),
+    ("YWG", "Winnipeg", "CA", "NA"),
+    ("YXE", "Saskatoon", "CA", "NA"),
+    ("YYC", "Calgary", "CA", "NA"),
+    ("YYZ", "Toronto", "CA", "NA"),
+    ("ZAG", "Zagreb", "HR", "EU"),
+    ("ZRH", "Zurich", "CH", "EU"),
+];
+
+// 查询数据中心所在位置
+pub fn lookup(colo: &str) -> Option<Location> {
+    let colo = colo.trim().to_uppercase();
+    COLO_TABLE
+        .binary_search_by(|(code, ..)| (*code).cmp(colo.as_str()))
+        .ok()
+        .map(|idx| {
+            let (colo, city, country, continent) = COLO_TABLE[idx];
+            Location { colo, city, country, continent }
+        })
+}
+
+// 地区过滤条件：三字码匹配数据中心，二字码匹配国家或大洲
+//
+// 两位代码与大洲代码相同时（如 NA、SA）视为大洲，可写作 country:SA 明确指定国家，
+// 也可写作 continent:EU 明确指定大洲
+#[derive(Debug, Clone, Default)]
+pub struct ColoFilter {
+    colos: HashSet<String>,
+    countries: HashSet<String>,
+    continents: HashSet<String>,
+}
+
+impl ColoFilter {
+    pub fn parse(filter: &str) -> Self {
+        let mut result = Self::default();
+        for item in filter.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let lower = item.to_lowercase();
+            if let Some(country) = lower.strip_prefix("country:") {
+                result.countries.insert(country.trim().to_uppercase());
+                continue;
+            }
+            if let Some(continent) = lower.strip_prefix("continent:") {
+                result.continents.insert(continent.trim().to_uppercase());
+                continue;
+            }
+
+            let code = item.to_uppercase();
+            match code.len() {
+                2 if CONTINENTS.contains(&code.as_str()) => {
+                    result.continents.insert(code);
+                }
+                2 => {
+                    result.countries.insert(code);
+                }
+                _ => {
+                    result.colos.insert(code);
+                }
+            }
+        }
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colos.is_empty() && self.countries.is_empty() && self.continents.is_empty()
+    }
+
+    pub fn matches(&self, colo: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let colo = colo.trim().to_uppercase();
+        if self.colos.contains(&colo) {
+            return true;
+        }
+        match lookup(&colo) {
+            Some(loc) => self.countries.contains(loc.country) || self.continents.contains(loc.continent),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        // lookup 依赖二分查找
+        assert!(COLO_TABLE.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn lookup_hit_and_miss() {
+        let loc = lookup(" lax ").unwrap();
+        assert_eq!((loc.colo, loc.city, loc.country, loc.continent), ("LAX", "Los Angeles", "US", "NA"));
+        assert!(lookup("XXX").is_none());
+        assert!(lookup("").is_none());
+    }
+
+    #[test]
+    fn matches_country() {
+        let filter = ColoFilter::parse("jp");
+        assert!(filter.matches("NRT"));
+        assert!(filter.matches("kix"));
+        assert!(!filter.matches("HKG"));
+        // 不在表中的三字码无法判断国家
+        assert!(!filter.matches("XXX"));
+    }
+
+    #[test]
+    fn matches_continent() {
+        let filter = ColoFilter::parse("EU");
+        assert!(filter.matches("FRA"));
+        assert!(!filter.matches("SIN"));
+
+        // 与大洲代码相同的两位代码默认视为大洲，country: 前缀指定国家
+        assert!(ColoFilter::parse("SA").matches("GRU"));
+        assert!(!ColoFilter::parse("SA").matches("JED"));
+        assert!(ColoFilter::parse("country:SA").matches("JED"));
+        assert!(!ColoFilter::parse("country:SA").matches("GRU"));
+        assert!(ColoFilter::parse("continent:as").matches("JED"));
+    }
+
+    #[test]
+    fn matches_raw_code() {
+        let filter = ColoFilter::parse("HKG");
+        assert!(filter.matches("hkg"));
+        assert!(!filter.matches("SIN"));
+        // 不在表中的三字码按原样匹配
+        assert!(ColoFilter::parse("XYZ").matches("XYZ"));
+    }
+
+    #[test]
+    fn matches_mixed_list() {
+        let filter = ColoFilter::parse(" HKG, jp ,,OC,country:us ");
+        assert!(!filter.is_empty());
+        for colo in ["HKG", "NRT", "SYD", "AKL", "LAX", "HNL"] {
+            assert!(filter.matches(colo), "{}", colo);
+        }
+        for colo in ["SIN", "TPE", "FRA", "YYZ"] {
+            assert!(!filter.matches(colo), "{}", colo);
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_all() {
+        let filter = ColoFilter::parse(" , ");
+        assert!(filter.is_empty());
+        assert!(filter.matches("XXX"));
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use anyhow::{anyhow, Context, Result};
+use crate::types::{Config, DownloadSpeedSet};
+
+// 最优 IP 发生变化时的退出码
+pub const EXIT_BEST_CHANGED: i32 = 4;
+
+// 上次结果文件中的一条记录
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub latency_ms: f64,
+    pub speed_mb: f64,
+}
+
+impl Entry {
+    fn key(&self) -> (IpAddr, u16) {
+        (self.ip, self.port)
+    }
+}
+
+// 读取 [-compare] 指定的结果文件，支持本程序输出的 csv、json、ndjson 格式
+pub fn load(path: &str) -> Result<Vec<Entry>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("无法读取 {}", path))?;
+    if content.trim_start().starts_with(['[', '{']) {
+        load_json(&content)
+    } else {
+        load_csv(&content)
+    }
+}
+
+fn load_csv(content: &str) -> Result<Vec<Entry>> {
+    // 按表头定位列，兼容 [-columns] 调整过的列顺序与 [-english-header]
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let index = |key: &str| headers.iter().position(|h| crate::csv::find_column(h).is_some_and(|c| c.key == key));
+    let ip_index = index("ip").ok_or_else(|| anyhow!("结果文件缺少 IP 地址列"))?;
+    let (port_index, latency_index, speed_index) = (index("port"), index("latency_ms"), index("download_speed_mb"));
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default().trim();
+        let Ok(ip) = field(Some(ip_index)).parse() else { continue };
+        entries.push(Entry {
+            ip,
+            port: field(port_index).parse().unwrap_or(0),
+            latency_ms: field(latency_index).parse().unwrap_or(0.0),
+            speed_mb: field(speed_index).trim_start_matches('≥').parse().unwrap_or(0.0),
+        });
+    }
+    Ok(entries)
+}
+
+fn load_json(content: &str) -> Result<Vec<Entry>> {
+    let values: Vec<serde_json::Value> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(content)?
+    } else {
+        content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+    values.iter()
+        .map(|v| {
+            let ip = v["ip"].as_str().and_then(|s| s.parse().ok()).ok_or_else(|| anyhow!("记录缺少 ip 字段"))?;
+            Ok(Entry {
+                ip,
+                port: v["port"].as_u64().unwrap_or(0) as u16,
+                latency_ms: v["latency_ms"].as_f64().unwrap_or(0.0),
+                speed_mb: v["download_speed_mb"].as_f64().unwrap_or(0.0),
+            })
+        })
+        .collect()
+}
+
+// 结果文件没有端口列时使用 [-tp]
+fn load_with_port(path: &str, config: &Config) -> Result<Vec<Entry>> {
+    let mut entries = load(path)?;
+    for entry in entries.iter_mut().filter(|e| e.port == 0) {
+        entry.port = config.tcp_port;
+    }
+    Ok(entries)
+}
+
+// 测速开始前读取，避免 [-o] 与 [-compare] 为同一文件时被本次结果覆盖
+pub fn load_previous(config: &Config) -> Option<Vec<Entry>> {
+    if config.compare.is_empty() {
+        return None;
+    }
+    match load_with_port(&config.compare, config) {
+        Ok(entries) => Some(entries),
+        Err(e) => {
+            println!("[错误] 读取对比文件失败：{:#}", e);
+            None
+        }
+    }
+}
+
+// retest 子命令：只对结果文件中的 IP 重新延迟测速与下载测速，结束后与原结果对比；
+// 未指定 [-o] 时写入原文件旁的 <名称>.retest.<扩展名>，不覆盖原结果
+pub fn prepare_retest(config: &mut Config, path: &str, keep_output: bool) -> Result<usize> {
+    let entries = load_with_port(path, config)?;
+    if entries.is_empty() {
+        return Err(anyhow!("{} 中没有可用的 IP", path));
+    }
+    config.ip_text = entries.iter()
+        .map(|e| format_addr(e.ip, e.port))
+        .collect::<Vec<_>>()
+        .join(",");
+    config.cf_official = false;
+    config.test_count = entries.len() as u32;
+    config.stop_after = 0;
+    config.compare = path.to_string();
+    if !keep_output {
+        config.output = retest_output(path);
+    }
+    Ok(entries.len())
+}
+
+// 如 result.csv -> result.retest.csv
+fn retest_output(path: &str) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("result");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.retest.{}", stem, ext),
+        None => format!("{}.retest", stem),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn format_addr(ip: IpAddr, port: u16) -> String {
+    match ip {
+        IpAddr::V4(_) => format!("{}:{}", ip, port),
+        IpAddr::V6(_) => format!("[{}]:{}", ip, port),
+    }
+}
+
+// 打印与上次结果的差异，返回最优 IP 是否变化
+// 前 [-p] 个结果视为优选集合；劣化按 [-degrade] 判断，启用下载测速时比较速度，否则比较延迟
+pub fn report(config: &Config, previous: &[Entry], data: &DownloadSpeedSet) -> bool {
+    let top = config.print_num.max(1) as usize;
+    let threshold = config.degrade_threshold.max(0.0);
+    let use_speed = !config.disable_download;
+    let current: HashMap<(IpAddr, u16), Entry> = data.iter()
+        .map(|d| {
+            let entry = Entry {
+                ip: d.ping_data.ip,
+                port: d.ping_data.port,
+                latency_ms: d.ping_data.delay.as_secs_f64() * 1000.0,
+                speed_mb: d.download_speed / 1024.0 / 1024.0,
+            };
+            (entry.key(), entry)
+        })
+        .collect();
+
+    println!("\n与上次结果对比 ({})：", config.compare);
+
+    let old_top = &previous[..previous.len().min(top)];
+    let mut regressed = 0;
+    for old in old_top {
+        let addr = format_addr(old.ip, old.port);
+        match current.get(&old.key()) {
+            None => {
+                regressed += 1;
+                println!("  [失效] {} 不在本次结果中", addr);
+            }
+            Some(new) => {
+                let worse = if use_speed {
+                    new.speed_mb < old.speed_mb * (1.0 - threshold)
+                } else {
+                    new.latency_ms > old.latency_ms * (1.0 + threshold)
+                };
+                if worse {
+                    regressed += 1;
+                }
+                println!(
+                    "  {} {}  延迟 {:.2} -> {:.2} ms ({:+.2})，速度 {:.2} -> {:.2} MB/s ({:+.2})",
+                    if worse { "[劣化]" } else { "[保持]" },
+                    addr, old.latency_ms, new.latency_ms, new.latency_ms - old.latency_ms,
+                    old.speed_mb, new.speed_mb, new.speed_mb - old.speed_mb
+                );
+            }
+        }
+    }
+
+    let old_keys: Vec<(IpAddr, u16)> = old_top.iter().map(Entry::key).collect();
+    let entered: Vec<String> = data.iter()
+        .take(top)
+        .filter(|d| !old_keys.contains(&(d.ping_data.ip, d.ping_data.port)))
+        .map(|d| format_addr(d.ping_data.ip, d.ping_data.port))
+        .collect();
+    if !entered.is_empty() {
+        println!("  [新增] {}", entered.join("，"));
+    }
+
+    let old_best = previous.first().map(Entry::key);
+    let new_best = data.first().map(|d| (d.ping_data.ip, d.ping_data.port));
+    let best_changed = old_best != new_best;
+    let describe = |best: Option<(IpAddr, u16)>| best.map(|(ip, port)| format_addr(ip, port)).unwrap_or_else(|| "无".to_string());
+    println!(
+        "  劣化 {} 个，新增 {} 个；最优 IP {}",
+        regressed,
+        entered.len(),
+        if best_changed {
+            format!("已变化：{} -> {}", describe(old_best), describe(new_best))
+        } else {
+            format!("未变化：{}", describe(new_best))
+        }
+    );
+    best_changed
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use crate::types::Config;
+
+const PROFILES_KEY: &str = "profiles";
+
+// 读取 TOML / YAML 配置文件，profile 指定时用 [profiles.<名称>] 覆盖基础配置
+pub fn load_config(path: &str, profile: Option<&str>) -> Result<Config> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("无法读取 {}", path))?;
+
+    let value = parse_document(path, &content)?;
+    let mut base = match value {
+        Value::Object(map) => map,
+        Value::Null => Map::new(),
+        _ => bail!("配置文件顶层必须是键值表"),
+    };
+
+    let profiles = base.remove(PROFILES_KEY);
+    if let Some(name) = profile {
+        let overlay = profiles
+            .as_ref()
+            .and_then(|p| p.get(name))
+            .and_then(|p| p.as_object())
+            .with_context(|| format!("配置文件中不存在方案 [{}.{}]", PROFILES_KEY, name))?;
+        for (key, value) in overlay {
+            base.insert(key.clone(), value.clone());
+        }
+    }
+
+    serde_json::from_value(Value::Object(base)).context("配置项格式错误")
+}
+
+// 按扩展名选择解析器，统一转换为 JSON 值便于合并
+fn parse_document(path: &str, content: &str) -> Result<Value> {
+    let ext = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match ext.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(content).context("YAML 解析失败"),
+        "json" => serde_json::from_str(content).context("JSON 解析失败"),
+        _ => {
+            let table: toml::Table = toml::from_str(content).context("TOML 解析失败")?;
+            serde_json::to_value(table).context("TOML 转换失败")
+        }
+    }
+}
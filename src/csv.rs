@@ -1,104 +1,532 @@
-use std::fs::File;
-use anyhow::Result;
-use std::io::BufWriter;
-use crate::types::{Config, DownloadSpeedSet};
-use crate::httping::HttpPing;
-use crate::download::build_client;
-use prettytable::{Table, Row, Cell, format};
-
-pub async fn export_csv(data: &mut DownloadSpeedSet, config: &Config) -> Result<()> {
-    if data.is_empty() {
-        return Ok(());
-    }
-
-    let http_ping = HttpPing::new(config.clone(), None);
-    for ip_data in data.iter_mut() {
-        if let Some(client) = build_client(&ip_data.ping_data.ip, config).await {
-            if let Ok(resp) = client.head(&config.url).send().await {
-                if let Some(colo) = http_ping.get_colo(resp.headers()) {
-                    ip_data.colo = colo;
-                }
-            }
-        }
-    }
-
-    if config.output.is_empty() {
-        return Ok(());
-    }
-
-    let file = File::create(&config.output)?;
-    let buf_writer = BufWriter::with_capacity(32 * 1024, file);
-    let mut writer = csv::Writer::from_writer(buf_writer);
-
-    // 写入表头
-    writer.write_record(&[
-        "IP 地址",
-        "已发送",
-        "已接收", 
-        "丢包率",
-        "平均延迟",
-        "下载速度 (MB/s)",
-        "数据中心",
-    ])?;
-
-    // 写入数据
-    for ip_data in data {
-        writer.write_record(&ip_data.to_string_vec())?;
-    }
-
-    writer.flush()?;
-    Ok(())
-}
-
-pub trait PrintResult {
-    fn print(&self);
-}
-
-impl PrintResult for DownloadSpeedSet {
-    fn print(&self) {
-        if self.is_empty() {
-            println!("\n[信息] 完整测速结果 IP 数量为 0，跳过输出结果。");
-            return;
-        }
-
-        let mut table = Table::new();
-        
-        // 设置表格样式
-        table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-        
-        // 添加表头，使用青色
-        table.add_row(Row::new(vec![
-            Cell::new("IP 地址").style_spec("Fc"),
-            Cell::new("已发送").style_spec("Fc"),
-            Cell::new("已接收").style_spec("Fc"),
-            Cell::new("丢包率").style_spec("Fc"),
-            Cell::new("平均延迟").style_spec("Fc"),
-            Cell::new("下载速度 (MB/s)").style_spec("Fc"),
-            Cell::new("数据中心").style_spec("Fc"),
-        ]));
-
-        // 添加数据行
-        for ip_data in self.iter().take(self[0].config.print_num.try_into().unwrap()) {
-            table.add_row(Row::new(vec![
-                Cell::new(&ip_data.ping_data.ip.to_string()),
-                Cell::new(&ip_data.ping_data.sended.to_string()),
-                Cell::new(&ip_data.ping_data.received.to_string()),
-                Cell::new(&format!("{:.2}", ip_data.loss_rate)),
-                Cell::new(&format!("{:.2}", ip_data.ping_data.delay.as_millis())),
-                Cell::new(&format!("{:.2}", ip_data.download_speed / 1024.0 / 1024.0)),
-                Cell::new(&ip_data.colo),
-            ]));
-        }
-
-        // 打印表格
-        table.printstd();
-
-        // 如果有输出文件，打印提示
-        if !self[0].config.output.is_empty() {
-            println!(
-                "\n完整测速结果已写入 {} 文件，可使用记事本/表格软件查看。",
-                self[0].config.output
-            );
-        }
-    }
+use std::fs::File;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use std::io::{BufWriter, Write};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use crate::types::{Config, CloudflareIPData, DownloadSpeedSet, OutputFormat};
+use crate::{cancel, output};
+use prettytable::{Table, Row, Cell, format};
+
+// JSON/NDJSON 输出的单条记录，字段名保持稳定
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultRecord {
+    pub ip: String,
+    pub port: u16,
+    pub sended: u32,
+    pub received: u32,
+    pub loss_rate: f32,
+    pub latency_ms: f64,
+    pub min_latency_ms: f64,
+    pub max_latency_ms: f64,
+    pub jitter_ms: f64,
+    pub download_speed_mb: f64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub download_capped: bool,
+    pub upload_speed_mb: f64,
+    pub colo: String,
+    pub city: String,
+    pub country: String,
+    pub continent: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttfb_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub h2_latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ip_country: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub ip_city: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub as_org: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub warp: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub http: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub tls: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub sgroup: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hops: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp_hop: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isp_hop_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soak_loss_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soak_latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soak_drift_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soak_max_burst: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub soak_disconnects: Option<u32>,
+    // 测速被中断，结果只包含中断前已完成的部分
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    pub timestamp: u64,
+}
+
+impl ResultRecord {
+    pub fn new(ip_data: &CloudflareIPData, timestamp: u64) -> Self {
+        let ping = &ip_data.ping_data;
+        let location = crate::colo::lookup(&ip_data.colo);
+        let timing = Some(ip_data.timing).filter(|_| ip_data.config.timing);
+        Self {
+            ip: ping.ip.to_string(),
+            port: ping.port,
+            sended: ping.sended,
+            received: ping.received,
+            loss_rate: ip_data.loss_rate,
+            latency_ms: ping.delay.as_secs_f64() * 1000.0,
+            min_latency_ms: ping.min_delay.as_secs_f64() * 1000.0,
+            max_latency_ms: ping.max_delay.as_secs_f64() * 1000.0,
+            jitter_ms: ping.jitter.as_secs_f64() * 1000.0,
+            download_speed_mb: ip_data.download_speed / 1024.0 / 1024.0,
+            download_capped: ip_data.download_capped,
+            upload_speed_mb: ip_data.upload_speed / 1024.0 / 1024.0,
+            colo: ip_data.colo.clone(),
+            city: location.map(|l| l.city).unwrap_or_default().to_string(),
+            country: location.map(|l| l.country).unwrap_or_default().to_string(),
+            continent: location.map(|l| l.continent).unwrap_or_default().to_string(),
+            connect_ms: timing.map(|t| t.connect.as_secs_f64() * 1000.0),
+            tls_ms: timing.map(|t| t.tls_handshake.as_secs_f64() * 1000.0),
+            ttfb_ms: timing.map(|t| t.ttfb.as_secs_f64() * 1000.0),
+            h2_latency_ms: ip_data.h2_latency.map(|d| d.as_secs_f64() * 1000.0),
+            ip_country: ip_data.geo.country.clone(),
+            ip_city: ip_data.geo.city.clone(),
+            asn: Some(ip_data.geo.asn).filter(|&asn| asn != 0),
+            as_org: ip_data.geo.as_org.clone(),
+            warp: ip_data.trace.warp.clone(),
+            http: ip_data.trace.http.clone(),
+            tls: ip_data.trace.tls.clone(),
+            sgroup: ip_data.trace.sgroup.clone(),
+            hops: ip_data.path.and_then(|p| p.hops),
+            isp_hop: ip_data.path.and_then(|p| p.isp_hop).map(|ip| ip.to_string()),
+            isp_hop_ms: ip_data.path.and_then(|p| p.isp_hop_ms),
+            soak_loss_rate: ip_data.soak.map(|s| s.loss_rate()),
+            soak_latency_ms: ip_data.soak.map(|s| s.latency_ms),
+            soak_drift_ms: ip_data.soak.map(|s| s.drift_ms),
+            soak_max_burst: ip_data.soak.map(|s| s.max_burst),
+            soak_disconnects: ip_data.soak.map(|s| s.disconnects),
+            partial: cancel::requested(),
+            timestamp,
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// 按指定格式写入结果文件，没有结果时不写入
+pub fn export_file(data: &DownloadSpeedSet, config: &Config, path: &str, format: OutputFormat) -> Result<()> {
+    if data.is_empty() || path.is_empty() {
+        return Ok(());
+    }
+    match format {
+        OutputFormat::Csv => export_csv(data, config, path),
+        OutputFormat::Json | OutputFormat::Ndjson => export_json(data, config, path, format),
+    }
+}
+
+fn export_json(data: &DownloadSpeedSet, config: &Config, path: &str, format: OutputFormat) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::with_capacity(32 * 1024, file);
+    let timestamp = unix_timestamp();
+    let columns = (!config.columns.is_empty()).then(|| selected_columns(config));
+    let records = data.iter()
+        .map(|d| JsonRecord::new(d, timestamp, columns.as_deref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    if format == OutputFormat::Ndjson {
+        for record in records {
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut writer, &records)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// 结果文件中的一列；key 同时是 JSON 字段名与 [-english-header] 的表头，不随版本变化
+#[derive(Debug, PartialEq, Eq)]
+pub struct Column {
+    pub key: &'static str,
+    pub header: &'static str,
+    aliases: &'static [&'static str],
+}
+
+const fn column(key: &'static str, header: &'static str, aliases: &'static [&'static str]) -> Column {
+    Column { key, header, aliases }
+}
+
+pub const COLUMNS: &[Column] = &[
+    column("ip", "IP 地址", &[]),
+    column("port", "端口", &[]),
+    column("sended", "已发送", &["sent"]),
+    column("received", "已接收", &[]),
+    column("loss_rate", "丢包率", &["loss"]),
+    column("latency_ms", "平均延迟", &["latency", "delay"]),
+    column("min_latency_ms", "最小延迟", &["min_latency"]),
+    column("max_latency_ms", "最大延迟", &["max_latency"]),
+    column("jitter_ms", "抖动", &["jitter"]),
+    column("download_speed_mb", "下载速度 (MB/s)", &["speed", "download"]),
+    column("download_capped", "下载受限", &["capped"]),
+    column("upload_speed_mb", "上传速度 (MB/s)", &["upload"]),
+    column("colo", "数据中心", &[]),
+    column("city", "城市", &[]),
+    column("country", "国家", &[]),
+    column("continent", "大洲", &[]),
+    column("connect_ms", "连接耗时", &["connect"]),
+    column("tls_ms", "TLS 握手", &[]),
+    column("ttfb_ms", "首字节", &["ttfb"]),
+    column("h2_latency_ms", "HTTP/2 复用延迟", &["h2_latency"]),
+    column("ip_country", "IP 国家", &[]),
+    column("ip_city", "IP 城市", &[]),
+    column("asn", "ASN", &[]),
+    column("as_org", "AS 组织", &[]),
+    column("warp", "WARP", &[]),
+    column("http", "HTTP 协议", &[]),
+    column("tls", "TLS 版本", &[]),
+    column("sgroup", "sgroup", &[]),
+    column("hops", "跳数", &[]),
+    column("isp_hop", "运营商末跳", &[]),
+    column("isp_hop_ms", "末跳延迟", &[]),
+    column("soak_loss_rate", "持续丢包率", &["soak_loss"]),
+    column("soak_latency_ms", "持续平均延迟", &["soak_latency"]),
+    column("soak_drift_ms", "延迟漂移", &["drift"]),
+    column("soak_max_burst", "最长连续丢包", &["burst"]),
+    column("soak_disconnects", "断连次数", &["disconnects"]),
+    column("partial", "部分结果", &[]),
+    column("timestamp", "时间戳", &["time"]),
+];
+
+// 按字段名、别名或中文表头查找列，如 "latency"、"latency_ms"、"平均延迟"
+pub fn find_column(name: &str) -> Option<&'static Column> {
+    let name = name.trim();
+    COLUMNS.iter().find(|c| {
+        c.key.eq_ignore_ascii_case(name) || c.header == name || c.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    })
+}
+
+// 解析 [-columns]，列的顺序即输出顺序
+pub fn parse_columns(list: &str) -> Result<Vec<&'static Column>, String> {
+    list.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| find_column(s).ok_or_else(|| format!("未知的列：{}", s)))
+        .collect()
+}
+
+pub fn column_keys() -> String {
+    COLUMNS.iter().map(|c| c.key).collect::<Vec<_>>().join(",")
+}
+
+// 未指定 [-columns] 时的默认列，随 [-timing]、[-h2-latency]、[-geoip-db]、[-cf-trace] 增加
+fn default_columns(config: &Config) -> Vec<&'static Column> {
+    let mut keys = vec![
+        "ip", "port", "sended", "received", "loss_rate", "latency_ms", "min_latency_ms", "max_latency_ms", "jitter_ms",
+        "download_speed_mb", "upload_speed_mb", "colo", "city", "country", "continent",
+    ];
+    if config.timing {
+        keys.extend(["connect_ms", "tls_ms", "ttfb_ms"]);
+    }
+    if config.h2_latency {
+        keys.push("h2_latency_ms");
+    }
+    if !config.geoip_db.is_empty() {
+        keys.extend(["ip_country", "ip_city", "asn", "as_org"]);
+    }
+    if config.cf_trace {
+        keys.extend(["warp", "http", "tls", "sgroup"]);
+    }
+    if config.trace {
+        keys.extend(["hops", "isp_hop", "isp_hop_ms"]);
+    }
+    if !config.soak.is_zero() {
+        keys.extend(["soak_loss_rate", "soak_latency_ms", "soak_drift_ms", "soak_max_burst", "soak_disconnects"]);
+    }
+    keys.into_iter().filter_map(find_column).collect()
+}
+
+fn selected_columns(config: &Config) -> Vec<&'static Column> {
+    if config.columns.is_empty() {
+        return default_columns(config);
+    }
+    // 参数解析时已检查过列名
+    parse_columns(&config.columns).unwrap_or_default()
+}
+
+fn csv_header(config: &Config) -> Vec<&'static str> {
+    header_of(config, &selected_columns(config))
+}
+
+fn header_of(config: &Config, columns: &[&'static Column]) -> Vec<&'static str> {
+    columns.iter().map(|c| if config.english_header { c.key } else { c.header }).collect()
+}
+
+// 小数保留两位，缺失的值留空；受 [-download-cap] 限制的下载速度写作 "≥6.25"
+fn csv_row(ip_data: &CloudflareIPData, columns: &[&Column]) -> Result<Vec<String>> {
+    let record = serde_json::to_value(ResultRecord::new(ip_data, unix_timestamp()))?;
+    Ok(columns.iter()
+        .map(|c| match &record[c.key] {
+            serde_json::Value::Number(n) if c.key == "download_speed_mb" && ip_data.download_capped => {
+                format!("≥{:.2}", n.as_f64().unwrap_or_default())
+            }
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Number(n) if n.is_f64() => format!("{:.2}", n.as_f64().unwrap_or_default()),
+            other => other.to_string(),
+        })
+        .collect())
+}
+
+// 指定 [-columns] 时的 JSON 记录：只包含所选字段并按所选顺序输出，缺失的值为 null
+struct SelectedRecord<'a> {
+    record: serde_json::Value,
+    columns: &'a [&'static Column],
+}
+
+impl Serialize for SelectedRecord<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for c in self.columns {
+            map.serialize_entry(c.key, &self.record[c.key])?;
+        }
+        map.end()
+    }
+}
+
+// JSON/NDJSON 的一条记录，指定 [-columns] 时只包含所选字段
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonRecord<'a> {
+    Full(Box<ResultRecord>),
+    Selected(SelectedRecord<'a>),
+}
+
+impl<'a> JsonRecord<'a> {
+    fn new(ip_data: &CloudflareIPData, timestamp: u64, columns: Option<&'a [&'static Column]>) -> Result<Self> {
+        let record = ResultRecord::new(ip_data, timestamp);
+        Ok(match columns {
+            Some(columns) => Self::Selected(SelectedRecord { record: serde_json::to_value(record)?, columns }),
+            None => Self::Full(Box::new(record)),
+        })
+    }
+}
+
+fn export_csv(data: &DownloadSpeedSet, config: &Config, path: &str) -> Result<()> {
+    let file = File::create(path)?;
+    let buf_writer = BufWriter::with_capacity(32 * 1024, file);
+    let mut writer = csv::Writer::from_writer(buf_writer);
+
+    // 被中断时默认列之后增加 "部分结果" 列
+    let mut columns = selected_columns(config);
+    if cancel::requested() && config.columns.is_empty() {
+        columns.extend(find_column("partial"));
+    }
+
+    // 写入表头
+    writer.write_record(header_of(config, &columns))?;
+
+    // 写入数据
+    for ip_data in data {
+        writer.write_record(csv_row(ip_data, &columns)?)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+// [-stream-output] 边测速边写入的结果文件
+enum StreamWriter {
+    Csv(Box<csv::Writer<File>>),
+    Ndjson(File),
+}
+
+lazy_static! {
+    static ref STREAM: Mutex<Option<StreamWriter>> = Mutex::new(None);
+}
+
+// 测速开始时创建结果文件，之后满足条件的结果逐条写入并立即落盘，测速中断时也能保留已有结果
+// 写入 [-o] 或 [-output] 中的第一个结果文件
+pub fn start_stream(config: &Config) {
+    *STREAM.lock().unwrap() = None;
+    if !config.stream_output || config.daemon {
+        return;
+    }
+    let Some((format, path)) = output::stream_target(config) else { return };
+    if format == OutputFormat::Json {
+        println!("[提示] JSON 格式无法边测速边写入，请使用 [-output-format csv] 或 ndjson");
+        return;
+    }
+    match open_stream(config, &path, format) {
+        Ok(writer) => *STREAM.lock().unwrap() = Some(writer),
+        Err(e) => println!("[错误] 无法创建结果文件 {}：{:#}", path, e),
+    }
+}
+
+fn open_stream(config: &Config, path: &str, format: OutputFormat) -> Result<StreamWriter> {
+    let file = File::create(path)?;
+    Ok(match format {
+        OutputFormat::Ndjson => StreamWriter::Ndjson(file),
+        _ => {
+            let mut writer = csv::Writer::from_writer(file);
+            writer.write_record(csv_header(config))?;
+            writer.flush()?;
+            StreamWriter::Csv(Box::new(writer))
+        }
+    })
+}
+
+// 写入一条结果；延迟测速与下载测速阶段各写入一次，同一 IP 以后写入的记录为准
+pub fn stream_record(ip_data: &CloudflareIPData) {
+    let mut stream = STREAM.lock().unwrap();
+    let Some(writer) = stream.as_mut() else { return };
+    if let Err(e) = write_stream(writer, ip_data) {
+        println!("[错误] 写入结果文件失败：{:#}", e);
+        *stream = None;
+    }
+}
+
+fn write_stream(writer: &mut StreamWriter, ip_data: &CloudflareIPData) -> Result<()> {
+    match writer {
+        StreamWriter::Csv(writer) => {
+            writer.write_record(csv_row(ip_data, &selected_columns(&ip_data.config))?)?;
+            writer.flush()?;
+        }
+        StreamWriter::Ndjson(file) => {
+            let columns = (!ip_data.config.columns.is_empty()).then(|| selected_columns(&ip_data.config));
+            let mut line = serde_json::to_vec(&JsonRecord::new(ip_data, unix_timestamp(), columns.as_deref())?)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            file.flush()?;
+        }
+    }
+    Ok(())
+}
+
+// 测速结束后关闭，随后由完整结果覆盖写入
+pub fn end_stream() {
+    *STREAM.lock().unwrap() = None;
+}
+
+pub trait PrintResult {
+    fn print(&self);
+}
+
+impl PrintResult for DownloadSpeedSet {
+    fn print(&self) {
+        if self.is_empty() {
+            println!("\n[信息] 完整测速结果 IP 数量为 0，跳过输出结果。");
+            return;
+        }
+
+        let mut table = Table::new();
+        let show_upload = self[0].config.upload_test;
+        let show_soak = self[0].soak.is_some();
+        let show_path = self[0].path.is_some();
+        // 存在非默认端口的结果时显示端口列
+        let show_port = self.iter().any(|d| d.ping_data.port != d.config.tcp_port);
+        
+        // 设置表格样式
+        table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+        
+        // 添加表头，使用青色
+        let mut header = vec![Cell::new("IP 地址").style_spec("Fc")];
+        if show_port {
+            header.push(Cell::new("端口").style_spec("Fc"));
+        }
+        header.extend([
+            Cell::new("已发送").style_spec("Fc"),
+            Cell::new("已接收").style_spec("Fc"),
+            Cell::new("丢包率").style_spec("Fc"),
+            Cell::new("平均延迟").style_spec("Fc"),
+            Cell::new("抖动").style_spec("Fc"),
+            Cell::new("下载速度 (MB/s)").style_spec("Fc"),
+        ]);
+        if show_upload {
+            header.push(Cell::new("上传速度 (MB/s)").style_spec("Fc"));
+        }
+        header.push(Cell::new("数据中心").style_spec("Fc"));
+        if show_path {
+            header.push(Cell::new("跳数").style_spec("Fc"));
+            header.push(Cell::new("末跳延迟").style_spec("Fc"));
+        }
+        if show_soak {
+            header.extend([
+                Cell::new("持续丢包率").style_spec("Fc"),
+                Cell::new("持续平均延迟").style_spec("Fc"),
+                Cell::new("延迟漂移").style_spec("Fc"),
+                Cell::new("断连次数").style_spec("Fc"),
+            ]);
+        }
+        table.add_row(Row::new(header));
+
+        // 添加数据行
+        for ip_data in self.iter().take(self[0].config.print_num.try_into().unwrap()) {
+            let mut row = vec![Cell::new(&ip_data.ping_data.ip.to_string())];
+            if show_port {
+                row.push(Cell::new(&ip_data.ping_data.port.to_string()));
+            }
+            row.extend([
+                Cell::new(&ip_data.ping_data.sended.to_string()),
+                Cell::new(&ip_data.ping_data.received.to_string()),
+                Cell::new(&format!("{:.2}", ip_data.loss_rate)),
+                Cell::new(&format!("{:.2}", ip_data.ping_data.delay.as_millis())),
+                Cell::new(&format!("{:.2}", ip_data.ping_data.jitter.as_secs_f64() * 1000.0)),
+                Cell::new(&format!("{}{:.2}", if ip_data.download_capped { "≥" } else { "" }, ip_data.download_speed / 1024.0 / 1024.0)),
+            ]);
+            if show_upload {
+                row.push(Cell::new(&format!("{:.2}", ip_data.upload_speed / 1024.0 / 1024.0)));
+            }
+            row.push(Cell::new(&ip_data.colo));
+            if show_path {
+                let path = ip_data.path.unwrap_or_default();
+                row.push(Cell::new(&path.hops.map(|h| h.to_string()).unwrap_or_default()));
+                row.push(Cell::new(&path.isp_hop_ms.map(|ms| format!("{:.2}", ms)).unwrap_or_default()));
+            }
+            if show_soak {
+                match &ip_data.soak {
+                    Some(soak) => row.extend([
+                        Cell::new(&format!("{:.2}", soak.loss_rate())),
+                        Cell::new(&format!("{:.2}", soak.latency_ms)),
+                        Cell::new(&format!("{:+.2}", soak.drift_ms)),
+                        Cell::new(&soak.disconnects.to_string()),
+                    ]),
+                    None => row.extend(std::iter::repeat_with(|| Cell::new("")).take(4)),
+                }
+            }
+            table.add_row(Row::new(row));
+        }
+
+        // 打印表格
+        table.printstd();
+
+        // 如果有输出文件，打印提示
+        let files = output::result_files(&self[0].config);
+        if !files.is_empty() {
+            println!(
+                "\n完整测速结果已写入 {} 文件，可使用记事本/表格软件查看。",
+                files.join("、")
+            );
+        }
+        if cancel::requested() {
+            println!("\n[信息] 测速被中断，以上为中断前已完成部分的结果。");
+        }
+    }
 } 
\ No newline at end of file
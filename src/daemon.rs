@@ -0,0 +1,138 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+use anyhow::Result;
+use crate::types::{Config, CloudflareIPData, DownloadSpeedSet};
+use crate::notify::Notifier;
+use crate::{metrics, scan, summary};
+use crate::debug_log;
+
+const HISTORY_SIZE: usize = 10; // 每个 IP 保留的历史记录数
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub delay: Duration,
+    pub download_speed: f64,
+}
+
+// 持续监控状态：每个 IP 的滚动历史记录和当前选用的最优 IP
+#[derive(Debug, Default)]
+pub struct Monitor {
+    history: HashMap<IpAddr, VecDeque<Sample>>,
+    best: Option<IpAddr>,
+    threshold: f64,
+    use_speed: bool,
+}
+
+impl Monitor {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            history: HashMap::new(),
+            best: None,
+            threshold: config.degrade_threshold.max(0.0),
+            use_speed: !config.disable_download,
+        }
+    }
+
+    pub fn best(&self) -> Option<IpAddr> {
+        self.best
+    }
+
+    pub fn history(&self, ip: &IpAddr) -> Option<&VecDeque<Sample>> {
+        self.history.get(ip)
+    }
+
+    // 判断当前最优 IP 是否劣化（需在 record 之前调用，以便与历史平均值比较）
+    pub fn is_degraded(&self, data: &DownloadSpeedSet) -> bool {
+        let best = match self.best {
+            Some(ip) => ip,
+            None => return true,
+        };
+        let current = match data.iter().find(|d| d.ping_data.ip == best) {
+            Some(d) => d,
+            None => return true, // 本轮最优 IP 已不可用
+        };
+        let samples = match self.history.get(&best) {
+            Some(s) if !s.is_empty() => s,
+            _ => return false,
+        };
+
+        if self.use_speed {
+            let avg = samples.iter().map(|s| s.download_speed).sum::<f64>() / samples.len() as f64;
+            current.download_speed < avg * (1.0 - self.threshold)
+        } else {
+            let avg = samples.iter().map(|s| s.delay.as_secs_f64()).sum::<f64>() / samples.len() as f64;
+            current.ping_data.delay.as_secs_f64() > avg * (1.0 + self.threshold)
+        }
+    }
+
+    pub fn record(&mut self, data: &DownloadSpeedSet) {
+        let now = SystemTime::now();
+        for ip_data in data {
+            let samples = self.history.entry(ip_data.ping_data.ip).or_default();
+            if samples.len() >= HISTORY_SIZE {
+                samples.pop_front();
+            }
+            samples.push_back(Sample {
+                time: now,
+                delay: ip_data.ping_data.delay,
+                download_speed: ip_data.download_speed,
+            });
+        }
+    }
+
+    pub fn set_best(&mut self, best: Option<&CloudflareIPData>) {
+        self.best = best.map(|d| d.ping_data.ip);
+    }
+}
+
+// 持续监控：循环执行测速，最优 IP 劣化时才重写结果并触发后续操作，Ctrl+C 退出
+pub async fn run(config: Config) -> Result<()> {
+    let mut monitor = Monitor::new(&config);
+    let mut notifier = Notifier::new(&config);
+    if !config.metrics_addr.is_empty() {
+        metrics::serve(&config.metrics_addr)?;
+    }
+    let mut round = 0u64;
+
+    loop {
+        round += 1;
+        println!("\n[监控] 第 {} 轮测速开始", round);
+
+        let mut run_config = config.clone();
+        match scan::run_pipeline(&mut run_config).await {
+            Ok(mut speed_data) => {
+                scan::record_history(&run_config, &speed_data);
+                summary::report(&run_config, &speed_data);
+                metrics::update(&speed_data);
+                if speed_data.is_empty() {
+                    println!("[监控] 本轮没有可用 IP，保留上一轮结果");
+                } else if monitor.is_degraded(&speed_data) {
+                    let previous = monitor.best();
+                    monitor.set_best(speed_data.first());
+                    match previous {
+                        Some(ip) => println!("[监控] 最优 IP {} 已劣化，切换为 {}", ip, speed_data[0].ping_data.ip),
+                        None => println!("[监控] 最优 IP 为 {}", speed_data[0].ping_data.ip),
+                    }
+                    scan::publish_results(&run_config, &mut speed_data).await?;
+                } else {
+                    println!("[监控] 最优 IP {} 状态正常，结果保持不变", monitor.best().unwrap());
+                }
+                monitor.record(&speed_data);
+                notifier.notify(&run_config, &speed_data).await;
+                debug_log!("监控历史 IP 数量: {}", monitor.history.len());
+            }
+            Err(e) => println!("[监控] 本轮测速失败：{:#}", e),
+        }
+
+        println!("[监控] 下一轮将在 {:?} 后开始", config.daemon_interval);
+        tokio::select! {
+            _ = tokio::time::sleep(config.daemon_interval) => {}
+            _ = tokio::signal::ctrl_c() => {
+                println!("\n[监控] 收到退出信号，停止监控");
+                return Ok(());
+            }
+        }
+    }
+}
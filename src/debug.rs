@@ -1,35 +1,58 @@
-#[cfg(feature = "debug")]
-use tracing_subscriber::{fmt, EnvFilter};
-
-pub fn init_debug() {
-    #[cfg(feature = "debug")]
-    {
-        static INIT: std::sync::Once = std::sync::Once::new();
-        INIT.call_once(|| {
-            fmt()
-                .with_env_filter(EnvFilter::from_default_env()
-                    .add_directive(tracing::Level::DEBUG.into()))
-                .with_target(false)
-                .with_thread_ids(true)
-                .with_thread_names(true)
-                .with_file(true)
-                .with_line_number(true)
-                .init();
-        });
-    }
-}
-
-// debug宏,只在debug feature启用时生效
-#[cfg(feature = "debug")]
-#[macro_export]
-macro_rules! debug_log {
-    ($($arg:tt)*) => {
-        tracing::debug!($($arg)*);
-    }
-}
-
-#[cfg(not(feature = "debug"))]
-#[macro_export]
-macro_rules! debug_log {
-    ($($arg:tt)*) => {};
-} 
\ No newline at end of file
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{fmt, EnvFilter};
+use crate::types::{Config, LogFormat};
+
+// 未指定 [-log-level] 时的级别，以 debug feature 编译时默认输出调试日志
+#[cfg(feature = "debug")]
+const DEFAULT_LEVEL: &str = "debug";
+#[cfg(not(feature = "debug"))]
+const DEFAULT_LEVEL: &str = "off";
+
+pub const LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+// 按 [-log-level]、[-log-file]、[-log-format] 初始化日志，设置了 RUST_LOG 环境变量时以其为准
+// 只有本程序的日志使用指定级别，依赖库 (hyper、reqwest 等) 只输出警告及以上
+// 每个测速阶段为一个 span，结束时记录耗时
+pub fn init(config: &Config) -> Result<(), String> {
+    let level = if config.log_level.is_empty() { DEFAULT_LEVEL } else { config.log_level.as_str() };
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) if level == "off" => return Ok(()),
+        Err(_) => EnvFilter::new(format!("warn,cloudflarest={0},CloudflareST_Rust={0}", level)),
+    };
+
+    let writer = if config.log_file.is_empty() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.log_file)
+            .map_err(|e| format!("无法打开日志文件 {}：{}", config.log_file, e))?;
+        BoxMakeWriter::new(Mutex::new(file))
+    };
+
+    let builder = fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(config.log_file.is_empty())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false)
+        .with_thread_ids(true);
+    let result = match config.log_format {
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).try_init(),
+        LogFormat::Text => builder.with_file(true).with_line_number(true).try_init(),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+// 调试日志，级别低于 [-log-level] 时不会格式化参数
+#[macro_export]
+macro_rules! debug_log {
+    ($($arg:tt)*) => {
+        tracing::debug!($($arg)*);
+    }
+}
@@ -0,0 +1,247 @@
+use std::net::IpAddr;
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use crate::types::{Config, DownloadSpeedSet};
+use crate::{cancel, urls};
+use crate::debug_log;
+
+pub(crate) const API_BASE: &str = "https://api.cloudflare.com/client/v4";
+const API_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<ApiMessage>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct ApiMessage {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct DnsRecord {
+    id: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    content: String,
+    #[serde(default)]
+    proxied: bool,
+}
+
+// 对单条记录的操作计划
+enum Action {
+    Update { id: String, ip: IpAddr, proxied: bool },
+    Create { ip: IpAddr },
+    Delete { id: String, content: String },
+}
+
+struct DnsApi {
+    client: Client,
+    zone_id: String,
+    token: String,
+}
+
+impl DnsApi {
+    fn new(zone_id: &str, token: &str, config: &Config) -> Result<Self> {
+        Ok(Self {
+            client: api_client(config)?,
+            zone_id: zone_id.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    async fn send<T: for<'de> Deserialize<'de>>(&self, req: reqwest::RequestBuilder) -> Result<Option<T>> {
+        send_api(req, &self.token).await
+    }
+
+    async fn list_records(&self, name: &str) -> Result<Vec<DnsRecord>> {
+        let url = format!("{}/zones/{}/dns_records", API_BASE, self.zone_id);
+        let req = self.client
+            .get(url)
+            .query(&[("name", name), ("per_page", "100")]);
+        let records: Vec<DnsRecord> = self.send(req).await?.unwrap_or_default();
+        Ok(records
+            .into_iter()
+            .filter(|r| r.record_type == "A" || r.record_type == "AAAA")
+            .collect())
+    }
+
+    async fn apply(&self, name: &str, action: &Action) -> Result<()> {
+        let base = format!("{}/zones/{}/dns_records", API_BASE, self.zone_id);
+        match action {
+            Action::Update { id, ip, proxied } => {
+                let body = json!({
+                    "type": record_type(ip),
+                    "name": name,
+                    "content": ip.to_string(),
+                    "ttl": 1,
+                    "proxied": proxied,
+                });
+                let req = self.client.put(format!("{}/{}", base, id)).json(&body);
+                self.send::<serde_json::Value>(req).await?;
+            }
+            Action::Create { ip } => {
+                let body = json!({
+                    "type": record_type(ip),
+                    "name": name,
+                    "content": ip.to_string(),
+                    "ttl": 1,
+                    "proxied": false,
+                });
+                let req = self.client.post(base).json(&body);
+                self.send::<serde_json::Value>(req).await?;
+            }
+            Action::Delete { id, .. } => {
+                let req = self.client.delete(format!("{}/{}", base, id));
+                self.send::<serde_json::Value>(req).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 访问 Cloudflare API 的客户端，gen-worker 部署 Worker 时同样使用
+pub(crate) fn api_client(config: &Config) -> Result<Client> {
+    urls::apply_resolve(Client::builder(), config)
+        .timeout(API_TIMEOUT)
+        .build()
+        .context("创建 HTTP 客户端失败")
+}
+
+// [-dns-token]，未指定时读取环境变量 CF_API_TOKEN
+pub(crate) fn api_token(config: &Config) -> Result<String> {
+    if !config.dns_api_token.is_empty() {
+        return Ok(config.dns_api_token.clone());
+    }
+    std::env::var("CF_API_TOKEN").map_err(|_| anyhow!("未指定 API 令牌 [-dns-token] 或环境变量 CF_API_TOKEN"))
+}
+
+// 发送请求并检查响应中的 success 字段，失败时返回 API 给出的错误
+pub(crate) async fn send_api<T: for<'de> Deserialize<'de>>(req: reqwest::RequestBuilder, token: &str) -> Result<Option<T>> {
+    let resp = req
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("请求 Cloudflare API 失败")?;
+    let status = resp.status();
+    let body: ApiResponse<T> = resp
+        .json()
+        .await
+        .with_context(|| format!("解析 Cloudflare API 响应失败 (HTTP {})", status))?;
+
+    if !body.success {
+        let msg = body.errors
+            .iter()
+            .map(|e| format!("[{}] {}", e.code, e.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        bail!("Cloudflare API 返回错误 (HTTP {}): {}", status, msg);
+    }
+    Ok(body.result)
+}
+
+fn record_type(ip: &IpAddr) -> &'static str {
+    if ip.is_ipv4() { "A" } else { "AAAA" }
+}
+
+// 计算让记录与目标 IP 一致所需的操作，只处理目标 IP 中出现的记录类型
+fn plan_actions(existing: &[DnsRecord], targets: &[IpAddr]) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for rtype in ["A", "AAAA"] {
+        let wanted: Vec<IpAddr> = targets.iter()
+            .filter(|ip| record_type(ip) == rtype)
+            .copied()
+            .collect();
+        if wanted.is_empty() {
+            continue;
+        }
+
+        let records: Vec<&DnsRecord> = existing.iter()
+            .filter(|r| r.record_type == rtype)
+            .collect();
+
+        // 已经指向目标 IP 的记录保持不变
+        let pending_ips: Vec<IpAddr> = wanted.iter()
+            .filter(|ip| !records.iter().any(|r| r.content.parse::<IpAddr>().ok() == Some(**ip)))
+            .copied()
+            .collect();
+        let mut spare_records = records.iter()
+            .filter(|r| !wanted.iter().any(|ip| r.content.parse::<IpAddr>().ok() == Some(*ip)));
+
+        for ip in pending_ips {
+            match spare_records.next() {
+                Some(r) => actions.push(Action::Update { id: r.id.clone(), ip, proxied: r.proxied }),
+                None => actions.push(Action::Create { ip }),
+            }
+        }
+        for r in spare_records {
+            actions.push(Action::Delete { id: r.id.clone(), content: r.content.clone() });
+        }
+    }
+
+    actions
+}
+
+// 将测速结果中最快的若干 IP 写入 Cloudflare DNS 记录
+pub async fn update_dns(config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+    if config.dns_zone_id.is_empty() || config.dns_records.is_empty() {
+        return Ok(());
+    }
+    if data.is_empty() {
+        println!("\n[信息] 测速结果 IP 数量为 0，跳过更新 DNS 记录。");
+        return Ok(());
+    }
+    // 被中断时的部分结果不用于更新记录
+    if cancel::requested() {
+        println!("\n[信息] 测速被中断，跳过更新 DNS 记录。");
+        return Ok(());
+    }
+
+    let token = api_token(config)?;
+
+    let targets: Vec<IpAddr> = data.iter()
+        .take(config.dns_top_n.max(1) as usize)
+        .map(|d| d.ping_data.ip)
+        .collect();
+    let api = DnsApi::new(&config.dns_zone_id, &token, config)?;
+
+    println!(
+        "\n开始更新 DNS 记录{}（IP 数量：{}）",
+        if config.dns_dry_run { "（试运行）" } else { "" },
+        targets.len()
+    );
+
+    for name in config.dns_records.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let existing = api.list_records(name).await
+            .with_context(|| format!("查询 {} 的 DNS 记录失败", name))?;
+        debug_log!("{} 现有记录: {:?}", name, existing);
+
+        let actions = plan_actions(&existing, &targets);
+        if actions.is_empty() {
+            println!("  {} 无需更新", name);
+            continue;
+        }
+
+        for action in &actions {
+            match action {
+                Action::Update { ip, .. } => println!("  更新 {} {} -> {}", record_type(ip), name, ip),
+                Action::Create { ip } => println!("  新增 {} {} -> {}", record_type(ip), name, ip),
+                Action::Delete { content, .. } => println!("  删除 {} -> {}", name, content),
+            }
+            if !config.dns_dry_run {
+                api.apply(name, action).await
+                    .with_context(|| format!("更新 {} 的 DNS 记录失败", name))?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,497 +1,769 @@
-use std::net::IpAddr;
-use std::time::{Duration, Instant};
-use reqwest::{Client, redirect, header::HeaderMap};
-use crate::types::{Config, PingDelaySet, DownloadSpeedSet, SpeedTestError};
-use crate::progress::Bar;
-use futures::StreamExt;
-use ewma::EWMA;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use rand::seq::SliceRandom;
-use crate::threadpool::GLOBAL_POOL;
-use std::collections::HashMap;
-use crate::debug_log;
-#[cfg(feature = "debug")]
-use tracing;
-
-const BUFFER_SIZE: usize = 1024;
-const DELAY_GROUP_INTERVAL: Duration = Duration::from_millis(2); // 2ms 分组间隔
-const MAX_RETRIES: u32 = 3; // 最大重试次数
-const CLIENT_POOL_SIZE: usize = 100; // 客户端连接池大小
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
-const TRANSFER_TIMEOUT: Duration = Duration::from_secs(2);
-const PROGRESS_MIN_SIZE: u64 = 1024 * 1024; // 1MB
-
-// 客户端连接池
-pub struct DownloadClient {
-    pool: Arc<Vec<Client>>,
-    index: Arc<AtomicUsize>,
-    active_connections: Arc<AtomicUsize>,
-}
-
-impl DownloadClient {
-    pub fn new() -> Self {
-        let mut clients = Vec::with_capacity(CLIENT_POOL_SIZE);
-        for _ in 0..CLIENT_POOL_SIZE {
-            let client = Client::builder()
-                .tcp_keepalive(Duration::from_secs(30))
-                .tcp_nodelay(true)
-                .pool_idle_timeout(Duration::from_secs(30))
-                .pool_max_idle_per_host(5)
-                .build()
-                .unwrap();
-            clients.push(client);
-        }
-        Self {
-            pool: Arc::new(clients),
-            index: Arc::new(AtomicUsize::new(0)),
-            active_connections: Arc::new(AtomicUsize::new(0)),
-        }
-    }
-
-    pub fn get_client(&self) -> Client {
-        let index = self.index.fetch_add(1, Ordering::Relaxed) % CLIENT_POOL_SIZE;
-        self.active_connections.fetch_add(1, Ordering::Relaxed);
-        self.pool[index].clone()
-    }
-
-    pub fn release_client(&self) {
-        self.active_connections.fetch_sub(1, Ordering::Relaxed);
-    }
-
-    pub fn get_active_connections(&self) -> usize {
-        self.active_connections.load(Ordering::Relaxed)
-    }
-}
-
-impl Drop for DownloadClient {
-    fn drop(&mut self) {
-        // 确保所有连接都被正确关闭
-        while self.get_active_connections() > 0 {
-            self.release_client();
-        }
-    }
-}
-
-// 将 IP 按延迟分组并打乱
-fn group_and_shuffle_ips(ip_set: PingDelaySet) -> PingDelaySet {
-    if ip_set.is_empty() {
-        return ip_set;
-    }
-
-    // 1. 按延迟分组
-    let mut delay_groups: Vec<Vec<_>> = Vec::new();
-    let mut current_group = Vec::new();
-    let mut current_delay = ip_set[0].ping_data.delay;
-
-    for ip_data in ip_set {
-        if ip_data.ping_data.delay > current_delay + DELAY_GROUP_INTERVAL {
-            // 如果延迟差距超过2ms，创建新组
-            if !current_group.is_empty() {
-                delay_groups.push(current_group);
-                current_group = Vec::new();
-            }
-            current_delay = ip_data.ping_data.delay;
-        }
-        current_group.push(ip_data);
-    }
-    
-    // 添加最后一组
-    if !current_group.is_empty() {
-        delay_groups.push(current_group);
-    }
-
-    // 2. 打乱每组内的 IP
-    let mut rng = rand::thread_rng();
-    for group in delay_groups.iter_mut() {
-        group.shuffle(&mut rng);
-    }
-
-    // 3. 合并所有组
-    delay_groups.into_iter().flatten().collect()
-}
-
-pub async fn test_download_speed(config: &mut Config, mut ip_set: PingDelaySet) -> Result<DownloadSpeedSet, SpeedTestError> {
-    check_download_default(config);
-
-    if config.disable_download {
-        return Ok(ip_set);
-    }
-
-    if ip_set.is_empty() {
-        println!("\n[信息] 延迟测速结果 IP 数量为 0，跳过下载测速。");
-        return Ok(ip_set);
-    }
-
-    // 1. 先按延迟排序
-    ip_set.sort();
-
-    // 2. 确定测试数量
-    let mut test_num = config.test_count;
-    if ip_set.len() < test_num as usize || config.min_speed > 0.0 {
-        test_num = ip_set.len() as u32;
-    }
-
-    // 3. 打印开始信息
-    println!(
-        "开始下载测速（下限：{:.2} MB/s, 数量：{}, 队列：{}）",
-        config.min_speed,
-        config.test_count,
-        test_num
-    );
-
-    // 4. 对要测速的 IP 进行分组和打乱
-    let ip_set = group_and_shuffle_ips(ip_set);
-
-    let bar_padding = " ".repeat(ip_set.len().to_string().len() + 5);
-    let bar = Bar::new(config.test_count as u64, &bar_padding, "");
-
-    // 使用 HashMap 存储每个 IP 的当前速度
-    let current_speeds = Arc::new(Mutex::new(HashMap::new()));
-    
-    // 5. 创建下载任务
-    let results = Arc::new(Mutex::new(Vec::new()));
-    let fallback_results = Arc::new(Mutex::new(Vec::new()));
-    let client_pool = Arc::new(DownloadClient::new());
-    let speed_set = Arc::new(Mutex::new(Vec::new()));
-    let ip_set = Arc::new(ip_set);
-    
-    let mut handles = Vec::new();
-    
-    for (_index, ip_data) in ip_set.iter().take(test_num.try_into().unwrap()).enumerate() {
-        let permit = GLOBAL_POOL.acquire().await;
-        
-        let config = config.clone();
-        let results = results.clone();
-        let fallback_results = fallback_results.clone();
-        let bar = bar.clone();
-        let client_pool = client_pool.clone();
-        let ip_data = ip_data.clone();
-        let speed_set = speed_set.clone();
-        let current_speeds = current_speeds.clone();
-
-        let handle = tokio::spawn(async move {
-            let client = client_pool.get_client();
-            let ip = ip_data.ping_data.ip;
-            
-            let result = download_with_retry(&ip, &config, &client, &current_speeds).await;
-
-            match result {
-                Ok(speed) => {
-                    let mut ip_data_clone = ip_data.clone();
-                    ip_data_clone.download_speed = speed;
-                    
-                    // 更新当前速度表
-                    let mut speeds = current_speeds.lock().unwrap();
-                    speeds.insert(ip, speed);
-                    
-                    // 计算总带宽
-                    let total_speed: f64 = speeds.values().sum();
-                    
-                    // 根据速度选择存储位置
-                    let results_vec = if speed >= config.min_speed * 1024.0 * 1024.0 {
-                        &results
-                    } else {
-                        &fallback_results
-                    };
-                    
-                    results_vec.lock().unwrap().push(ip_data_clone.clone());
-                    speed_set.lock().unwrap().push(ip_data_clone);
-                    bar.grow(1, &format!("{:.2} MB/s", total_speed / 1024.0 / 1024.0));
-                }
-                Err(_) => {
-                    // 计算当前总带宽
-                    let speeds = current_speeds.lock().unwrap();
-                    let total_speed: f64 = speeds.values().sum();
-                    bar.grow(1, &format!("{:.2} MB/s", total_speed / 1024.0 / 1024.0));
-                }
-            }
-
-            // 测试完成后移除该 IP 的速度记录
-            current_speeds.lock().unwrap().remove(&ip);
-            drop(permit);
-        });
-        handles.push(handle);
-    }
-
-    // 等待所有任务完成
-    futures::future::join_all(handles).await;
-    
-    // 获取结果
-    let mut speed_set = results.lock().unwrap().clone();
-    let fallback_set = fallback_results.lock().unwrap().clone();
-
-    // 如果没有满足速度要求的结果，使用 fallback
-    if speed_set.is_empty() {
-        speed_set = fallback_set;
-    }
-
-    bar.done();
-    speed_set.sort();
-    
-    // 如果不是禁用下载测速，则按下载速度排序
-    if !config.disable_download {
-        speed_set.sort_by(|a, b| b.download_speed.partial_cmp(&a.download_speed).unwrap());
-    }
-    
-    Ok(speed_set)
-}
-
-fn check_download_default(config: &mut Config) {
-    let default_config = Config::default();
-    if config.url.is_empty() {
-        config.url = default_config.url;
-    }
-    if config.download_time == Duration::ZERO {
-        config.download_time = default_config.download_time;
-    }
-    if config.test_count == 0 {
-        config.test_count = default_config.test_count;
-    }
-    if config.min_speed <= 0.0 {
-        config.min_speed = default_config.min_speed;
-    }
-}
-
-async fn download_with_retry(
-    ip: &IpAddr,
-    config: &Config,
-    client: &Client,
-    current_speeds: &Arc<Mutex<HashMap<IpAddr, f64>>>
-) -> Result<f64, SpeedTestError> {
-    let mut retries = MAX_RETRIES;
-    let mut last_error = None;
-    
-    while retries > 0 {
-        match download_handler(ip, config, client, current_speeds).await {
-            Ok(speed) => {
-                if speed > 0.0 {
-                    return Ok(speed);
-                }
-            }
-            Err(e) => {
-                last_error = Some(e);
-                retries -= 1;
-                if retries > 0 {
-                    tokio::time::sleep(Duration::from_millis(100)).await;
-                }
-            }
-        }
-    }
-
-    if let Some(e) = last_error {
-        Err(e)
-    } else {
-        Ok(0.0)
-    }
-}
-
-async fn download_handler(
-    ip: &IpAddr,
-    config: &Config,
-    client: &Client,
-    current_speeds: &Arc<Mutex<HashMap<IpAddr, f64>>>
-) -> Result<f64, SpeedTestError> {
-    let task_id = rand::random::<usize>();
-    GLOBAL_POOL.start_task(task_id);
-
-    debug_log!("开始下载测速 IP: {}", ip);
-    
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "User-Agent",
-        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_12_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/98.0.4758.80 Safari/537.36"
-            .parse()
-            .unwrap(),
-    );
-
-    let req = reqwest::Request::new(
-        reqwest::Method::GET,
-        config.url.parse().unwrap()
-    );
-    
-    let mut req = req;
-    *req.headers_mut() = headers;
-
-    let response = match client.execute(req).await {
-        Ok(resp) => {
-            debug_log!("收到响应: 状态码={}", resp.status());
-            resp
-        },
-        Err(_e) => {
-            debug_log!("请求失败: {}", _e);
-            return Ok(0.0);
-        }
-    };
-
-    if response.status() != 200 {
-        debug_log!("非200状态码: {}", response.status());
-        return Ok(0.0);
-    }
-
-    let time_start = Instant::now();
-    let time_end = time_start + config.download_time;
-    let content_length = response.content_length();
-
-    if let Some(_size) = content_length {
-        debug_log!("开始下载: 文件大小={} bytes", _size);
-    }
-
-    let mut content_read = 0u64;
-    let time_slice = config.download_time / 100;
-    let mut time_counter = 1;
-    let mut last_content_read = 0u64;
-    
-    let mut next_time = time_start + time_slice * time_counter;
-    let mut ewma = EWMA::new(0.5);
-    let mut last_time_slice = time_start;
-
-    let mut buffer = vec![0u8; BUFFER_SIZE];
-    let mut stream = response.bytes_stream();
-
-    let mut speed_samples = Vec::new();
-    let sample_interval = config.download_time / 10;
-    let mut next_sample = time_start + sample_interval;
-
-    let mut last_transfer = Instant::now();
-
-    while let Some(chunk) = stream.next().await {
-        let current_time = Instant::now();
-        
-        // 每次收到数据块就记录进展
-        GLOBAL_POOL.record_progress(task_id);
-        
-        let _chunk_size = chunk.as_ref().map(|c| c.len()).unwrap_or(0);
-        debug_log!("接收数据块: {} bytes, 总计: {} bytes", _chunk_size, content_read + _chunk_size as u64);
-        
-        // 检查总下载时间
-        if current_time >= time_end {
-            break;
-        }
-        
-        // 检查传输超时
-        if current_time.duration_since(last_transfer) > TRANSFER_TIMEOUT {
-            return Ok(0.0);  // 超时时返回 0 速度
-        }
-
-        // 更新传输时间
-        last_transfer = current_time;
-        
-        match chunk {
-            Ok(data) => {
-                // 使用 buffer 来读取数据
-                let mut pos = 0;
-                while pos < data.len() {
-                    let bytes_to_copy = (data.len() - pos).min(buffer.len());
-                    buffer[..bytes_to_copy].copy_from_slice(&data[pos..pos + bytes_to_copy]);
-                    content_read += bytes_to_copy as u64;
-                    pos += bytes_to_copy;
-
-                    // 检查是否需要采样
-                    let current_time = Instant::now();
-                    if current_time >= next_sample {
-                        let duration = current_time.duration_since(last_time_slice);
-                        if duration > Duration::ZERO {
-                            let speed = (content_read - last_content_read) as f64 / duration.as_secs_f64();
-                            speed_samples.push(speed);
-                            ewma.add(speed);
-                            current_speeds.lock().unwrap().insert(*ip, ewma.value());
-                            
-                            last_content_read = content_read;
-                            last_time_slice = current_time;
-                            next_sample = current_time + sample_interval;
-                        }
-                    }
-                }
-
-                // 处理下载完成的情况
-                if let Some(total_size) = content_length {
-                    if content_read >= total_size {
-                        let final_speed = calculate_final_speed(&speed_samples, ewma.value());
-                        return Ok(final_speed);
-                    }
-                }
-            }
-            Err(e) => {
-                if content_read == 0 {
-                    return Err(SpeedTestError::RequestError(e));
-                }
-                // 如果已经有一些数据，计算部分速度
-                let final_speed = calculate_final_speed(&speed_samples, ewma.value());
-                return Ok(final_speed);
-            }
-        }
-
-        // 定期采样速度
-        if current_time >= next_time && content_read >= PROGRESS_MIN_SIZE {
-            time_counter += 1;
-            next_time = time_start + time_slice * time_counter;
-            
-            let duration = current_time.duration_since(last_time_slice);
-            if duration > Duration::ZERO {
-                let speed = (content_read - last_content_read) as f64 / duration.as_secs_f64();
-                speed_samples.push(speed);
-                ewma.add(speed);
-                
-                // 更新当前速度
-                current_speeds.lock().unwrap().insert(*ip, ewma.value());
-                
-                last_content_read = content_read;
-                last_time_slice = current_time;
-            }
-        }
-    }
-
-    debug_log!("下载完成: IP={}, 总下载量={} bytes", ip, content_read);
-    GLOBAL_POOL.end_task(task_id);
-
-    let final_speed = calculate_final_speed(&speed_samples, ewma.value());
-    debug_log!("最终速度: {:.2} MB/s", final_speed / 1024.0 / 1024.0);
-    Ok(final_speed)
-}
-
-fn calculate_final_speed(samples: &[f64], ewma_value: f64) -> f64 {
-    if samples.is_empty() {
-        return ewma_value;
-    }
-
-    // 去除最高和最低的 10% 样本
-    let mut sorted_samples = samples.to_vec();
-    sorted_samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    
-    let trim_count = (samples.len() as f64 * 0.1) as usize;
-    let valid_samples = &sorted_samples[trim_count..sorted_samples.len() - trim_count];
-    
-    if valid_samples.is_empty() {
-        return ewma_value;
-    }
-
-    // 计算平均速度
-    let avg_speed = valid_samples.iter().sum::<f64>() / valid_samples.len() as f64;
-    
-    // 使用 EWMA 值和平均值的加权平均作为最终速度
-    ewma_value * 0.7 + avg_speed * 0.3
-}
-
-pub async fn build_client(ip: &IpAddr, config: &Config) -> Option<Client> {
-    Client::builder()
-        .timeout(config.download_time)
-        .connect_timeout(CONNECT_TIMEOUT)
-        .pool_idle_timeout(Duration::from_secs(30))
-        .pool_max_idle_per_host(5)
-        .local_address(Some(*ip))
-        .tcp_keepalive(Duration::from_secs(30))
-        .tcp_nodelay(true)
-        .redirect(redirect::Policy::custom(|attempt| {
-            // 限制最多重定向 10 次
-            if attempt.previous().len() > 10 {
-                attempt.error("too many redirects")
-            } else if attempt.previous().first().map(|u| u.as_str()) == Some(&Config::default().url) {
-                attempt.stop()
-            } else {
-                attempt.follow()
-            }
-        }))
-        .build()
-        .ok()
-} 
\ No newline at end of file
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use reqwest::{Client, redirect};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use crate::types::{CloudflareIPData, Config, PingDelaySet, DownloadSpeedSet, SpeedTestError, USER_AGENT};
+use crate::progress::Bar;
+use futures::StreamExt;
+use ewma::EWMA;
+use std::sync::{Arc, Mutex};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use crate::threadpool::GLOBAL_POOL;
+use crate::failure::{self, ProbeError};
+use crate::{cancel, csv, exclude, summary, tui};
+use crate::{interface, proxy, ratelimit, tls, urls};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::task::JoinHandle;
+use crate::debug_log;
+
+const BUFFER_SIZE: usize = 1024;
+const DELAY_GROUP_INTERVAL: Duration = Duration::from_millis(2); // 2ms 分组间隔
+const MAX_RETRIES: u32 = 3; // 最大重试次数
+const PROGRESS_MIN_SIZE: u64 = 1024 * 1024; // 1MB
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5); // 下载测速的最短连接超时
+const CONVERGE_WINDOW: usize = 8; // [-dt-converge] 判断速度稳定所用的最近样本数
+const PIPELINE_WARMUP: Duration = Duration::from_secs(1); // [-pipeline] 开始下载前收集候选的时长
+
+// 每个下载连接的实时速度，按 (地址, 连接序号) 记录
+type SpeedMap = Arc<Mutex<HashMap<(SocketAddr, usize), f64>>>;
+
+// 一次下载测速的结果；capped 表示受 [-download-cap] 限制，实际速度不低于 speed
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    speed: f64,
+    capped: bool,
+}
+
+// 单个下载连接：目标地址、连接序号与请求的字节范围
+struct Connection {
+    addr: SocketAddr,
+    index: usize,
+    range: Option<String>,
+}
+
+// 将 IP 按延迟分组并打乱，指定 [-seed] 时打乱顺序可复现
+fn group_and_shuffle_ips(ip_set: PingDelaySet, seed: Option<u64>) -> PingDelaySet {
+    if ip_set.is_empty() {
+        return ip_set;
+    }
+
+    // 1. 按延迟分组
+    let mut delay_groups: Vec<Vec<_>> = Vec::new();
+    let mut current_group = Vec::new();
+    let mut current_delay = ip_set[0].ping_data.delay;
+
+    for ip_data in ip_set {
+        if ip_data.ping_data.delay > current_delay + DELAY_GROUP_INTERVAL {
+            // 如果延迟差距超过2ms，创建新组
+            if !current_group.is_empty() {
+                delay_groups.push(current_group);
+                current_group = Vec::new();
+            }
+            current_delay = ip_data.ping_data.delay;
+        }
+        current_group.push(ip_data);
+    }
+    
+    // 添加最后一组
+    if !current_group.is_empty() {
+        delay_groups.push(current_group);
+    }
+
+    // 2. 打乱每组内的 IP
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    for group in delay_groups.iter_mut() {
+        group.shuffle(&mut rng);
+    }
+
+    // 3. 合并所有组
+    delay_groups.into_iter().flatten().collect()
+}
+
+// 下载测速的 IP 数量：[-dn] 个，设置了 [-sl] 时为全部
+fn candidate_count(config: &Config, len: usize) -> u32 {
+    if len < config.test_count as usize || config.min_speed > 0.0 {
+        len as u32
+    } else {
+        config.test_count
+    }
+}
+
+// 只保留会被下载测速的 IP (按延迟排序、分组打乱后的前若干个)，
+// 用于只需对下载测速候选进行的 [-timing] 与 [-h2-latency]；禁用下载测速时保留全部
+pub fn select_candidates(config: &Config, mut ip_set: PingDelaySet) -> PingDelaySet {
+    if config.disable_download {
+        return ip_set;
+    }
+    ip_set.sort();
+    let count = candidate_count(config, ip_set.len()) as usize;
+    let mut ip_set = group_and_shuffle_ips(ip_set, config.seed);
+    ip_set.truncate(count);
+    ip_set
+}
+
+pub async fn test_download_speed(config: &mut Config, mut ip_set: PingDelaySet) -> Result<DownloadSpeedSet, SpeedTestError> {
+    check_download_default(config);
+
+    if config.disable_download {
+        return Ok(ip_set);
+    }
+
+    if ip_set.is_empty() {
+        println!("\n[信息] 延迟测速结果 IP 数量为 0，跳过下载测速。");
+        return Ok(ip_set);
+    }
+
+    // 1. 先按延迟排序
+    ip_set.sort();
+
+    // 2. 确定测试数量
+    let test_num = candidate_count(config, ip_set.len());
+
+    // 3. 打印开始信息
+    println!(
+        "开始下载测速（下限：{:.2} MB/s, 数量：{}, 队列：{}）",
+        config.min_speed,
+        config.test_count,
+        test_num
+    );
+    configure_bandwidth(config);
+
+    // 4. 对要测速的 IP 进行分组和打乱
+    let ip_set = group_and_shuffle_ips(ip_set, config.seed);
+
+    let bar_padding = " ".repeat(ip_set.len().to_string().len() + 5);
+    let mut test = SpeedTest::new(config, Bar::new(config.test_count as u64, &bar_padding, "").phase("下载测速"));
+
+    // 5. 创建下载任务
+    for ip_data in ip_set.iter().take(test_num.try_into().unwrap()) {
+        let permit = GLOBAL_POOL.acquire().await;
+        // 在交互界面中提前结束时不再开始新的下载，已开始的下载照常完成
+        if tui::abort_requested() || cancel::requested() {
+            break;
+        }
+        test.spawn(ip_data.clone(), permit);
+    }
+
+    let speed_set = test.finish().await;
+    // 下载测速开始前被中断时保留延迟测速结果
+    if speed_set.is_empty() && cancel::requested() {
+        return Ok(ip_set.to_vec());
+    }
+    Ok(speed_set)
+}
+
+// [-pipeline]：延迟测速进行中即开始下载测速，每有空闲名额就从已通过的 IP 中取延迟最低的一个；
+// 延迟测速结束 (通道关闭) 且没有剩余 IP，或已测够 [-dn] 个时结束
+pub async fn test_download_pipelined(config: &mut Config, mut candidates: UnboundedReceiver<CloudflareIPData>) -> Result<DownloadSpeedSet, SpeedTestError> {
+    check_download_default(config);
+
+    // 没有 [-sl] 时只需测够 [-dn] 个，否则测试全部通过的 IP
+    let limit = if config.min_speed > 0.0 { usize::MAX } else { config.test_count as usize };
+    println!(
+        "边延迟测速边下载测速（下限：{:.2} MB/s, 数量：{}）",
+        config.min_speed,
+        config.test_count
+    );
+    configure_bandwidth(config);
+
+    let mut queue: BinaryHeap<Reverse<CloudflareIPData>> = BinaryHeap::new();
+    let mut open = true;
+
+    // 先等待第一个通过的 IP，再收集一小段时间，避免最先返回的 IP 直接占满名额
+    match candidates.recv().await {
+        Some(ip_data) => queue.push(Reverse(ip_data)),
+        None => {
+            println!("\n[信息] 延迟测速结果 IP 数量为 0，跳过下载测速。");
+            return Ok(DownloadSpeedSet::new());
+        }
+    }
+    let warmup = tokio::time::sleep(PIPELINE_WARMUP);
+    tokio::pin!(warmup);
+    while open {
+        tokio::select! {
+            _ = &mut warmup => break,
+            received = candidates.recv() => match received {
+                Some(ip_data) => queue.push(Reverse(ip_data)),
+                None => open = false,
+            },
+        }
+    }
+
+    let mut test = SpeedTest::new(config, Bar::new(config.test_count as u64, "", "").phase("下载测速"));
+    let mut started = 0;
+    while started < limit {
+        let permit = GLOBAL_POOL.acquire().await;
+        if tui::abort_requested() || cancel::requested() {
+            break;
+        }
+        // 取出等待期间新通过的 IP，通道为空且队列也为空时等待下一个
+        while open {
+            match candidates.try_recv() {
+                Ok(ip_data) => queue.push(Reverse(ip_data)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => open = false,
+            }
+        }
+        if queue.is_empty() && open {
+            match candidates.recv().await {
+                Some(ip_data) => queue.push(Reverse(ip_data)),
+                None => open = false,
+            }
+        }
+        let Some(Reverse(ip_data)) = queue.pop() else { break };
+        debug_log!("下载测速 {}（延迟 {:?}，待测 {} 个）", ip_data.ping_data.ip, ip_data.ping_data.delay, queue.len());
+        test.spawn(ip_data, permit);
+        started += 1;
+    }
+    // 不再取用的 IP 由延迟测速结果保留，这里只需等待已开始的下载
+    drop(candidates);
+
+    Ok(test.finish().await)
+}
+
+// 设置 [-download-cap] 总带宽上限
+fn configure_bandwidth(config: &Config) {
+    ratelimit::configure_bandwidth(config.download_cap);
+    if config.download_cap > 0.0 {
+        println!("[信息] 下载测速总带宽上限 {:.2} MB/s，达到上限的结果记为 ≥ 测得速度", config.download_cap / 1024.0 / 1024.0);
+    }
+}
+
+// 一轮下载测速中各任务共享的状态
+struct SpeedTest {
+    config: Config,
+    bar: Bar,
+    results: Arc<Mutex<DownloadSpeedSet>>,
+    fallback_results: Arc<Mutex<DownloadSpeedSet>>,
+    // 使用 HashMap 存储每个 IP 的当前速度
+    current_speeds: SpeedMap,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl SpeedTest {
+    fn new(config: &Config, bar: Bar) -> Self {
+        Self {
+            config: config.clone(),
+            bar,
+            results: Arc::new(Mutex::new(Vec::new())),
+            fallback_results: Arc::new(Mutex::new(Vec::new())),
+            current_speeds: Arc::new(Mutex::new(HashMap::new())),
+            handles: Vec::new(),
+        }
+    }
+
+    // 对单个 IP 下载测速，permit 在测速完成后释放
+    fn spawn(&mut self, ip_data: CloudflareIPData, permit: OwnedSemaphorePermit) {
+        let config = self.config.clone();
+        let results = self.results.clone();
+        let fallback_results = self.fallback_results.clone();
+        let bar = self.bar.clone();
+        let current_speeds = self.current_speeds.clone();
+
+        let handle = tokio::spawn(async move {
+            let ip = ip_data.ping_data.ip;
+            let port = ip_data.ping_data.port;
+
+            // 每个 IP 使用固定解析到该 IP 的客户端
+            let result = match build_client(&ip, port, &config).await {
+                Ok(client) => download_with_retry(&ip, port, &config, &client, &current_speeds).await,
+                Err(e) => Err(e),
+            };
+
+            exclude::record_download(ip, result.is_err());
+            summary::record_download(result.is_err());
+            // 下载失败的 IP 只记录失败原因，不计入结果与备选结果，避免 [-sl 0] 时以 0 速度输出
+            let sample = match result {
+                Ok(sample) => sample,
+                Err(e) => {
+                    failure::record(ip, port, "download", &e);
+                    tui::record_speed(ip, port, 0.0);
+                    let mut speeds = current_speeds.lock().unwrap();
+                    speeds.retain(|(addr, _), _| *addr != SocketAddr::new(ip, port));
+                    let total_speed: f64 = speeds.values().sum();
+                    drop(speeds);
+                    bar.grow(1, &format!("{:.2} MB/s", total_speed / 1024.0 / 1024.0));
+                    drop(permit);
+                    return;
+                }
+            };
+            let speed = sample.speed;
+
+            tui::record_speed(ip, port, speed);
+            let mut ip_data = ip_data;
+            ip_data.download_speed = speed;
+            ip_data.download_capped = sample.capped;
+
+            // 更新当前速度表
+            let mut speeds = current_speeds.lock().unwrap();
+            speeds.retain(|(addr, _), _| *addr != SocketAddr::new(ip, port));
+            speeds.insert((SocketAddr::new(ip, port), 0), speed);
+
+            // 计算总带宽
+            let total_speed: f64 = speeds.values().sum();
+            drop(speeds);
+
+            // 根据速度选择存储位置
+            let results_vec = if speed >= config.min_speed * 1024.0 * 1024.0 {
+                if speed > 0.0 {
+                    csv::stream_record(&ip_data);
+                }
+                &results
+            } else {
+                &fallback_results
+            };
+
+            results_vec.lock().unwrap().push(ip_data);
+            bar.grow(1, &format!("{:.2} MB/s", total_speed / 1024.0 / 1024.0));
+
+            // 测试完成后移除该 IP 的速度记录
+            current_speeds.lock().unwrap().retain(|(addr, _), _| *addr != SocketAddr::new(ip, port));
+            drop(permit);
+        });
+        self.handles.push(handle);
+    }
+
+    // 等待所有任务完成，按下载速度排序后返回
+    async fn finish(self) -> DownloadSpeedSet {
+        // 收到中断信号后超过等待时间仍未完成的下载被取消，不计入结果
+        let aborts: Vec<_> = self.handles.iter().map(|h| h.abort_handle()).collect();
+        tokio::select! {
+            _ = futures::future::join_all(self.handles) => {}
+            _ = cancel::grace_expired() => aborts.iter().for_each(|h| h.abort()),
+        }
+
+        // 获取结果
+        let mut speed_set = self.results.lock().unwrap().clone();
+        let fallback_set = self.fallback_results.lock().unwrap().clone();
+
+        // 如果没有满足速度要求的结果，使用 fallback
+        if speed_set.is_empty() {
+            speed_set = fallback_set;
+        }
+
+        self.bar.done();
+        speed_set.sort();
+
+        // 如果不是禁用下载测速，则按下载速度排序
+        if !self.config.disable_download {
+            speed_set.sort_by(|a, b| b.download_speed.partial_cmp(&a.download_speed).unwrap());
+        }
+        speed_set
+    }
+}
+
+fn check_download_default(config: &mut Config) {
+    let default_config = Config::default();
+    if config.url.is_empty() {
+        config.url = default_config.url;
+    }
+    if config.download_time == Duration::ZERO {
+        config.download_time = default_config.download_time;
+    }
+    if config.test_count == 0 {
+        config.test_count = default_config.test_count;
+    }
+    if config.min_speed <= 0.0 {
+        config.min_speed = default_config.min_speed;
+    }
+}
+
+async fn download_with_retry(
+    ip: &IpAddr,
+    port: u16,
+    config: &Config,
+    client: &Client,
+    current_speeds: &SpeedMap
+) -> Result<Sample, ProbeError> {
+    let mut retries = MAX_RETRIES;
+    let mut last_error = None;
+    
+    while retries > 0 {
+        match download_parallel(SocketAddr::new(*ip, port), config, client, current_speeds).await {
+            Ok(sample) if sample.speed > 0.0 => return Ok(sample),
+            // 速度为 0 同样计为一次失败
+            Ok(_) => {}
+            Err(e) => last_error = Some(e),
+        }
+        retries -= 1;
+        if retries > 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| ProbeError::Other("下载速度为 0".to_string())))
+}
+
+// 默认请求头：User-Agent、Host 与 [-header]/[-cookie]
+fn request_headers(config: &Config) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "User-Agent",
+        USER_AGENT
+            .parse()
+            .unwrap(),
+    );
+
+    // 无效的 [-host-header] 已在参数解析时报错
+    if let Some(host) = config.host_header().and_then(|host| HeaderValue::from_str(host).ok()) {
+        headers.insert("Host", host);
+    }
+    config.apply_headers(&mut headers);
+    headers
+}
+
+// 多连接下载时，先用 HEAD 请求获取文件大小，支持分段时每个连接下载其中一段，否则都下载完整文件
+async fn plan_ranges(url: &str, config: &Config, client: &Client, connections: usize) -> Vec<Option<String>> {
+    let response = client.head(url).headers(request_headers(config)).send().await.ok();
+    let ranges_supported = response.as_ref().is_some_and(|r| {
+        r.headers().get(ACCEPT_RANGES).and_then(|v| v.to_str().ok()) == Some("bytes")
+    });
+    let length = response
+        .as_ref()
+        .filter(|r| r.status().is_success())
+        .and_then(|r| r.headers().get(CONTENT_LENGTH)?.to_str().ok()?.parse::<u64>().ok())
+        .filter(|&len| len >= connections as u64);
+
+    match length {
+        Some(len) if ranges_supported => (0..connections as u64)
+            .map(|i| Some(format!("bytes={}-{}", len * i / connections as u64, len * (i + 1) / connections as u64 - 1)))
+            .collect(),
+        _ => vec![None; connections],
+    }
+}
+
+// 按 [-download-connections] 对同一 IP 同时建立多个下载连接，速度为各连接之和
+async fn download_parallel(
+    addr: SocketAddr,
+    config: &Config,
+    client: &Client,
+    current_speeds: &SpeedMap
+) -> Result<Sample, ProbeError> {
+    let url = urls::with_port(&config.request_url(), addr.port());
+    let connections = config.download_connections.max(1);
+    let ranges = if connections > 1 {
+        plan_ranges(&url, config, client, connections).await
+    } else {
+        vec![None]
+    };
+
+    let handlers = ranges.into_iter().enumerate().map(|(index, range)| {
+        let conn = Connection { addr, index, range };
+        let url = &url;
+        async move { download_handler(&conn, url, config, client, current_speeds).await }
+    });
+    let results = futures::future::join_all(handlers).await;
+
+    let mut total = Sample::default();
+    let mut last_error = None;
+    let mut succeeded = false;
+    for result in results {
+        match result {
+            Ok(sample) => {
+                total.speed += sample.speed;
+                total.capped |= sample.capped;
+                succeeded = true;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) if !succeeded => Err(e),
+        _ => Ok(total),
+    }
+}
+
+async fn download_handler(
+    conn: &Connection,
+    url: &str,
+    config: &Config,
+    client: &Client,
+    current_speeds: &SpeedMap
+) -> Result<Sample, ProbeError> {
+    let parsed_url = reqwest::Url::parse(url).map_err(|e| ProbeError::Other(format!("无效的测速地址 {}：{}", url, e)))?;
+    let task = GLOBAL_POOL.start_task();
+
+    debug_log!("开始下载测速: {} 连接 {}", conn.addr, conn.index);
+
+    let mut headers = request_headers(config);
+    if let Some(range) = conn.range.as_deref() {
+        headers.insert(RANGE, range.parse().unwrap());
+    }
+    let mut req = reqwest::Request::new(reqwest::Method::GET, parsed_url);
+    *req.headers_mut() = headers;
+
+    let response = match client.execute(req).await {
+        Ok(resp) => {
+            debug_log!("收到响应: 状态码={}", resp.status());
+            resp
+        },
+        Err(e) => {
+            debug_log!("请求失败: {}", e);
+            return Err(ProbeError::from_reqwest(&e));
+        }
+    };
+
+    // 分段请求时服务端返回 206
+    if response.status() != 200 && response.status() != 206 {
+        debug_log!("非200状态码: {}", response.status());
+        urls::report_status(url, response.status().as_u16());
+        return Err(ProbeError::BadStatus(response.status().as_u16()));
+    }
+
+    let time_start = Instant::now();
+    let time_end = time_start + config.download_time;
+    let content_length = response.content_length();
+
+    if let Some(_size) = content_length {
+        debug_log!("开始下载: 文件大小={} bytes", _size);
+    }
+
+    let mut content_read = 0u64;
+    let time_slice = config.download_time / 100;
+    let mut time_counter = 1;
+    let mut last_content_read = 0u64;
+    
+    let mut next_time = time_start + time_slice * time_counter;
+    let mut ewma = EWMA::new(0.5);
+    let mut last_time_slice = time_start;
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut stream = response.bytes_stream();
+
+    let mut speed_samples = Vec::new();
+    let sample_interval = config.download_time / 10;
+    let mut next_sample = time_start + sample_interval;
+
+    let mut last_transfer = Instant::now();
+    // [-dt-converge] 与 [-download-budget] 提前结束的条件，多连接时流量预算按连接平分
+    let min_end = time_start + config.download_min_time.min(config.download_time);
+    let budget = config.download_budget.div_ceil(config.download_connections.max(1) as u64);
+    // 因 [-download-cap] 暂停读取的时长，超过测速时长的 10% 视为受限
+    let mut throttled = Duration::ZERO;
+    let finish = |speed: f64, throttled: Duration| Sample {
+        speed,
+        capped: throttled > config.download_time / 10,
+    };
+
+    while let Some(chunk) = stream.next().await {
+        let current_time = Instant::now();
+        
+        // 每次收到数据块就记录进展
+        task.record_progress();
+        
+        let _chunk_size = chunk.as_ref().map(|c| c.len()).unwrap_or(0);
+        debug_log!("接收数据块: {} bytes, 总计: {} bytes", _chunk_size, content_read + _chunk_size as u64);
+        
+        // 检查总下载时间
+        if current_time >= time_end {
+            break;
+        }
+        
+        // 检查传输超时
+        if current_time.duration_since(last_transfer) > config.download_timeout {
+            return Err(ProbeError::Timeout);
+        }
+
+        // 更新传输时间
+        last_transfer = current_time;
+        
+        match chunk {
+            Ok(data) => {
+                // 使用 buffer 来读取数据
+                let mut pos = 0;
+                while pos < data.len() {
+                    let bytes_to_copy = (data.len() - pos).min(buffer.len());
+                    buffer[..bytes_to_copy].copy_from_slice(&data[pos..pos + bytes_to_copy]);
+                    content_read += bytes_to_copy as u64;
+                    pos += bytes_to_copy;
+
+                    // 检查是否需要采样
+                    let current_time = Instant::now();
+                    if current_time >= next_sample {
+                        let duration = current_time.duration_since(last_time_slice);
+                        if duration > Duration::ZERO {
+                            let speed = (content_read - last_content_read) as f64 / duration.as_secs_f64();
+                            speed_samples.push(speed);
+                            ewma.add(speed);
+                            current_speeds.lock().unwrap().insert((conn.addr, conn.index), ewma.value());
+                            
+                            last_content_read = content_read;
+                            last_time_slice = current_time;
+                            next_sample = current_time + sample_interval;
+                        }
+                    }
+                }
+
+                // 处理下载完成的情况
+                if let Some(total_size) = content_length {
+                    if content_read >= total_size {
+                        let final_speed = calculate_final_speed(&speed_samples, ewma.value());
+                        return Ok(finish(final_speed, throttled));
+                    }
+                }
+
+                let waited = ratelimit::consume_bandwidth(data.len()).await;
+                if waited > Duration::ZERO {
+                    throttled += waited;
+                    // 限速等待不计入传输超时
+                    last_transfer = Instant::now();
+                }
+            }
+            Err(e) => {
+                if content_read == 0 {
+                    return Err(ProbeError::from_reqwest(&e));
+                }
+                // 如果已经有一些数据，计算部分速度
+                let final_speed = calculate_final_speed(&speed_samples, ewma.value());
+                return Ok(finish(final_speed, throttled));
+            }
+        }
+
+        // 定期采样速度
+        if current_time >= next_time && content_read >= PROGRESS_MIN_SIZE {
+            time_counter += 1;
+            next_time = time_start + time_slice * time_counter;
+            
+            let duration = current_time.duration_since(last_time_slice);
+            if duration > Duration::ZERO {
+                let speed = (content_read - last_content_read) as f64 / duration.as_secs_f64();
+                speed_samples.push(speed);
+                ewma.add(speed);
+                
+                // 更新当前速度
+                current_speeds.lock().unwrap().insert((conn.addr, conn.index), ewma.value());
+                
+                last_content_read = content_read;
+                last_time_slice = current_time;
+            }
+        }
+
+        if current_time >= min_end && converged(&speed_samples, config.download_converge) {
+            debug_log!("速度已稳定，提前结束: {} 用时 {:?}", conn.addr, current_time - time_start);
+            break;
+        }
+        // 被中断时以已测得的速度结束，不等待 [-dt]
+        if cancel::requested() && !speed_samples.is_empty() {
+            debug_log!("测速被中断，提前结束: {} 用时 {:?}", conn.addr, current_time - time_start);
+            break;
+        }
+        if budget > 0 && content_read >= budget {
+            debug_log!("达到流量预算，提前结束: {} 用时 {:?}", conn.addr, current_time - time_start);
+            // 下载量较少时可能还没有速度样本，按平均速度计算
+            if speed_samples.is_empty() {
+                let elapsed = current_time.duration_since(time_start).as_secs_f64();
+                if elapsed > 0.0 {
+                    ewma.add(content_read as f64 / elapsed);
+                }
+            }
+            break;
+        }
+    }
+
+    debug_log!("下载完成: {}, 总下载量={} bytes", conn.addr, content_read);
+
+    let final_speed = calculate_final_speed(&speed_samples, ewma.value());
+    debug_log!("最终速度: {:.2} MB/s", final_speed / 1024.0 / 1024.0);
+    Ok(finish(final_speed, throttled))
+}
+
+// 最近若干样本的相对标准差低于阈值时视为速度已稳定
+fn converged(samples: &[f64], threshold: f64) -> bool {
+    if threshold <= 0.0 || samples.len() < CONVERGE_WINDOW {
+        return false;
+    }
+    let window = &samples[samples.len() - CONVERGE_WINDOW..];
+    let mean = window.iter().sum::<f64>() / window.len() as f64;
+    if mean <= 0.0 {
+        return false;
+    }
+    let variance = window.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / window.len() as f64;
+    variance.sqrt() / mean < threshold
+}
+
+fn calculate_final_speed(samples: &[f64], ewma_value: f64) -> f64 {
+    if samples.is_empty() {
+        return ewma_value;
+    }
+
+    // 去除最高和最低的 10% 样本
+    let mut sorted_samples = samples.to_vec();
+    sorted_samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    
+    let trim_count = (samples.len() as f64 * 0.1) as usize;
+    let valid_samples = &sorted_samples[trim_count..sorted_samples.len() - trim_count];
+    
+    if valid_samples.is_empty() {
+        return ewma_value;
+    }
+
+    // 计算平均速度
+    let avg_speed = valid_samples.iter().sum::<f64>() / valid_samples.len() as f64;
+    
+    // 使用 EWMA 值和平均值的加权平均作为最终速度
+    ewma_value * 0.7 + avg_speed * 0.3
+}
+
+// 构建固定连接到指定 IP 的客户端：测速相关域名全部解析到该 IP，请求地址需经 urls::with_port 带上端口
+pub async fn build_client(ip: &IpAddr, port: u16, config: &Config) -> Result<Client, ProbeError> {
+    let addr = SocketAddr::new(*ip, port);
+    // 测速域名总是解析到待测 IP，[-resolve] 只影响其余域名 (如重定向后的地址)
+    let mut builder = urls::apply_resolve(tls::apply(Client::builder(), config), config);
+    for host in urls::hosts(config) {
+        builder = builder.resolve(&host, addr);
+    }
+    // 经 [-proxy] 时由本地中转连接代理，不再绑定源地址
+    if config.proxy.is_empty() {
+        if let Some(local) = interface::local_address(config, *ip) {
+            builder = builder.local_address(local);
+        }
+    }
+    builder = proxy::apply(builder, *ip, config).await?;
+    builder
+        .timeout(config.download_time)
+        .connect_timeout(CONNECT_TIMEOUT.max(config.connect_timeout))
+        .pool_idle_timeout(Duration::from_secs(30))
+        .pool_max_idle_per_host(5)
+        .tcp_keepalive(Duration::from_secs(30))
+        .tcp_nodelay(true)
+        .redirect(redirect::Policy::custom(|attempt| {
+            // 限制最多重定向 10 次
+            if attempt.previous().len() > 10 {
+                attempt.error("too many redirects")
+            } else if attempt.previous().first().map(|u| u.as_str()) == Some(&Config::default().url) {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()
+        .map_err(|e| ProbeError::from_reqwest(&e))
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PingData;
+
+    // 下载失败的 IP 不应以 0 速度通过 [-sl 0]
+    #[tokio::test]
+    async fn failed_download_is_not_a_result() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut config = Config {
+            url: format!("http://127.0.0.1:{}/file", port),
+            min_speed: 0.0,
+            ..Config::default()
+        };
+        let ping_data = PingData::new("127.0.0.1".parse().unwrap(), port, 4, 4, Duration::from_millis(1));
+        let results = test_download_speed(&mut config, vec![CloudflareIPData::new(ping_data)]).await.unwrap();
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use ipnet::IpNet;
+use lazy_static::lazy_static;
+use crate::ip::{parse_entry, remove_comments};
+use crate::types::Config;
+use crate::debug_log;
+
+lazy_static! {
+    // 本轮下载测速结果：true 为失败
+    static ref DOWNLOAD_RESULTS: Mutex<HashMap<IpAddr, bool>> = Mutex::new(HashMap::new());
+}
+
+// 排除的 IP 段，按起始地址排序并合并重叠部分
+#[derive(Debug, Default)]
+pub struct ExcludeList {
+    v4: Vec<(u128, u128)>,
+    v6: Vec<(u128, u128)>,
+}
+
+fn net_range(net: &IpNet) -> (u128, u128) {
+    match net {
+        IpNet::V4(net) => (u32::from(net.network()) as u128, u32::from(net.broadcast()) as u128),
+        IpNet::V6(net) => (u128::from(net.network()), u128::from(net.broadcast())),
+    }
+}
+
+fn ip_value(ip: &IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(*ip) as u128,
+        IpAddr::V6(ip) => u128::from(*ip),
+    }
+}
+
+fn merge(ranges: &mut Vec<(u128, u128)>) {
+    ranges.sort();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+// 包含 value 的区间
+fn find(ranges: &[(u128, u128)], value: u128) -> Option<(u128, u128)> {
+    let idx = ranges.partition_point(|&(start, _)| start <= value);
+    idx.checked_sub(1)
+        .map(|i| ranges[i])
+        .filter(|&(_, end)| value <= end)
+}
+
+impl ExcludeList {
+    // 解析排除列表文本，格式与 IP 段数据文件相同（每行或英文逗号分隔，支持注释）
+    pub fn parse(text: &str) -> Self {
+        let mut list = Self::default();
+        for line in text.lines() {
+            let line = remove_comments(line);
+            for entry in line.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                match parse_entry(entry) {
+                    Some((net, _)) if net.addr().is_ipv4() => list.v4.push(net_range(&net)),
+                    Some((net, _)) => list.v6.push(net_range(&net)),
+                    None => {
+                        debug_log!("无效的排除条目: {}", entry);
+                    }
+                }
+            }
+        }
+        merge(&mut list.v4);
+        merge(&mut list.v6);
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    fn ranges(&self, is_v4: bool) -> &[(u128, u128)] {
+        if is_v4 { &self.v4 } else { &self.v6 }
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        find(self.ranges(ip.is_ipv4()), ip_value(ip)).is_some()
+    }
+
+    // 整个网段是否都被排除
+    pub fn covers(&self, net: &IpNet) -> bool {
+        let (start, end) = net_range(net);
+        find(self.ranges(net.addr().is_ipv4()), start).is_some_and(|(_, e)| end <= e)
+    }
+}
+
+// 读取 [-exclude] 与 [-exclude-file] 指定的排除列表，文件不存在时忽略
+pub fn load(config: &Config) -> ExcludeList {
+    let mut text = config.exclude.replace(',', "\n");
+    if !config.exclude_file.is_empty() {
+        match std::fs::read_to_string(&config.exclude_file) {
+            Ok(content) => {
+                text.push('\n');
+                text.push_str(&content);
+            }
+            Err(_e) => {
+                debug_log!("读取排除文件 {} 失败: {}", config.exclude_file, _e);
+            }
+        }
+    }
+    ExcludeList::parse(&text)
+}
+
+// 记录单个 IP 的下载测速结果
+pub fn record_download(ip: IpAddr, failed: bool) {
+    DOWNLOAD_RESULTS.lock().unwrap().insert(ip, failed);
+}
+
+fn failures_path(config: &Config) -> String {
+    format!("{}.failures", config.exclude_file)
+}
+
+// 按本轮下载测速结果更新失败计数，连续失败达到 [-auto-exclude] 次的 IP 追加到排除文件
+pub fn update_blocklist(config: &Config) {
+    let results: HashMap<IpAddr, bool> = std::mem::take(&mut *DOWNLOAD_RESULTS.lock().unwrap());
+    if config.auto_exclude == 0 || config.exclude_file.is_empty() || results.is_empty() {
+        return;
+    }
+
+    // 失败计数文件，每行 "IP 次数"
+    let path = failures_path(config);
+    let mut counts: HashMap<IpAddr, u32> = std::fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (ip, count) = line.split_once(' ')?;
+            Some((ip.trim().parse().ok()?, count.trim().parse().ok()?))
+        })
+        .collect();
+
+    let mut blocked = Vec::new();
+    for (ip, failed) in results {
+        if !failed {
+            counts.remove(&ip);
+            continue;
+        }
+        let count = counts.entry(ip).or_insert(0);
+        *count += 1;
+        if *count >= config.auto_exclude {
+            blocked.push(ip);
+        }
+    }
+    for ip in &blocked {
+        counts.remove(ip);
+    }
+
+    if !blocked.is_empty() {
+        blocked.sort();
+        let appended = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.exclude_file)
+            .and_then(|mut file| {
+                for ip in &blocked {
+                    writeln!(file, "{} # 连续 {} 次下载测速失败", ip, config.auto_exclude)?;
+                }
+                Ok(())
+            });
+        match appended {
+            Ok(()) => println!("[信息] 已将 {} 个连续下载失败的 IP 加入排除文件 {}", blocked.len(), config.exclude_file),
+            Err(e) => println!("[错误] 写入排除文件 {} 失败：{}", config.exclude_file, e),
+        }
+    }
+
+    let content: String = counts.iter().map(|(ip, count)| format!("{} {}\n", ip, count)).collect();
+    if let Err(e) = std::fs::write(&path, content) {
+        println!("[错误] 写入失败计数文件 {} 失败：{}", path, e);
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use lazy_static::lazy_static;
+use thiserror::Error;
+use crate::types::Config;
+
+// 单次探测失败的原因
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProbeError {
+    #[error("超时")]
+    Timeout,
+
+    #[error("连接被拒绝")]
+    ConnRefused,
+
+    #[error("TLS 错误：{0}")]
+    TlsError(String),
+
+    #[error("状态码无效：{0}")]
+    BadStatus(u16),
+
+    #[error("数据中心不匹配：{0}")]
+    ColoMismatch(String),
+
+    #[error("响应内容不符：{0}")]
+    BodyMismatch(String),
+
+    #[error("DNS 解析失败：{0}")]
+    DnsError(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ProbeError {
+    // 统计与导出时使用的分类名
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProbeError::Timeout => "timeout",
+            ProbeError::ConnRefused => "conn_refused",
+            ProbeError::TlsError(_) => "tls_error",
+            ProbeError::BadStatus(_) => "bad_status",
+            ProbeError::ColoMismatch(_) => "colo_mismatch",
+            ProbeError::BodyMismatch(_) => "body_mismatch",
+            ProbeError::DnsError(_) => "dns_error",
+            ProbeError::Other(_) => "other",
+        }
+    }
+
+    pub fn from_io_error(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut => ProbeError::Timeout,
+            io::ErrorKind::ConnectionRefused => ProbeError::ConnRefused,
+            _ => ProbeError::Other(e.to_string()),
+        }
+    }
+
+    // 沿错误链查找能判断原因的错误，适用于 hyper 与 reqwest 的错误
+    pub fn classify(err: &(dyn StdError + 'static)) -> Self {
+        let mut current = Some(err);
+        while let Some(e) = current {
+            if let Some(io_err) = e.downcast_ref::<io::Error>() {
+                match io_err.kind() {
+                    io::ErrorKind::TimedOut => return ProbeError::Timeout,
+                    io::ErrorKind::ConnectionRefused => return ProbeError::ConnRefused,
+                    _ => {}
+                }
+            }
+            if e.downcast_ref::<native_tls::Error>().is_some() {
+                return ProbeError::TlsError(e.to_string());
+            }
+            let message = e.to_string();
+            if message.starts_with("dns error") {
+                return ProbeError::DnsError(message);
+            }
+            let lower = message.to_lowercase();
+            if lower.contains("certificate") || lower.contains("tls") || lower.contains("handshake") {
+                return ProbeError::TlsError(message);
+            }
+            if lower.contains("timed out") {
+                return ProbeError::Timeout;
+            }
+            current = e.source();
+        }
+        ProbeError::Other(err.to_string())
+    }
+
+    pub fn from_hyper(e: &hyper::Error) -> Self {
+        if e.is_timeout() {
+            return ProbeError::Timeout;
+        }
+        Self::classify(e)
+    }
+
+    pub fn from_reqwest(e: &reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return ProbeError::Timeout;
+        }
+        if let Some(status) = e.status() {
+            return ProbeError::BadStatus(status.as_u16());
+        }
+        Self::classify(e)
+    }
+}
+
+// 统计时显示的中文名称
+pub fn kind_label(kind: &str) -> &str {
+    match kind {
+        "timeout" => "超时",
+        "conn_refused" => "连接被拒绝",
+        "tls_error" => "TLS 错误",
+        "bad_status" => "状态码无效",
+        "colo_mismatch" => "数据中心不匹配",
+        "body_mismatch" => "响应内容不符",
+        "dns_error" => "DNS 解析失败",
+        "other" => "其他错误",
+        other => other,
+    }
+}
+
+type FailureWriter = csv::Writer<BufWriter<File>>;
+
+lazy_static! {
+    static ref COUNTS: Mutex<BTreeMap<&'static str, usize>> = Mutex::new(BTreeMap::new());
+    // 指定 [-debug-failures] 时逐条写入失败记录
+    static ref WRITER: Mutex<Option<FailureWriter>> = Mutex::new(None);
+}
+
+// 每轮测速开始时清空统计，并按 [-debug-failures] 重新创建失败记录文件
+pub fn start(config: &Config) {
+    COUNTS.lock().unwrap().clear();
+    let mut writer = WRITER.lock().unwrap();
+    *writer = None;
+    if config.debug_failures.is_empty() {
+        return;
+    }
+    let opened = File::create(&config.debug_failures).map(|file| {
+        let mut w = csv::Writer::from_writer(BufWriter::new(file));
+        w.write_record(["IP 地址", "端口", "阶段", "原因", "详情"]).ok();
+        w
+    });
+    match opened {
+        Ok(w) => *writer = Some(w),
+        Err(e) => println!("[错误] 无法创建失败记录文件 {}：{}", config.debug_failures, e),
+    }
+}
+
+// 记录一个 IP 在某阶段（ping / download）的失败原因
+pub fn record(ip: IpAddr, port: u16, stage: &str, err: &ProbeError) {
+    tracing::debug!(%ip, port, stage, kind = err.kind(), "测速失败：{}", err);
+    *COUNTS.lock().unwrap().entry(err.kind()).or_insert(0) += 1;
+    if let Some(writer) = WRITER.lock().unwrap().as_mut() {
+        let ip = ip.to_string();
+        let port = port.to_string();
+        let detail = err.to_string();
+        writer.write_record([ip.as_str(), port.as_str(), stage, err.kind(), detail.as_str()]).ok();
+    }
+}
+
+// 各分类的失败数量
+pub fn counts() -> BTreeMap<&'static str, usize> {
+    COUNTS.lock().unwrap().clone()
+}
+
+// 写出尚未落盘的失败记录
+pub fn flush() {
+    if let Some(writer) = WRITER.lock().unwrap().as_mut() {
+        writer.flush().ok();
+    }
+}
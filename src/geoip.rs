@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use lazy_static::lazy_static;
+use crate::types::{Config, DownloadSpeedSet};
+
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+const DATA_SEPARATOR: usize = 16;
+const MAX_DEPTH: usize = 32; // 指针与嵌套的解码深度上限，避免损坏的文件造成死循环
+
+lazy_static! {
+    // 按 [-geoip-db] 缓存已读取的数据库
+    static ref DATABASES: Mutex<HashMap<String, Option<Arc<GeoDb>>>> = Mutex::new(HashMap::new());
+}
+
+// MaxMind DB 数据区中的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint(u128),
+    Int(i32),
+    Map(Vec<(String, Value)>),
+    Array(Vec<Value>),
+    Bool(bool),
+}
+
+impl Value {
+    // 按键路径取值，如 ["country", "iso_code"]
+    pub fn get(&self, path: &[&str]) -> Option<&Value> {
+        let Some((first, rest)) = path.split_first() else { return Some(self) };
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == first)?.1.get(rest),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint(n) => u64::try_from(*n).ok(),
+            Value::Int(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
+// 单个 .mmdb 文件（GeoLite2 / GeoIP2 / IP2Location 的 mmdb 版本）
+#[derive(Debug)]
+pub struct Reader {
+    buf: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    ip_version: u64,
+    data_start: usize,
+    ipv4_start: usize, // IPv6 数据库中 IPv4 地址 (::a.b.c.d) 所在的节点
+}
+
+impl Reader {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let buf = std::fs::read(path).map_err(|e| format!("读取 {} 失败：{}", path, e))?;
+        Self::from_bytes(buf).map_err(|e| format!("{}：{}", path, e))
+    }
+
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self, String> {
+        let marker = buf.windows(METADATA_MARKER.len())
+            .rposition(|w| w == METADATA_MARKER)
+            .ok_or("不是 MaxMind DB 文件")?;
+        let meta_start = marker + METADATA_MARKER.len();
+        let (metadata, _) = Decoder { buf: &buf, base: meta_start }.decode(meta_start, 0).ok_or("元数据损坏")?;
+        let field = |name: &str| metadata.get(&[name]).and_then(Value::as_u64).ok_or(format!("元数据缺少 {}", name));
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")? as usize;
+        let ip_version = field("ip_version")?;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(format!("不支持的记录长度 {}", record_size));
+        }
+        let tree_size = node_count.checked_mul(record_size).ok_or("搜索树损坏")? / 4;
+        if tree_size.saturating_add(DATA_SEPARATOR) > marker {
+            return Err("搜索树损坏".to_string());
+        }
+
+        let mut reader = Self {
+            buf,
+            node_count,
+            record_size,
+            ip_version,
+            data_start: tree_size + DATA_SEPARATOR,
+            ipv4_start: 0,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = reader.record(node, 0);
+            }
+            reader.ipv4_start = node;
+        }
+        Ok(reader)
+    }
+
+    // 节点的左 (bit = 0) 或右 (bit = 1) 记录
+    fn record(&self, node: usize, bit: u8) -> usize {
+        let b = &self.buf[node * self.record_size / 4..];
+        let be = |bytes: &[u8]| bytes.iter().fold(0usize, |acc, &x| (acc << 8) | x as usize);
+        match (self.record_size, bit) {
+            (24, 0) => be(&b[0..3]),
+            (24, _) => be(&b[3..6]),
+            (28, 0) => ((b[3] as usize >> 4) << 24) | be(&b[0..3]),
+            (28, _) => ((b[3] as usize & 0x0F) << 24) | be(&b[4..7]),
+            (_, 0) => be(&b[0..4]),
+            (_, _) => be(&b[4..8]),
+        }
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Option<Value> {
+        let (bits, start): (Vec<u8>, usize) = match ip {
+            IpAddr::V4(v4) => (v4.octets().to_vec(), self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return None,
+            IpAddr::V6(v6) => (v6.octets().to_vec(), 0),
+        };
+        let mut node = start;
+        for i in 0..bits.len() * 8 {
+            if node >= self.node_count {
+                break;
+            }
+            let bit = (bits[i / 8] >> (7 - i % 8)) & 1;
+            node = self.record(node, bit);
+        }
+        // 等于 node_count 表示没有数据，小于 node_count + DATA_SEPARATOR 的记录指向分隔区，属于损坏的文件
+        let offset = self.data_start + node.checked_sub(self.node_count + DATA_SEPARATOR)?;
+        Decoder { buf: &self.buf, base: self.data_start }.decode(offset, 0).map(|(v, _)| v)
+    }
+}
+
+// 数据区解码器；指针相对于 base（数据区或元数据起始位置）
+struct Decoder<'a> {
+    buf: &'a [u8],
+    base: usize,
+}
+
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Option<&[u8]> {
+        self.buf.get(offset..offset.checked_add(len)?)
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Option<u128> {
+        Some(self.bytes(offset, len)?.iter().fold(0u128, |acc, &x| (acc << 8) | x as u128))
+    }
+
+    // 返回解码出的值与下一个字段的位置
+    fn decode(&self, offset: usize, depth: usize) -> Option<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let ctrl = *self.buf.get(offset)?;
+        let mut pos = offset + 1;
+        let mut kind = ctrl >> 5;
+
+        if kind == 1 {
+            let ss = (ctrl >> 3) & 0x3;
+            let vvv = (ctrl & 0x7) as usize;
+            let (pointer, len) = match ss {
+                0 => ((vvv << 8) | self.uint(pos, 1)? as usize, 1),
+                1 => (((vvv << 16) | self.uint(pos, 2)? as usize) + 2048, 2),
+                2 => (((vvv << 24) | self.uint(pos, 3)? as usize) + 526336, 3),
+                _ => (self.uint(pos, 4)? as usize, 4),
+            };
+            let (value, _) = self.decode(self.base + pointer, depth + 1)?;
+            return Some((value, pos + len));
+        }
+        if kind == 0 {
+            kind = 7 + *self.buf.get(pos)?;
+            pos += 1;
+        }
+
+        let mut size = (ctrl & 0x1F) as usize;
+        match size {
+            29 => {
+                size = 29 + self.uint(pos, 1)? as usize;
+                pos += 1;
+            }
+            30 => {
+                size = 285 + self.uint(pos, 2)? as usize;
+                pos += 2;
+            }
+            31 => {
+                size = 65821 + self.uint(pos, 3)? as usize;
+                pos += 3;
+            }
+            _ => {}
+        }
+
+        Some(match kind {
+            2 => (Value::String(String::from_utf8_lossy(self.bytes(pos, size)?).into_owned()), pos + size),
+            3 => (Value::Double(f64::from_be_bytes(self.bytes(pos, 8)?.try_into().ok()?)), pos + 8),
+            4 => (Value::Bytes(self.bytes(pos, size)?.to_vec()), pos + size),
+            5 | 6 | 9 | 10 => (Value::Uint(self.uint(pos, size)?), pos + size),
+            8 => (Value::Int(self.uint(pos, size)? as u32 as i32), pos + size),
+            7 => {
+                let mut entries = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (key, next) = self.decode(pos, depth + 1)?;
+                    let (value, next) = self.decode(next, depth + 1)?;
+                    entries.push((key.as_str()?.to_string(), value));
+                    pos = next;
+                }
+                (Value::Map(entries), pos)
+            }
+            11 => {
+                let mut items = Vec::with_capacity(size.min(64));
+                for _ in 0..size {
+                    let (value, next) = self.decode(pos, depth + 1)?;
+                    items.push(value);
+                    pos = next;
+                }
+                (Value::Array(items), pos)
+            }
+            14 => (Value::Bool(size != 0), pos),
+            15 => (Value::Double(f32::from_be_bytes(self.bytes(pos, 4)?.try_into().ok()?) as f64), pos + 4),
+            _ => return None,
+        })
+    }
+}
+
+// IP 本身的地理位置与所属 ASN（与数据中心无关）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoInfo {
+    pub country: String, // ISO 3166-1 二字码
+    pub city: String,
+    pub asn: u32,
+    pub as_org: String,
+}
+
+impl GeoInfo {
+    // 依次合并各数据库的字段，先出现的优先，便于同时使用 City 与 ASN 数据库
+    fn merge(&mut self, record: &Value) {
+        let text = |path: &[&str]| record.get(path).and_then(Value::as_str).unwrap_or_default().to_string();
+        if self.country.is_empty() {
+            self.country = text(&["country", "iso_code"]);
+        }
+        if self.country.is_empty() {
+            self.country = text(&["registered_country", "iso_code"]);
+        }
+        if self.city.is_empty() {
+            self.city = text(&["city", "names", "en"]);
+        }
+        if self.asn == 0 {
+            self.asn = record.get(&["autonomous_system_number"]).and_then(Value::as_u64).unwrap_or(0) as u32;
+        }
+        if self.as_org.is_empty() {
+            self.as_org = text(&["autonomous_system_organization"]);
+        }
+    }
+}
+
+// [-geoip-db] 指定的一个或多个数据库
+#[derive(Debug)]
+pub struct GeoDb {
+    readers: Vec<Reader>,
+}
+
+impl GeoDb {
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        for record in self.readers.iter().filter_map(|r| r.lookup(ip)) {
+            info.merge(&record);
+        }
+        info
+    }
+}
+
+fn paths(config: &Config) -> Vec<&str> {
+    config.geoip_db.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect()
+}
+
+// 检查数据库能否读取，用于启动时提前报错
+pub fn validate(config: &Config) -> Result<(), String> {
+    for path in paths(config) {
+        Reader::open(path)?;
+    }
+    Ok(())
+}
+
+// 读取 [-geoip-db]，未指定或读取失败时返回 None
+pub fn database(config: &Config) -> Option<Arc<GeoDb>> {
+    if config.geoip_db.is_empty() {
+        return None;
+    }
+    let mut databases = DATABASES.lock().unwrap();
+    databases.entry(config.geoip_db.clone())
+        .or_insert_with(|| {
+            let readers: Vec<Reader> = paths(config).into_iter().filter_map(|p| Reader::open(p).ok()).collect();
+            (!readers.is_empty()).then(|| Arc::new(GeoDb { readers }))
+        })
+        .clone()
+}
+
+// 解析 ASN 列表，如 "13335,AS209242"
+pub fn parse_asns(list: &str) -> Result<HashSet<u32>, String> {
+    list.split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let digits = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")).unwrap_or(s);
+            digits.parse().map_err(|_| format!("无效的 ASN：{}", s))
+        })
+        .collect()
+}
+
+// 候选 IP 过滤条件：[-ip-country]、[-asn]、[-exclude-asn]
+#[derive(Debug)]
+pub struct GeoFilter {
+    db: Arc<GeoDb>,
+    countries: HashSet<String>,
+    asns: HashSet<u32>,
+    exclude_asns: HashSet<u32>,
+}
+
+impl GeoFilter {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let countries: HashSet<String> = config.ip_country.split(',')
+            .map(|s| s.trim().to_ascii_uppercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        // 参数解析时已检查过格式
+        let asns = parse_asns(&config.asn).unwrap_or_default();
+        let exclude_asns = parse_asns(&config.exclude_asn).unwrap_or_default();
+        if countries.is_empty() && asns.is_empty() && exclude_asns.is_empty() {
+            return None;
+        }
+        let Some(db) = database(config) else {
+            println!("[错误] [-ip-country]、[-asn]、[-exclude-asn] 需要 [-geoip-db] 指定可用的数据库，已忽略");
+            return None;
+        };
+        Some(Self { db, countries, asns, exclude_asns })
+    }
+
+    // 数据库中查不到 ASN 的 IP 不会被 [-exclude-asn] 排除，但不满足 [-asn]
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let info = self.db.lookup(ip);
+        (self.countries.is_empty() || self.countries.contains(&info.country))
+            && (self.asns.is_empty() || self.asns.contains(&info.asn))
+            && !self.exclude_asns.contains(&info.asn)
+    }
+}
+
+// 为测速结果填入 IP 的国家、城市与 ASN
+pub fn annotate(config: &Config, data: &mut DownloadSpeedSet) {
+    let Some(db) = database(config) else { return };
+    for ip_data in data.iter_mut() {
+        ip_data.geo = db.lookup(ip_data.ping_data.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 MaxMind DB 格式编码数据区的值
+    fn ctrl(kind: u8, size: usize) -> Vec<u8> {
+        assert!(size < 29);
+        if kind <= 7 {
+            vec![(kind << 5) | size as u8]
+        } else {
+            vec![size as u8, kind - 7]
+        }
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        [ctrl(2, s.len()), s.as_bytes().to_vec()].concat()
+    }
+
+    fn uint32(n: u32) -> Vec<u8> {
+        let bytes: Vec<u8> = n.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        [ctrl(6, bytes.len()), bytes].concat()
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = ctrl(7, entries.len());
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    fn pointer(offset: usize) -> Vec<u8> {
+        assert!(offset < 2048);
+        vec![(1 << 5) | (offset >> 8) as u8, offset as u8]
+    }
+
+    // IPv4 数据库：1.0.0.0/8 指向数据区偏移 data_offset，其他地址没有数据；
+    // 搜索树为 8 个节点的链，每个节点按 1 的对应位走向下一节点
+    fn database(data: &[u8], data_offset: usize, leaf: Option<usize>) -> Reader {
+        let node_count = 8usize;
+        let mut buf = Vec::new();
+        for i in 0..node_count {
+            let next = if i + 1 < node_count {
+                i + 1
+            } else {
+                leaf.unwrap_or(node_count + DATA_SEPARATOR + data_offset)
+            };
+            let bit = (1u8 >> (7 - i)) & 1;
+            let (left, right) = if bit == 0 { (next, node_count) } else { (node_count, next) };
+            buf.extend_from_slice(&(left as u32).to_be_bytes()[1..]);
+            buf.extend_from_slice(&(right as u32).to_be_bytes()[1..]);
+        }
+        buf.extend_from_slice(&[0; DATA_SEPARATOR]);
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(METADATA_MARKER);
+        buf.extend(map(&[
+            ("node_count", uint32(node_count as u32)),
+            ("record_size", uint32(24)),
+            ("ip_version", uint32(4)),
+        ]));
+        Reader::from_bytes(buf).unwrap()
+    }
+
+    #[test]
+    fn lookup_decodes_record() {
+        let record = map(&[
+            ("country", map(&[("iso_code", string("US"))])),
+            ("autonomous_system_number", uint32(13335)),
+        ]);
+        let reader = database(&record, 0, None);
+        let value = reader.lookup("1.2.3.4".parse().unwrap()).unwrap();
+        assert_eq!(value.get(&["country", "iso_code"]), Some(&Value::String("US".to_string())));
+        assert_eq!(value.get(&["autonomous_system_number"]).and_then(Value::as_u64), Some(13335));
+        assert_eq!(reader.lookup("2.0.0.0".parse().unwrap()), None);
+        assert_eq!(reader.lookup("::1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn lookup_follows_pointers() {
+        // 偏移 0 为共用的字符串，记录中的国家代码以指针引用
+        let shared = string("JP");
+        let record = map(&[("country", map(&[("iso_code", pointer(0))]))]);
+        let reader = database(&[shared.clone(), record].concat(), shared.len(), None);
+        let db = GeoDb { readers: vec![reader] };
+        assert_eq!(db.lookup("1.1.1.1".parse().unwrap()).country, "JP");
+    }
+
+    #[test]
+    fn lookup_rejects_record_inside_separator() {
+        let reader = database(&string("x"), 0, Some(8 + 5));
+        assert_eq!(reader.lookup("1.0.0.1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn decode_stops_on_pointer_loop() {
+        let buf = pointer(0);
+        assert_eq!(Decoder { buf: &buf, base: 0 }.decode(0, 0), None);
+    }
+
+    #[test]
+    fn from_bytes_rejects_non_mmdb() {
+        assert!(Reader::from_bytes(b"not a database".to_vec()).is_err());
+    }
+
+    #[test]
+    fn parse_asns_accepts_prefix() {
+        assert_eq!(parse_asns("13335, AS209242,as132892,").unwrap(), HashSet::from([13335, 209242, 132892]));
+        assert!(parse_asns("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_asns_rejects_invalid() {
+        assert_eq!(parse_asns("13335,ASX").unwrap_err(), "无效的 ASN：ASX");
+        assert!(parse_asns("-1").is_err());
+    }
+}
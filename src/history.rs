@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use prettytable::{Table, Row, Cell, format};
+use rusqlite::{params, Connection};
+use crate::types::DownloadSpeedSet;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS results (
+    run_id      INTEGER NOT NULL REFERENCES runs(id),
+    ip          TEXT    NOT NULL,
+    colo        TEXT    NOT NULL,
+    sended      INTEGER NOT NULL,
+    received    INTEGER NOT NULL,
+    loss_rate   REAL    NOT NULL,
+    latency_ms  REAL    NOT NULL,
+    jitter_ms   REAL    NOT NULL,
+    download_mb REAL    NOT NULL,
+    upload_mb   REAL    NOT NULL,
+    timestamp   INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_results_timestamp ON results(timestamp);
+CREATE INDEX IF NOT EXISTS idx_results_ip ON results(ip);
+";
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+fn open(path: &str) -> Result<Connection> {
+    let conn = Connection::open(path).with_context(|| format!("无法打开数据库 {}", path))?;
+    conn.execute_batch(SCHEMA).context("初始化数据库失败")?;
+    Ok(conn)
+}
+
+// 追加本轮测速结果
+pub fn save_run(path: &str, data: &DownloadSpeedSet) -> Result<()> {
+    if path.is_empty() || data.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = open(path)?;
+    let timestamp = now();
+    let tx = conn.transaction()?;
+    tx.execute("INSERT INTO runs (started_at) VALUES (?1)", params![timestamp])?;
+    let run_id = tx.last_insert_rowid();
+
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO results (run_id, ip, colo, sended, received, loss_rate, latency_ms, jitter_ms, download_mb, upload_mb, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        for ip_data in data {
+            stmt.execute(params![
+                run_id,
+                ip_data.ping_data.ip.to_string(),
+                ip_data.colo,
+                ip_data.ping_data.sended,
+                ip_data.ping_data.received,
+                ip_data.loss_rate as f64,
+                ip_data.ping_data.delay.as_secs_f64() * 1000.0,
+                ip_data.ping_data.jitter.as_secs_f64() * 1000.0,
+                ip_data.download_speed / 1024.0 / 1024.0,
+                ip_data.upload_speed / 1024.0 / 1024.0,
+                timestamp,
+            ])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+// 单个 IP 的历史记录摘要
+#[derive(Clone, Debug)]
+pub struct RunEntry {
+    pub ip: String,
+    pub colo: String,
+    pub latency_ms: f64,
+    pub download_mb: f64,
+}
+
+// 读取最近一轮测速中排名前 n 的 IP（按写入顺序，即测速结果排序）
+pub fn last_run(path: &str, n: u32) -> Result<Vec<RunEntry>> {
+    let conn = open(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT ip, colo, latency_ms, download_mb FROM results
+         WHERE run_id = (SELECT MAX(id) FROM runs)
+         ORDER BY rowid LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![n], |row| {
+        Ok(RunEntry {
+            ip: row.get(0)?,
+            colo: row.get(1)?,
+            latency_ms: row.get(2)?,
+            download_mb: row.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+pub(crate) fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+fn header(table: &mut Table, names: &[&str]) {
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.add_row(Row::new(names.iter().map(|n| Cell::new(n).style_spec("Fc")).collect()));
+}
+
+// 打印最近若干天的历史统计：最优 IP 与各数据中心延迟分位数
+pub fn print_history(path: &str, days: u32, limit: u32) -> Result<()> {
+    let conn = open(path)?;
+    let since = now() - Duration::from_secs(days as u64 * 86400).as_secs() as i64;
+
+    let runs: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM runs WHERE started_at >= ?1",
+        params![since],
+        |row| row.get(0),
+    )?;
+    println!("最近 {} 天共 {} 轮测速记录（{}）\n", days, runs, path);
+    if runs == 0 {
+        return Ok(());
+    }
+
+    // 最优 IP：优先按平均下载速度，其次按平均延迟
+    let mut stmt = conn.prepare(
+        "SELECT ip, colo, COUNT(*), AVG(loss_rate), AVG(latency_ms), AVG(download_mb), MAX(timestamp)
+         FROM results WHERE timestamp >= ?1
+         GROUP BY ip
+         ORDER BY AVG(download_mb) DESC, AVG(latency_ms) ASC
+         LIMIT ?2",
+    )?;
+    let mut table = Table::new();
+    header(&mut table, &["IP 地址", "数据中心", "出现次数", "平均丢包率", "平均延迟", "平均下载速度 (MB/s)"]);
+    let rows = stmt.query_map(params![since, limit], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, f64>(3)?,
+            row.get::<_, f64>(4)?,
+            row.get::<_, f64>(5)?,
+        ))
+    })?;
+    for row in rows {
+        let (ip, colo, count, loss, latency, speed) = row?;
+        table.add_row(Row::new(vec![
+            Cell::new(&ip),
+            Cell::new(&colo),
+            Cell::new(&count.to_string()),
+            Cell::new(&format!("{:.2}", loss)),
+            Cell::new(&format!("{:.2}", latency)),
+            Cell::new(&format!("{:.2}", speed)),
+        ]));
+    }
+    println!("最优 IP：");
+    table.printstd();
+
+    // 各数据中心延迟分位数
+    let mut stmt = conn.prepare("SELECT colo, latency_ms FROM results WHERE timestamp >= ?1")?;
+    let mut by_colo: HashMap<String, Vec<f64>> = HashMap::new();
+    let rows = stmt.query_map(params![since], |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)))?;
+    for row in rows {
+        let (colo, latency) = row?;
+        by_colo.entry(colo).or_default().push(latency);
+    }
+
+    let mut colos: Vec<_> = by_colo.into_iter().collect();
+    colos.sort_by_key(|(_, latencies)| std::cmp::Reverse(latencies.len()));
+
+    let mut table = Table::new();
+    header(&mut table, &["数据中心", "样本数", "P50 延迟", "P90 延迟", "P99 延迟"]);
+    for (colo, mut latencies) in colos {
+        latencies.sort_by(f64::total_cmp);
+        table.add_row(Row::new(vec![
+            Cell::new(if colo.is_empty() { "-" } else { &colo }),
+            Cell::new(&latencies.len().to_string()),
+            Cell::new(&format!("{:.2}", percentile(&latencies, 0.50))),
+            Cell::new(&format!("{:.2}", percentile(&latencies, 0.90))),
+            Cell::new(&format!("{:.2}", percentile(&latencies, 0.99))),
+        ]));
+    }
+    println!("\n各数据中心延迟分布：");
+    table.printstd();
+    Ok(())
+}
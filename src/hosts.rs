@@ -0,0 +1,61 @@
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use crate::types::{Config, DownloadSpeedSet};
+
+// hosts 文件格式："IP 域名"
+fn render_hosts(ips: &[String], domains: &[&str]) -> String {
+    let mut content = String::new();
+    for domain in domains {
+        for ip in ips {
+            content += &format!("{} {}\n", ip, domain);
+        }
+    }
+    content
+}
+
+// dnsmasq 格式："address=/域名/IP"，同一域名写入多个 IP 时依次返回
+fn render_dnsmasq(ips: &[String], domains: &[&str]) -> String {
+    let mut content = String::new();
+    for domain in domains {
+        for ip in ips {
+            content += &format!("address=/{}/{}\n", domain, ip);
+        }
+    }
+    content
+}
+
+// 先写入同目录的临时文件再重命名，读取方不会看到写了一半的文件
+fn write_atomic(path: &str, content: &str) -> Result<()> {
+    let file_name = Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or("hosts");
+    let tmp = Path::new(path).with_file_name(format!(".{}.tmp", file_name));
+    std::fs::write(&tmp, content).with_context(|| format!("无法写入 {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("无法写入 {}", path))?;
+    Ok(())
+}
+
+// 把最快的若干 IP 写入 path，dnsmasq 为 true 时使用 dnsmasq 格式，整个文件会被覆盖
+pub fn write_file(config: &Config, data: &DownloadSpeedSet, path: &str, dnsmasq: bool) -> Result<()> {
+    let domains: Vec<&str> = config.hosts_domains.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).collect();
+    if domains.is_empty() {
+        bail!("请使用 [-hosts-domains] 指定要写入的域名");
+    }
+    if data.is_empty() {
+        println!("\n[信息] 测速结果 IP 数量为 0，跳过写入 {}。", path);
+        return Ok(());
+    }
+
+    let ips: Vec<String> = data.iter()
+        .take(config.hosts_top_n.max(1) as usize)
+        .map(|d| d.ping_data.ip.to_string())
+        .collect();
+    let header = format!("# 由 CloudflareST-Rust 生成，最快的 {} 个 IP\n", ips.len());
+
+    if dnsmasq {
+        write_atomic(path, &(header + &render_dnsmasq(&ips, &domains)))?;
+        println!("已写入 dnsmasq 配置：{}", path);
+    } else {
+        write_atomic(path, &(header + &render_hosts(&ips, &domains)))?;
+        println!("已写入 hosts 文件：{}", path);
+    }
+    Ok(())
+}
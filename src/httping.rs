@@ -1,264 +1,524 @@
-use std::net::IpAddr;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use hyper::{Client, Request, Body, Method};
-use hyper::client::HttpConnector;
-use hyper_tls::HttpsConnector;
-use hyper::header::HeaderMap;
-use hyper::body::HttpBody;
-use regex::Regex;
-use lazy_static::lazy_static;
-use crate::types::{Config, PingData, PingDelaySet};
-use crate::progress::Bar;
-use futures::StreamExt;
-use tokio::io::AsyncWriteExt;
-use std::collections::HashMap;
-use std::sync::Mutex;
-use crate::threadpool::GLOBAL_POOL;
-use crate::types::CloudflareIPData;
-
-lazy_static! {
-    static ref COLO_REGEX: Regex = Regex::new(r"[A-Z]{3}").unwrap();
-}
-
-const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_12_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/98.0.4758.80 Safari/537.36";
-
-#[derive(Clone)]
-pub struct HttpPing {
-    config: Config,
-    colo_filter: Option<String>,
-    colo_map: Option<Arc<HashMap<String, String>>>,
-}
-
-impl HttpPing {
-    pub fn new(config: Config, colo_filter: Option<&str>) -> Self {
-        let colo_map = colo_filter.map(|filter| {
-            let map = filter
-                .split(',')
-                .map(|s| {
-                    let s = s.trim().to_uppercase();
-                    (s.clone(), s)
-                })
-                .collect();
-            Arc::new(map)
-        });
-
-        Self {
-            config,
-            colo_filter: colo_filter.map(|s| s.to_string()),
-            colo_map,
-        }
-    }
-
-    pub async fn check_connection(&self, client: &Client<HttpsConnector<HttpConnector>>, url: &str) -> Option<bool> {
-        let request = Request::builder()
-            .method("HEAD")
-            .uri(url)
-            .header("Accept", "*/*")
-            .header("User-Agent", USER_AGENT)
-            .body(Body::empty())
-            .ok()?;
-
-        let response = match client.request(request).await {
-            Ok(r) => r,
-            Err(_) => return None,
-        };
-
-        let status = response.status().as_u16();
-        let headers = response.headers().clone();
-
-        // 显式处理响应体
-        let mut body = response.into_body();
-        let mut sink = tokio::io::sink();
-        while let Some(chunk) = body.next().await {
-            if chunk.is_err() || sink.write(&chunk.unwrap()).await.is_err() {
-                return None;
-            }
-        }
-
-        let valid_status = if self.config.httping_status_code == 0 
-            || self.config.httping_status_code < 100 
-            || self.config.httping_status_code > 599 
-        {
-            let default_config = Config::default();
-            status == default_config.httping_status_code
-        } else {
-            status == self.config.httping_status_code
-        };
-
-        if !valid_status {
-            return Some(false);
-        }
-
-        if !self.config.httping_cf_colo.is_empty() {
-            if let Some(colo) = self.get_colo(&headers) {
-                if !self.match_colo(&colo) {
-                    return Some(false);
-                }
-            } else {
-                return Some(false);
-            }
-        }
-
-        Some(true)
-    }
-
-    pub fn get_colo(&self, headers: &HeaderMap) -> Option<String> {
-        let cf_ray = if headers.get("Server").map_or(false, |v| v.to_str().ok() == Some("cloudflare")) {
-            headers.get("CF-RAY").and_then(|v| v.to_str().ok())
-        } else {
-            headers.get("x-amz-cf-pop").and_then(|v| v.to_str().ok())
-        };
-
-        cf_ray.and_then(|ray| self.extract_colo(ray))
-    }
-
-    fn extract_colo(&self, cf_ray: &str) -> Option<String> {
-        COLO_REGEX.find(cf_ray).map(|m| m.as_str().to_string())
-    }
-
-    pub fn match_colo(&self, colo: &str) -> bool {
-        if let Some(filter) = &self.colo_filter {
-            filter.contains(colo)
-        } else if let Some(map) = &self.colo_map {
-            map.contains_key(colo)
-        } else {
-            true
-        }
-    }
-
-    async fn build_client(&self, ip: IpAddr) -> Client<HttpsConnector<HttpConnector>> {
-        let mut http = HttpConnector::new();
-        http.set_local_address(Some(ip));
-        let https = HttpsConnector::new_with_connector(http);
-        Client::builder().build::<_, Body>(https)
-    }
-
-    pub async fn http_ping(&self, config: &Config, ip: IpAddr) -> Option<PingData> {
-        let task_id = rand::random::<usize>();
-        GLOBAL_POOL.start_task(task_id);
-
-        let client = self.build_client(ip).await;
-
-        // 检查连接时也记录进展
-        if let Some(true) = self.check_connection(&client, &config.url).await {
-            GLOBAL_POOL.record_progress(task_id);
-        }
-
-        let mut received = 0;
-        let mut total_delay = Duration::ZERO;
-        let mut delays = Vec::with_capacity(config.ping_times as usize);
-
-        for i in 0..config.ping_times {
-            let start = Instant::now();
-            let request = Request::builder()
-                .method(Method::HEAD)
-                .uri(&config.url)
-                .header("Accept", "*/*")
-                .header("User-Agent", USER_AGENT)
-                .header("Connection", if i == config.ping_times - 1 { "close" } else { "keep-alive" })
-                .body(Body::empty())
-                .ok()?;
-
-            match client.request(request).await {
-                Ok(response) => {
-                    let status = response.status();
-                    if !status.is_success() && !status.is_redirection() {
-                        continue;
-                    }
-
-                    let mut body = response.into_body();
-                    let mut sink = tokio::io::sink();
-                    let mut success = true;
-                    
-                    while let Some(chunk) = body.data().await {
-                        if chunk.is_err() || sink.write(&chunk.unwrap()).await.is_err() {
-                            success = false;
-                            break;
-                        }
-                    }
-
-                    if success {
-                        let delay = start.elapsed();
-                        received += 1;
-                        total_delay += delay;
-                        delays.push(delay);
-                        // 每次成功的请求都记录进展
-                        GLOBAL_POOL.record_progress(task_id);
-                    }
-                }
-                Err(_) => continue,
-            }
-        }
-
-        GLOBAL_POOL.end_task(task_id);
-
-        if received == 0 {
-            return None;
-        }
-
-        // 计算平均延迟，保持精确度
-        let avg_delay = if delays.len() > 2 {
-            // 去掉最高和最低值后取平均
-            delays.sort();
-            let valid_delays = &delays[1..delays.len()-1];
-            let sum: Duration = valid_delays.iter().sum();
-            sum / valid_delays.len() as u32
-        } else {
-            total_delay / received as u32
-        };
-
-        Some(PingData::new(
-            ip,
-            config.ping_times,
-            received,
-            avg_delay,
-        ))
-    }
-
-    pub async fn http_ping_all(&self, config: &Config, ip_list: &[IpAddr]) -> PingDelaySet {
-        let results = Arc::new(Mutex::new(Vec::new()));
-        let bar = Bar::new(ip_list.len() as u64, "可用:", "");
-        let mut handles = Vec::new();
-
-        for &ip in ip_list {
-            let permit = GLOBAL_POOL.acquire().await;
-            let config = config.clone();
-            let results = Arc::clone(&results);
-            let http_ping = self.clone();
-            let bar = bar.clone();
-
-            let handle = tokio::spawn(async move {
-                if let Some(ping_data) = http_ping.http_ping(&config, ip).await {
-                    let mut ip_data = CloudflareIPData::new(ping_data);
-                    ip_data.config = config;
-                    let mut results = results.lock().unwrap();
-                    results.push(ip_data);
-                    let now_able = results.len();
-                    bar.grow(1, &now_able.to_string());
-                } else {
-                    let results = results.lock().unwrap();
-                    bar.grow(1, &results.len().to_string());
-                }
-                drop(permit);
-            });
-
-            handles.push(handle);
-        }
-
-        futures::future::join_all(handles).await;
-        
-        let mut results = results.lock().unwrap();
-        let mut ping_data = results.drain(..).collect::<Vec<_>>();
-        drop(results);
-        
-        bar.done();
-        ping_data.sort();
-        ping_data
-    }
-}
-
-pub async fn http_ping(config: &Config, ip: IpAddr) -> Option<PingData> {
-    let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
-    http_ping.http_ping(config, ip).await
-}
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use hyper::{Client, Request, Body, Method};
+use hyper_tls::HttpsConnector;
+use hyper::header::HeaderMap;
+use hyper::body::HttpBody;
+use crate::types::{Config, PingData, PingDelaySet, TraceInfo, USER_AGENT};
+use crate::checkpoint::Checkpoint;
+use crate::failure::ProbeError;
+use crate::progress::Bar;
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+use crate::threadpool::{GLOBAL_POOL, Outcome};
+use crate::colo::ColoFilter;
+use crate::cdn::Provider;
+use crate::ip::IpStream;
+use crate::proxy::ProbeConnector;
+use crate::{ratelimit, tcping, tls, urls};
+use crate::types::CloudflareIPData;
+
+
+// [-expect-body-*] 最多读取的响应体大小
+const BODY_CHECK_LIMIT: usize = 1024 * 1024;
+
+// 未指定有效状态码时接受全部 2xx/3xx
+const DEFAULT_ALLOWED_STATUS: (u16, u16) = (200, 399);
+
+// 有效的 HTTP 状态码集合，如 "200,204,301-308,403"
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusSet(Vec<(u16, u16)>);
+
+impl StatusSet {
+    // 解析英文逗号分隔的状态码或范围，含无效项时返回 None
+    pub fn parse(expr: &str) -> Option<Self> {
+        let mut ranges = Vec::new();
+        for item in expr.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (start, end) = match item.split_once('-') {
+                Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+                None => {
+                    let code = item.parse().ok()?;
+                    (code, code)
+                }
+            };
+            if !(100..=599).contains(&start) || !(100..=599).contains(&end) || start > end {
+                return None;
+            }
+            ranges.push((start, end));
+        }
+        if ranges.is_empty() { None } else { Some(Self(ranges)) }
+    }
+
+    // [-allowed-status] 优先，其次为 [-httping-code] 指定的单个状态码；
+    // [-httping-code] 为默认值 200 或无效时接受全部 2xx/3xx
+    pub fn from_config(config: &Config) -> Self {
+        if let Some(set) = Self::parse(&config.allowed_status) {
+            return set;
+        }
+        match config.httping_status_code {
+            code @ 100..=599 if code != Config::default().httping_status_code => Self(vec![(code, code)]),
+            _ => Self(vec![DEFAULT_ALLOWED_STATUS]),
+        }
+    }
+
+    pub fn contains(&self, status: u16) -> bool {
+        self.0.iter().any(|&(start, end)| (start..=end).contains(&status))
+    }
+}
+
+// 固定连接到测速 IP 的 httping 客户端
+pub type ProbeClient = Client<HttpsConnector<ProbeConnector>>;
+
+#[derive(Clone)]
+pub struct HttpPing {
+    config: Config,
+    colo_filter: Option<Arc<ColoFilter>>,
+    cdn: Provider,
+    allowed_status: Arc<StatusSet>,
+}
+
+impl HttpPing {
+    pub fn new(config: Config, colo_filter: Option<&str>) -> Self {
+        Self {
+            allowed_status: Arc::new(StatusSet::from_config(&config)),
+            cdn: Provider::parse(&config.cdn_provider).unwrap_or_default(),
+            config,
+            colo_filter: colo_filter.map(|filter| Arc::new(ColoFilter::parse(filter))),
+        }
+    }
+
+    // 检查状态码、数据中心与 [-expect-body-*]，不满足时返回原因
+    pub async fn check_connection(&self, client: &ProbeClient, url: &str) -> Result<(), ProbeError> {
+        let expects_body = self.config.expects_body();
+        let mut builder = Request::builder()
+            .method(if expects_body { Method::GET } else { Method::HEAD })
+            .uri(url)
+            .header("Accept", "*/*")
+            .header("User-Agent", USER_AGENT);
+        if let Some(host) = self.config.host_header() {
+            builder = builder.header("Host", host);
+        }
+        let mut request = builder.body(Body::empty()).map_err(|e| ProbeError::Other(e.to_string()))?;
+        self.config.apply_headers(request.headers_mut());
+
+        ratelimit::acquire().await;
+        let response = tokio::time::timeout(self.config.httping_timeout, client.request(request)).await
+            .map_err(|_| ProbeError::Timeout)?
+            .map_err(|e| ProbeError::from_hyper(&e))?;
+
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        urls::report_status(url, status);
+
+        // 显式处理响应体，需要校验时保留前 BODY_CHECK_LIMIT 字节
+        let mut body = response.into_body();
+        let mut sink = tokio::io::sink();
+        let mut content = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| ProbeError::from_hyper(&e))?;
+            if !expects_body {
+                sink.write(&chunk).await.map_err(|e| ProbeError::from_io_error(&e))?;
+            } else if content.len() + chunk.len() > BODY_CHECK_LIMIT {
+                truncated = true;
+                break;
+            } else {
+                content.extend_from_slice(&chunk);
+            }
+        }
+
+        if !self.allowed_status.contains(status) {
+            return Err(ProbeError::BadStatus(status));
+        }
+        if expects_body {
+            if truncated {
+                return Err(ProbeError::BodyMismatch(format!("超过 {} 字节", BODY_CHECK_LIMIT)));
+            }
+            check_body(&self.config, &content)?;
+        }
+
+        if !self.config.httping_cf_colo.is_empty() {
+            match self.get_colo(&headers) {
+                Some(colo) if self.match_colo(&colo) => {}
+                Some(colo) => return Err(ProbeError::ColoMismatch(colo)),
+                None => return Err(ProbeError::ColoMismatch("未知".to_string())),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_colo(&self, headers: &HeaderMap) -> Option<String> {
+        self.cdn.detect(headers)
+    }
+
+    pub fn match_colo(&self, colo: &str) -> bool {
+        match &self.colo_filter {
+            Some(filter) => filter.matches(colo),
+            None => true,
+        }
+    }
+
+    pub async fn build_client(&self, ip: IpAddr) -> ProbeClient {
+        let https = tls::https_connector(ProbeConnector::new(ip, &self.config), &self.config);
+        Client::builder().build::<_, Body>(https)
+    }
+
+    pub async fn http_ping(&self, config: &Config, ip: IpAddr, port: u16) -> Result<PingData, ProbeError> {
+        let client = self.build_client(ip).await;
+        self.ping_with(&client, config, ip, port).await
+    }
+
+    // 在已有客户端上测速，第一次请求同时建立后续复用的连接
+    pub async fn ping_with(&self, client: &ProbeClient, config: &Config, ip: IpAddr, port: u16) -> Result<PingData, ProbeError> {
+        let task = GLOBAL_POOL.start_task();
+
+        // 检查连接时也记录进展；请求发往该 IP 的测速端口，与结果中的端口一致
+        let url = urls::with_port(&config.request_url(), port);
+        let mut last_error = ProbeError::Timeout;
+        match self.check_connection(client, &url).await {
+            Ok(()) => task.record_progress(),
+            // 状态码无效、数据中心不符 [-cfcolo] 或响应被劫持时，后续 HEAD 请求即使成功也不计入
+            Err(e @ (ProbeError::BadStatus(_) | ProbeError::ColoMismatch(_) | ProbeError::BodyMismatch(_))) => return Err(e),
+            Err(e) => last_error = e,
+        }
+
+        let mut delays = Vec::with_capacity(config.ping_times as usize);
+
+        for i in 0..config.ping_times {
+            ratelimit::acquire().await;
+            let start = Instant::now();
+            let mut builder = Request::builder()
+                .method(Method::HEAD)
+                .uri(&url)
+                .header("Accept", "*/*")
+                .header("User-Agent", USER_AGENT)
+                .header("Connection", if i == config.ping_times - 1 { "close" } else { "keep-alive" });
+            if let Some(host) = config.host_header() {
+                builder = builder.header("Host", host);
+            }
+            let mut request = builder.body(Body::empty()).map_err(|e| ProbeError::Other(e.to_string()))?;
+            config.apply_headers(request.headers_mut());
+
+            let Ok(result) = tokio::time::timeout(config.httping_timeout, client.request(request)).await else {
+                GLOBAL_POOL.record_outcome(Outcome::Timeout);
+                last_error = ProbeError::Timeout;
+                continue;
+            };
+            match result {
+                Ok(response) => {
+                    GLOBAL_POOL.record_outcome(Outcome::Success);
+                    let status = response.status();
+                    urls::report_status(&url, status.as_u16());
+                    if !self.allowed_status.contains(status.as_u16()) {
+                        last_error = ProbeError::BadStatus(status.as_u16());
+                        continue;
+                    }
+
+                    let mut body = response.into_body();
+                    let mut sink = tokio::io::sink();
+                    let mut success = true;
+                    
+                    while let Some(chunk) = body.data().await {
+                        let written = match chunk {
+                            Ok(chunk) => sink.write(&chunk).await.map_err(|e| ProbeError::from_io_error(&e)),
+                            Err(e) => Err(ProbeError::from_hyper(&e)),
+                        };
+                        if let Err(e) = written {
+                            last_error = e;
+                            success = false;
+                            break;
+                        }
+                    }
+
+                    if success {
+                        delays.push(start.elapsed());
+                        // 每次成功的请求都记录进展
+                        task.record_progress();
+                    }
+                }
+                Err(e) => {
+                    if e.is_connect() || e.is_timeout() {
+                        GLOBAL_POOL.record_outcome(Outcome::Timeout);
+                    }
+                    last_error = ProbeError::from_hyper(&e);
+                    continue;
+                }
+            }
+        }
+
+        PingData::from_delays(ip, port, config.ping_times, delays).ok_or(last_error)
+    }
+
+    pub async fn http_ping_all(&self, config: &Config, ips: IpStream, checkpoint: &Checkpoint) -> PingDelaySet {
+        let bar = Bar::new(ips.total() as u64, "可用:", "").phase("延迟测速");
+        tcping::ping_all(config, ips, checkpoint, &bar, |ip_with_port, config| {
+            let http_ping = self.clone();
+            async move { http_ping.http_ping(&config, ip_with_port.ip, ip_with_port.get_port(config.tcp_port)).await }
+        })
+        .await
+    }
+}
+
+// 按 [-expect-body-sha256]、[-expect-body-contains] 校验响应体
+fn check_body(config: &Config, body: &[u8]) -> Result<(), ProbeError> {
+    if !config.expect_body_sha256.is_empty() {
+        let digest = ring::digest::digest(&ring::digest::SHA256, body);
+        let hash: String = digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        if hash != config.expect_body_sha256 {
+            return Err(ProbeError::BodyMismatch(format!("SHA-256 为 {}", hash)));
+        }
+    }
+    if !config.expect_body_contains.is_empty()
+        && !String::from_utf8_lossy(body).contains(&config.expect_body_contains)
+    {
+        return Err(ProbeError::BodyMismatch(format!("不包含 \"{}\"", config.expect_body_contains)));
+    }
+    Ok(())
+}
+
+// TCPing 模式下对连接成功的 IP 与端口请求测速地址并校验响应体
+pub async fn verify_body(config: &Config, ip: IpAddr, port: u16) -> Result<(), ProbeError> {
+    // 只校验响应体，不按 [-cfcolo] 过滤
+    let mut check_config = config.clone();
+    check_config.httping_cf_colo.clear();
+    let http_ping = HttpPing::new(check_config, None);
+    let client = http_ping.build_client(ip).await;
+    http_ping.check_connection(&client, &urls::with_port(&config.request_url(), port)).await
+}
+
+pub async fn http_ping(config: &Config, ip: IpAddr, port: u16) -> Result<PingData, ProbeError> {
+    let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
+    http_ping.http_ping(config, ip, port).await
+}
+
+// 由测速地址得到同域名的 /cdn-cgi/trace 地址
+fn trace_url(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}://{}:{}/cdn-cgi/trace", url.scheme(), host, port),
+        None => format!("{}://{}/cdn-cgi/trace", url.scheme(), host),
+    })
+}
+
+// 解析 /cdn-cgi/trace 返回的 key=value 文本
+pub fn parse_trace(body: &str) -> TraceInfo {
+    let mut info = TraceInfo::default();
+    for line in body.lines() {
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().to_string();
+            match key.trim() {
+                "colo" => info.colo = value,
+                "warp" => info.warp = value,
+                "http" => info.http = value,
+                "tls" => info.tls = value,
+                "sgroup" => info.sgroup = value,
+                _ => {}
+            }
+        }
+    }
+    info
+}
+
+// 获取数据中心时的请求，携带与测速相同的 Host 与自定义请求头
+async fn send(client: &ProbeClient, method: Method, url: &str, config: &Config) -> Option<hyper::Response<Body>> {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(url)
+        .header("User-Agent", USER_AGENT);
+    if let Some(host) = config.host_header() {
+        builder = builder.header("Host", host);
+    }
+    let mut request = builder.body(Body::empty()).ok()?;
+    config.apply_headers(request.headers_mut());
+    tokio::time::timeout(config.httping_timeout, client.request(request)).await.ok()?.ok()
+}
+
+async fn fetch_trace(client: &ProbeClient, url: &str, config: &Config) -> Option<TraceInfo> {
+    let resp = send(client, Method::GET, url, config).await?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let body = tokio::time::timeout(config.httping_timeout, hyper::body::to_bytes(resp.into_body())).await.ok()?.ok()?;
+    Some(parse_trace(&String::from_utf8_lossy(&body)))
+}
+
+const FILL_COLO_CONCURRENCY: usize = 32;
+
+// 为测速结果获取数据中心（已获取过的跳过），启用 [-cf-trace] 时优先使用 /cdn-cgi/trace 的结果
+pub async fn fill_colo(data: &mut [CloudflareIPData], config: &Config) {
+    let http_ping = HttpPing::new(config.clone(), None);
+    let url = config.request_url();
+    let trace_url = if config.cf_trace { trace_url(&url) } else { None };
+
+    futures::stream::iter(data.iter_mut().filter(|d| d.colo.is_empty()))
+        .for_each_concurrent(FILL_COLO_CONCURRENCY, |ip_data| {
+            let http_ping = &http_ping;
+            let trace_url = &trace_url;
+            let url = &url;
+            async move {
+                let port = ip_data.ping_data.port;
+                let client = http_ping.build_client(ip_data.ping_data.ip).await;
+                if let Some(url) = trace_url {
+                    if let Some(trace) = fetch_trace(&client, &urls::with_port(url, port), config).await {
+                        ip_data.colo = trace.colo.clone();
+                        ip_data.trace = trace;
+                    }
+                }
+                if !ip_data.colo.is_empty() {
+                    return;
+                }
+                if let Some(resp) = send(&client, Method::HEAD, &urls::with_port(url, port), config).await {
+                    if let Some(colo) = http_ping.get_colo(resp.headers()) {
+                        ip_data.colo = colo;
+                    }
+                }
+            }
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+    const CF_LAX: &str = "Server: cloudflare\r\nCF-RAY: 8a1b2c3d4e5f6789-LAX\r\n";
+
+    // 本地 HTTP 服务：每个请求都以给定的状态码与响应头回应，连接保持复用
+    async fn serve(status: u16, headers: &'static str) -> u16 {
+        let listener = tokio::net::TcpListener::bind((LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 4096];
+                    let mut pending = Vec::new();
+                    loop {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => pending.extend_from_slice(&buf[..n]),
+                        }
+                        while let Some(end) = pending.windows(4).position(|w| w == b"\r\n\r\n") {
+                            pending.drain(..end + 4);
+                            let response = format!("HTTP/1.1 {} Test\r\n{}Content-Length: 0\r\n\r\n", status, headers);
+                            if stream.write_all(response.as_bytes()).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    fn colo_config(port: u16, colo: &str) -> Config {
+        Config {
+            url: format!("http://cf.test:{}/", port),
+            httping: true,
+            httping_cf_colo: colo.to_string(),
+            ping_times: 2,
+            ..Config::default()
+        }
+    }
+
+    // 请求发往候选的端口，而不是测速地址的默认端口
+    #[tokio::test]
+    async fn probes_candidate_port() {
+        let port = serve(200, CF_LAX).await;
+        let config = Config { url: "http://cf.test/".to_string(), ..colo_config(port, "") };
+        let ping = http_ping(&config, LOCALHOST, port).await.unwrap();
+        assert_eq!((ping.port, ping.received), (port, 2));
+    }
+
+    // [-ports] 的每个 (IP, 端口) 分别测速，结果中的端口即实际请求的端口
+    #[tokio::test]
+    async fn each_port_is_measured_separately() {
+        let ok = serve(200, CF_LAX).await;
+        let failing = serve(503, CF_LAX).await;
+        let config = Config { url: "http://cf.test/".to_string(), ports: format!("{},{}", ok, failing), ..colo_config(ok, "") };
+        let ips = crate::ip::parse_ip_text("127.0.0.1", &config);
+        assert_eq!(ips.total(), 2);
+        let results = HttpPing::new(config.clone(), None).http_ping_all(&config, ips, &Checkpoint::default()).await;
+        let ports: Vec<u16> = results.iter().map(|d| d.ping_data.port).collect();
+        assert_eq!(ports, [ok]);
+    }
+
+    // TCPing 的响应体校验同样请求候选的端口
+    #[tokio::test]
+    async fn verify_body_uses_candidate_port() {
+        let port = serve(200, CF_LAX).await;
+        let config = Config { url: "http://cf.test/".to_string(), expect_body_contains: "ok".to_string(), ..Config::default() };
+        let result = verify_body(&config, LOCALHOST, port).await;
+        assert!(matches!(result, Err(ProbeError::BodyMismatch(_))), "{:?}", result);
+    }
+
+    // 数据中心不符 [-cfcolo] 的 IP 不产生结果，后续 HEAD 请求成功也不计入
+    #[tokio::test]
+    async fn colo_mismatch_is_rejected() {
+        let port = serve(200, CF_LAX).await;
+        let result = http_ping(&colo_config(port, "SJC"), LOCALHOST, port).await;
+        assert_eq!(result.unwrap_err(), ProbeError::ColoMismatch("LAX".to_string()));
+
+        let ping = http_ping(&colo_config(port, "LAX"), LOCALHOST, port).await.unwrap();
+        assert_eq!((ping.sended, ping.received), (2, 2));
+    }
+
+    // 不匹配 [-cfcolo] 的 IP 不是结果，不计入 [-stop-after]，排在后面的匹配 IP 仍会被测速
+    #[tokio::test]
+    async fn colo_mismatch_does_not_count_for_stop_after() {
+        let lax = serve(200, CF_LAX).await;
+        let sjc = serve(200, "Server: cloudflare\r\nCF-RAY: 8a1b2c3d4e5f6789-SJC\r\n").await;
+        let config = Config {
+            url: "http://cf.test/".to_string(),
+            ports: format!("{},{}", lax, sjc),
+            stop_after: 1,
+            ..colo_config(lax, "SJC")
+        };
+        let ips = crate::ip::parse_ip_text("127.0.0.1", &config);
+        let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
+        let results = http_ping.http_ping_all(&config, ips, &Checkpoint::default()).await;
+        let qualified: Vec<u16> = results.iter().filter(|d| d.meets_ping_filters(&config)).map(|d| d.ping_data.port).collect();
+        assert_eq!(qualified, [sjc]);
+        assert_eq!(results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bad_status_is_rejected() {
+        let port = serve(503, CF_LAX).await;
+        let result = http_ping(&colo_config(port, ""), LOCALHOST, port).await;
+        assert_eq!(result.unwrap_err(), ProbeError::BadStatus(503));
+    }
+
+    #[test]
+    fn default_status_accepts_2xx_and_3xx() {
+        let set = StatusSet::from_config(&Config::default());
+        for code in [200, 204, 206, 301, 302, 307, 308] {
+            assert!(set.contains(code), "{}", code);
+        }
+        assert!(!set.contains(403));
+        assert!(!set.contains(500));
+    }
+
+    #[test]
+    fn explicit_status_code_is_exact() {
+        let config = Config { httping_status_code: 204, ..Config::default() };
+        let set = StatusSet::from_config(&config);
+        assert!(set.contains(204));
+        assert!(!set.contains(200));
+    }
+
+    #[test]
+    fn allowed_status_ranges() {
+        let set = StatusSet::parse("200, 204,301-308,403").unwrap();
+        assert!(set.contains(204) && set.contains(305) && set.contains(403));
+        assert!(!set.contains(309));
+        assert!(StatusSet::parse("200,abc").is_none());
+        assert!(StatusSet::parse("308-301").is_none());
+        assert!(StatusSet::parse("").is_none());
+    }
+}
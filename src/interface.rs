@@ -0,0 +1,277 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+use crate::types::Config;
+
+// 将套接字绑定到指定网卡：Linux 使用 SO_BINDTODEVICE
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_device(socket: socket2::SockRef<'_>, name: &str, _ipv4: bool) -> io::Result<()> {
+    socket.bind_device(Some(name.as_bytes()))
+}
+
+// macOS 等系统按网卡序号绑定 (IP_BOUND_IF / IPV6_BOUND_IF)
+#[cfg(any(target_os = "ios", target_os = "macos", target_os = "tvos", target_os = "watchos", target_os = "visionos"))]
+fn bind_device(socket: socket2::SockRef<'_>, name: &str, ipv4: bool) -> io::Result<()> {
+    let index = index(name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("网卡 {} 不存在", name)))?;
+    if ipv4 {
+        socket.bind_device_by_index_v4(Some(index))
+    } else {
+        socket.bind_device_by_index_v6(Some(index))
+    }
+}
+
+// Windows 按网卡序号指定出口 (IP_UNICAST_IF / IPV6_UNICAST_IF)
+#[cfg(windows)]
+fn bind_device(socket: socket2::SockRef<'_>, name: &str, ipv4: bool) -> io::Result<()> {
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{setsockopt, IPPROTO_IP, IPPROTO_IPV6, IPV6_UNICAST_IF, IP_UNICAST_IF, SOCKET_ERROR};
+
+    let adapter = adapter::find(name).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("网卡 {} 不存在", name)))?;
+    // IPv4 的序号为网络字节序，IPv6 为主机字节序
+    let (level, option, value) = if ipv4 {
+        (IPPROTO_IP, IP_UNICAST_IF, adapter.index_v4.to_be())
+    } else {
+        (IPPROTO_IPV6, IPV6_UNICAST_IF, adapter.index_v6)
+    };
+    if value == 0 {
+        return Err(io::Error::new(io::ErrorKind::AddrNotAvailable, format!("网卡 {} 未启用 {}", name, if ipv4 { "IPv4" } else { "IPv6" })));
+    }
+    // SAFETY: value 在调用期间有效，长度与类型一致
+    let ret = unsafe {
+        setsockopt(
+            socket.as_raw_socket() as _,
+            level,
+            option,
+            &value as *const u32 as *const u8,
+            std::mem::size_of::<u32>() as i32,
+        )
+    };
+    if ret == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// 其他系统无法直接绑定网卡，改为绑定网卡的地址
+#[cfg(not(any(
+    target_os = "android", target_os = "fuchsia", target_os = "linux",
+    target_os = "ios", target_os = "macos", target_os = "tvos", target_os = "watchos", target_os = "visionos",
+    windows,
+)))]
+fn bind_device(socket: socket2::SockRef<'_>, name: &str, ipv4: bool) -> io::Result<()> {
+    let addr = address(name, ipv4).ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, format!("网卡 {} 没有可用的地址", name)))?;
+    socket.bind(&SocketAddr::new(addr, 0).into())
+}
+
+#[cfg(unix)]
+fn index(name: &str) -> Option<std::num::NonZeroU32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    std::num::NonZeroU32::new(unsafe { libc::if_nametoindex(name.as_ptr()) })
+}
+
+// 网卡上的全部地址
+#[cfg(unix)]
+fn addresses(name: &str) -> Vec<IpAddr> {
+    let mut list = Vec::new();
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    unsafe {
+        if libc::getifaddrs(&mut ifap) != 0 {
+            return list;
+        }
+        let mut current = ifap;
+        while let Some(ifa) = current.as_ref() {
+            current = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || std::ffi::CStr::from_ptr(ifa.ifa_name).to_bytes() != name.as_bytes() {
+                continue;
+            }
+            match i32::from((*ifa.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    list.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr))));
+                }
+                libc::AF_INET6 => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    list.push(IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr)));
+                }
+                _ => {}
+            }
+        }
+        libc::freeifaddrs(ifap);
+    }
+    list
+}
+
+#[cfg(windows)]
+fn addresses(name: &str) -> Vec<IpAddr> {
+    adapter::find(name).map(|adapter| adapter.addresses).unwrap_or_default()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn addresses(_name: &str) -> Vec<IpAddr> {
+    Vec::new()
+}
+
+// Windows 网卡信息，通过 GetAdaptersAddresses 按网卡名称或适配器 GUID 查找
+#[cfg(windows)]
+mod adapter {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use windows_sys::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, ERROR_SUCCESS};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER, GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES_LH,
+    };
+    use windows_sys::Win32::Networking::WinSock::{AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN, SOCKADDR_IN6};
+
+    pub struct Adapter {
+        pub index_v4: u32, // 未启用 IPv4 时为 0
+        pub index_v6: u32, // 未启用 IPv6 时为 0
+        pub addresses: Vec<IpAddr>,
+    }
+
+    // 以 0 结尾的 UTF-16 字符串
+    unsafe fn wide(ptr: *const u16) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+        let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+    }
+
+    // 以 0 结尾的 ANSI 字符串
+    unsafe fn ansi(ptr: *const u8) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr(ptr as *const std::ffi::c_char).to_string_lossy().into_owned()
+    }
+
+    pub fn find(name: &str) -> Option<Adapter> {
+        let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+        let mut size: u32 = 16 * 1024;
+        // 按 8 字节对齐分配缓冲区，空间不足时按返回的大小重试
+        let mut buffer: Vec<u64>;
+        loop {
+            buffer = vec![0u64; (size as usize).div_ceil(8)];
+            // SAFETY: 缓冲区大小由 size 给出
+            let ret = unsafe {
+                GetAdaptersAddresses(AF_UNSPEC as u32, flags, std::ptr::null(), buffer.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH, &mut size)
+            };
+            match ret {
+                ERROR_SUCCESS => break,
+                ERROR_BUFFER_OVERFLOW => continue,
+                _ => return None,
+            }
+        }
+
+        // SAFETY: 链表节点均位于 buffer 中，buffer 在遍历期间有效
+        unsafe {
+            let mut current = buffer.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH;
+            while let Some(adapter) = current.as_ref() {
+                current = adapter.Next;
+                let friendly = wide(adapter.FriendlyName);
+                let guid = ansi(adapter.AdapterName);
+                if friendly != name && !guid.eq_ignore_ascii_case(name) {
+                    continue;
+                }
+
+                let mut addresses = Vec::new();
+                let mut unicast = adapter.FirstUnicastAddress;
+                while let Some(entry) = unicast.as_ref() {
+                    unicast = entry.Next;
+                    let sockaddr = entry.Address.lpSockaddr;
+                    if sockaddr.is_null() {
+                        continue;
+                    }
+                    match (*sockaddr).sa_family {
+                        AF_INET => {
+                            let sa = &*(sockaddr as *const SOCKADDR_IN);
+                            addresses.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.S_un.S_addr))));
+                        }
+                        AF_INET6 => {
+                            let sa = &*(sockaddr as *const SOCKADDR_IN6);
+                            addresses.push(IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.u.Byte)));
+                        }
+                        _ => {}
+                    }
+                }
+
+                return Some(Adapter {
+                    index_v4: adapter.Anonymous1.Anonymous.IfIndex,
+                    index_v6: adapter.Ipv6IfIndex,
+                    addresses,
+                });
+            }
+        }
+        None
+    }
+}
+
+// 网卡上与目标同类型的地址，IPv6 优先使用非链路本地地址
+fn address(name: &str, ipv4: bool) -> Option<IpAddr> {
+    let mut candidates: Vec<IpAddr> = addresses(name).into_iter().filter(|a| a.is_ipv4() == ipv4).collect();
+    candidates.sort_by_key(|a| matches!(a, IpAddr::V6(v6) if v6.segments()[0] & 0xffc0 == 0xfe80));
+    candidates.first().copied()
+}
+
+// 检查 [-interface] 指定的网卡是否存在
+#[cfg(unix)]
+pub fn check(name: &str) -> Result<(), String> {
+    index(name).map(|_| ()).ok_or_else(|| format!("网卡 {} 不存在", name))
+}
+
+#[cfg(windows)]
+pub fn check(name: &str) -> Result<(), String> {
+    adapter::find(name).map(|_| ()).ok_or_else(|| format!("网卡 {} 不存在", name))
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn check(_name: &str) -> Result<(), String> {
+    Err("当前系统不支持 [-interface]".to_string())
+}
+
+// 建立 TCP 连接，指定 [-interface] 时从该网卡发出
+pub async fn connect(addr: SocketAddr, config: &Config) -> io::Result<TcpStream> {
+    if config.interface.is_empty() {
+        return TcpStream::connect(addr).await;
+    }
+    tcp_socket(addr, config)?.connect(addr).await
+}
+
+// 创建连接 addr 用的 TCP 套接字，调用方可在连接前修改选项 (如 TTL)
+pub fn tcp_socket(addr: SocketAddr, config: &Config) -> io::Result<TcpSocket> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    if !config.interface.is_empty() {
+        bind_device((&socket).into(), &config.interface, addr.is_ipv4())?;
+    }
+    Ok(socket)
+}
+
+// 创建连接到 addr 的 UDP 套接字，指定 [-interface] 时从该网卡发出
+pub async fn udp_socket(addr: SocketAddr, config: &Config) -> io::Result<UdpSocket> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).into()
+    } else {
+        (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0).into()
+    };
+    let socket = if config.interface.is_empty() {
+        UdpSocket::bind(bind).await?
+    } else {
+        let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::DGRAM, None)?;
+        bind_device((&socket).into(), &config.interface, addr.is_ipv4())?;
+        // Windows 上未绑定的套接字取本地地址会失败，同样需要绑定
+        if socket.local_addr().ok().and_then(|a| a.as_socket()).is_none_or(|a| a.port() == 0) {
+            socket.bind(&bind.into())?;
+        }
+        socket.set_nonblocking(true)?;
+        UdpSocket::from_std(socket.into())?
+    };
+    socket.connect(addr).await?;
+    Ok(socket)
+}
+
+// reqwest / hyper 客户端无法绑定网卡，使用该网卡上与目标同类型的地址作为源地址
+pub fn local_address(config: &Config, target: IpAddr) -> Option<IpAddr> {
+    if config.interface.is_empty() {
+        return None;
+    }
+    address(&config.interface, target.is_ipv4())
+}
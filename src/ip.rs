@@ -1,487 +1,542 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use std::io;
-use ipnet::IpNet;
-use rand::Rng;
-use crate::types::{Config, parse_test_amount};
-use futures::StreamExt;
-use num_cpus;
-use crate::debug_log;
-#[cfg(feature = "debug")]
-use tracing;
-use std::str::FromStr;
-use num_bigint::BigUint;
-use num_traits::{One, ToPrimitive};
-use sha3::{Shake256, digest::{Update, ExtendableOutput, XofReader}};
-
-const DEFAULT_IPV6_TEST_COUNT: u32 = 1u32 << 8;  // 256 = 2^8
-
-#[derive(Clone)]
-pub struct IPWithPort {
-    pub ip: IpAddr,
-    pub port: Option<u16>,
-}
-
-impl IPWithPort {
-    pub fn get_port(&self, config_port: u16) -> u16 {
-        self.port.unwrap_or(
-            if config_port != 0 { 
-                config_port 
-            } else { 
-                443 
-            }
-        )
-    }
-}
-
-struct IPRanges {
-    ips: Vec<IPWithPort>,
-    mask: String,
-    first_ip: Vec<u8>,
-    ip_net: IpNet,
-    config: Config,
-}
-
-impl IPRanges {
-    fn new(config: Config) -> Self {
-        Self {
-            ips: Vec::new(),
-            mask: String::new(),
-            first_ip: Vec::new(),
-            ip_net: "0.0.0.0/0".parse().unwrap(),
-            config,
-        }
-    }
-
-    fn parse_cidr(&mut self, ip: &str) {
-        let (ip_str, port) = self.parse_ip_with_port(ip);
-        
-        if !ip_str.contains('/') {
-            if let Ok(single_ip) = ip_str.parse::<IpAddr>() {
-                self.append_ip(single_ip, port);
-                return;
-            } else {
-                return;
-            }
-        }
-
-        if let Ok(network) = ip_str.parse() {
-            self.ip_net = network;
-            let prefix_len = self.get_prefix_len(&network);
-            self.mask = format!("/{}", prefix_len);
-            self.first_ip = self.get_network_bytes(&network);
-        }
-    }
-
-    fn calculate_select_count(&self, ip_text: &str) -> Vec<BigUint> {
-        debug_log!("开始计算选择数量");
-        let mut cidr_counts = Vec::new();
-        
-        for line in ip_text.lines() {
-            let line = Self::remove_comments(line);
-            if line.is_empty() { continue; }
-
-            for ip_str in line.split(',') {
-                let ip_str = ip_str.trim();
-                debug_log!("处理 CIDR: {}", ip_str);
-                
-                let select_count = if let Ok(network) = IpNet::from_str(ip_str) {
-                    match network {
-                        IpNet::V4(net) => {
-                            debug_log!("IPv4 CIDR: {}, 前缀长度: {}", net, net.prefix_len());
-                            if net.prefix_len() == 32 {
-                                debug_log!("单个 IPv4 地址");
-                                BigUint::one()
-                            } else if self.config.test_all {
-                                // 使用 all4 时直接返回最大值
-                                BigUint::from(u32::MAX)
-                            } else if let Some(amount) = &self.config.ipv4_amount {
-                                BigUint::from(parse_test_amount(&amount.to_string(), true))
-                            } else {
-                                // 每64个IP选1个
-                                let available_ips = 2u32.pow((32 - net.prefix_len()) as u32);
-                                BigUint::from((available_ips + 63) / 64)
-                            }
-                        },
-                        IpNet::V6(net) => {
-                            debug_log!("IPv6 CIDR: {}, 前缀长度: {}", net, net.prefix_len());
-                            if net.prefix_len() == 128 {
-                                debug_log!("单个 IPv6 地址");
-                                BigUint::one()
-                            } else {
-                                if let Some(amount) = &self.config.ipv6_amount {
-                                    BigUint::from(parse_test_amount(&amount.to_string(), false))
-                                } else if let Some(mode) = self.config.ipv6_num_mode.as_deref() {
-                                    match mode {
-                                        "more" => BigUint::from(1u32 << 18), // 2^18 = 262144
-                                        "lots" => BigUint::from(1u32 << 16), // 2^16 = 65536
-                                        "many" => BigUint::from(1u32 << 12), // 2^12 = 4096
-                                        "some" => BigUint::from(1u32 << 10), // 2^10 = 1024
-                                        _ => BigUint::from(DEFAULT_IPV6_TEST_COUNT)
-                                    }
-                                } else {
-                                    // 默认每个 CIDR 测试 256 个 IP
-                                    BigUint::from(DEFAULT_IPV6_TEST_COUNT)
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    debug_log!("无效的 CIDR 格式: {}", ip_str);
-                    BigUint::one()
-                };
-                
-                cidr_counts.push(select_count);
-            }
-        }
-        
-        cidr_counts
-    }
-
-    pub async fn load_ips_concurrent(&mut self, ip_text: &str, test_all: bool) -> io::Result<()> {
-        debug_log!("开始解析 IP 数据");
-
-        let cidr_counts = self.calculate_select_count(ip_text);
-        let _total_expected = cidr_counts.iter().sum::<BigUint>();
-        debug_log!("预计选取 IP 数量: {}", _total_expected);
-
-        let ip_chunks: Vec<_> = ip_text
-            .lines()
-            .filter_map(|line| {
-                let line = Self::remove_comments(line);
-                if line.is_empty() {
-                    None
-                } else {
-                    debug_log!("处理行: {}", line);
-                    Some(line)
-                }
-            })
-            .flat_map(|line| {
-                let chunks: Vec<_> = line.split(',')
-                    .map(|s| s.trim())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-                debug_log!("分割后的 CIDR: {:?}", chunks);
-                chunks
-            })
-            .collect();
-
-        debug_log!("处理的 CIDR 总数: {}", ip_chunks.len());
-
-        let mut handles = Vec::new();
-        
-        for (idx, chunk) in ip_chunks.iter().enumerate() {
-            let chunk = chunk.to_string();
-            let config = self.config.clone();
-            let select_count = if idx < cidr_counts.len() {
-                debug_log!("CIDR {}: 目标生成数量 {}", chunk, cidr_counts[idx]);
-                Some(cidr_counts[idx].clone())
-            } else {
-                None
-            };
-            
-            let handle = tokio::spawn(async move {
-                let mut ranges = IPRanges::new(config);
-                ranges.parse_cidr(&chunk);
-                match ranges.ip_net {
-                    IpNet::V4(_) => {
-                        ranges.choose_ipv4(test_all, select_count);
-                    },
-                    IpNet::V6(_) => {
-                        ranges.choose_ipv6(select_count);
-                    }
-                }
-                ranges.ips
-            });
-            
-            handles.push(handle);
-        }
-
-        let results = futures::stream::iter(handles)
-            .buffer_unordered(num_cpus::get())
-            .collect::<Vec<_>>()
-            .await;
-
-        let mut _total_generated = 0;
-        for result in results {
-            if let Ok(ips) = result {
-                debug_log!("添加 CIDR 生成的 IP 数量: {}", ips.len());
-                _total_generated += ips.len();
-                self.ips.extend(ips);
-            }
-        }
-
-        debug_log!("IP 解析完成，共 {} 个有效 IP", self.ips.len());
-        Ok(())
-    }
-
-    fn append_ip(&mut self, ip: IpAddr, port: Option<u16>) {
-        self.ips.push(IPWithPort { ip, port });
-    }
-
-    fn current_ip(&self) -> IpAddr {
-        match self.ip_net {
-            IpNet::V4(net) => IpAddr::V4(net.addr()),
-            IpNet::V6(net) => IpAddr::V6(net.addr()),
-        }
-    }
-
-    fn get_prefix_len(&self, network: &IpNet) -> u8 {
-        network.prefix_len()
-    }
-
-    fn get_network_bytes(&self, network: &IpNet) -> Vec<u8> {
-        match network {
-            IpNet::V4(net) => {
-                let bytes = net.addr().octets();
-                bytes.to_vec()
-            },
-            IpNet::V6(net) => {
-                let bytes = net.addr().octets();
-                bytes.to_vec()
-            }
-        }
-    }
-
-    fn get_ips(&self) -> Vec<IpAddr> {
-        self.ips.iter().map(|ip| ip.ip).collect()
-    }
-
-    fn parse_ip_with_port(&self, ip_str: &str) -> (String, Option<u16>) {
-        if ip_str.contains('[') && ip_str.contains(']') {
-            let start = ip_str.find('[').unwrap();
-            let end = ip_str.find(']').unwrap();
-            let ip = &ip_str[start + 1..end];
-            let port = if end + 1 < ip_str.len() && ip_str[end + 1..].starts_with(':') {
-                ip_str[end + 2..].parse::<u16>().ok()
-            } else {
-                None
-            };
-            return (ip.to_string(), port);
-        }
-
-        if ip_str.contains(':') && is_ipv4(ip_str) {
-            let parts: Vec<&str> = ip_str.split(':').collect();
-            if parts.len() == 2 {
-                let port = parts[1].parse::<u16>().ok();
-                return (parts[0].to_string(), port);
-            }
-        }
-
-        (ip_str.to_string(), None)
-    }
-
-    fn remove_comments(line: &str) -> &str {
-        match line.find('#') {
-            Some(idx) => &line[..idx],
-            None => match line.find("//") {
-                Some(idx) => &line[..idx],
-                None => line,
-            }
-        }.trim()
-    }
-
-    fn choose_random_ips_o1(&mut self, target_amount: BigUint, _is_ipv4: bool) -> Vec<IpAddr> {
-        debug_log!("开始随机选择 IP，目标数量: {}, 是否IPv4: {}", target_amount, _is_ipv4);
-        
-        let mut rng = rand::thread_rng();
-        let target = target_amount.to_usize().unwrap_or(0);
-        let seed = rng.gen::<u64>();
-        
-        debug_log!("初始化随机数生成器，种子: {}", seed);
-        
-        match &self.ip_net {
-            IpNet::V4(net) => {
-                debug_log!("处理 IPv4 网段: {}", net);
-                let mut ip_bytes = net.network().octets().to_vec();
-                let prefix_len = BigUint::from(net.prefix_len());
-                let total_bits = BigUint::from(32u32);
-                let variable_bits = &total_bits - &prefix_len;
-                
-                debug_log!("网段前缀长度: {}, 可变位数: {}", prefix_len, variable_bits);
-                debug_log!("初始 IP 字节: {:?}", ip_bytes);
-                
-                let start_byte = prefix_len.to_usize().unwrap() / 8;
-                let bit_offset = prefix_len.to_usize().unwrap() % 8;
-                let bytes_needed = ((variable_bits.clone() + BigUint::from(7u32)) / BigUint::from(8u32)).to_usize().unwrap();
-                
-                let mut i: u64 = 0;
-                while self.ips.len() < target {
-                    // 为每个IP生成新的哈希
-                    let mut hasher = Shake256::default();
-                    hasher.update(&seed.to_be_bytes());
-                    hasher.update(&i.to_be_bytes());
-                    let mut reader = hasher.finalize_xof();
-                    
-                    // 生成所需字节数的随机数据
-                    let mut random_bytes = vec![0u8; bytes_needed];
-                    reader.read(&mut random_bytes);
-                    
-                    // 处理第一个可能不完整的字节
-                    if bit_offset != 0 {
-                        let mask = !0u8 >> bit_offset;
-                        let preserved = ip_bytes[start_byte] & !mask;
-                        let variable = random_bytes[0] & mask;
-                        ip_bytes[start_byte] = preserved | variable;
-                    }
-                    
-                    // 处理剩余的完整字节
-                    let start_pos = if bit_offset != 0 { 1 } else { 0 };
-                    for j in 0..bytes_needed - start_pos {
-                        let byte_pos = start_byte + start_pos + j;
-                        if byte_pos < ip_bytes.len() {
-                            ip_bytes[byte_pos] = random_bytes[j + start_pos];
-                        }
-                    }
-                    
-                    // 构造IP并添加
-                    let ip = IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&ip_bytes[..4]).unwrap()));
-                    debug_log!("生成的IP: {}", ip);
-                    self.append_ip_with_limit(ip, None);
-                    i += 1;
-                }
-            },
-            IpNet::V6(net) => {
-                debug_log!("处理 IPv6 网段: {}", net);
-                let mut ip_bytes = net.network().octets().to_vec();
-                let prefix_len = BigUint::from(net.prefix_len());
-                let total_bits = BigUint::from(128u32);
-                let variable_bits = &total_bits - &prefix_len;
-                
-                debug_log!("网段前缀长度: {}, 可变位数: {}", prefix_len, variable_bits);
-                debug_log!("初始网络字节: {:?}", ip_bytes);
-                
-                let start_byte = prefix_len.to_usize().unwrap() / 8;
-                let bit_offset = prefix_len.to_usize().unwrap() % 8;
-                let bytes_needed = ((variable_bits.clone() + BigUint::from(7u32)) / BigUint::from(8u32)).to_usize().unwrap();
-                
-                let mut i: u64 = 0;
-                while self.ips.len() < target {
-                    let mut hasher = Shake256::default();
-                    hasher.update(&seed.to_be_bytes());
-                    hasher.update(&i.to_be_bytes());
-                    let mut reader = hasher.finalize_xof();
-                    
-                    let mut random_bytes = vec![0u8; bytes_needed];
-                    reader.read(&mut random_bytes);
-                    
-                    if bit_offset != 0 {
-                        let mask = !0u8 >> bit_offset;
-                        let preserved = ip_bytes[start_byte] & !mask;
-                        let variable = random_bytes[0] & mask;
-                        ip_bytes[start_byte] = preserved | variable;
-                    }
-                    
-                    let start_pos = if bit_offset != 0 { 1 } else { 0 };
-                    for j in 0..bytes_needed - start_pos {
-                        let byte_pos = start_byte + start_pos + j;
-                        if byte_pos < ip_bytes.len() {
-                            ip_bytes[byte_pos] = random_bytes[j + start_pos];
-                        }
-                    }
-                    
-                    let ip = IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&ip_bytes[..16]).unwrap()));
-                    debug_log!("生成的IP: {}", ip);
-                    self.append_ip_with_limit(ip, None);
-                    i += 1;
-                }
-            }
-        }
-        
-        debug_log!("IP生成完成，最终生成数量: {}", self.ips.len());
-        self.get_ips()
-    }
-    
-    fn choose_ipv4(&mut self, test_all: bool, target_amount: Option<BigUint>) {
-        if self.mask == "/32" {
-            self.append_ip_with_limit(self.current_ip(), None);
-            return;
-        }
-
-        let target = if test_all {
-            BigUint::from(u32::MAX)
-        } else if let Some(amount) = target_amount {
-            amount
-        } else {
-            // 每64个IP选1个
-            let prefix_len = self.ip_net.prefix_len();
-            let available_ips = 2u32.pow((32 - prefix_len) as u32);
-            BigUint::from((available_ips + 63) / 64)
-        };
-
-        let ips = self.choose_random_ips_o1(target, true);
-        for ip in ips {
-            self.append_ip_with_limit(ip, None);
-        }
-    }
-
-    fn choose_ipv6(&mut self, target_amount: Option<BigUint>) {
-        if self.mask == "/128" {
-            self.append_ip_with_limit(self.current_ip(), None);
-            return;
-        }
-
-        let target = target_amount.unwrap_or_else(|| BigUint::from(DEFAULT_IPV6_TEST_COUNT));
-        debug_log!("IPv6 目标生成数量: {}", target);
-
-        // 清空之前的 IP
-        self.ips.clear();
-        
-        let _ips = self.choose_random_ips_o1(target, false);
-        debug_log!("生成 IP 数量: {}", _ips.len());
-        
-        debug_log!("最终保留 IP 数量: {}", self.ips.len());
-    }
-
-    fn append_ip_with_limit(&mut self, ip: IpAddr, port: Option<u16>) {
-        if self.ips.len() >= self.config.max_ip_count {
-            // 超过阈值，随机丢弃一个已有的 IP
-            let mut rng = rand::thread_rng();
-            let remove_idx = rng.gen_range(0..self.ips.len());
-            self.ips.swap_remove(remove_idx);
-        }
-        
-        // 添加新 IP
-        self.ips.push(IPWithPort { ip, port });
-    }
-}
-
-pub async fn load_ip_ranges_concurrent(config: &Config) -> io::Result<Vec<IpAddr>> {
-    debug_log!("开始加载 IP 文件");
-    
-    let mut ranges = IPRanges::new(config.clone());
-    let test_all = config.is_test_all();
-    
-    if !config.ip_text.is_empty() {
-        debug_log!("使用命令行指定的 IP: {}", config.ip_text);
-        ranges.load_ips_concurrent(&config.ip_text, test_all).await?;
-    } else {
-        debug_log!("尝试读取 IP 文件: {}", &config.ip_file);
-        
-        match std::fs::read_to_string(&config.ip_file) {
-            Ok(content) => {
-                debug_log!("成功读取 IP 文件，大小: {} bytes", content.len());
-                if content.trim().is_empty() {
-                    debug_log!("IP 文件内容为空");
-                    return Ok(Vec::new());
-                }
-                ranges.load_ips_concurrent(&content, test_all).await?;
-            }
-            Err(_e) => {
-                debug_log!("读取 IP 文件失败: {}", _e);
-                return Ok(Vec::new());
-            }
-        }
-    }
-    
-    Ok(ranges.get_ips())
-}
-
-fn is_ipv4(ip: &str) -> bool {
-    ip.contains('.')
-}
-
-// 初始化随机数种子
-pub fn init_rand_seed() {
-    let mut rng = rand::thread_rng();
-    rng.gen::<u64>();
-}
\ No newline at end of file
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::io;
+use ipnet::IpNet;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::types::Config;
+use crate::{ip_source, warp};
+use crate::exclude::{self, ExcludeList};
+use crate::geoip::GeoFilter;
+use crate::debug_log;
+
+const DEFAULT_IPV6_TEST_COUNT: u128 = 1 << 8;  // 256 = 2^8
+
+#[derive(Clone, Debug)]
+pub struct IPWithPort {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+}
+
+impl IPWithPort {
+    pub fn get_port(&self, config_port: u16) -> u16 {
+        self.port.unwrap_or(
+            if config_port != 0 {
+                config_port
+            } else {
+                443
+            }
+        )
+    }
+}
+
+// 在长度为 size 的范围内分层抽样：均分为 count 份，每份随机取一个位置
+#[derive(Clone, Debug)]
+struct Strata {
+    size: u128,
+    count: u128,
+}
+
+impl Strata {
+    fn new(size: u128, count: u128) -> Self {
+        Self { size, count: count.clamp(1, size) }
+    }
+
+    // 第 i 份中的随机位置，余数均摊到前 rem 份
+    fn pick(&self, i: u128, rng: &mut StdRng) -> u128 {
+        let block = self.size / self.count;
+        let rem = self.size % self.count;
+        let start = i * block + i.min(rem);
+        let len = block + u128::from(i < rem);
+        if len > 1 { start + rng.gen_range(0..len) } else { start }
+    }
+}
+
+// 单个 IP 段的候选生成器，按需生成而不在内存中展开
+// 先在网段内分层抽取子网（outer），再在每个子网内抽取地址（inner）；不按子网抽样时 inner 只有一个位置
+#[derive(Clone, Debug)]
+pub struct CidrSampler {
+    net: IpNet,
+    base: u128,        // 网段起始地址
+    is_v4: bool,
+    ports: Vec<Option<u16>>, // 每个地址依次使用的端口
+    outer: Strata,
+    inner: Strata,
+    shift: u32,        // 子网内地址位数
+    index: u128,       // 已生成数量
+    current: u128,     // 当前子网的偏移
+    offset: u128,      // 当前地址的偏移
+    rng: StdRng,
+}
+
+impl CidrSampler {
+    fn new(net: IpNet, ports: Vec<Option<u16>>, count: u128, per_subnet: Option<(u32, u128)>, seed: u64) -> Self {
+        let (base, is_v4, bits) = match net {
+            IpNet::V4(net) => (u32::from(net.network()) as u128, true, 32 - net.prefix_len() as u32),
+            IpNet::V6(net) => (u128::from(net.network()), false, 128 - net.prefix_len() as u32),
+        };
+        let span = |bits: u32| if bits >= 128 { u128::MAX } else { 1u128 << bits };
+
+        // per_subnet 为 (子网地址位数, 每个子网的地址数量)，仅在网段大于子网时生效
+        let (outer, inner, shift) = match per_subnet {
+            Some((sub_bits, per)) if bits > sub_bits => (
+                Strata::new(span(bits - sub_bits), count),
+                Strata::new(span(sub_bits), per),
+                sub_bits,
+            ),
+            _ => (Strata::new(span(bits), count), Strata::new(1, 1), 0),
+        };
+
+        Self {
+            net,
+            base,
+            is_v4,
+            ports: if ports.is_empty() { vec![None] } else { ports },
+            outer,
+            inner,
+            shift,
+            index: 0,
+            current: 0,
+            offset: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // 每个子网生成的候选数量（地址数量 × 端口数量）
+    fn per_subnet(&self) -> u128 {
+        self.inner.count.saturating_mul(self.ports.len() as u128)
+    }
+
+    fn amount(&self) -> u128 {
+        self.outer.count.saturating_mul(self.per_subnet())
+    }
+
+    fn remaining(&self) -> u128 {
+        self.amount() - self.index
+    }
+
+    fn addr(&self, offset: u128) -> IpAddr {
+        let value = self.base.wrapping_add(offset);
+        if self.is_v4 {
+            IpAddr::V4(Ipv4Addr::from(value as u32))
+        } else {
+            IpAddr::V6(Ipv6Addr::from(value))
+        }
+    }
+}
+
+impl Iterator for CidrSampler {
+    type Item = IPWithPort;
+
+    fn next(&mut self) -> Option<IPWithPort> {
+        if self.index >= self.amount() {
+            return None;
+        }
+        // 同一地址依次搭配每个端口
+        let ports = self.ports.len() as u128;
+        let k = (self.index % ports) as usize;
+        let port = self.ports[k];
+        if k == 0 {
+            let n = self.index / ports;
+            let i = n / self.inner.count;
+            let j = n % self.inner.count;
+            if j == 0 {
+                let subnet = self.outer.pick(i, &mut self.rng);
+                self.current = if self.shift >= 128 { 0 } else { subnet << self.shift };
+            }
+            self.offset = self.current + self.inner.pick(j, &mut self.rng);
+        }
+        self.index += 1;
+
+        Some(IPWithPort { ip: self.addr(self.offset), port })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = usize::try_from(self.remaining()).unwrap_or(usize::MAX);
+        (remaining, Some(remaining))
+    }
+}
+
+// 全部 IP 段的候选流，依次从各个网段按需生成
+#[derive(Clone, Debug, Default)]
+pub struct IpStream {
+    sources: VecDeque<CidrSampler>,
+    exclude: Option<Arc<ExcludeList>>,
+    geo: Option<Arc<GeoFilter>>,
+    position: u128, // 已生成的候选数量（含被排除的）
+}
+
+impl IpStream {
+    // 剩余候选数量
+    pub fn total(&self) -> usize {
+        let total: u128 = self.sources.iter().map(|s| s.remaining()).sum();
+        usize::try_from(total).unwrap_or(usize::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.iter().all(|s| s.remaining() == 0)
+    }
+
+    // 已生成的候选数量，检查点据此记录扫描位置
+    pub fn position(&self) -> u128 {
+        self.position
+    }
+
+    // 从检查点继续时跳过已扫描的部分；各网段的随机数独立，整段跳过不影响后续网段的抽样结果
+    pub fn skip_to(&mut self, position: u128) {
+        while self.position < position {
+            let Some(source) = self.sources.front_mut() else { break };
+            let rest = position - self.position;
+            if source.remaining() <= rest {
+                self.position += source.remaining();
+                self.sources.pop_front();
+            } else {
+                for _ in 0..rest {
+                    source.next();
+                }
+                self.position += rest;
+            }
+        }
+    }
+
+    // 跳过排除列表中的 IP，整个网段都被排除时直接移除该网段
+    // 部分排除的网段在生成时过滤，因此剩余候选数量为上限值
+    pub fn exclude(&mut self, list: ExcludeList) {
+        if list.is_empty() {
+            return;
+        }
+        let _before = self.sources.len();
+        self.sources.retain(|s| !list.covers(&s.net));
+        debug_log!("排除列表移除 {} 个网段", _before - self.sources.len());
+        self.exclude = Some(Arc::new(list));
+    }
+
+    // 跳过不满足 [-ip-country] 的 IP，数量只能在生成时得知，剩余候选数量同样为上限值
+    pub fn geo_filter(&mut self, filter: GeoFilter) {
+        self.geo = Some(Arc::new(filter));
+    }
+
+    // 追加另一组 IP 段，排除列表与过滤条件在合并后统一设置
+    fn append(&mut self, other: IpStream) {
+        self.sources.extend(other.sources);
+    }
+
+    // 总数超过上限时按比例减少每个网段的选取数量（每个网段至少保留 1 个）；
+    // 网段数量多于上限时每个网段只取 1 个仍会超出，此时再均匀地间隔保留部分网段
+    fn limit(&mut self, max_count: usize) {
+        let total: u128 = self.sources.iter().map(|s| s.amount()).sum();
+        let max_count = max_count.max(1) as u128;
+        if total <= max_count {
+            return;
+        }
+        debug_log!("候选 IP 数量 {} 超过上限 {}，按比例缩减", total, max_count);
+        // 只减少抽取的子网数量，子网内的地址与端口数量保持不变
+        for source in &mut self.sources {
+            let per_subnet = source.per_subnet();
+            let count = (source.amount() * max_count / total).max(per_subnet);
+            source.outer.count = (count / per_subnet).max(1);
+        }
+
+        let total: u128 = self.sources.iter().map(|s| s.amount()).sum();
+        if total <= max_count {
+            return;
+        }
+        let n = self.sources.len() as u128;
+        let keep = (n * max_count / total).max(1);
+        let mut budget = max_count;
+        let mut index = 0u128;
+        self.sources.retain(|source| {
+            let selected = index * keep / n != (index + 1) * keep / n;
+            index += 1;
+            if selected && source.amount() <= budget {
+                budget -= source.amount();
+                true
+            } else {
+                false
+            }
+        });
+        debug_log!("网段数量超过上限，保留 {} 个网段", self.sources.len());
+    }
+}
+
+impl Iterator for IpStream {
+    type Item = IPWithPort;
+
+    fn next(&mut self) -> Option<IPWithPort> {
+        loop {
+            let source = self.sources.front_mut()?;
+            let next = source.next();
+            if next.is_some() {
+                self.position += 1;
+            }
+            match next {
+                Some(ip) if self.exclude.as_ref().is_some_and(|list| list.contains(&ip.ip)) => continue,
+                Some(ip) if self.geo.as_ref().is_some_and(|filter| !filter.allows(ip.ip)) => continue,
+                Some(ip) => return Some(ip),
+                None => {
+                    self.sources.pop_front();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let total = self.total();
+        (total, Some(total))
+    }
+}
+
+// 每个网段的选取数量；IPv6 按 /64 抽样时为抽取的 /64 子网数量
+fn select_count(net: &IpNet, config: &Config) -> u128 {
+    match net {
+        IpNet::V4(net) => {
+            let available_ips = 1u128 << (32 - net.prefix_len());
+            if net.prefix_len() == 32 {
+                1
+            } else if config.test_all {
+                available_ips
+            } else if let Some(amount) = config.ipv4_amount {
+                amount as u128
+            } else if config.ipv4_num_mode.as_deref() == Some("many") {
+                1 << 12 // 2^12 = 4096
+            } else {
+                // 每64个IP选1个
+                available_ips.div_ceil(64)
+            }
+        }
+        IpNet::V6(net) => {
+            if net.prefix_len() == 128 {
+                1
+            } else if net.prefix_len() >= 64 && config.ipv6_sample_per_64 > 0 {
+                // 网段不大于 /64 时直接按每个 /64 的数量抽取
+                config.ipv6_sample_per_64 as u128
+            } else if let Some(amount) = config.ipv6_amount {
+                amount as u128
+            } else {
+                match config.ipv6_num_mode.as_deref() {
+                    Some("more") => 1 << 18, // 2^18 = 262144
+                    Some("lots") => 1 << 16, // 2^16 = 65536
+                    Some("many") => 1 << 12, // 2^12 = 4096
+                    Some("some") => 1 << 10, // 2^10 = 1024
+                    // 默认每个 CIDR 测试 256 个 IP
+                    _ => DEFAULT_IPV6_TEST_COUNT,
+                }
+            }
+        }
+    }
+}
+
+fn parse_ip_with_port(ip_str: &str) -> (String, Option<u16>) {
+    if ip_str.contains('[') && ip_str.contains(']') {
+        let start = ip_str.find('[').unwrap();
+        let end = ip_str.find(']').unwrap();
+        let ip = &ip_str[start + 1..end];
+        let port = if end + 1 < ip_str.len() && ip_str[end + 1..].starts_with(':') {
+            ip_str[end + 2..].parse::<u16>().ok()
+        } else {
+            None
+        };
+        return (ip.to_string(), port);
+    }
+
+    if ip_str.contains(':') && is_ipv4(ip_str) {
+        let parts: Vec<&str> = ip_str.split(':').collect();
+        if parts.len() == 2 {
+            let port = parts[1].parse::<u16>().ok();
+            return (parts[0].to_string(), port);
+        }
+    }
+
+    (ip_str.to_string(), None)
+}
+
+pub(crate) fn remove_comments(line: &str) -> &str {
+    match line.find('#') {
+        Some(idx) => &line[..idx],
+        None => match line.find("//") {
+            Some(idx) => &line[..idx],
+            None => line,
+        }
+    }.trim()
+}
+
+// 解析单个条目：IP、CIDR，可带端口
+pub(crate) fn parse_entry(entry: &str) -> Option<(IpNet, Option<u16>)> {
+    let (ip_str, port) = parse_ip_with_port(entry);
+    if ip_str.contains('/') {
+        return ip_str.parse::<IpNet>().ok().map(|net| (net, port));
+    }
+    ip_str.parse::<IpAddr>().ok().map(|ip| (IpNet::from(ip), port))
+}
+
+// 解析端口列表，英文逗号或空白分隔，如 "443,2053,8443"；含无效端口或为空时返回 None
+pub fn parse_ports(expr: &str) -> Option<Vec<u16>> {
+    let ports: Option<Vec<u16>> = expr
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse::<u16>().ok().filter(|&p| p != 0))
+        .collect();
+    ports.filter(|p| !p.is_empty())
+}
+
+// 拆分行尾以空白分隔的端口列表，如 "104.16.0.0/24 443,2053,8443"
+fn split_port_list(line: &str) -> (&str, Option<Vec<u16>>) {
+    if let Some((entries, rest)) = line.split_once(char::is_whitespace) {
+        if let Some(ports) = parse_ports(rest) {
+            return (entries, Some(ports));
+        }
+    }
+    (line, None)
+}
+
+// 解析 IP 段文本（每行或英文逗号分隔，行尾可跟端口列表），生成候选流
+pub fn parse_ip_text(ip_text: &str, config: &Config) -> IpStream {
+    debug_log!("开始解析 IP 数据");
+    let mut stream = IpStream::default();
+    // 指定 [-seed] 时抽样结果可复现
+    let mut seed_rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    // 未指定端口的条目使用 [-ports] 中的全部端口，WARP 模式默认测速常用的 WARP 端口
+    let default_ports = parse_ports(&config.ports)
+        .or_else(|| if config.warp { parse_ports(warp::DEFAULT_PORTS) } else { None })
+        .unwrap_or_default();
+    let per_64 = match config.ipv6_sample_per_64 {
+        0 => None,
+        n => Some((64, n as u128)),
+    };
+
+    for line in ip_text.lines() {
+        let line = remove_comments(line);
+        if line.is_empty() {
+            continue;
+        }
+        let (entries, ports) = split_port_list(line);
+        for entry in entries.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let Some((net, port)) = parse_entry(entry) else {
+                debug_log!("无效的 CIDR 格式: {}", entry);
+                continue;
+            };
+            // 条目自带的端口优先，其次为行尾的端口列表，最后为 [-ports]
+            let ports: Vec<Option<u16>> = match port {
+                Some(port) => vec![Some(port)],
+                None => ports.as_ref().unwrap_or(&default_ports).iter().map(|&p| Some(p)).collect(),
+            };
+            let count = select_count(&net, config);
+            let per_subnet = if net.addr().is_ipv6() { per_64 } else { None };
+            let sampler = CidrSampler::new(net, ports, count, per_subnet, seed_rng.gen());
+            debug_log!("CIDR {}: 目标生成数量 {}", net, sampler.amount());
+            stream.sources.push_back(sampler);
+        }
+    }
+
+    stream.limit(config.max_ip_count);
+    debug_log!("IP 解析完成，共 {} 个候选 IP", stream.total());
+    stream
+}
+
+// 按配置读取 IP 段数据并应用排除列表，返回按需生成的候选流
+pub async fn ip_stream(config: &Config) -> io::Result<IpStream> {
+    let mut stream = load_sources(config).await?;
+    stream.exclude(exclude::load(config));
+    if let Some(filter) = GeoFilter::from_config(config) {
+        stream.geo_filter(filter);
+    }
+    Ok(stream)
+}
+
+// 优先级：[-cf-official] 官方 IP 段 > [-ip] 指定数据 > [-f] 文件（可为 http(s) 地址）
+// [-dual-stack] 时 [-f] 之外再读取 [-f6]，官方 IP 段与 [-ip] 本身可同时包含两种地址
+async fn load_sources(config: &Config) -> io::Result<IpStream> {
+    if config.cf_official {
+        debug_log!("使用 Cloudflare 官方 IP 段");
+        let content = ip_source::fetch_cf_official().await?;
+        return Ok(parse_ip_text(&content, config));
+    }
+
+    if !config.ip_text.is_empty() {
+        debug_log!("使用命令行指定的 IP: {}", config.ip_text);
+        return Ok(parse_ip_text(&config.ip_text, config));
+    }
+
+    let mut stream = load_file(&config.ip_file, config).await?;
+    if config.dual_stack && config.ip_file_v6 != config.ip_file {
+        stream.append(load_file(&config.ip_file_v6, config).await?);
+        stream.limit(config.max_ip_count);
+    }
+    Ok(stream)
+}
+
+async fn load_file(path: &str, config: &Config) -> io::Result<IpStream> {
+    if ip_source::is_url(path) {
+        debug_log!("下载 IP 段列表: {}", path);
+        let content = ip_source::fetch_cached(path).await?;
+        return Ok(parse_ip_text(&content, config));
+    }
+
+    debug_log!("尝试读取 IP 文件: {}", path);
+    match std::fs::read_to_string(path) {
+        Ok(content) => {
+            debug_log!("成功读取 IP 文件，大小: {} bytes", content.len());
+            Ok(parse_ip_text(&content, config))
+        }
+        Err(_e) if config.warp => {
+            debug_log!("读取 IP 文件失败: {}，使用内置 WARP IP 段", _e);
+            Ok(parse_ip_text(warp::DEFAULT_RANGES, config))
+        }
+        Err(_e) => {
+            debug_log!("读取 IP 文件失败: {}", _e);
+            Ok(IpStream::default())
+        }
+    }
+}
+
+fn is_ipv4(ip: &str) -> bool {
+    ip.contains('.')
+}
+
+// 初始化随机数种子
+pub fn init_rand_seed() {
+    let mut rng = rand::thread_rng();
+    rng.gen::<u64>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_ip_count: usize) -> Config {
+        Config { max_ip_count, seed: Some(1), ..Config::default() }
+    }
+
+    fn segments(n: usize) -> String {
+        (0..n).map(|i| format!("10.{}.{}.0/24", i / 256, i % 256)).collect::<Vec<_>>().join(",")
+    }
+
+    #[test]
+    fn limit_scales_segments_proportionally() {
+        let mut config = config(100);
+        config.test_all = true;
+        let stream = parse_ip_text(&segments(4), &config);
+        assert_eq!(stream.total(), 100);
+        assert_eq!(stream.sources.len(), 4);
+    }
+
+    #[test]
+    fn limit_drops_segments_beyond_budget() {
+        let stream = parse_ip_text(&segments(500), &config(100));
+        assert!(stream.total() <= 100, "total {}", stream.total());
+        assert!(stream.total() >= 90);
+        // 保留的网段分布在整个列表中
+        let last = stream.sources.back().unwrap().net;
+        assert!(last.to_string().starts_with("10.1."), "last segment {}", last);
+    }
+
+    #[test]
+    fn limit_keeps_small_inputs() {
+        let stream = parse_ip_text("1.1.1.1,1.0.0.1", &config(100));
+        assert_eq!(stream.count(), 2);
+    }
+}
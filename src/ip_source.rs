@@ -0,0 +1,116 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use crate::debug_log;
+
+// Cloudflare 官方公布的 IP 段
+pub const CF_OFFICIAL_URLS: &[&str] = &[
+    "https://www.cloudflare.com/ips-v4",
+    "https://www.cloudflare.com/ips-v6",
+];
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+// 缓存目录：$XDG_CACHE_HOME/cloudflarest，其次 ~/.cache/cloudflarest，都不可用时使用当前目录
+fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("cloudflarest");
+    }
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+        return PathBuf::from(home).join(".cache").join("cloudflarest");
+    }
+    PathBuf::from(".cfst-cache")
+}
+
+// 由地址生成缓存文件名
+fn cache_name(url: &str) -> String {
+    let name: String = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .take(120)
+        .collect();
+    format!("{}.txt", name)
+}
+
+// 下载 IP 段列表，使用 ETag 重新验证本地缓存；下载失败时退回缓存内容
+pub async fn fetch_cached(url: &str) -> io::Result<String> {
+    let dir = cache_dir();
+    let body_path = dir.join(cache_name(url));
+    let etag_path = body_path.with_extension("etag");
+
+    let cached = std::fs::read_to_string(&body_path).ok();
+    let etag = std::fs::read_to_string(&etag_path).ok().filter(|_| cached.is_some());
+
+    match fetch(url, etag.as_deref()).await {
+        Ok(Fetched::NotModified) => {
+            debug_log!("IP 段列表未变化，使用缓存: {}", url);
+            Ok(cached.unwrap_or_default())
+        }
+        Ok(Fetched::Body { body, etag }) => {
+            debug_log!("已下载 IP 段列表: {} ({} bytes)", url, body.len());
+            if std::fs::create_dir_all(&dir).is_ok() {
+                let _ = std::fs::write(&body_path, &body);
+                match etag {
+                    Some(etag) => { let _ = std::fs::write(&etag_path, etag); }
+                    None => { let _ = std::fs::remove_file(&etag_path); }
+                }
+            }
+            Ok(body)
+        }
+        Err(e) => match cached {
+            Some(body) => {
+                println!("[提示] 下载 IP 段列表失败 ({})，使用缓存：{}", e, url);
+                Ok(body)
+            }
+            None => Err(io::Error::other(format!("下载 IP 段列表失败 {}：{}", url, e))),
+        },
+    }
+}
+
+enum Fetched {
+    NotModified,
+    Body { body: String, etag: Option<String> },
+}
+
+async fn fetch(url: &str, etag: Option<&str>) -> Result<Fetched, String> {
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut req = client.get(url);
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag.trim());
+    }
+
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    match resp.status() {
+        StatusCode::NOT_MODIFIED if etag.is_some() => Ok(Fetched::NotModified),
+        status if status.is_success() => {
+            let etag = resp.headers()
+                .get(ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            let body = resp.text().await.map_err(|e| e.to_string())?;
+            Ok(Fetched::Body { body, etag })
+        }
+        status => Err(format!("HTTP {}", status)),
+    }
+}
+
+// 下载并合并 Cloudflare 官方 IPv4 / IPv6 段
+pub async fn fetch_cf_official() -> io::Result<String> {
+    let mut text = String::new();
+    for url in CF_OFFICIAL_URLS {
+        text.push_str(&fetch_cached(url).await?);
+        text.push('\n');
+    }
+    Ok(text)
+}
@@ -0,0 +1,56 @@
+//! CloudflareST-Rust 核心库
+//!
+//! 包含 IP 段解析、延迟测速（TCPing / HTTPing）、下载测速与结果导出，
+//! 其他 Rust 程序可通过 [`ScanBuilder`] 直接调用完整的测速流程，无需调用命令行再解析 CSV。
+
+pub mod types;
+pub mod aggregate;
+pub mod download;
+pub mod pipeline;
+pub mod upload;
+pub mod timing;
+pub mod multiplex;
+pub mod httping;
+pub mod colo;
+pub mod cdn;
+pub mod urls;
+pub mod tls;
+pub mod interface;
+pub mod proxy;
+pub mod ip;
+pub mod ip_source;
+pub mod exclude;
+pub mod geoip;
+pub mod tcping;
+pub mod synping;
+pub mod traceroute;
+pub mod warp;
+pub mod worker;
+pub mod progress;
+pub mod quiet;
+pub mod tui;
+pub mod cancel;
+pub mod csv;
+pub mod output;
+pub mod version;
+pub mod threadpool;
+pub mod ratelimit;
+pub mod debug;
+pub mod scan;
+pub mod checkpoint;
+pub mod dns_update;
+pub mod hosts;
+pub mod daemon;
+pub mod config_file;
+pub mod history;
+pub mod compare;
+pub mod summary;
+pub mod score;
+pub mod soak;
+pub mod failure;
+pub mod notify;
+pub mod metrics;
+pub mod server;
+
+pub use scan::{ScanBuilder, PingResult, SpeedResult};
+pub use types::Config;
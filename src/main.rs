@@ -1,314 +1,1228 @@
-mod types;
-mod download;
-mod httping;
-mod ip;
-mod tcping;
-mod progress;
-mod csv;
-mod version;
-mod threadpool;
-mod debug;
-
-#[cfg(feature = "debug")]
-use tracing;
-
-use anyhow::Result;
-use std::time::Duration;
-use crate::types::{Config, DelayFilter, parse_test_amount};
-use crate::csv::PrintResult;
-use crate::httping::HttpPing;
-
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-const NAME: &str = "CloudflareST-Rust";
-const HELP_TEXT: &str = r#"
-CloudflareST-Rust
-
-参数：
-    -t 4
-        延迟测速次数；单个 IP 延迟测速的次数；(默认 4 次)
-    -dn 10
-        下载测速数量；延迟测速并排序后，从最低延迟起下载测速的数量；(默认 10 个)
-    -dt 10
-        下载测速时间；单个 IP 下载测速最长时间，不能太短；(默认 10 秒)
-    -tp 443
-        指定测速端口；延迟测速/下载测速时使用的端口；(默认 443 端口)
-    -url https://cf.xiu2.xyz/url
-        指定测速地址；延迟测速(HTTPing)/下载测速时使用的地址，默认地址不保证可用性，建议自建；
-
-    -httping
-        切换测速模式；延迟测速模式改为 HTTP 协议，所用测试地址为 [-url] 参数；(默认 TCPing)
-    -httping-code 200
-        有效状态代码；HTTPing 延迟测速时网页返回的有效 HTTP 状态码，仅限一个；(默认 200 301 302)
-    -cfcolo HKG,KHH,NRT,LAX,SEA,SJC,FRA,MAD
-        匹配指定地区；地区名为当地机场三字码，英文逗号分隔，仅 HTTPing 模式可用；(默认 所有地区)
-
-    -tl 200
-        平均延迟上限；只输出低于指定平均延迟的 IP，各上下限条件可搭配使用；(默认 9999 ms)
-    -tll 40
-        平均延迟下限；只输出高于指定平均延迟的 IP；(默认 0 ms)
-    -tlr 0.2
-        丢包几率上限；只输出低于/等于指定丢包率的 IP，范围 0.00~1.00，0 过滤掉任何丢包的 IP；(默认 1.00)
-    -sl 5
-        下载速度下限；只输出高于指定下载速度的 IP，凑够指定数量 [-dn] 才会停止测速；(默认 0.00 MB/s)
-
-    -p 10
-        显示结果数量；测速后直接显示指定数量的结果，为 0 时不显示结果直接退出；(默认 10 个)
-    -f ip.txt
-        IP段数据文件；如路径含有空格请加上引号；支持其他 CDN IP段；(默认 ip.txt)
-    -ip 1.1.1.1,2.2.2.2/24,2606:4700::/32
-        指定IP段数据；直接通过参数指定要测速的 IP 段数据，英文逗号分隔；(默认 空)
-    -o result.csv
-        写入结果文件；如路径含有空格请加上引号；值为空时不写入文件 [-o ""]；(默认 result.csv)
-
-    -dd
-        禁用下载测速；禁用后测速结果会按延迟排序 (默认按下载速度排序)；(默认 启用)
-    -all4
-        测速全部的 IPv4；(IPv4 默认每 64 个随机测速 1 个 IP)
-    -more6
-        测试更多 IPv6；(表示 -v6 18，即每个 CIDR 测速 2^18 即 262144 个)
-    -lots6
-        测试较多 IPv6；(表示 -v6 16，即每个 CIDR 测速 2^16 即 65536 个)
-    -many6
-        测试很多 IPv6；(表示 -v6 12，即每个 CIDR 测速 2^12 即 4096 个)
-    -some6
-        测试一些 IPv6；(表示 -v6 10，即每个 CIDR 测 2^10 即 1024 个)
-    -many4
-        测试一点 IPv4；(表示 -v4 12，即每个 CIDR 测速 2^12 即 4096 个)
-
-    -v4
-        指定 IPv4 测试数量 (指定二的指数，如 -v4 8 表示测试 2^8=256 个 IP)
-    -v6
-        指定 IPv6 测试数量 (指定二的指数，如 -v6 12 表示测试 2^12=4096 个 IP)
-
-    -v
-        打印程序版本 + 检查版本更新
-    -h
-        打印帮助说明
-    -max-ips 500000
-        IP总量上限；当IP数量超过此值时会随机丢弃已有IP；(默认 500000)
-"#;
-
-// 新增参数解析结构体
-struct Args {
-    args: Vec<(String, Option<String>)>,
-}
-
-impl Args {
-    fn new() -> Self {
-        Self { args: Vec::new() }
-    }
-
-    fn parse(args: Vec<String>) -> Self {
-        let mut parsed = Self::new();
-        let mut i = 1;  // 跳过程序名
-        
-        while i < args.len() {
-            let arg = &args[i];
-            
-            // 确保是参数标志
-            if !arg.starts_with('-') {
-                i += 1;
-                continue;
-            }
-
-            let name = arg.trim_start_matches('-').to_string();
-            
-            // 检查是否是无值标志参数
-            match name.as_str() {
-                "v" | "h" | "httping" | "dd" | "all4" | "more6" | "lots6" | "many6" | "some6" | "many4" => {
-                    parsed.args.push((name, None));
-                    i += 1;
-                    continue;
-                }
-                _ => {}
-            }
-            
-            // 处理带值的参数
-            if i + 1 < args.len() && !args[i + 1].starts_with('-') {
-                parsed.args.push((name, Some(args[i + 1].clone())));
-                i += 2;  // 跳过参数名和值
-            } else {
-                i += 1;
-            }
-        }
-
-        parsed
-    }
-
-    fn get(&self, name: &str) -> Option<&str> {
-        self.args.iter()
-            .find(|(n, _)| n == name)
-            .and_then(|(_, v)| v.as_deref())
-    }
-
-    fn has(&self, name: &str) -> bool {
-        self.args.iter().any(|(n, _)| n == name)
-    }
-}
-
-fn init() {
-    debug::init_debug();
-}
-
-fn main() -> Result<()> {
-    init();  // 初始化debug
-    
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            debug_log!("启动异步运行时");
-            let args = Args::parse(std::env::args().collect());
-
-            // 处理无值参数
-            if args.has("h") {
-                println!("{}", HELP_TEXT);
-                wait_for_input();
-                return Ok(());
-            }
-
-            if args.has("v") {
-                print_version();
-                println!("检查版本更新中...");
-                if let Some(new_version) = version::check_update().await {
-                    println!("*** 发现新版本 [{}]！请前往 [https://github.com/GuangYu-yu/CloudflareST-Rust] 更新！ ***", new_version);
-                } else {
-                    println!("当前为最新版本 [{}]！", VERSION);
-                }
-                wait_for_input();
-                return Ok(());
-            }
-
-            // 创建配置
-            let mut config = Config::default();
-
-            // 解析参数
-            if let Some(v) = args.get("t") {
-                config.ping_times = v.parse().unwrap_or(4);
-            }
-            if let Some(v) = args.get("dn") {
-                config.test_count = v.parse().unwrap_or(10);
-            }
-            if let Some(v) = args.get("dt") {
-                config.download_time = Duration::from_secs(v.parse().unwrap_or(10));
-            }
-            if let Some(v) = args.get("tp") {
-                config.tcp_port = v.parse().unwrap_or(443);
-            }
-            if let Some(v) = args.get("url") {
-                config.url = v.to_string();
-            }
-            if args.has("httping") {
-                config.httping = true;
-            }
-            if let Some(v) = args.get("httping-code") {
-                config.httping_status_code = v.parse().unwrap_or(200);
-            }
-            if let Some(v) = args.get("cfcolo") {
-                config.httping_cf_colo = v.to_string();
-            }
-            if let Some(v) = args.get("tl") {
-                config.max_delay = Duration::from_millis(v.parse().unwrap_or(9999));
-            }
-            if let Some(v) = args.get("tll") {
-                config.min_delay = Duration::from_millis(v.parse().unwrap_or(0));
-            }
-            if let Some(v) = args.get("tlr") {
-                config.max_loss_rate = v.parse().unwrap_or(1.0);
-            }
-            if let Some(v) = args.get("sl") {
-                config.min_speed = v.parse().unwrap_or(0.0);
-            }
-            if let Some(v) = args.get("p") {
-                config.print_num = v.parse().unwrap_or(10);
-            }
-            if let Some(v) = args.get("f") {
-                config.ip_file = v.to_string();
-            }
-            if let Some(v) = args.get("ip") {
-                config.ip_text = v.to_string();
-            }
-            if let Some(v) = args.get("o") {
-                config.output = v.to_string();
-            }
-            if args.has("dd") {
-                config.disable_download = true;
-            }
-            if args.has("all4") {
-                config.test_all = true;
-            }
-            if args.has("many4") {
-                config.ipv4_num_mode = Some("many".to_string());
-            }
-            if args.has("more6") {
-                config.ipv6_num_mode = Some("more".to_string());
-            }
-            if args.has("lots6") {
-                config.ipv6_num_mode = Some("lots".to_string());
-            }
-            if args.has("many6") {
-                config.ipv6_num_mode = Some("many".to_string());
-            }
-            if args.has("some6") {
-                config.ipv6_num_mode = Some("some".to_string());
-            }
-            if let Some(v) = args.get("v4") {
-                config.ipv4_amount = Some(parse_test_amount(v, true));
-            }
-            if let Some(v) = args.get("v6") {
-                config.ipv6_amount = Some(parse_test_amount(v, false));
-            }
-            if let Some(v) = args.get("max-ips") {
-                config.max_ip_count = v.parse().unwrap_or(500_000);
-            }
-
-            println!("CloudflareST-Rust {}\n", VERSION);
-            
-            ip::init_rand_seed();
-            check_config(&config);
-
-            // 执行测速
-            let ping_data = if config.httping {
-                // 使用 HTTP 测速
-                let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
-                let ips = ip::load_ip_ranges_concurrent(&config).await?;
-                http_ping.http_ping_all(&config, &ips).await
-            } else {
-                // 使用 TCP 测速
-                let ping = tcping::new_ping(config.clone()).await?;
-                ping.run().await?
-            };
-
-            let ping_data = ping_data.filter_delay().filter_loss_rate();
-
-            let mut speed_data = download::test_download_speed(&mut config, ping_data).await?;
-            csv::export_csv(&mut speed_data, &config).await?;
-            speed_data.print();
-
-            wait_for_input();
-            Ok(())
-        })
-}
-
-fn check_config(config: &Config) {
-    if config.min_speed > 0.0 && config.max_delay == Duration::from_millis(9999) {
-        println!("[提示] 使用下载速度下限时，建议同时设置延迟上限，避免测速时间过长");
-    }
-}
-
-fn print_version() {
-    println!("{} v{}", NAME, VERSION);
-    
-    #[cfg(debug_assertions)]
-    println!("Debug Build");
-    
-    #[cfg(not(debug_assertions))]
-    println!("Release Build");
-}
-
-fn wait_for_input() {
-    println!("\n按回车键退出...");
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap_or_default();
-}
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use cloudflarest::{cancel, cdn, compare, config_file, daemon, debug, geoip, history, interface, ip, notify, output, proxy, quiet, scan, score, server, summary, tls, tui, urls, version, warp, worker};
+use cloudflarest::httping::StatusSet;
+use cloudflarest::cdn::Provider;
+use cloudflarest::types::{Config, parse_test_amount, parse_duration, parse_header, parse_rate, parse_bandwidth, parse_size};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const NAME: &str = "CloudflareST-Rust";
+const HELP_TEXT: &str = r#"
+CloudflareST-Rust
+
+用法：
+    CloudflareST-Rust [参数]
+        测速；按 Ctrl-C (或收到 SIGTERM) 时不再开始新的测速，进行中的请求最多再等待 3 秒，随后写入已完成的部分结果
+        (标记为部分结果) 并以退出码 130 退出，再次按下立即退出；
+    CloudflareST-Rust history -db results.sqlite [-days 7] [-p 10]
+        查询历史测速记录；显示最近 N 天内平均速度最快的 IP 及各数据中心延迟分位数 (P50/P90/P99)；
+    CloudflareST-Rust retest result.csv [参数]
+        重新测速；只对结果文件 (csv/json/ndjson) 中的 IP 重新延迟测速与下载测速，结束后按 [-compare] 的方式与原结果对比，
+        未指定 [-o] 时写入原文件旁的 <名称>.retest.<扩展名> (如 result.retest.csv)，原文件保持不变；
+    CloudflareST-Rust gen-worker [cfst-worker.js] [-worker-account 账户ID -dns-token 令牌] [-worker-name cfst-speed]
+        生成测速用的 Cloudflare Worker 脚本 (/ping、/down?bytes=N、/up)；指定 [-worker-account] 时通过 API 部署并启用
+        workers.dev 地址，令牌需要 Workers 脚本编辑权限，完成后显示可用于 [-worker] 的地址；
+
+参数：
+    -t 4
+        延迟测速次数；单个 IP 延迟测速的次数；(默认 4 次)
+    -syn
+        SYN 延迟测速；TCP 延迟测速时通过原始套接字只发送 SYN，以内核收到 SYN-ACK 的时间计时，不完成握手，
+        排除握手与用户态调度带来的抖动；Linux 需要 root 权限或 CAP_NET_RAW，Windows 需以 windivert 功能编译，
+        并将 WinDivert.dll 与驱动放在程序目录、以管理员身份运行，不可用时自动改用 connect() 测速；(默认 禁用)
+    -dn 10
+        下载测速数量；延迟测速并排序后，从最低延迟起下载测速的数量；(默认 10 个)
+    -dt 10
+        下载测速时间；单个 IP 下载测速最长时间，不能太短；(默认 10 秒)
+    -dt-converge 0.05
+        自适应下载时间；最近 8 个速度样本的相对标准差低于该值时提前结束该 IP 的下载测速，[-dt] 作为上限；(默认 0 不启用)
+    -dt-min 2s
+        最短下载时间；启用 [-dt-converge] 时至少下载的时长，支持 ms/s 单位；(默认 2s)
+    -download-budget 100MB
+        下载流量预算；单个 IP 下载达到该流量即结束测速，支持 KB、MB、GB 单位，不带单位为 MB；(默认 0 不限制)
+    -pipeline
+        边延迟测速边下载测速；延迟测速进行中即从已通过的 IP 中按延迟从低到高下载测速，候选 IP 较多时可大幅缩短总耗时；
+        下载会占用带宽，可能使同时进行的延迟测速结果偏高；与 [-per-colo]、[-dual-stack] 同时使用时按原顺序测速；(默认 禁用)
+    -soak 10m
+        稳定性测试；测速结束后对排名前 [-soak-n] 的 IP 持续探测该时长，记录丢包、断连与延迟漂移，
+        按期间的丢包率、断连次数、平均延迟重新排序后再输出结果；支持 s/m/h 单位；(默认 0 不启用)
+    -soak-interval 10s
+        稳定性测试间隔；每隔该时长对每个 IP 探测一次 (模式与延迟测速相同)；(默认 10s)
+    -soak-n 5
+        稳定性测试数量；(默认 5 个)
+    -connect-timeout 1s
+        连接超时；延迟测速、下载测速建立 TCP 连接的超时，支持 ms/s 单位，高延迟线路 (如卫星) 可适当调大；
+        下载测速的连接超时不低于 5s；(默认 1s)
+    -httping-timeout 10s
+        HTTPing 超时；HTTPing 单次请求等待响应的超时；(默认 10s)
+    -download-timeout 2s
+        下载超时；下载测速时超过该时长未收到数据则判定该 IP 下载失败；(默认 2s)
+    -download-connections 4
+        下载连接数；对同一 IP 同时建立多个连接 (支持时分段请求) 并合计速度，更接近浏览器/下载工具的实际表现；(默认 1)
+    -tp 443
+        指定测速端口；延迟测速/下载测速时使用的端口；(默认 443 端口)
+    -ports 443,2053,2083,8443
+        多端口测速；每个 IP 依次测速列表中的全部端口，每个 (IP, 端口) 单独作为一条结果，条目自带端口时以条目为准；(默认 空，只测 [-tp])
+    -url https://cf.xiu2.xyz/url
+        指定测速地址；延迟测速(HTTPing)/下载测速时使用的地址，默认地址不保证可用性，建议自建；
+        可用英文逗号分隔多个地址或指定地址列表文件 (每行一个)，将轮流使用，返回 429/404/5xx 的地址暂停使用 60 秒；
+
+    -worker https://cfst-speed.example.workers.dev
+        使用自建测速 Worker；以 gen-worker 部署的 Worker 作为下载测速 (/down) 与上传测速 (/up) 地址，
+        同时指定 [-url]、[-upload-url] 时以其为准；(默认 空)
+
+    -sni example.com
+        指定 SNI；延迟测速(HTTPing)/下载测速时用该域名替换测速地址中的域名，用于测试其他接入 Cloudflare 的域名；(默认 测速地址的域名)
+    -host-header example.com
+        指定 Host 请求头；(默认 与 SNI 相同)
+    -ca-cert ca.pem
+        自定义根证书；HTTPing/下载测速校验服务端证书时使用的 CA 证书 (PEM)；(默认 系统根证书)
+    -client-cert client.pem
+        客户端证书；用于 mTLS 认证的客户端证书 (PEM)；(默认 空)
+    -client-key client.key
+        客户端私钥；与 [-client-cert] 配套的 PKCS#8 私钥 (PEM)，为空时从证书文件中读取；(默认 空)
+    -insecure
+        跳过证书校验；不校验服务端证书与域名，仅用于测试；(默认 校验)
+    -header "Authorization: Bearer xxx"
+        自定义请求头；HTTPing/下载测速请求附加的请求头，可重复指定多个，同名时替换默认请求头 (如 User-Agent)；(默认 空)
+    -resolve example.com:443:1.2.3.4
+        自定义解析；与 curl --resolve 相同，可重复指定多个，用于测速地址以外的域名 (如重定向后的下载地址、DNS 更新与通知接口)，
+        端口应与请求地址的端口一致；测速地址的域名总是解析到待测 IP；(默认 空)
+    -cookie "a=1; b=2"
+        请求 Cookie；HTTPing/下载测速请求附加的 Cookie，如 Cloudflare Access 的 CF_Authorization；(默认 空)
+
+    -httping
+        切换测速模式；延迟测速模式改为 HTTP 协议，所用测试地址为 [-url] 参数；(默认 TCPing)
+    -warp
+        WARP 模式；向 IP:端口 发送 WireGuard 握手包 (UDP)，按握手往返时间与成功率测速，不进行下载测速，不能与 [-httping] 同时使用；
+        未指定 [-ports] 时测速 2408,500,1701,4500 端口，未指定 IP 数据且 IP 文件不存在时使用内置 WARP IP 段；(默认 关闭)
+    -warp-key xxxx
+        WARP 私钥；WireGuard 私钥 (base64)，未注册的密钥可能收不到响应，可使用 wgcf 等工具注册获取；(默认 随机生成)
+    -warp-peer bmXOC+F1FxEMF9dyiK2H5/1SUtzH0JuVo51h2wPfgyo=
+        WARP 公钥；服务端 WireGuard 公钥 (base64)；(默认 Cloudflare WARP 公钥)
+    -warp-reserved 1,2,3
+        WARP reserved；握手包中的 3 字节 reserved 字段 (客户端 ID)；(默认 0,0,0)
+    -httping-code 200
+        有效状态代码；HTTPing 延迟测速时网页返回的有效 HTTP 状态码，仅限一个，指定 200 以外的值时只接受该状态码；(默认 全部 2xx/3xx)
+    -allowed-status 200,204,301-308,403
+        有效状态代码列表；英文逗号分隔，支持范围，指定后忽略 [-httping-code]；(默认 空)
+    -expect-body-sha256 e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
+        校验响应体哈希；检查连接时改用 GET 请求测速地址 (最多读取 1MB)，响应体的 SHA-256 不一致的 IP 视为被劫持并丢弃，
+        TCPing 模式下对连接成功的 IP 额外发送该请求；(默认 空，不校验)
+    -expect-body-contains "cloudflare"
+        校验响应体内容；同上，响应体不包含该文本的 IP 被丢弃，可用于排除强制门户、WAF 拦截页；(默认 空，不校验)
+    -cfcolo HKG,KHH,NRT,LAX,SEA,SJC,FRA,MAD
+        匹配指定地区；地区名为当地机场三字码，英文逗号分隔，仅 HTTPing 模式可用；(默认 所有地区)
+        也可使用国家二字码 (如 US,DE) 或大洲代码 (AF,AS,EU,NA,OC,SA)；与大洲代码相同的国家请写作 country:SA；
+    -cdn-provider fastly
+        CDN 类型；按对应响应头识别节点代码，可选 auto,cloudflare,cloudfront,fastly,bunny,cdn77,gcore；
+        auto 依次尝试各 CDN；Bunny、CDN77、GCore 的节点代码不是机场三字码；(默认 auto)
+
+    -timing
+        分阶段耗时；对延迟测速结果直连 IP 分别测量 TCP 连接、TLS 握手、首字节 (TTFB) 耗时并写入结果；
+    -h2-latency
+        HTTP/2 复用延迟；对延迟测速结果每个 IP 保持一条 HTTP/2 连接，连接建立后在同一连接上发送 [-t] 次请求，
+        记录平均请求延迟 (不含连接与握手) 并写入结果，适合评估长连接代理；HTTPS 需服务端支持 h2；(默认 关闭)
+    -cf-trace
+        获取节点信息；测速完成后请求测速地址同域名的 /cdn-cgi/trace，记录 colo、warp、http、tls、sgroup；
+    -trace
+        路径探测；测速完成后对排名前 [-trace-n] 的 IP 逐跳发送 TCP SYN (递增 TTL)，记录跳数及最后一个运营商路由器的地址与延迟，
+        延迟相同的 IP 可能经过不同的互联路径；路由器地址仅 Linux 可获取，指定 [-geoip-db] ASN 数据库时跳过目标 ASN 内部的路由器；(默认 禁用)
+    -trace-n 5
+        路径探测数量；(默认 5 个)
+    -trace-max-ttl 30
+        最大跳数；(默认 30)
+
+    -tl 200
+        平均延迟上限；只输出低于指定平均延迟的 IP，各上下限条件可搭配使用；(默认 9999 ms)
+    -tll 40
+        平均延迟下限；只输出高于指定平均延迟的 IP；(默认 0 ms)
+    -tlr 0.2
+        丢包几率上限；只输出低于/等于指定丢包率的 IP，范围 0.00~1.00，0 过滤掉任何丢包的 IP；(默认 1.00)
+    -max-loss 0.2
+        同 [-tlr]；
+    -max-jitter 20
+        延迟抖动上限；只输出低于/等于指定抖动（延迟标准差）的 IP；(默认 9999 ms)
+    -sl 5
+        下载速度下限；只输出高于指定下载速度的 IP，凑够指定数量 [-dn] 才会停止测速；(默认 0.00 MB/s)
+
+    -p 10
+        显示结果数量；测速后直接显示指定数量的结果，为 0 时不显示结果直接退出；(默认 10 个)
+    -per-colo 3
+        按数据中心选取；每个数据中心对延迟最低的 N 个 IP 下载测速，结果包含每个数据中心最快的 N 个 IP；(默认 0 不启用)
+    -f ip.txt
+        IP段数据文件；如路径含有空格请加上引号；支持其他 CDN IP段；也可为 http(s) 地址，下载后缓存并按 ETag 更新；(默认 ip.txt)
+        每行可写 IP:端口 (如 104.16.1.1:2053、[2606:4700::1]:8443)，或在 IP 段后空格跟端口列表 (如 104.16.0.0/24 443,2053,8443)；
+    -cf-official
+        使用官方 IP 段；测速前下载 Cloudflare 公布的 IPv4/IPv6 段 (带缓存)，忽略 [-f] 与 [-ip]；
+    -dual-stack
+        双栈对比；同一轮同时测速 IPv4 与 IPv6 (读取 [-f] 与 [-f6]，或 [-ip]/[-cf-official] 中的两种地址)，
+        两种地址各下载测速 [-dn] 个，结果合并排序，并对比两者最优的延迟与速度；(默认 禁用)
+    -f6 ipv6.txt
+        IPv6 段数据文件；启用 [-dual-stack] 时在 [-f] 之外额外读取，格式同 [-f]；(默认 ipv6.txt)
+    -exclude 1.2.3.0/24,5.6.7.8
+        排除 IP 段；测速前从候选 IP 中移除指定的 IP/IP 段，英文逗号分隔；(默认 空)
+    -exclude-file bad.txt
+        排除列表文件；格式同 IP 段数据文件，与 [-exclude] 合并生效；(默认 空)
+    -auto-exclude 3
+        自动排除；IP 连续 N 轮下载测速失败后追加到 [-exclude-file]，失败计数保存在 <文件名>.failures；(默认 0 不启用)
+    -geoip-db GeoLite2-City.mmdb,GeoLite2-ASN.mmdb
+        地理位置数据库；MaxMind DB 格式 (GeoLite2/GeoIP2/IP2Location mmdb)，英文逗号分隔多个，
+        为结果写入 IP 本身的国家、城市与 ASN (与数据中心无关)；(默认 空)
+    -ip-country US,JP
+        IP 国家；只测速 [-geoip-db] 中属于指定国家 (二字码) 的 IP，英文逗号分隔；(默认 空，不过滤)
+    -asn 13335,209242
+        ASN；只测速 [-geoip-db] 中属于指定 ASN 的 IP，可区分 Cloudflare 自有段与合作伙伴/BYOIP 段，
+        英文逗号分隔，可带 AS 前缀；需要 ASN 数据库 (如 GeoLite2-ASN.mmdb)；(默认 空，不过滤)
+    -exclude-asn 4809,4134
+        排除 ASN；不测速属于指定 ASN 的 IP (如国内合作伙伴段)，与 [-asn] 可同时使用；(默认 空)
+    -ip 1.1.1.1,2.2.2.2/24,2606:4700::/32
+        指定IP段数据；直接通过参数指定要测速的 IP 段数据，英文逗号分隔，支持 IP:端口；(默认 空)
+    -o result.csv
+        写入结果文件；如路径含有空格请加上引号；值为空时不写入文件 [-o ""]；(默认 result.csv)
+    -output-format csv
+        结果文件格式；可选 csv、json、ndjson，JSON 字段名固定为英文；(默认 csv)
+    -output csv=result.csv -output table
+        输出目标；可重复指定，格式为 类型=文件，类型可选 csv、json、ndjson、sqlite、hosts、dnsmasq 与 table (终端表格，不需要文件)；
+        指定后替代 [-o] / [-output-format] 与默认的结果表格，[-output-hosts] / [-output-dnsmasq] 仍然写入；(默认 空)
+    -stream-output
+        边测速边写入结果；满足条件的结果产生后立即追加到 [-o] 并落盘，测速中断时保留已有结果，测速完成后以完整结果覆盖；
+        写入 [-o] 或 [-output] 中的第一个结果文件，仅支持 csv、ndjson，同一 IP 在延迟测速与下载测速阶段各写入一次，以后写入的为准；(默认 禁用)
+    -columns ip,colo,loss,latency,speed
+        输出列；指定结果文件 (csv/json/ndjson) 包含的列及顺序，英文逗号分隔，列名为 JSON 字段名或其简称 (如 loss、latency、speed)，
+        可选 ip,port,sended,received,loss_rate,latency_ms,min_latency_ms,max_latency_ms,jitter_ms,download_speed_mb,
+        download_capped,upload_speed_mb,colo,city,country,continent,connect_ms,tls_ms,ttfb_ms,h2_latency_ms,ip_country,ip_city,asn,
+        as_org,warp,http,tls,sgroup,timestamp；未启用对应功能的列留空；(默认 空，使用默认列)
+    -english-header
+        英文表头；CSV 表头使用与 JSON 相同的英文字段名，不受版本更新与列增减影响，便于脚本解析；(默认 禁用)
+    -summary summary.json
+        写入汇总统计；测速结束后将延迟分位数、速度分布、各数据中心数量、失败原因等统计写入 JSON 文件；(默认 空，只打印)
+    -debug-failures failures.csv
+        写入失败记录；将每个测速失败的 IP、阶段及原因（超时、连接被拒绝、TLS 错误、状态码无效等）写入 CSV 文件，用于排查整段 IP 失败的原因；(默认 空，不写入)
+    -log-level debug
+        日志级别；可选 off,error,warn,info,debug,trace，info 记录各阶段 (生成、延迟测速、下载测速、写入等) 的开始、结果与耗时，
+        debug 另外记录每个 IP 的失败原因与请求细节；也可用 RUST_LOG 环境变量指定；
+        [-v] 已用于显示版本，因此以该参数代替常见的 -v/-vv；(默认 off 不输出)
+    -log-file cfst.log
+        日志文件；日志追加写入指定文件，不再输出到终端；(默认 空，输出到标准错误)
+    -log-format json
+        日志格式；可选 text、json，json 每行一条记录，包含所在阶段；(默认 text)
+    -compare old.csv
+        对比上次结果；测速结束后列出前 [-p] 个旧结果中失效或劣化 (按 [-degrade] 判断) 的 IP、新进入的 IP 及延迟/速度变化，
+        最优 IP 变化时以退出码 4 退出；可与 [-o] 为同一文件；(默认 空，不对比)
+    -quiet
+        安静模式；供脚本调用，不显示进度与结果表，结束后只输出最优 IP (非 [-tp] 端口时为 IP:端口)，没有满足条件的 IP 时不输出，
+        不等待回车退出；出错时错误信息输出到标准错误；
+        退出码：0 找到满足条件的 IP，2 没有 IP 满足延迟/速度条件，3 配置或网络错误，4 最优 IP 变化 ([-compare])，130 被中断；(默认 禁用)
+    -tui
+        交互界面；测速时显示实时排序的结果表、各阶段进度与当前速度，可按 s 提前结束当前阶段、r 重新测速选中的 IP，
+        按 q / Esc / Ctrl-C 与中断信号相同，写入已完成的部分结果，再次按下立即退出；不支持 [-daemon]；(默认 禁用)
+
+    -dd
+        禁用下载测速；禁用后测速结果会按延迟排序 (默认按下载速度排序)；(默认 启用)
+    -score "speed*0.6 - latency_ms*0.3 - loss*100"
+        评分排序；按公式计算每个结果的分数，从高到低排序，替代默认排序；也可使用预设 latency-first、speed-first、balanced；
+        可用指标：speed、upload (MB/s)，latency_ms、min_latency_ms、max_latency_ms、jitter_ms (ms)，loss (丢包率 0~1)，
+        connect_ms、tls_ms、ttfb_ms ([-timing])、h2_latency_ms ([-h2-latency])，未测量的结果排在最后；(默认 空)
+    -upload-test
+        启用上传测速；下载测速后对结果 IP 逐个上传测速，单个 IP 最长时间同 [-dt]；(默认 禁用)
+    -upload-url https://speed.cloudflare.com/__up
+        上传测速地址；接收 POST 请求的地址，可使用自建 Workers；
+    -upload-size 10
+        上传数据量；单个 IP 上传测速的数据量，单位 MB；(默认 10 MB)
+    -all4
+        测速全部的 IPv4；(IPv4 默认每 64 个随机测速 1 个 IP)
+    -more6
+        测试更多 IPv6；(表示 -v6 18，即每个 CIDR 测速 2^18 即 262144 个)
+    -lots6
+        测试较多 IPv6；(表示 -v6 16，即每个 CIDR 测速 2^16 即 65536 个)
+    -many6
+        测试很多 IPv6；(表示 -v6 12，即每个 CIDR 测速 2^12 即 4096 个)
+    -some6
+        测试一些 IPv6；(表示 -v6 10，即每个 CIDR 测 2^10 即 1024 个)
+    -many4
+        测试一点 IPv4；(表示 -v4 12，即每个 CIDR 测速 2^12 即 4096 个)
+
+    -v4
+        指定 IPv4 测试数量 (指定二的指数，如 -v4 8 表示测试 2^8=256 个 IP)
+    -v6
+        指定 IPv6 测试数量 (指定二的指数，如 -v6 12 表示测试 2^12=4096 个 IP)
+    -ipv6-sample-per-64 16
+        按 /64 子网抽样；IPv6 网段先均匀抽取 /64 子网，再在每个 /64 内抽取指定数量的地址，
+        此时 [-v6]/[-more6] 等指定的是抽取的 /64 子网数量；(默认 0 不按子网抽样)
+    -seed 42
+        随机种子；指定后相同输入的抽样结果与下载测速的选取顺序可复现，便于对比配置改动；(默认 随机)
+
+    -dns-zone 023e105f4ecef8ad9ca31a8372d0c353
+        Cloudflare 区域 ID；与 [-dns-records] 同时指定时，测速完成后把最快的 IP 写入 DNS 记录；(默认 空)
+    -dns-token xxxxxx
+        Cloudflare API 令牌；需要 DNS 编辑权限，未指定时读取环境变量 CF_API_TOKEN；
+    -dns-records cdn.example.com,img.example.com
+        要更新的记录名；英文逗号分隔，按 IP 类型写入 A/AAAA 记录；(默认 空)
+    -dns-top 1
+        写入记录的 IP 数量；每个记录名写入结果中最快的前 N 个 IP；(默认 1 个)
+    -dns-dry-run
+        DNS 试运行；只打印将要进行的修改，不实际调用 API 修改记录；
+    -output-hosts /etc/hosts.d/cf.conf
+        写入 hosts 文件；测速完成后把最快的 IP 按 "IP 域名" 格式写入，整个文件会被替换 (先写临时文件再重命名)；(默认 空)
+    -output-dnsmasq /etc/dnsmasq.d/cf.conf
+        写入 dnsmasq 配置；格式为 address=/域名/IP，写入方式同 [-output-hosts]；(默认 空)
+    -hosts-domains cdn.example.com,assets.example.com
+        写入的域名；[-output-hosts]、[-output-dnsmasq] 使用的域名，英文逗号分隔；(默认 空)
+    -hosts-top 1
+        写入的 IP 数量；每个域名写入结果中最快的前 N 个 IP；(默认 1 个)
+    -aggregate subnets.csv
+        合并网段；把通过的 IP (满足延迟、丢包、抖动条件，下载测速过的还需满足 [-sl]) 合并为尽量少的网段，
+        按通过数排序写入 CSV，列出每个网段的通过数/测试数、通过率、平均延迟与速度，适合配置防火墙或路由；(默认 空)
+    -aggregate-ratio 0.8
+        合并通过率；网段内已测试的 IP 中通过的比例不低于该值时合并为一个网段，否则继续拆分；(默认 0.8)
+
+    -daemon
+        持续监控模式；按 [-interval] 间隔循环测速，仅在最优 IP 劣化时重写结果文件并更新 DNS；
+    -interval 30m
+        监控间隔；支持 s/m/h 单位；(默认 30m)
+    -degrade 0.2
+        劣化阈值；最优 IP 本轮延迟高于或速度低于其历史平均值超过该比例时视为劣化；(默认 0.2)
+    -metrics 0.0.0.0:9090
+        指标服务；监控模式下在该地址提供 Prometheus 格式的 /metrics，按数据中心输出最优延迟与速度等指标；(默认 空，不启用)
+    -serve 127.0.0.1:8080
+        接口服务；启动后不立即测速，由 POST /scan 触发 (请求体为参数名到值的 JSON 对象，如 {"dn":5,"httping":true}，
+        未指定的参数沿用启动时的配置)，GET /status 查看当前阶段与进度，GET /results 获取最近一次结果；
+        请求体只能指定测速参数，输出文件、数据库、通知、DNS 更新等参数及无效的参数值返回 400；
+        同一时间只进行一次测速；(默认 空，不启用)
+
+    -db results.sqlite
+        历史数据库；每轮测速结果 (含时间戳) 追加写入 SQLite 数据库，可用 history 子命令查询；(默认 空，不记录)
+
+    -notify-webhook https://example.com/hook
+        通用 Webhook；测速完成后 POST JSON 摘要 (text、best_changed、previous_best、results)；(默认 空)
+    -tg-token 123456:ABC -tg-chat 10000
+        Telegram 通知；机器人令牌与会话 ID 同时指定时推送结果摘要；(默认 空)
+    -discord-webhook https://discord.com/api/webhooks/...
+        Discord 通知；(默认 空)
+    -notify-top 3
+        通知 IP 数量；摘要中列出最快的前 N 个 IP，并与上一轮 (或 [-db] 中最近一轮) 的最优 IP 对比；(默认 3 个)
+    -notify-on-change
+        仅在最优 IP 变化时通知；(默认 每轮都通知)
+
+    -config cfst.toml
+        配置文件；支持 TOML / YAML，键名与 Config 字段一致，时长可写作 "10s"、"200ms"；
+        优先级：命令行参数 > 环境变量 (CFST_参数名，如 CFST_DN=5) > 配置文件 > 默认值；
+    -profile fast
+        配置方案；使用配置文件中 [profiles.fast] 覆盖基础配置；
+
+    -v
+        打印程序版本 + 检查版本更新
+    -h
+        打印帮助说明
+    -max-ips 500000
+        IP总量上限；当IP数量超过此值时会随机丢弃已有IP；(默认 500000)
+    -stop-after 5
+        提前结束延迟测速；满足延迟/丢包/抖动条件的 IP 达到指定数量后立即进入下载测速，不再测完全部 IP；
+        HTTPing 模式下不匹配 [-cfcolo] 的 IP 在延迟测速时即被丢弃，不计入该数量；(默认 0 测完全部)
+    -checkpoint scan.ckpt
+        保存检查点；延迟测速期间每 10 秒把扫描位置与已完成的结果写入文件，全部测速完成后自动删除；(默认 空，不保存)
+    -resume scan.ckpt
+        从检查点继续；读取检查点，跳过已测速的 IP 并恢复已有结果，之后继续保存到该文件；需使用相同的 IP 段与参数；
+    -adaptive
+        自适应并发；按超时率与本地资源压力 (AIMD) 动态增减并发数，适合低性能路由器；(默认 按任务卡顿比例调整)
+    -max-concurrency 1024
+        并发上限；延迟测速的最大并发数，两种调整方式均不超过该值；(默认 1024)
+    -rate-limit 500/s
+        探测速率上限；所有延迟测速任务共享的每秒发包 (连接/请求) 数上限，支持 /s、/m 单位，避免触发运营商或 Cloudflare 限制；(默认 不限制)
+    -download-cap 50mbps
+        下载带宽上限；所有下载测速连接共享的总带宽，支持 kbps、mbps、gbps (比特) 与 KB/s、MB/s (字节)，不带单位为 MB/s，
+        避免测速占满共享的上行/下行带宽；达到上限的结果记为 "≥ 测得速度"；(默认 不限制)
+    -interface eth1
+        绑定网卡；延迟测速与耗时测量的连接从指定网卡发出 (Linux 使用 SO_BINDTODEVICE，macOS、Windows 按网卡序号绑定)，用于多线路路由器；
+        Windows 上可使用网卡名称 (如 以太网、WLAN) 或适配器 GUID；
+        下载、上传测速与 HTTPing 使用该网卡的地址作为源地址；(默认 空，由系统路由决定)
+    -proxy socks5://127.0.0.1:1080
+        代理；HTTPing 与下载、上传测速经 SOCKS5 或 HTTP CONNECT 代理连接，隧道目标为测速 IP 而非域名，TCP 延迟测速不经过代理；
+        支持 socks5://[用户名:密码@]主机:端口、http://[用户名:密码@]主机:端口；(默认 空，直连)
+"#;
+
+// 无值标志参数
+const FLAG_ARGS: &[&str] = &[
+    "v", "h", "httping", "timing", "h2-latency", "cf-trace", "dd", "upload-test", "dns-dry-run", "daemon", "notify-on-change", "adaptive", "cf-official", "insecure", "warp", "tui", "stream-output", "english-header", "dual-stack", "quiet", "trace", "pipeline", "syn",
+    "all4", "more6", "lots6", "many6", "some6", "many4",
+];
+
+// POST /scan 可以指定的参数：只影响本次测速的候选 IP、测速方式与筛选条件，
+// 不含输出文件、数据库、通知、DNS 更新等写入本机或外部服务的参数，也不含读取本机文件的参数
+const SCAN_ARGS: &[&str] = &[
+    "t", "dn", "dt", "tp", "ports", "url", "httping", "httping-code", "httping-timeout", "allowed-status", "cfcolo", "cdn-provider",
+    "tl", "tll", "tlr", "max-jitter", "max-loss", "sl", "p", "dd", "score", "per-colo", "stop-after",
+    "ip", "v4", "v6", "all4", "many4", "many6", "more6", "lots6", "some6", "ipv6-sample-per-64", "max-ips", "cf-official", "dual-stack",
+    "seed", "exclude", "asn", "exclude-asn", "ip-country",
+    "timing", "h2-latency", "cf-trace", "upload-test", "upload-url", "upload-size",
+    "download-budget", "download-cap", "download-connections", "download-timeout", "dt-converge", "dt-min", "connect-timeout",
+    "max-concurrency", "adaptive", "rate-limit", "pipeline", "syn", "soak", "soak-interval", "soak-n", "trace", "trace-max-ttl", "trace-n",
+    "warp", "warp-key", "warp-peer", "warp-reserved", "worker", "sni", "host-header", "header", "cookie", "resolve", "insecure",
+    "expect-body-contains", "expect-body-sha256", "interface", "proxy",
+];
+
+// 环境变量前缀，如 CFST_DN=5 等同于 -dn 5，CFST_HTTPING=1 等同于 -httping
+const ENV_PREFIX: &str = "CFST_";
+
+// 退出码：0 为找到满足条件的 IP，最优 IP 变化 ([-compare]) 见 compare::EXIT_BEST_CHANGED，被中断见 cancel::EXIT_INTERRUPTED
+const EXIT_NO_RESULTS: i32 = 2; // 没有满足延迟/速度条件的 IP
+const EXIT_ERROR: i32 = 3;      // 配置错误或测速过程出错
+
+// 新增参数解析结构体
+struct Args {
+    command: Option<String>,  // 子命令，如 history
+    operands: Vec<String>,    // 子命令之后的非参数项，如 retest 的结果文件
+    args: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn new() -> Self {
+        Self { command: None, operands: Vec::new(), args: Vec::new() }
+    }
+
+    fn parse(args: Vec<String>) -> Self {
+        let mut parsed = Self::new();
+        let mut i = 1;  // 跳过程序名
+        
+        while i < args.len() {
+            let arg = &args[i];
+            
+            // 确保是参数标志，第一个非参数项视为子命令
+            if !arg.starts_with('-') {
+                if i == 1 {
+                    parsed.command = Some(arg.clone());
+                } else if parsed.command.is_some() {
+                    parsed.operands.push(arg.clone());
+                }
+                i += 1;
+                continue;
+            }
+
+            let name = arg.trim_start_matches('-').to_string();
+            
+            // 检查是否是无值标志参数
+            if FLAG_ARGS.contains(&name.as_str()) {
+                parsed.args.push((name, None));
+                i += 1;
+                continue;
+            }
+            
+            // 处理带值的参数
+            if i + 1 < args.len() && !args[i + 1].starts_with('-') {
+                parsed.args.push((name, Some(args[i + 1].clone())));
+                i += 2;  // 跳过参数名和值
+            } else {
+                i += 1;
+            }
+        }
+
+        parsed
+    }
+
+    fn from_env() -> Self {
+        let mut parsed = Self::new();
+        for (key, value) in std::env::vars() {
+            let name = match key.strip_prefix(ENV_PREFIX) {
+                Some(name) if !name.is_empty() => name.to_lowercase().replace('_', "-"),
+                _ => continue,
+            };
+            if FLAG_ARGS.contains(&name.as_str()) {
+                if matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on") {
+                    parsed.args.push((name, None));
+                }
+            } else {
+                parsed.args.push((name, Some(value)));
+            }
+        }
+        parsed
+    }
+
+    // [-serve] 的 POST /scan 请求体：true 为指定无值参数，false 为不指定，其余值视为参数值
+    // 只接受 SCAN_ARGS 中的测速参数，输出、通知、DNS 等写入本机或外部服务的参数不能通过接口修改
+    fn from_json(map: &serde_json::Map<String, serde_json::Value>) -> Result<Self, String> {
+        let mut parsed = Self::new();
+        for (name, value) in map {
+            let name = name.trim_start_matches('-').to_string();
+            if !SCAN_ARGS.contains(&name.as_str()) {
+                return Err(format!("参数 {} 不能通过接口指定", name));
+            }
+            let value = match value {
+                serde_json::Value::Bool(false) | serde_json::Value::Null => continue,
+                serde_json::Value::Bool(true) if FLAG_ARGS.contains(&name.as_str()) => None,
+                serde_json::Value::String(s) => Some(s.clone()),
+                _ if FLAG_ARGS.contains(&name.as_str()) => return Err(format!("参数 {} 不需要值，请使用 true", name)),
+                other => Some(other.to_string()),
+            };
+            parsed.args.push((name, value));
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.args.iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    // 可重复指定的参数的全部值
+    fn get_all(&self, name: &str) -> Vec<&str> {
+        self.args.iter()
+            .filter(|(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_deref())
+            .collect()
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.args.iter().any(|(n, _)| n == name)
+    }
+}
+
+fn main() -> Result<()> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            let args = Args::parse(std::env::args().collect());
+
+            // 处理无值参数
+            if args.has("h") {
+                println!("{}", HELP_TEXT);
+                wait_for_input();
+                return Ok(());
+            }
+
+            if args.has("v") {
+                print_version();
+                println!("检查版本更新中...");
+                if let Some(new_version) = version::check_update().await {
+                    println!("*** 发现新版本 [{}]！请前往 [https://github.com/GuangYu-yu/CloudflareST-Rust] 更新！ ***", new_version);
+                } else {
+                    println!("当前为最新版本 [{}]！", VERSION);
+                }
+                wait_for_input();
+                return Ok(());
+            }
+
+            // 创建配置：默认值 < 配置文件 < 环境变量 < 命令行参数
+            let env_args = Args::from_env();
+            let config_path = args.get("config").or(env_args.get("config"));
+            let profile = args.get("profile").or(env_args.get("profile"));
+            let mut config = match config_path {
+                Some(path) => match config_file::load_config(path, profile) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        // 配置文件读取失败时也按命令行的 [-quiet] 处理
+                        if args.has("quiet") || env_args.has("quiet") {
+                            quiet::start().ok();
+                        }
+                        fail(&format!("读取配置文件失败：{:#}", e))
+                    }
+                },
+                None => Config::default(),
+            };
+            let errors = [apply_args(&mut config, &env_args), apply_args(&mut config, &args)].concat();
+            if !errors.is_empty() {
+                // 参数无效时不再测速，[-quiet] 下同样以退出码 3 结束
+                if args.has("quiet") || env_args.has("quiet") {
+                    quiet::start().ok();
+                }
+                fail(&errors.join("；"))
+            }
+            if let Err(e) = debug::init(&config) {
+                println!("[错误] {}", e);
+            }
+            // [-quiet] 只用于单次测速，监控与接口模式照常输出
+            if config.quiet && !config.daemon && config.serve_addr.is_empty() && args.command.as_deref() != Some("history") {
+                config.tui = false;
+                if let Err(e) = quiet::start() {
+                    println!("[错误] {}", e);
+                }
+            }
+
+            if args.command.as_deref() == Some("retest") {
+                let Some(path) = args.operands.first() else {
+                    fail("请指定要重新测速的结果文件，如 retest result.csv");
+                };
+                match compare::prepare_retest(&mut config, path, args.get("o").is_some()) {
+                    Ok(count) => println!("[信息] 重新测速 {} 中的 {} 个 IP", path, count),
+                    Err(e) => fail(&format!("读取结果文件失败：{:#}", e)),
+                }
+            } else if let Some(command) = args.command.as_deref() {
+                run_command(command, &config, &args).await;
+                wait_for_input();
+                return Ok(());
+            }
+
+            println!("CloudflareST-Rust {}\n", VERSION);
+            
+            ip::init_rand_seed();
+            check_config(&config);
+            if let Err(e) = tls::validate(&config) {
+                fail(&e);
+            }
+            if let Err(e) = geoip::validate(&config) {
+                fail(&e);
+            }
+            if let Err(e) = output::validate(&config) {
+                fail(&e);
+            }
+            if let Err(e) = urls::validate(&config) {
+                fail(&e);
+            }
+            if let Err(e) = warp::validate(&config) {
+                fail(&e);
+            }
+            if let Err(e) = score::validate(&config) {
+                fail(&e);
+            }
+
+            // 执行测速
+            if config.daemon {
+                return daemon::run(config).await;
+            }
+            if !config.serve_addr.is_empty() {
+                let base = config.clone();
+                let builder: server::ConfigBuilder = Arc::new(move |overrides| {
+                    let mut config = base.clone();
+                    let errors = apply_args(&mut config, &Args::from_json(overrides)?);
+                    if !errors.is_empty() {
+                        return Err(errors.join("；"));
+                    }
+                    tls::validate(&config)?;
+                    geoip::validate(&config)?;
+                    output::validate(&config)?;
+                    urls::validate(&config)?;
+                    warp::validate(&config)?;
+                    score::validate(&config)?;
+                    Ok(config)
+                });
+                return server::run(&config, builder).await;
+            }
+
+            cancel::install();
+            let mut notifier = notify::Notifier::new(&config);
+            if config.tui {
+                if let Err(e) = tui::start(&config) {
+                    println!("[错误] 无法启动交互界面：{}", e);
+                }
+            }
+            let previous = compare::load_previous(&config);
+            let speed_data = scan::run_pipeline(&mut config).await;
+            tui::finish(speed_data.as_deref().unwrap_or_default()).await;
+            let mut speed_data = speed_data.unwrap_or_else(|e| fail(&format!("{:#}", e)));
+            scan::record_history(&config, &speed_data);
+            if let Err(e) = scan::publish_results(&config, &mut speed_data).await {
+                fail(&format!("{:#}", e));
+            }
+            summary::report(&config, &speed_data);
+            notifier.notify(&config, &speed_data).await;
+            let best_changed = previous.is_some_and(|previous| compare::report(&config, &previous, &speed_data));
+
+            // 满足延迟与 [-sl] 条件的最优 IP
+            let best = speed_data.iter().find(|d| d.meets_speed_filter(&config));
+            quiet::finish();
+            if let Some(best) = best.filter(|_| config.quiet) {
+                match best.ping_data.port {
+                    port if port == config.tcp_port => println!("{}", best.ping_data.ip),
+                    port => println!("{}", std::net::SocketAddr::new(best.ping_data.ip, port)),
+                }
+            }
+
+            // 被中断时不再等待按键，以 130 退出
+            if cancel::requested() {
+                std::process::exit(cancel::EXIT_INTERRUPTED);
+            }
+            wait_for_input();
+            if best.is_none() {
+                std::process::exit(EXIT_NO_RESULTS);
+            }
+            if best_changed {
+                std::process::exit(compare::EXIT_BEST_CHANGED);
+            }
+            Ok(())
+        })
+}
+
+// 执行子命令
+async fn run_command(command: &str, config: &Config, args: &Args) {
+    match command {
+        "history" => {
+            if config.db.is_empty() {
+                println!("[错误] 请使用 [-db] 指定历史数据库");
+                return;
+            }
+            let days = args.get("days").and_then(|v| v.parse().ok()).unwrap_or(7);
+            if let Err(e) = history::print_history(&config.db, days, config.print_num.max(1)) {
+                println!("[错误] 查询历史记录失败：{:#}", e);
+            }
+        }
+        "gen-worker" => {
+            if let Err(e) = worker::generate(config, args.operands.first().map(String::as_str)).await {
+                println!("[错误] 生成 Worker 失败：{:#}", e);
+            }
+        }
+        _ => println!("[错误] 未知子命令：{}，使用 -h 查看帮助", command),
+    }
+}
+
+// 将参数应用到配置，未指定的参数保持原值；返回无效参数的错误信息，
+// 命令行中的无效参数只提示并忽略，POST /scan 中的则拒绝请求
+fn apply_args(config: &mut Config, args: &Args) -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Some(v) = args.get("t") {
+        config.ping_times = v.parse().unwrap_or(4);
+    }
+    if let Some(v) = args.get("dn") {
+        config.test_count = v.parse().unwrap_or(10);
+    }
+    if args.has("syn") {
+        config.syn = true;
+    }
+    if args.has("pipeline") {
+        config.pipeline = true;
+    }
+    if let Some(v) = args.get("dt") {
+        config.download_time = Duration::from_secs(v.parse().unwrap_or(10));
+    }
+    if let Some(v) = args.get("dt-converge") {
+        match v.parse::<f64>() {
+            Ok(threshold) if threshold >= 0.0 => config.download_converge = threshold,
+            _ => errors.push(format!("无效的 [-dt-converge]：{}，示例：0.05", v)),
+        }
+    }
+    if let Some(v) = args.get("dt-min") {
+        match parse_duration(v) {
+            Some(d) => config.download_min_time = d,
+            None => errors.push(format!("无效的时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("download-budget") {
+        match parse_size(v) {
+            Some(size) => config.download_budget = size as u64,
+            None => errors.push(format!("无效的大小：{}，示例：100MB", v)),
+        }
+    }
+    if let Some(v) = args.get("soak") {
+        match parse_duration(v) {
+            Some(d) => config.soak = d,
+            None => errors.push(format!("无效的时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("soak-interval") {
+        match parse_duration(v) {
+            Some(d) if !d.is_zero() => config.soak_interval = d,
+            _ => errors.push(format!("无效的时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("soak-n") {
+        match v.parse() {
+            Ok(n) if n > 0 => config.soak_count = n,
+            _ => errors.push(format!("无效的 [-soak-n]：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("connect-timeout") {
+        match parse_duration(v) {
+            Some(d) if !d.is_zero() => config.connect_timeout = d,
+            _ => errors.push(format!("无效的超时时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("httping-timeout") {
+        match parse_duration(v) {
+            Some(d) if !d.is_zero() => config.httping_timeout = d,
+            _ => errors.push(format!("无效的超时时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("download-timeout") {
+        match parse_duration(v) {
+            Some(d) if !d.is_zero() => config.download_timeout = d,
+            _ => errors.push(format!("无效的超时时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("download-connections") {
+        config.download_connections = v.parse().unwrap_or(1);
+    }
+    if let Some(v) = args.get("tp") {
+        config.tcp_port = v.parse().unwrap_or(443);
+    }
+    if let Some(v) = args.get("ports") {
+        if ip::parse_ports(v).is_some() {
+            config.ports = v.to_string();
+        } else {
+            errors.push(format!("无效的端口列表：{}", v));
+        }
+    }
+    if let Some(v) = args.get("url") {
+        config.url = v.to_string();
+    }
+    if let Some(v) = args.get("sni") {
+        config.sni = v.to_string();
+    }
+    if let Some(v) = args.get("host-header") {
+        match hyper::header::HeaderValue::from_str(v) {
+            Ok(_) => config.host_header = v.to_string(),
+            Err(_) => errors.push(format!("无效的 Host 请求头：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("ca-cert") {
+        config.ca_cert = v.to_string();
+    }
+    if let Some(v) = args.get("client-cert") {
+        config.client_cert = v.to_string();
+    }
+    if let Some(v) = args.get("client-key") {
+        config.client_key = v.to_string();
+    }
+    if args.has("insecure") {
+        config.insecure = true;
+    }
+    let headers = args.get_all("header");
+    if !headers.is_empty() {
+        config.headers.clear();
+        for header in headers {
+            if parse_header(header).is_some() {
+                config.headers.push(header.to_string());
+            } else {
+                errors.push(format!("无效的请求头：{}，格式应为 \"Name: value\"", header));
+            }
+        }
+    }
+    let resolve = args.get_all("resolve");
+    if !resolve.is_empty() {
+        config.resolve.clear();
+        for entry in resolve {
+            if urls::parse_resolve(entry).is_some() {
+                config.resolve.push(entry.to_string());
+            } else {
+                errors.push(format!("无效的解析：{}，格式应为 \"域名:端口:IP\"", entry));
+            }
+        }
+    }
+    if let Some(v) = args.get("cookie") {
+        config.cookie = v.to_string();
+    }
+    if args.has("httping") {
+        config.httping = true;
+    }
+    if args.has("warp") {
+        config.warp = true;
+    }
+    if let Some(v) = args.get("warp-key") {
+        config.warp_private_key = v.to_string();
+    }
+    if let Some(v) = args.get("warp-peer") {
+        config.warp_public_key = v.to_string();
+    }
+    if let Some(v) = args.get("warp-reserved") {
+        config.warp_reserved = v.to_string();
+    }
+    if let Some(v) = args.get("httping-code") {
+        config.httping_status_code = v.parse().unwrap_or(200);
+    }
+    if let Some(v) = args.get("allowed-status") {
+        if StatusSet::parse(v).is_some() {
+            config.allowed_status = v.to_string();
+        } else {
+            errors.push(format!("无效的状态码列表：{}", v));
+        }
+    }
+    if let Some(v) = args.get("expect-body-sha256") {
+        let hash = v.trim().to_lowercase();
+        if hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            config.expect_body_sha256 = hash;
+        } else {
+            errors.push(format!("无效的 SHA-256：{}，应为 64 位十六进制", v));
+        }
+    }
+    if let Some(v) = args.get("expect-body-contains") {
+        config.expect_body_contains = v.to_string();
+    }
+    if let Some(v) = args.get("cfcolo") {
+        config.httping_cf_colo = v.to_string();
+    }
+    if let Some(v) = args.get("cdn-provider") {
+        if Provider::parse(v).is_some() {
+            config.cdn_provider = v.to_string();
+        } else {
+            errors.push(format!("未知的 CDN 类型：{}，可选 auto,{}", v, cdn::names()));
+        }
+    }
+    if args.has("timing") {
+        config.timing = true;
+    }
+    if args.has("h2-latency") {
+        config.h2_latency = true;
+    }
+    if args.has("trace") {
+        config.trace = true;
+    }
+    if let Some(v) = args.get("trace-n") {
+        match v.parse() {
+            Ok(n) if n > 0 => config.trace_count = n,
+            _ => errors.push(format!("无效的 [-trace-n]：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("trace-max-ttl") {
+        match v.parse() {
+            Ok(ttl) if ttl > 0 => config.trace_max_ttl = ttl,
+            _ => errors.push(format!("无效的 [-trace-max-ttl]：{}，范围 1-255", v)),
+        }
+    }
+    if args.has("cf-trace") {
+        config.cf_trace = true;
+    }
+    if let Some(v) = args.get("tl") {
+        config.max_delay = Duration::from_millis(v.parse().unwrap_or(9999));
+    }
+    if let Some(v) = args.get("tll") {
+        config.min_delay = Duration::from_millis(v.parse().unwrap_or(0));
+    }
+    if let Some(v) = args.get("tlr") {
+        config.max_loss_rate = v.parse().unwrap_or(1.0);
+    }
+    if let Some(v) = args.get("max-loss") {
+        config.max_loss_rate = v.parse().unwrap_or(1.0);
+    }
+    if let Some(v) = args.get("max-jitter") {
+        config.max_jitter = Duration::from_millis(v.parse().unwrap_or(9999));
+    }
+    if let Some(v) = args.get("sl") {
+        config.min_speed = v.parse().unwrap_or(0.0);
+    }
+    if let Some(v) = args.get("p") {
+        config.print_num = v.parse().unwrap_or(10);
+    }
+    if let Some(v) = args.get("per-colo") {
+        config.per_colo = v.parse().unwrap_or(0);
+    }
+    if let Some(v) = args.get("f") {
+        config.ip_file = v.to_string();
+    }
+    if let Some(v) = args.get("f6") {
+        config.ip_file_v6 = v.to_string();
+    }
+    if args.has("dual-stack") {
+        config.dual_stack = true;
+    }
+    if let Some(v) = args.get("ip") {
+        config.ip_text = v.to_string();
+    }
+    if args.has("cf-official") {
+        config.cf_official = true;
+    }
+    if let Some(v) = args.get("exclude") {
+        config.exclude = v.to_string();
+    }
+    if let Some(v) = args.get("exclude-file") {
+        config.exclude_file = v.to_string();
+    }
+    if let Some(v) = args.get("auto-exclude") {
+        config.auto_exclude = v.parse().unwrap_or(0);
+    }
+    if let Some(v) = args.get("geoip-db") {
+        config.geoip_db = v.to_string();
+    }
+    if let Some(v) = args.get("ip-country") {
+        config.ip_country = v.to_string();
+    }
+    if let Some(v) = args.get("asn") {
+        match geoip::parse_asns(v) {
+            Ok(_) => config.asn = v.to_string(),
+            Err(e) => errors.push(format!("[-asn] {}", e)),
+        }
+    }
+    if let Some(v) = args.get("exclude-asn") {
+        match geoip::parse_asns(v) {
+            Ok(_) => config.exclude_asn = v.to_string(),
+            Err(e) => errors.push(format!("[-exclude-asn] {}", e)),
+        }
+    }
+    if let Some(v) = args.get("o") {
+        config.output = v.to_string();
+    }
+    if let Some(v) = args.get("summary") {
+        config.summary_file = v.to_string();
+    }
+    if let Some(v) = args.get("log-level") {
+        let level = v.trim().to_lowercase();
+        if debug::LEVELS.contains(&level.as_str()) {
+            config.log_level = level;
+        } else {
+            errors.push(format!("未知的日志级别：{}，可选 {}", v, debug::LEVELS.join(",")));
+        }
+    }
+    if let Some(v) = args.get("log-file") {
+        config.log_file = v.to_string();
+    }
+    if let Some(v) = args.get("log-format") {
+        match v.parse() {
+            Ok(format) => config.log_format = format,
+            Err(e) => errors.push(e),
+        }
+    }
+    if let Some(v) = args.get("debug-failures") {
+        config.debug_failures = v.to_string();
+    }
+    if let Some(v) = args.get("compare") {
+        config.compare = v.to_string();
+    }
+    if args.has("tui") {
+        config.tui = true;
+    }
+    if args.has("stream-output") {
+        config.stream_output = true;
+    }
+    if let Some(v) = args.get("columns") {
+        match cloudflarest::csv::parse_columns(v) {
+            Ok(columns) if !columns.is_empty() => config.columns = v.to_string(),
+            Ok(_) => {}
+            Err(e) => errors.push(format!("[-columns] {}，可选 {}", e, cloudflarest::csv::column_keys())),
+        }
+    }
+    if args.has("english-header") {
+        config.english_header = true;
+    }
+    if let Some(v) = args.get("output-format") {
+        match v.parse() {
+            Ok(format) => config.output_format = format,
+            Err(e) => errors.push(format!("{}，可选 csv、json、ndjson", e)),
+        }
+    }
+    let outputs = args.get_all("output");
+    if !outputs.is_empty() {
+        config.outputs = outputs.into_iter().map(String::from).collect();
+    }
+    if args.has("dd") {
+        config.disable_download = true;
+    }
+    if let Some(v) = args.get("score") {
+        match score::Score::parse(v) {
+            Ok(_) => config.score = v.to_string(),
+            Err(e) => errors.push(format!("无效的评分公式：{}，{}", v, e)),
+        }
+    }
+    if args.has("upload-test") {
+        config.upload_test = true;
+    }
+    if let Some(v) = args.get("upload-url") {
+        config.upload_url = v.to_string();
+    }
+    if let Some(v) = args.get("worker") {
+        if !v.starts_with("https://") && !v.starts_with("http://") {
+            errors.push(format!("无效的 [-worker]：{}，示例：https://cfst-speed.example.workers.dev", v));
+        } else {
+            if args.get("url").is_none() {
+                config.url = worker::download_url(v);
+            }
+            if args.get("upload-url").is_none() {
+                config.upload_url = worker::upload_url(v);
+            }
+        }
+    }
+    if let Some(v) = args.get("worker-account") {
+        config.worker_account = v.to_string();
+    }
+    if let Some(v) = args.get("worker-name") {
+        config.worker_name = v.to_string();
+    }
+    if let Some(v) = args.get("upload-size") {
+        config.upload_size = v.parse::<u64>().unwrap_or(10) * 1024 * 1024;
+    }
+    if args.has("all4") {
+        config.test_all = true;
+    }
+    if args.has("many4") {
+        config.ipv4_num_mode = Some("many".to_string());
+    }
+    if args.has("more6") {
+        config.ipv6_num_mode = Some("more".to_string());
+    }
+    if args.has("lots6") {
+        config.ipv6_num_mode = Some("lots".to_string());
+    }
+    if args.has("many6") {
+        config.ipv6_num_mode = Some("many".to_string());
+    }
+    if args.has("some6") {
+        config.ipv6_num_mode = Some("some".to_string());
+    }
+    if let Some(v) = args.get("v4") {
+        config.ipv4_amount = Some(parse_test_amount(v, true));
+    }
+    if let Some(v) = args.get("v6") {
+        config.ipv6_amount = Some(parse_test_amount(v, false));
+    }
+    if let Some(v) = args.get("ipv6-sample-per-64") {
+        config.ipv6_sample_per_64 = v.parse().unwrap_or(0);
+    }
+    if let Some(v) = args.get("seed") {
+        config.seed = v.parse().ok();
+        if config.seed.is_none() {
+            errors.push(format!("无效的随机种子：{}，应为非负整数", v));
+        }
+    }
+    if let Some(v) = args.get("max-ips") {
+        config.max_ip_count = v.parse().unwrap_or(500_000);
+    }
+    if args.has("adaptive") {
+        config.adaptive_concurrency = true;
+    }
+    if let Some(v) = args.get("checkpoint") {
+        config.checkpoint = v.to_string();
+    }
+    if let Some(v) = args.get("resume") {
+        config.checkpoint = v.to_string();
+        config.resume = true;
+    }
+    if let Some(v) = args.get("stop-after") {
+        config.stop_after = v.parse().unwrap_or(0);
+    }
+    if let Some(v) = args.get("max-concurrency") {
+        config.max_concurrency = v.parse().unwrap_or(1024);
+    }
+    if let Some(v) = args.get("rate-limit") {
+        match parse_rate(v) {
+            Some(rate) => config.rate_limit = rate,
+            None => errors.push(format!("无效的速率：{}，示例：500/s、30000/m", v)),
+        }
+    }
+    if let Some(v) = args.get("download-cap") {
+        match parse_bandwidth(v) {
+            Some(cap) => config.download_cap = cap,
+            None => errors.push(format!("无效的带宽：{}，示例：50mbps、10MB/s", v)),
+        }
+    }
+    if let Some(v) = args.get("interface") {
+        match interface::check(v) {
+            Ok(()) => config.interface = v.to_string(),
+            Err(e) => errors.push(e),
+        }
+    }
+    if let Some(v) = args.get("proxy") {
+        match proxy::Proxy::parse(v) {
+            Ok(_) => config.proxy = v.to_string(),
+            Err(e) => errors.push(e),
+        }
+    }
+    if let Some(v) = args.get("dns-zone") {
+        config.dns_zone_id = v.to_string();
+    }
+    if let Some(v) = args.get("dns-token") {
+        config.dns_api_token = v.to_string();
+    }
+    if let Some(v) = args.get("dns-records") {
+        config.dns_records = v.to_string();
+    }
+    if let Some(v) = args.get("dns-top") {
+        config.dns_top_n = v.parse().unwrap_or(1);
+    }
+    if args.has("dns-dry-run") {
+        config.dns_dry_run = true;
+    }
+    if let Some(v) = args.get("output-hosts") {
+        config.output_hosts = v.to_string();
+    }
+    if let Some(v) = args.get("output-dnsmasq") {
+        config.output_dnsmasq = v.to_string();
+    }
+    if let Some(v) = args.get("hosts-domains") {
+        config.hosts_domains = v.to_string();
+    }
+    if let Some(v) = args.get("hosts-top") {
+        config.hosts_top_n = v.parse().unwrap_or(1);
+    }
+    if let Some(v) = args.get("aggregate") {
+        config.aggregate = v.to_string();
+    }
+    if let Some(v) = args.get("aggregate-ratio") {
+        match v.parse::<f64>() {
+            Ok(ratio) if ratio > 0.0 && ratio <= 1.0 => config.aggregate_ratio = ratio,
+            _ => errors.push(format!("无效的 [-aggregate-ratio]：{}，范围 (0, 1]", v)),
+        }
+    }
+    if args.has("quiet") {
+        config.quiet = true;
+    }
+    if args.has("daemon") {
+        config.daemon = true;
+    }
+    if let Some(v) = args.get("interval") {
+        match parse_duration(v) {
+            Some(d) if !d.is_zero() => config.daemon_interval = d,
+            _ => errors.push(format!("无效的时长：{}", v)),
+        }
+    }
+    if let Some(v) = args.get("degrade") {
+        match v.parse::<f64>() {
+            Ok(t) if t >= 0.0 => config.degrade_threshold = t,
+            _ => errors.push(format!("无效的 [-degrade]：{}，示例：0.2", v)),
+        }
+    }
+    if let Some(v) = args.get("metrics") {
+        config.metrics_addr = v.to_string();
+    }
+    if let Some(v) = args.get("serve") {
+        config.serve_addr = v.to_string();
+    }
+    if let Some(v) = args.get("db") {
+        config.db = v.to_string();
+    }
+    if let Some(v) = args.get("notify-webhook") {
+        config.notify_webhook = v.to_string();
+    }
+    if let Some(v) = args.get("tg-token") {
+        config.telegram_token = v.to_string();
+    }
+    if let Some(v) = args.get("tg-chat") {
+        config.telegram_chat_id = v.to_string();
+    }
+    if let Some(v) = args.get("discord-webhook") {
+        config.discord_webhook = v.to_string();
+    }
+    if let Some(v) = args.get("notify-top") {
+        config.notify_top_n = v.parse().unwrap_or(3);
+    }
+    if args.has("notify-on-change") {
+        config.notify_on_change = true;
+    }
+    errors
+}
+
+fn check_config(config: &Config) {
+    if config.min_speed > 0.0 && config.max_delay == Duration::from_millis(9999) {
+        println!("[提示] 使用下载速度下限时，建议同时设置延迟上限，避免测速时间过长");
+    }
+}
+
+fn print_version() {
+    println!("{} v{}", NAME, VERSION);
+    
+    #[cfg(debug_assertions)]
+    println!("Debug Build");
+    
+    #[cfg(not(debug_assertions))]
+    println!("Release Build");
+}
+
+// 输出错误并以 EXIT_ERROR 退出；[-quiet] 时错误输出到标准错误
+fn fail(message: &str) -> ! {
+    quiet::finish();
+    if quiet::active() {
+        eprintln!("[错误] {}", message);
+    } else {
+        println!("[错误] {}", message);
+    }
+    wait_for_input();
+    std::process::exit(EXIT_ERROR);
+}
+
+fn wait_for_input() {
+    if quiet::active() {
+        return;
+    }
+    println!("\n按回车键退出...");
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).unwrap_or_default();
+}
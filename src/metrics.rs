@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use lazy_static::lazy_static;
+use crate::types::DownloadSpeedSet;
+
+// 单个数据中心的指标
+#[derive(Default)]
+struct ColoStats {
+    best_latency_ms: f64,
+    best_speed_mbps: f64,
+    ips: usize,
+}
+
+#[derive(Default)]
+struct State {
+    colos: BTreeMap<String, ColoStats>,
+    best: Option<(String, String)>, // (IP, 数据中心)
+    last_run: u64,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+static IPS_TESTED: AtomicU64 = AtomicU64::new(0);
+static ROUNDS: AtomicU64 = AtomicU64::new(0);
+
+// 累计延迟测速的 IP 数量
+pub fn add_tested(n: usize) {
+    IPS_TESTED.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+// 用本轮结果更新指标，没有结果时保留上一轮的值
+pub fn update(speed_data: &DownloadSpeedSet) {
+    ROUNDS.fetch_add(1, Ordering::Relaxed);
+    if speed_data.is_empty() {
+        return;
+    }
+
+    let mut colos: BTreeMap<String, ColoStats> = BTreeMap::new();
+    for ip_data in speed_data {
+        let colo = if ip_data.colo.is_empty() { "unknown".to_string() } else { ip_data.colo.clone() };
+        let latency = ip_data.ping_data.delay.as_secs_f64() * 1000.0;
+        let speed = ip_data.download_speed * 8.0 / 1_000_000.0;
+        let stats = colos.entry(colo).or_insert(ColoStats {
+            best_latency_ms: f64::MAX,
+            ..Default::default()
+        });
+        stats.best_latency_ms = stats.best_latency_ms.min(latency);
+        stats.best_speed_mbps = stats.best_speed_mbps.max(speed);
+        stats.ips += 1;
+    }
+
+    let mut state = STATE.lock().unwrap();
+    state.colos = colos;
+    state.best = speed_data.first().map(|d| (d.ping_data.ip.to_string(), d.colo.clone()));
+    state.last_run = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// 生成 Prometheus 文本格式
+fn render() -> String {
+    let state = STATE.lock().unwrap();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# HELP cfst_best_latency_ms Lowest average latency among result IPs, by colo.");
+    let _ = writeln!(out, "# TYPE cfst_best_latency_ms gauge");
+    for (colo, stats) in &state.colos {
+        let _ = writeln!(out, "cfst_best_latency_ms{{colo=\"{}\"}} {:.3}", escape(colo), stats.best_latency_ms);
+    }
+
+    let _ = writeln!(out, "# HELP cfst_best_speed_mbps Highest download speed among result IPs in megabits per second, by colo.");
+    let _ = writeln!(out, "# TYPE cfst_best_speed_mbps gauge");
+    for (colo, stats) in &state.colos {
+        let _ = writeln!(out, "cfst_best_speed_mbps{{colo=\"{}\"}} {:.3}", escape(colo), stats.best_speed_mbps);
+    }
+
+    let _ = writeln!(out, "# HELP cfst_result_ips Number of result IPs, by colo.");
+    let _ = writeln!(out, "# TYPE cfst_result_ips gauge");
+    for (colo, stats) in &state.colos {
+        let _ = writeln!(out, "cfst_result_ips{{colo=\"{}\"}} {}", escape(colo), stats.ips);
+    }
+
+    let _ = writeln!(out, "# HELP cfst_best_ip_info Current best IP.");
+    let _ = writeln!(out, "# TYPE cfst_best_ip_info gauge");
+    if let Some((ip, colo)) = &state.best {
+        let _ = writeln!(out, "cfst_best_ip_info{{ip=\"{}\",colo=\"{}\"}} 1", escape(ip), escape(colo));
+    }
+
+    let _ = writeln!(out, "# HELP cfst_ips_tested_total Total number of IPs latency-tested.");
+    let _ = writeln!(out, "# TYPE cfst_ips_tested_total counter");
+    let _ = writeln!(out, "cfst_ips_tested_total {}", IPS_TESTED.load(Ordering::Relaxed));
+
+    let _ = writeln!(out, "# HELP cfst_rounds_total Total number of completed test rounds.");
+    let _ = writeln!(out, "# TYPE cfst_rounds_total counter");
+    let _ = writeln!(out, "cfst_rounds_total {}", ROUNDS.load(Ordering::Relaxed));
+
+    let _ = writeln!(out, "# HELP cfst_last_run_timestamp_seconds Unix time of the last round with results.");
+    let _ = writeln!(out, "# TYPE cfst_last_run_timestamp_seconds gauge");
+    let _ = writeln!(out, "cfst_last_run_timestamp_seconds {}", state.last_run);
+
+    out
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(render())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+// 在后台启动 /metrics 服务
+pub fn serve(addr: &str) -> Result<()> {
+    let addr: SocketAddr = addr.parse().with_context(|| format!("无效的监听地址 {}", addr))?;
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("监听 {} 失败", addr))?
+        .serve(make_svc);
+
+    println!("[监控] 指标地址：http://{}/metrics", addr);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            println!("[错误] 指标服务异常退出：{}", e);
+        }
+    });
+    Ok(())
+}
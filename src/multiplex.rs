@@ -0,0 +1,137 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::timeout;
+use hyper::{Body, Method, Request, Uri};
+use hyper::client::conn;
+use crate::types::{CloudflareIPData, Config, USER_AGENT};
+use crate::progress::Bar;
+use crate::{interface, ratelimit, tls};
+use crate::debug_log;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const MULTIPLEX_CONCURRENCY: usize = 64;
+
+struct Target {
+    uri: Uri, // 以 Host 作为 :authority
+    sni: String,
+    https: bool,
+}
+
+impl Target {
+    fn parse(config: &Config) -> Option<Self> {
+        let url = reqwest::Url::parse(&config.request_url()).ok()?;
+        let sni = url.host_str()?.to_string();
+        let authority = match (config.host_header(), url.port()) {
+            (Some(host), _) => host.to_string(),
+            (None, Some(port)) => format!("{}:{}", sni, port),
+            (None, None) => sni.clone(),
+        };
+        let path = match url.query() {
+            Some(q) => format!("{}?{}", url.path(), q),
+            None => url.path().to_string(),
+        };
+        let uri = format!("{}://{}{}", url.scheme(), authority, path).parse().ok()?;
+        Some(Self {
+            uri,
+            sni,
+            https: url.scheme() == "https",
+        })
+    }
+}
+
+// 在已建立的 HTTP/2 连接上依次发送请求，返回每个请求从发出到收到响应头的平均耗时
+// 第一个请求会等待 SETTINGS 交换，只用于预热，不计入结果
+async fn round_trips<S>(io: S, config: &Config, target: &Target) -> Option<Duration>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = conn::Builder::new().http2_only(true).handshake::<_, Body>(io).await.ok()?;
+    let connection = tokio::spawn(connection);
+
+    let rounds = config.ping_times.max(1);
+    let mut total = Duration::ZERO;
+    let mut measured = 0;
+    for round in 0..=rounds {
+        let mut request = Request::builder()
+            .method(Method::HEAD)
+            .uri(target.uri.clone())
+            .header(hyper::header::USER_AGENT, USER_AGENT)
+            .body(Body::empty())
+            .ok()?;
+        config.apply_headers(request.headers_mut());
+
+        ratelimit::acquire().await;
+        let start = Instant::now();
+        let sent = async {
+            futures::future::poll_fn(|cx| sender.poll_ready(cx)).await.ok()?;
+            sender.send_request(request).await.ok()
+        };
+        let Some(response) = timeout(PROBE_TIMEOUT, sent).await.ok().flatten() else { break };
+        if round > 0 {
+            total += start.elapsed();
+            measured += 1;
+        }
+        drop(response);
+    }
+    connection.abort();
+
+    (measured > 0).then(|| total / measured)
+}
+
+// 直连指定 IP 并协商 HTTP/2；HTTPS 通过 ALPN，HTTP 使用明文 HTTP/2 (h2c)
+async fn measure(ip: IpAddr, port: u16, config: &Config, target: &Target, tls: &tokio_native_tls::TlsConnector) -> Option<Duration> {
+    let addr = SocketAddr::new(ip, port);
+    ratelimit::acquire().await;
+    let stream = timeout(PROBE_TIMEOUT.max(config.connect_timeout), interface::connect(addr, config)).await.ok()?.ok()?;
+    // 小帧较多，关闭 Nagle 算法以免请求被延迟发送
+    stream.set_nodelay(true).ok()?;
+
+    let result = if target.https {
+        let stream = timeout(PROBE_TIMEOUT, tls.connect(&target.sni, stream)).await.ok()?.ok()?;
+        if stream.get_ref().negotiated_alpn().ok().flatten().as_deref() != Some(b"h2") {
+            debug_log!("未协商 HTTP/2: {}", ip);
+            return None;
+        }
+        round_trips(stream, config, target).await
+    } else {
+        round_trips(stream, config, target).await
+    };
+    if result.is_none() {
+        debug_log!("HTTP/2 复用延迟测量失败: {}", ip);
+    }
+    result
+}
+
+// 对延迟测速结果测量 HTTP/2 复用延迟，反映长连接建立后的稳定表现
+pub async fn measure_multiplex(config: &Config, data: &mut [CloudflareIPData]) {
+    if !config.h2_latency || data.is_empty() {
+        return;
+    }
+    let target = match Target::parse(config) {
+        Some(t) => t,
+        None => return,
+    };
+    let tls = match tls::h2_connector(config) {
+        Ok(c) => tokio_native_tls::TlsConnector::from(c),
+        Err(_) => return,
+    };
+
+    println!("开始 HTTP/2 复用延迟测量（数量：{}）", data.len());
+    let bar = Bar::new(data.len() as u64, "", "").phase("复用延迟");
+
+    futures::stream::iter(data.iter_mut())
+        .for_each_concurrent(MULTIPLEX_CONCURRENCY, |ip_data| {
+            let target = &target;
+            let tls = &tls;
+            let bar = &bar;
+            async move {
+                ip_data.h2_latency = measure(ip_data.ping_data.ip, ip_data.ping_data.port, config, target, tls).await;
+                bar.grow(1, "");
+            }
+        })
+        .await;
+
+    bar.done();
+}
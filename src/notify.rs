@@ -0,0 +1,162 @@
+use std::fmt::Write as _;
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde_json::json;
+use crate::history::{self, RunEntry};
+use crate::types::{Config, DownloadSpeedSet};
+use crate::urls;
+use crate::debug_log;
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(15);
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+// 测速完成后推送结果摘要，保存上一轮结果用于对比
+pub struct Notifier {
+    previous: Vec<RunEntry>,
+}
+
+impl Notifier {
+    // 指定 [-db] 时以数据库中最近一轮结果作为对比基准
+    pub fn new(config: &Config) -> Self {
+        let previous = if is_enabled(config) && !config.db.is_empty() {
+            history::last_run(&config.db, config.notify_top_n.max(1)).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        Self { previous }
+    }
+
+    // 按配置发送通知，出错时只打印不中断测速
+    pub async fn notify(&mut self, config: &Config, speed_data: &DownloadSpeedSet) {
+        if !is_enabled(config) || speed_data.is_empty() {
+            return;
+        }
+
+        let current: Vec<RunEntry> = speed_data
+            .iter()
+            .take(config.notify_top_n.max(1) as usize)
+            .map(|d| RunEntry {
+                ip: d.ping_data.ip.to_string(),
+                colo: d.colo.clone(),
+                latency_ms: d.ping_data.delay.as_secs_f64() * 1000.0,
+                download_mb: d.download_speed / 1024.0 / 1024.0,
+            })
+            .collect();
+
+        let best_changed = self.previous.first().map(|p| &p.ip) != current.first().map(|c| &c.ip);
+        if config.notify_on_change && !best_changed {
+            debug_log!("最优 IP 未变化，跳过通知");
+            self.previous = current;
+            return;
+        }
+
+        let text = summary(&current, self.previous.first(), best_changed);
+        if let Err(e) = send_all(config, &text, &current, self.previous.first(), best_changed).await {
+            println!("\n[错误] 发送通知失败：{:#}", e);
+        }
+        self.previous = current;
+    }
+}
+
+fn is_enabled(config: &Config) -> bool {
+    !config.notify_webhook.is_empty()
+        || !config.discord_webhook.is_empty()
+        || (!config.telegram_token.is_empty() && !config.telegram_chat_id.is_empty())
+}
+
+// 生成纯文本摘要
+fn summary(current: &[RunEntry], previous: Option<&RunEntry>, best_changed: bool) -> String {
+    let mut text = String::from("CloudflareST-Rust 测速完成\n");
+    let best = &current[0];
+    match previous {
+        Some(prev) if best_changed => {
+            let _ = writeln!(text, "最优 IP 由 {} 变为 {}", prev.ip, best.ip);
+        }
+        Some(prev) => {
+            let _ = writeln!(
+                text,
+                "最优 IP {} 未变化（速度 {:+.2} MB/s，延迟 {:+.2} ms）",
+                best.ip,
+                best.download_mb - prev.download_mb,
+                best.latency_ms - prev.latency_ms
+            );
+        }
+        None => {}
+    }
+    for (i, entry) in current.iter().enumerate() {
+        let _ = writeln!(
+            text,
+            "{}. {}  {:.2} MB/s  {:.2} ms  {}",
+            i + 1,
+            entry.ip,
+            entry.download_mb,
+            entry.latency_ms,
+            if entry.colo.is_empty() { "-" } else { &entry.colo }
+        );
+    }
+    text
+}
+
+async fn send_all(
+    config: &Config,
+    text: &str,
+    current: &[RunEntry],
+    previous: Option<&RunEntry>,
+    best_changed: bool,
+) -> Result<()> {
+    let client = urls::apply_resolve(Client::builder(), config)
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .context("创建 HTTP 客户端失败")?;
+    let mut sends = Vec::new();
+
+    if !config.notify_webhook.is_empty() {
+        let results: Vec<_> = current
+            .iter()
+            .map(|e| json!({
+                "ip": e.ip,
+                "colo": e.colo,
+                "latency_ms": e.latency_ms,
+                "download_speed_mb": e.download_mb,
+            }))
+            .collect();
+        let body = json!({
+            "text": text,
+            "best_changed": best_changed,
+            "previous_best": previous.map(|p| p.ip.clone()),
+            "results": results,
+        });
+        sends.push(("Webhook", post(&client, &config.notify_webhook, &body).await));
+    }
+
+    if !config.telegram_token.is_empty() && !config.telegram_chat_id.is_empty() {
+        let url = format!("{}/bot{}/sendMessage", TELEGRAM_API, config.telegram_token);
+        let body = json!({ "chat_id": config.telegram_chat_id, "text": text });
+        sends.push(("Telegram", post(&client, &url, &body).await));
+    }
+
+    if !config.discord_webhook.is_empty() {
+        let body = json!({ "content": text });
+        sends.push(("Discord", post(&client, &config.discord_webhook, &body).await));
+    }
+
+    // 某个渠道失败时其余渠道照常发送，结束后汇总全部失败的渠道
+    let errors: Vec<String> = sends
+        .into_iter()
+        .filter_map(|(channel, result)| result.err().map(|e| format!("{}：{:#}", channel, e)))
+        .collect();
+    if !errors.is_empty() {
+        bail!("{}", errors.join("；"));
+    }
+    Ok(())
+}
+
+async fn post(client: &Client, url: &str, body: &serde_json::Value) -> Result<()> {
+    let resp = client.post(url).json(body).send().await.context("请求失败")?;
+    let status = resp.status();
+    if !status.is_success() {
+        bail!("返回 HTTP {}", status);
+    }
+    Ok(())
+}
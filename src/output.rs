@@ -0,0 +1,231 @@
+use std::str::FromStr;
+use anyhow::Result;
+use crate::csv::{self, PrintResult};
+use crate::types::{Config, DownloadSpeedSet, OutputFormat};
+use crate::{history, hosts};
+
+// 一种结果输出目标；测速结束后 [-output] 指定的各目标依次写入
+pub trait OutputSink {
+    // 用于错误信息，如 "CSV 文件 result.csv"
+    fn describe(&self) -> String;
+    fn write(&self, config: &Config, data: &DownloadSpeedSet) -> Result<()>;
+}
+
+// [-output] 的一项，格式为 "类型=文件"，table 不需要文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputSpec {
+    File(OutputFormat, String),
+    Sqlite(String),
+    Table,
+    Hosts(String),
+    Dnsmasq(String),
+}
+
+impl FromStr for OutputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, path) = match s.split_once('=') {
+            Some((kind, path)) => (kind.trim().to_lowercase(), path.trim().to_string()),
+            None => (s.trim().to_lowercase(), String::new()),
+        };
+        if kind == "table" {
+            return match path.is_empty() {
+                true => Ok(OutputSpec::Table),
+                false => Err(format!("table 输出到终端，不需要指定文件：{}", s)),
+            };
+        }
+        if path.is_empty() {
+            return Err(format!("请指定 {} 的文件，如 {}=result.{}", kind, kind, kind));
+        }
+        match kind.as_str() {
+            "csv" => Ok(OutputSpec::File(OutputFormat::Csv, path)),
+            "json" => Ok(OutputSpec::File(OutputFormat::Json, path)),
+            "ndjson" | "jsonl" => Ok(OutputSpec::File(OutputFormat::Ndjson, path)),
+            "sqlite" | "db" => Ok(OutputSpec::Sqlite(path)),
+            "hosts" => Ok(OutputSpec::Hosts(path)),
+            "dnsmasq" => Ok(OutputSpec::Dnsmasq(path)),
+            _ => Err(format!("未知的输出类型：{}，可选 csv、json、ndjson、sqlite、table、hosts、dnsmasq", kind)),
+        }
+    }
+}
+
+impl OutputSpec {
+    pub fn sink(&self) -> Box<dyn OutputSink> {
+        match self {
+            OutputSpec::File(format, path) => Box::new(FileSink { format: *format, path: path.clone() }),
+            OutputSpec::Sqlite(path) => Box::new(SqliteSink { path: path.clone() }),
+            OutputSpec::Table => Box::new(TableSink),
+            OutputSpec::Hosts(path) => Box::new(HostsSink { path: path.clone(), dnsmasq: false }),
+            OutputSpec::Dnsmasq(path) => Box::new(HostsSink { path: path.clone(), dnsmasq: true }),
+        }
+    }
+}
+
+// 检查 [-output]
+pub fn validate(config: &Config) -> Result<(), String> {
+    for spec in &config.outputs {
+        spec.parse::<OutputSpec>()?;
+    }
+    Ok(())
+}
+
+// 本轮的全部输出目标：未指定 [-output] 时为 [-o] / [-output-format] 的文件与终端表格，
+// [-output-hosts] / [-output-dnsmasq] 始终写入
+pub fn specs(config: &Config) -> Vec<OutputSpec> {
+    let mut specs: Vec<OutputSpec> = if config.outputs.is_empty() {
+        let mut specs = Vec::new();
+        if !config.output.is_empty() {
+            specs.push(OutputSpec::File(config.output_format, config.output.clone()));
+        }
+        specs.push(OutputSpec::Table);
+        specs
+    } else {
+        config.outputs.iter().filter_map(|s| s.parse().ok()).collect()
+    };
+    for spec in [
+        (!config.output_hosts.is_empty()).then(|| OutputSpec::Hosts(config.output_hosts.clone())),
+        (!config.output_dnsmasq.is_empty()).then(|| OutputSpec::Dnsmasq(config.output_dnsmasq.clone())),
+    ].into_iter().flatten() {
+        if !specs.contains(&spec) {
+            specs.push(spec);
+        }
+    }
+    specs
+}
+
+// 结果文件 (csv / json / ndjson) 的路径，用于表格之后的提示
+pub fn result_files(config: &Config) -> Vec<String> {
+    specs(config)
+        .into_iter()
+        .filter_map(|spec| match spec {
+            OutputSpec::File(_, path) => Some(path),
+            _ => None,
+        })
+        .collect()
+}
+
+// [-stream-output] 边测速边写入的文件：第一个结果文件
+pub fn stream_target(config: &Config) -> Option<(OutputFormat, String)> {
+    specs(config).into_iter().find_map(|spec| match spec {
+        OutputSpec::File(format, path) => Some((format, path)),
+        _ => None,
+    })
+}
+
+// 依次写入全部输出目标，某个目标失败时其余照常写入；只有主结果文件（第一个结果文件）写入失败时返回错误，
+// hosts、数据库等其他目标的错误只打印，不影响之后的 DNS 更新与网段汇总
+pub fn write_all(config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+    let specs = specs(config);
+    let primary = specs.iter().position(|spec| matches!(spec, OutputSpec::File(..)));
+    let mut primary_error = None;
+    for (index, sink) in specs.iter().map(OutputSpec::sink).enumerate() {
+        if let Err(e) = sink.write(config, data) {
+            let e = e.context(format!("写入 {} 失败", sink.describe()));
+            if Some(index) == primary {
+                primary_error = Some(e);
+            } else {
+                println!("\n[错误] {:#}", e);
+            }
+        }
+    }
+    primary_error.map_or(Ok(()), Err)
+}
+
+struct FileSink {
+    format: OutputFormat,
+    path: String,
+}
+
+impl OutputSink for FileSink {
+    fn describe(&self) -> String {
+        let kind = match self.format {
+            OutputFormat::Csv => "CSV",
+            OutputFormat::Json => "JSON",
+            OutputFormat::Ndjson => "NDJSON",
+        };
+        format!("{} 文件 {}", kind, self.path)
+    }
+
+    fn write(&self, config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+        csv::export_file(data, config, &self.path, self.format)
+    }
+}
+
+// 与 [-db] 相同的历史数据库
+struct SqliteSink {
+    path: String,
+}
+
+impl OutputSink for SqliteSink {
+    fn describe(&self) -> String {
+        format!("历史数据库 {}", self.path)
+    }
+
+    fn write(&self, _config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+        history::save_run(&self.path, data)?;
+        if !data.is_empty() {
+            println!("已写入历史数据库：{}", self.path);
+        }
+        Ok(())
+    }
+}
+
+// 终端表格，显示 [-p] 个结果
+struct TableSink;
+
+impl OutputSink for TableSink {
+    fn describe(&self) -> String {
+        "结果表格".to_string()
+    }
+
+    fn write(&self, _config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+        data.print();
+        Ok(())
+    }
+}
+
+struct HostsSink {
+    path: String,
+    dnsmasq: bool,
+}
+
+impl OutputSink for HostsSink {
+    fn describe(&self) -> String {
+        format!("{} 文件 {}", if self.dnsmasq { "dnsmasq" } else { "hosts" }, self.path)
+    }
+
+    fn write(&self, config: &Config, data: &DownloadSpeedSet) -> Result<()> {
+        hosts::write_file(config, data, &self.path, self.dnsmasq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<OutputSpec, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_kinds() {
+        assert_eq!(parse("csv=result.csv"), Ok(OutputSpec::File(OutputFormat::Csv, "result.csv".to_string())));
+        assert_eq!(parse("JSON = out.json"), Ok(OutputSpec::File(OutputFormat::Json, "out.json".to_string())));
+        assert_eq!(parse("jsonl=out.jsonl"), Ok(OutputSpec::File(OutputFormat::Ndjson, "out.jsonl".to_string())));
+        assert_eq!(parse("db=history.db"), Ok(OutputSpec::Sqlite("history.db".to_string())));
+        assert_eq!(parse("hosts=/etc/hosts"), Ok(OutputSpec::Hosts("/etc/hosts".to_string())));
+        assert_eq!(parse("dnsmasq=cf.conf"), Ok(OutputSpec::Dnsmasq("cf.conf".to_string())));
+        assert_eq!(parse("table"), Ok(OutputSpec::Table));
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        // table 输出到终端，不接受文件
+        assert!(parse("table=out.txt").is_err());
+        // 其他类型必须指定文件
+        assert!(parse("csv").is_err());
+        assert!(parse("json=").is_err());
+        assert!(parse("xml=out.xml").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use crate::types::{CloudflareIPData, Config};
+
+// [-pipeline] 时延迟测速把通过的 IP 送入下载测速，未开启时为 None
+static FEED: Mutex<Option<UnboundedSender<CloudflareIPData>>> = Mutex::new(None);
+
+// 按数据中心选取、双栈分别选取需要完整的延迟测速结果，WARP 没有下载测速
+pub fn supported(config: &Config) -> bool {
+    !config.disable_download && config.per_colo == 0 && !config.dual_stack && !config.warp
+}
+
+// 开始接收延迟测速结果，返回交给下载测速的接收端
+pub fn open() -> UnboundedReceiver<CloudflareIPData> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    *FEED.lock().unwrap() = Some(sender);
+    receiver
+}
+
+// 延迟测速中满足延迟、丢包率、抖动条件的 IP
+pub fn offer(ip_data: &CloudflareIPData) {
+    if let Some(sender) = FEED.lock().unwrap().as_ref() {
+        // 下载测速已测够时接收端关闭，忽略发送失败
+        let _ = sender.send(ip_data.clone());
+    }
+}
+
+// 延迟测速结束，下载测速取完剩余的 IP 后结束
+pub fn close() {
+    FEED.lock().unwrap().take();
+}
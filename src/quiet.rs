@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// 被替换前的标准输出：Unix 为文件描述符，Windows 为句柄
+#[cfg(unix)]
+type Saved = i32;
+#[cfg(windows)]
+type Saved = usize;
+#[cfg(not(any(unix, windows)))]
+type Saved = ();
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+// 被替换前的标准输出，恢复时使用
+static SAVED: Mutex<Option<Saved>> = Mutex::new(None);
+
+// 是否为 [-quiet] 模式，进度条据此隐藏，也不再等待回车退出
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// 进入 [-quiet] 模式：隐藏进度条，把标准输出重定向到 /dev/null (Windows 为 NUL)，直到 finish
+pub fn start() -> io::Result<()> {
+    ACTIVE.store(true, Ordering::Relaxed);
+    io::stdout().flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::IntoRawFd;
+        let null = std::fs::OpenOptions::new().write(true).open("/dev/null")?.into_raw_fd();
+        // SAFETY: 只操作本进程的文件描述符，失败时返回错误
+        unsafe {
+            let saved = libc::dup(libc::STDOUT_FILENO);
+            if saved < 0 || libc::dup2(null, libc::STDOUT_FILENO) < 0 {
+                let err = io::Error::last_os_error();
+                libc::close(null);
+                if saved >= 0 {
+                    libc::close(saved);
+                }
+                return Err(err);
+            }
+            libc::close(null);
+            *SAVED.lock().unwrap() = Some(saved);
+        }
+    }
+    // 标准库每次写入都会重新获取标准输出句柄，替换句柄即可丢弃之后的输出
+    #[cfg(windows)]
+    {
+        use std::os::windows::io::IntoRawHandle;
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::Console::{GetStdHandle, SetStdHandle, STD_OUTPUT_HANDLE};
+        let null = std::fs::OpenOptions::new().write(true).open("NUL")?.into_raw_handle();
+        // SAFETY: null 为刚打开的句柄，替换失败时关闭
+        unsafe {
+            let saved = GetStdHandle(STD_OUTPUT_HANDLE);
+            if SetStdHandle(STD_OUTPUT_HANDLE, null as _) == 0 {
+                let err = io::Error::last_os_error();
+                CloseHandle(null as _);
+                return Err(err);
+            }
+            *SAVED.lock().unwrap() = Some(saved as usize);
+        }
+    }
+    Ok(())
+}
+
+// 恢复标准输出，之后的输出 (最优 IP) 正常显示，可重复调用
+pub fn finish() {
+    let Some(saved) = SAVED.lock().unwrap().take() else {
+        return;
+    };
+    io::stdout().flush().ok();
+    #[cfg(unix)]
+    // SAFETY: saved 为 start 中复制的原标准输出
+    unsafe {
+        libc::dup2(saved, libc::STDOUT_FILENO);
+        libc::close(saved);
+    }
+    #[cfg(windows)]
+    // SAFETY: saved 为 start 中保存的原标准输出句柄，当前句柄为 start 打开的 NUL
+    unsafe {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::Console::{GetStdHandle, SetStdHandle, STD_OUTPUT_HANDLE};
+        let null = GetStdHandle(STD_OUTPUT_HANDLE);
+        if SetStdHandle(STD_OUTPUT_HANDLE, saved as _) != 0 {
+            CloseHandle(null);
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    let _ = saved;
+}
@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
+
+// 全局令牌桶：按预约时间片发放令牌，避免大量任务同时醒来争抢
+struct TokenBucket {
+    rate: Option<f64>,          // 每秒令牌数，None 为不限制
+    burst: Duration,            // 允许的突发量（以时间计）
+    next: Instant,              // 下一个令牌的可用时间
+}
+
+impl TokenBucket {
+    fn new() -> Self {
+        Self {
+            rate: None,
+            burst: Duration::ZERO,
+            next: Instant::now(),
+        }
+    }
+
+    // 设置每秒令牌数上限，0 为不限制；突发量为 0.1 秒的令牌数（至少 1 个）
+    fn set_rate(&mut self, rate: f64) {
+        if rate <= 0.0 || !rate.is_finite() {
+            self.rate = None;
+            return;
+        }
+        self.rate = Some(rate);
+        self.burst = Duration::from_secs_f64(((rate / 10.0).floor().max(1.0) - 1.0) / rate);
+        self.next = Instant::now();
+    }
+
+    // 预约 n 个令牌，返回可以继续的时间；不限制时返回 None
+    fn reserve(&mut self, n: u32) -> Option<Instant> {
+        let rate = self.rate?;
+        let now = Instant::now();
+        // 空闲期间积累的令牌不超过突发量
+        let earliest = now.checked_sub(self.burst).unwrap_or(now);
+        let slot = self.next.max(earliest);
+        self.next = slot + Duration::from_secs_f64(n as f64 / rate);
+        Some(slot)
+    }
+}
+
+lazy_static! {
+    static ref BUCKET: Mutex<TokenBucket> = Mutex::new(TokenBucket::new());
+    // 下载测速的总带宽，每个令牌为 1 字节
+    static ref BANDWIDTH: Mutex<TokenBucket> = Mutex::new(TokenBucket::new());
+}
+
+// 设置每秒探测次数上限，0 为不限制
+pub fn configure(rate: f64) {
+    BUCKET.lock().unwrap().set_rate(rate);
+}
+
+// 设置下载测速的总带宽上限 (字节/秒)，0 为不限制
+pub fn configure_bandwidth(bytes_per_sec: f64) {
+    BANDWIDTH.lock().unwrap().set_rate(bytes_per_sec);
+}
+
+// 等待到预约的时间，返回实际等待的时长
+async fn wait(bucket: &Mutex<TokenBucket>, n: u32) -> Duration {
+    let Some(wait_until) = bucket.lock().unwrap().reserve(n) else {
+        return Duration::ZERO;
+    };
+    let now = Instant::now();
+    if wait_until > now {
+        tokio::time::sleep_until(wait_until.into()).await;
+    }
+    wait_until.saturating_duration_since(now)
+}
+
+// 发送每个探测包前调用，超过速率上限时等待
+pub async fn acquire() {
+    wait(&BUCKET, 1).await;
+}
+
+// 下载测速每收到一段数据后调用，超过带宽上限时暂停读取，由 TCP 流量控制让服务端放慢发送
+// 返回因限速等待的时长
+pub async fn consume_bandwidth(bytes: usize) -> Duration {
+    wait(&BANDWIDTH, bytes.min(u32::MAX as usize) as u32).await
+}
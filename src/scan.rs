@@ -0,0 +1,679 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use anyhow::Result;
+use tracing::{info, info_span, Instrument};
+use crate::types::{Config, CloudflareIPData, DelayFilter, PingDelaySet, DownloadSpeedSet, TraceInfo, Timing, parse_test_amount};
+use crate::httping::{self, HttpPing};
+use crate::csv;
+use crate::threadpool::GLOBAL_POOL;
+use crate::checkpoint::{self, Checkpoint};
+use crate::geoip::GeoInfo;
+use crate::debug_log;
+use crate::{aggregate, cancel, dns_update, download, exclude, failure, geoip, history, metrics, multiplex, output, pipeline, ratelimit, score, soak, summary, tcping, timing, traceroute, upload};
+
+// [-per-colo] 每批查询数据中心的 IP 数
+const COLO_BATCH: usize = 128;
+
+// 测速流程依赖进程级的全局状态 (线程池、限速、统计、结果流与中断标记等)，同一时间只能进行一次
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct RunGuard;
+
+impl RunGuard {
+    fn acquire() -> Result<Self> {
+        if RUNNING.swap(true, Ordering::AcqRel) {
+            anyhow::bail!("已有测速正在进行，同一进程同一时间只能进行一次测速");
+        }
+        Ok(Self)
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::Release);
+    }
+}
+
+/// 单个 IP 的延迟测速结果
+#[derive(Debug, Clone)]
+pub struct PingResult {
+    pub ip: IpAddr,
+    pub sended: u32,
+    pub received: u32,
+    pub loss_rate: f32,
+    pub delay: Duration,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: Duration,
+}
+
+impl From<&CloudflareIPData> for PingResult {
+    fn from(data: &CloudflareIPData) -> Self {
+        Self {
+            ip: data.ping_data.ip,
+            sended: data.ping_data.sended,
+            received: data.ping_data.received,
+            loss_rate: data.loss_rate,
+            delay: data.ping_data.delay,
+            min_delay: data.ping_data.min_delay,
+            max_delay: data.ping_data.max_delay,
+            jitter: data.ping_data.jitter,
+        }
+    }
+}
+
+/// 单个 IP 的完整测速结果（延迟 + 下载速度）
+#[derive(Debug, Clone)]
+pub struct SpeedResult {
+    pub ping: PingResult,
+    pub download_speed: f64, // 下载速度（字节/秒）
+    pub download_capped: bool, // 下载测速受 [-download-cap] 限制，实际速度不低于记录值
+    pub upload_speed: f64,   // 上传速度（字节/秒）
+    pub colo: String,        // 数据中心
+    pub trace: TraceInfo,    // /cdn-cgi/trace 节点信息
+    pub timing: Timing,      // 分阶段耗时
+    pub h2_latency: Option<Duration>, // HTTP/2 复用延迟
+    pub geo: GeoInfo,        // IP 的国家、城市与 ASN
+}
+
+impl SpeedResult {
+    pub fn download_speed_mb(&self) -> f64 {
+        self.download_speed / 1024.0 / 1024.0
+    }
+
+    pub fn upload_speed_mb(&self) -> f64 {
+        self.upload_speed / 1024.0 / 1024.0
+    }
+}
+
+impl From<&CloudflareIPData> for SpeedResult {
+    fn from(data: &CloudflareIPData) -> Self {
+        Self {
+            ping: PingResult::from(data),
+            download_speed: data.download_speed,
+            download_capped: data.download_capped,
+            upload_speed: data.upload_speed,
+            colo: data.colo.clone(),
+            trace: data.trace.clone(),
+            timing: data.timing,
+            h2_latency: data.h2_latency,
+            geo: data.geo.clone(),
+        }
+    }
+}
+
+/// 测速流程构建器，参数与命令行一一对应，未设置的参数使用默认值
+///
+/// 测速使用进程级的全局状态，同一进程同一时间只能进行一次测速，
+/// 已有测速进行中时 [ping](Self::ping) 与 [run](Self::run) 返回错误
+///
+/// ```no_run
+/// # async fn demo() -> anyhow::Result<()> {
+/// let results = cloudflarest::ScanBuilder::new()
+///     .ip_text("104.16.0.0/24")
+///     .test_count(5)
+///     .run()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScanBuilder {
+    config: Config,
+}
+
+impl ScanBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn ping_times(mut self, times: u32) -> Self {
+        self.config.ping_times = times;
+        self
+    }
+
+    pub fn test_count(mut self, count: u32) -> Self {
+        self.config.test_count = count;
+        self
+    }
+
+    pub fn download_time(mut self, time: Duration) -> Self {
+        self.config.download_time = time;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.config.connect_timeout = timeout;
+        self
+    }
+
+    pub fn httping_timeout(mut self, timeout: Duration) -> Self {
+        self.config.httping_timeout = timeout;
+        self
+    }
+
+    pub fn download_timeout(mut self, timeout: Duration) -> Self {
+        self.config.download_timeout = timeout;
+        self
+    }
+
+    pub fn download_connections(mut self, connections: usize) -> Self {
+        self.config.download_connections = connections;
+        self
+    }
+
+    /// 速度稳定后提前结束下载测速，threshold 为样本相对标准差，min_time 为最短下载时间
+    pub fn download_converge(mut self, threshold: f64, min_time: Duration) -> Self {
+        self.config.download_converge = threshold;
+        self.config.download_min_time = min_time;
+        self
+    }
+
+    /// 单个 IP 的下载流量上限（字节），0 为不限制
+    pub fn download_budget(mut self, bytes: u64) -> Self {
+        self.config.download_budget = bytes;
+        self
+    }
+
+    pub fn tcp_port(mut self, port: u16) -> Self {
+        self.config.tcp_port = port;
+        self
+    }
+
+    /// 多端口测速的端口列表，如 "443,2053,8443"
+    pub fn ports(mut self, ports: &str) -> Self {
+        self.config.ports = ports.to_string();
+        self
+    }
+
+    /// WARP 模式，private_key 为空时随机生成
+    pub fn warp(mut self, enabled: bool, private_key: &str) -> Self {
+        self.config.warp = enabled;
+        self.config.warp_private_key = private_key.to_string();
+        self
+    }
+
+    /// 自定义根证书与 mTLS 客户端证书，key 为空时从证书文件读取私钥
+    pub fn tls_files(mut self, ca_cert: &str, client_cert: &str, client_key: &str) -> Self {
+        self.config.ca_cert = ca_cert.to_string();
+        self.config.client_cert = client_cert.to_string();
+        self.config.client_key = client_key.to_string();
+        self
+    }
+
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.config.insecure = insecure;
+        self
+    }
+
+    /// 追加自定义请求头，格式为 "Name: value"
+    pub fn header(mut self, header: &str) -> Self {
+        self.config.headers.push(header.to_string());
+        self
+    }
+
+    pub fn cookie(mut self, cookie: &str) -> Self {
+        self.config.cookie = cookie.to_string();
+        self
+    }
+
+    /// 满足条件的 IP 达到 n 个后提前结束延迟测速
+    pub fn stop_after(mut self, n: usize) -> Self {
+        self.config.stop_after = n;
+        self
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.config.url = url.to_string();
+        self
+    }
+
+    pub fn sni(mut self, sni: &str) -> Self {
+        self.config.sni = sni.to_string();
+        self
+    }
+
+    pub fn host_header(mut self, host: &str) -> Self {
+        self.config.host_header = host.to_string();
+        self
+    }
+
+    pub fn httping(mut self, enable: bool) -> Self {
+        self.config.httping = enable;
+        self
+    }
+
+    pub fn httping_status_code(mut self, code: u16) -> Self {
+        self.config.httping_status_code = code;
+        self
+    }
+
+    /// 有效状态码列表，如 "200,204,301-308"
+    pub fn allowed_status(mut self, allowed: &str) -> Self {
+        self.config.allowed_status = allowed.to_string();
+        self
+    }
+
+    pub fn cf_colo(mut self, colo: &str) -> Self {
+        self.config.httping_cf_colo = colo.to_string();
+        self
+    }
+
+    pub fn cf_trace(mut self, enable: bool) -> Self {
+        self.config.cf_trace = enable;
+        self
+    }
+
+    pub fn timing(mut self, enable: bool) -> Self {
+        self.config.timing = enable;
+        self
+    }
+
+    pub fn h2_latency(mut self, enable: bool) -> Self {
+        self.config.h2_latency = enable;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.config.max_delay = delay;
+        self
+    }
+
+    pub fn min_delay(mut self, delay: Duration) -> Self {
+        self.config.min_delay = delay;
+        self
+    }
+
+    pub fn max_loss_rate(mut self, rate: f32) -> Self {
+        self.config.max_loss_rate = rate;
+        self
+    }
+
+    pub fn max_jitter(mut self, jitter: Duration) -> Self {
+        self.config.max_jitter = jitter;
+        self
+    }
+
+    pub fn min_speed(mut self, speed: f64) -> Self {
+        self.config.min_speed = speed;
+        self
+    }
+
+    pub fn ip_file(mut self, path: &str) -> Self {
+        self.config.ip_file = path.to_string();
+        self
+    }
+
+    pub fn ip_text(mut self, text: &str) -> Self {
+        self.config.ip_text = text.to_string();
+        self
+    }
+
+    pub fn cf_official(mut self, enable: bool) -> Self {
+        self.config.cf_official = enable;
+        self
+    }
+
+    /// 同时测速 IPv4 与 IPv6，v6_file 为 [ip_file](Self::ip_file) 之外额外读取的 IPv6 段文件
+    pub fn dual_stack(mut self, v6_file: &str) -> Self {
+        self.config.dual_stack = true;
+        self.config.ip_file_v6 = v6_file.to_string();
+        self
+    }
+
+    pub fn exclude(mut self, exclude: &str) -> Self {
+        self.config.exclude = exclude.to_string();
+        self
+    }
+
+    pub fn exclude_file(mut self, path: &str) -> Self {
+        self.config.exclude_file = path.to_string();
+        self
+    }
+
+    pub fn disable_download(mut self, disable: bool) -> Self {
+        self.config.disable_download = disable;
+        self
+    }
+
+    pub fn upload_test(mut self, enable: bool) -> Self {
+        self.config.upload_test = enable;
+        self
+    }
+
+    pub fn upload_url(mut self, url: &str) -> Self {
+        self.config.upload_url = url.to_string();
+        self
+    }
+
+    pub fn upload_size(mut self, bytes: u64) -> Self {
+        self.config.upload_size = bytes;
+        self
+    }
+
+    pub fn test_all(mut self, enable: bool) -> Self {
+        self.config.test_all = enable;
+        self
+    }
+
+    pub fn ipv4_amount(mut self, amount: &str) -> Self {
+        self.config.ipv4_amount = Some(parse_test_amount(amount, true));
+        self
+    }
+
+    pub fn ipv6_amount(mut self, amount: &str) -> Self {
+        self.config.ipv6_amount = Some(parse_test_amount(amount, false));
+        self
+    }
+
+    pub fn ipv6_sample_per_64(mut self, per_64: u32) -> Self {
+        self.config.ipv6_sample_per_64 = per_64;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    pub fn max_ip_count(mut self, count: usize) -> Self {
+        self.config.max_ip_count = count;
+        self
+    }
+
+    pub fn per_colo(mut self, n: u32) -> Self {
+        self.config.per_colo = n;
+        self
+    }
+
+    pub fn adaptive_concurrency(mut self, enable: bool) -> Self {
+        self.config.adaptive_concurrency = enable;
+        self
+    }
+
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        self.config.max_concurrency = max;
+        self
+    }
+
+    pub fn rate_limit(mut self, per_second: f64) -> Self {
+        self.config.rate_limit = per_second;
+        self
+    }
+
+    /// 下载测速的总带宽上限（字节/秒），0 为不限制
+    pub fn download_cap(mut self, bytes_per_sec: f64) -> Self {
+        self.config.download_cap = bytes_per_sec;
+        self
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+
+    /// 仅执行延迟测速
+    pub async fn ping(&self) -> Result<Vec<PingResult>> {
+        let _guard = RunGuard::acquire()?;
+        let ping_data = ping_stage(&self.config).await?;
+        Ok(ping_data.iter().map(PingResult::from).collect())
+    }
+
+    /// 执行延迟测速 + 下载测速
+    pub async fn run(&self) -> Result<Vec<SpeedResult>> {
+        let mut config = self.config.clone();
+        let speed_data = run_pipeline(&mut config).await?;
+        Ok(speed_data.iter().map(SpeedResult::from).collect())
+    }
+}
+
+// 延迟测速，返回经过延迟、丢包率、抖动过滤的结果
+async fn ping_stage(config: &Config) -> Result<PingDelaySet> {
+    GLOBAL_POOL.configure(config.adaptive_concurrency, config.max_concurrency);
+    ratelimit::configure(config.rate_limit);
+
+    // [-resume] 时跳过检查点中已测速的候选
+    let (checkpoint, ips) = Checkpoint::start(config).instrument(info_span!("generate")).await?;
+    let candidates = ips.total();
+    info!(candidates, "候选 IP 生成完成");
+    metrics::add_tested(candidates);
+    let skipped = checkpoint.skipped();
+    let ping_data = async {
+        if config.httping {
+            // 使用 HTTP 测速
+            let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
+            Ok(http_ping.http_ping_all(config, ips, &checkpoint).await)
+        } else {
+            // 使用 TCP 测速
+            tcping::ping_with(config.clone(), ips, checkpoint).run().await
+        }
+    }
+    .instrument(info_span!("ping", mode = if config.httping { "httping" } else { "tcping" }))
+    .await?;
+
+    summary::record_ping(candidates + skipped, &ping_data);
+    aggregate::record_responded(&ping_data);
+    let responded = ping_data.len();
+    let ping_data = ping_data
+        .filter_delay(config)
+        .filter_loss_rate(config)
+        .filter_jitter(config);
+    summary::record_qualified(ping_data.len());
+    aggregate::record_qualified(&ping_data);
+    info!(responded, qualified = ping_data.len(), "延迟测速完成");
+    Ok(ping_data)
+}
+
+// 完整测速流程：延迟测速 -> 下载测速 -> 上传测速 -> 获取数据中心；已有测速进行中时返回错误
+pub async fn run_pipeline(config: &mut Config) -> Result<DownloadSpeedSet> {
+    let _guard = RunGuard::acquire()?;
+    summary::reset();
+    failure::start(config);
+    aggregate::start(config);
+    csv::start_stream(config);
+    let result = match run_stages(config).await {
+        Ok(mut speed_data) => {
+            score::rank(&mut speed_data, config);
+            soak::run(config, &mut speed_data).instrument(info_span!("soak")).await;
+            traceroute::run(config, &mut speed_data).instrument(info_span!("trace")).await;
+            Ok(speed_data)
+        }
+        Err(e) => Err(e),
+    };
+    csv::end_stream();
+    if result.is_ok() {
+        checkpoint::remove(config);
+    }
+    result
+}
+
+async fn run_stages(config: &mut Config) -> Result<DownloadSpeedSet> {
+    if config.pipeline && !pipeline::supported(config) && !config.disable_download {
+        println!("[信息] [-pipeline] 不适用于 [-per-colo]、[-dual-stack] 与 WARP，按顺序进行延迟测速与下载测速");
+    }
+    let mut speed_data = if config.pipeline && pipeline::supported(config) {
+        pipelined_stages(config).await?
+    } else {
+        let mut ping_data = ping_stage(config).await?;
+        // WARP 接入点只做握手测速，没有下载等后续阶段；延迟测速中被中断时直接使用已完成的结果
+        if config.warp || cancel::requested() {
+            return Ok(ping_data);
+        }
+        // 按数据中心选取时，需要先获取数据中心，每个数据中心只对延迟最低的 N 个 IP 下载测速
+        if config.per_colo > 0 {
+            ping_data = fill_colo_for_selection(ping_data, config).await;
+            ping_data = select_per_colo(ping_data, config.per_colo);
+            config.test_count = ping_data.len() as u32;
+        }
+
+        // 分阶段耗时与 HTTP/2 复用延迟只需测量下载测速的候选 IP
+        if config.timing || config.h2_latency {
+            ping_data = download_candidates(config, ping_data);
+        }
+        timing::measure_timing(config, &mut ping_data).instrument(info_span!("timing")).await;
+        multiplex::measure_multiplex(config, &mut ping_data).instrument(info_span!("multiplex")).await;
+
+        let queued = ping_data.len();
+        let speed_data = async {
+            if config.dual_stack {
+                download_per_family(config, ping_data).await
+            } else {
+                Ok(download::test_download_speed(config, ping_data).await?)
+            }
+        }
+        .instrument(info_span!("download"))
+        .await?;
+        info!(queued, results = speed_data.len(), "下载测速完成");
+        speed_data
+    };
+    exclude::update_blocklist(config);
+    // 被中断后跳过上传测速与数据中心查询等需要网络请求的阶段
+    if !cancel::requested() {
+        upload::test_upload_speed(config, &mut speed_data).instrument(info_span!("upload")).await;
+        httping::fill_colo(&mut speed_data, config).instrument(info_span!("colo")).await;
+    }
+    geoip::annotate(config, &mut speed_data);
+
+    if config.per_colo > 0 {
+        speed_data = select_per_colo(speed_data, config.per_colo);
+    }
+    Ok(speed_data)
+}
+
+// [-pipeline]：延迟测速与下载测速同时进行，延迟测速通过的 IP 经通道交给下载测速，
+// 连接时间分解与多路复用测试在下载测速之后只对下载测速的 IP 进行
+async fn pipelined_stages(config: &mut Config) -> Result<DownloadSpeedSet> {
+    let candidates = pipeline::open();
+    let mut download_config = config.clone();
+    let (ping_data, speed_data) = tokio::join!(
+        async {
+            let result = ping_stage(config).await;
+            pipeline::close();
+            result
+        },
+        download::test_download_pipelined(&mut download_config, candidates).instrument(info_span!("download")),
+    );
+    *config = download_config;
+    let ping_data = ping_data?;
+    let qualified = ping_data.len();
+    let mut speed_data = speed_data?;
+    info!(qualified, results = speed_data.len(), "下载测速完成");
+    if cancel::requested() {
+        // 下载测速开始前被中断时保留延迟测速结果
+        return Ok(if speed_data.is_empty() { ping_data } else { speed_data });
+    }
+
+    timing::measure_timing(config, &mut speed_data).instrument(info_span!("timing")).await;
+    multiplex::measure_multiplex(config, &mut speed_data).instrument(info_span!("multiplex")).await;
+    Ok(speed_data)
+}
+
+// 下载测速的候选 IP，[-dual-stack] 时 IPv4 与 IPv6 分别选取
+fn download_candidates(config: &Config, ping_data: PingDelaySet) -> PingDelaySet {
+    if !config.dual_stack {
+        return download::select_candidates(config, ping_data);
+    }
+    let (v4, v6): (PingDelaySet, PingDelaySet) = ping_data.into_iter().partition(|d| d.ping_data.ip.is_ipv4());
+    let mut candidates = download::select_candidates(config, v4);
+    candidates.extend(download::select_candidates(config, v6));
+    candidates
+}
+
+// [-dual-stack]：IPv4 与 IPv6 各自按延迟选取 [-dn] 个下载测速，避免延迟更低的一方占满名额，之后合并排序
+async fn download_per_family(config: &mut Config, ping_data: PingDelaySet) -> Result<DownloadSpeedSet> {
+    let (v4, v6): (PingDelaySet, PingDelaySet) = ping_data.into_iter().partition(|d| d.ping_data.ip.is_ipv4());
+    let mut speed_data = DownloadSpeedSet::new();
+    for (family, data) in [("IPv4", v4), ("IPv6", v6)] {
+        if data.is_empty() {
+            continue;
+        }
+        println!("{} ({} 个)：", family, data.len());
+        speed_data.extend(download::test_download_speed(config, data).await?);
+    }
+    speed_data.sort();
+    if !config.disable_download {
+        speed_data.sort_by(|a, b| b.download_speed.total_cmp(&a.download_speed));
+    }
+    Ok(speed_data)
+}
+
+// [-per-colo] 选取前获取数据中心：按延迟从低到高分批查询 (HTTPing 已得到的直接使用)，
+// 一批查询没有发现新的数据中心且已发现的数据中心都已有 n 个 IP 时停止，避免对全部延迟测速结果再请求一遍；
+// 未查询的 IP 不参与选取
+async fn fill_colo_for_selection(mut data: PingDelaySet, config: &Config) -> PingDelaySet {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    let mut filled = 0;
+    while filled < data.len() {
+        let end = (filled + COLO_BATCH).min(data.len());
+        httping::fill_colo(&mut data[filled..end], config).await;
+        let known = counts.len();
+        for ip_data in data[filled..end].iter().filter(|d| !d.colo.is_empty()) {
+            *counts.entry(ip_data.colo.clone()).or_insert(0) += 1;
+        }
+        filled = end;
+        if counts.len() == known && counts.values().all(|&count| count >= config.per_colo) {
+            break;
+        }
+    }
+    debug_log!("已查询 {} / {} 个 IP 的数据中心，发现 {} 个数据中心", filled, data.len(), counts.len());
+    data.truncate(filled);
+    data
+}
+
+// 保持原有顺序，每个数据中心只保留前 n 个 IP
+fn select_per_colo(data: PingDelaySet, n: u32) -> PingDelaySet {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    data.into_iter()
+        .filter(|ip_data| {
+            let count = counts.entry(ip_data.colo.clone()).or_insert(0);
+            *count += 1;
+            *count <= n
+        })
+        .collect()
+}
+
+// 把本轮结果追加到 [-db] 指定的历史数据库
+pub fn record_history(config: &Config, speed_data: &DownloadSpeedSet) {
+    if let Err(e) = history::save_run(&config.db, speed_data) {
+        println!("\n[错误] 写入历史数据库失败：{:#}", e);
+    }
+}
+
+// 输出测速结果：写入 [-output] 的各输出目标 (结果文件、表格、hosts 等) 并执行 DNS 更新
+pub async fn publish_results(config: &Config, speed_data: &mut DownloadSpeedSet) -> Result<()> {
+    info_span!("write").in_scope(|| output::write_all(config, speed_data))?;
+    info!(results = speed_data.len(), outputs = output::specs(config).len(), "结果已写入");
+
+    if let Err(e) = dns_update::update_dns(config, speed_data).instrument(info_span!("dns")).await {
+        println!("\n[错误] 更新 DNS 记录失败：{:#}", e);
+    }
+    if let Err(e) = aggregate::write(config, speed_data) {
+        println!("\n[错误] 写入网段文件失败：{:#}", e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rejects_concurrent_scan() {
+        let running = RunGuard::acquire().unwrap();
+        let err = ScanBuilder::new().ping().await.unwrap_err();
+        assert!(err.to_string().contains("已有测速正在进行"));
+        assert!(run_pipeline(&mut Config::default()).await.is_err());
+        // 结束后可以再次开始测速
+        drop(running);
+        assert!(RunGuard::acquire().is_ok());
+    }
+}
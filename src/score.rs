@@ -0,0 +1,293 @@
+use crate::types::{CloudflareIPData, Config};
+
+// 预设公式，速度单位 MB/s，延迟与抖动单位 ms，丢包率为 0~1
+const PRESETS: &[(&str, &str)] = &[
+    ("latency-first", "-latency_ms - jitter_ms*0.5 - loss*1000"),
+    ("speed-first", "speed*10 - latency_ms*0.05 - loss*100"),
+    ("balanced", "speed*2 - latency_ms*0.2 - jitter_ms*0.2 - loss*100"),
+];
+
+// 公式中可用的指标
+#[derive(Debug, Clone, Copy)]
+enum Var {
+    Speed,      // speed：下载速度 (MB/s)
+    Upload,     // upload：上传速度 (MB/s)
+    Latency,    // latency_ms：平均延迟
+    MinLatency, // min_latency_ms：最低延迟
+    MaxLatency, // max_latency_ms：最高延迟
+    Jitter,     // jitter_ms：抖动
+    Loss,       // loss：丢包率
+    Connect,    // connect_ms：TCP 连接耗时 [-timing]
+    Tls,        // tls_ms：TLS 握手耗时 [-timing]
+    Ttfb,       // ttfb_ms：首字节耗时 [-timing]
+    H2Latency,  // h2_latency_ms：HTTP/2 复用延迟 [-h2-latency]
+}
+
+impl Var {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "speed" => Some(Var::Speed),
+            "upload" => Some(Var::Upload),
+            "latency_ms" | "latency" => Some(Var::Latency),
+            "min_latency_ms" => Some(Var::MinLatency),
+            "max_latency_ms" => Some(Var::MaxLatency),
+            "jitter_ms" | "jitter" => Some(Var::Jitter),
+            "loss" => Some(Var::Loss),
+            "connect_ms" | "connect" => Some(Var::Connect),
+            "tls_ms" => Some(Var::Tls),
+            "ttfb_ms" | "ttfb" => Some(Var::Ttfb),
+            "h2_latency_ms" | "h2_latency" => Some(Var::H2Latency),
+            _ => None,
+        }
+    }
+
+    // 未测量的分阶段耗时与复用延迟为 NaN，该结果排在最后
+    fn value(self, data: &CloudflareIPData) -> f64 {
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        let timing = |d: std::time::Duration| if data.timing.connect.is_zero() { f64::NAN } else { ms(d) };
+        match self {
+            Var::Speed => data.download_speed / 1024.0 / 1024.0,
+            Var::Upload => data.upload_speed / 1024.0 / 1024.0,
+            Var::Latency => ms(data.ping_data.delay),
+            Var::MinLatency => ms(data.ping_data.min_delay),
+            Var::MaxLatency => ms(data.ping_data.max_delay),
+            Var::Jitter => ms(data.ping_data.jitter),
+            Var::Loss => data.loss_rate as f64,
+            Var::Connect => timing(data.timing.connect),
+            Var::Tls => timing(data.timing.tls_handshake),
+            Var::Ttfb => timing(data.timing.ttfb),
+            Var::H2Latency => data.h2_latency.map_or(f64::NAN, ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Var(Var),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, data: &CloudflareIPData) -> f64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Var(v) => v.value(data),
+            Expr::Neg(e) => -e.eval(data),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(data), b.eval(data));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    _ => a / b,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+fn tokenize(formula: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = formula.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Num(s.parse().map_err(|_| format!("无效的数字：{}", s))?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(s));
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else {
+            return Err(format!("无效的字符：{}", c));
+        }
+    }
+    Ok(tokens)
+}
+
+// 递归下降解析：expr = term (('+'|'-') term)*，term = factor (('*'|'/') factor)*
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek_op() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek_op() {
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("公式不完整")?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Ident(name) => Var::parse(&name)
+                .map(Expr::Var)
+                .ok_or_else(|| format!("未知的指标：{}", name)),
+            Token::Op('-') => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Token::Op('+') => self.factor(),
+            Token::Op('(') => {
+                let inner = self.expr()?;
+                if self.peek_op() != Some(')') {
+                    return Err("缺少右括号".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Op(c) => Err(format!("意外的符号：{}", c)),
+        }
+    }
+}
+
+// 结果排序的评分公式 [-score]，分数越高排名越靠前
+#[derive(Debug, Clone)]
+pub struct Score(Expr);
+
+impl Score {
+    // 解析预设名称或自定义公式
+    pub fn parse(formula: &str) -> Result<Self, String> {
+        let formula = PRESETS.iter()
+            .find(|(name, _)| *name == formula.trim())
+            .map_or(formula, |(_, preset)| preset);
+        let mut parser = Parser { tokens: tokenize(formula)?, pos: 0 };
+        if parser.tokens.is_empty() {
+            return Err("公式为空".to_string());
+        }
+        let expr = parser.expr()?;
+        if parser.pos < parser.tokens.len() {
+            return Err("公式末尾有多余内容".to_string());
+        }
+        Ok(Self(expr))
+    }
+
+    pub fn eval(&self, data: &CloudflareIPData) -> f64 {
+        self.0.eval(data)
+    }
+}
+
+// 检查 [-score]，配置文件中的公式不经过参数解析，需在测速前校验
+pub fn validate(config: &Config) -> Result<(), String> {
+    if config.score.is_empty() {
+        return Ok(());
+    }
+    Score::parse(&config.score)
+        .map(|_| ())
+        .map_err(|e| format!("无效的评分公式：{}，{}", config.score, e))
+}
+
+// 指定 [-score] 时按分数从高到低重新排序，分数相同的保持原有顺序
+pub fn rank(data: &mut [CloudflareIPData], config: &Config) {
+    if config.score.is_empty() {
+        return;
+    }
+    // 公式已由 validate 校验
+    let score = match Score::parse(&config.score) {
+        Ok(score) => score,
+        Err(_) => return,
+    };
+    let key = |d: &CloudflareIPData| {
+        let s = score.eval(d);
+        if s.is_nan() { f64::NEG_INFINITY } else { s }
+    };
+    data.sort_by(|a, b| key(b).total_cmp(&key(a)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::types::PingData;
+
+    fn data(ip: &str, delay_ms: u64, received: u32, speed_mb: f64) -> CloudflareIPData {
+        let ping = PingData::new(ip.parse().unwrap(), 443, 4, received, Duration::from_millis(delay_ms));
+        let mut data = CloudflareIPData::new(ping);
+        data.download_speed = speed_mb * 1024.0 * 1024.0;
+        data
+    }
+
+    fn eval(formula: &str, data: &CloudflareIPData) -> f64 {
+        Score::parse(formula).unwrap().eval(data)
+    }
+
+    #[test]
+    fn eval_respects_precedence() {
+        let d = data("1.1.1.1", 100, 4, 20.0);
+        assert_eq!(eval("speed*10 - latency_ms/4", &d), 175.0);
+        assert_eq!(eval("(speed + 10) * 2", &d), 60.0);
+        assert_eq!(eval("-latency + -(-5)", &d), -95.0);
+        assert_eq!(eval("loss", &data("1.1.1.1", 100, 3, 0.0)), 0.25);
+    }
+
+    #[test]
+    fn presets_expand() {
+        let d = data("1.1.1.1", 100, 4, 20.0);
+        assert_eq!(eval("speed-first", &d), 195.0);
+        assert_eq!(eval(" latency-first ", &d), -100.0);
+    }
+
+    #[test]
+    fn parse_rejects_invalid() {
+        for formula in ["", "speed +", "(speed", "speed)", "bogus*2", "speed % 2", "1..2"] {
+            assert!(Score::parse(formula).is_err(), "{}", formula);
+        }
+    }
+
+    #[test]
+    fn unmeasured_timing_is_nan() {
+        assert!(eval("connect_ms", &data("1.1.1.1", 100, 4, 0.0)).is_nan());
+        assert!(eval("h2_latency_ms + 1", &data("1.1.1.1", 100, 4, 0.0)).is_nan());
+    }
+
+    #[test]
+    fn rank_sorts_by_score_nan_last() {
+        let mut measured = data("1.0.0.3", 10, 4, 0.0);
+        measured.timing.connect = Duration::from_millis(50);
+        let mut results = vec![data("1.0.0.1", 10, 4, 0.0), measured, data("1.0.0.2", 10, 4, 0.0)];
+        let config = Config { score: "-connect_ms".to_string(), ..Config::default() };
+        rank(&mut results, &config);
+        let order: Vec<String> = results.iter().map(|d| d.ping_data.ip.to_string()).collect();
+        // 未测量的结果排在最后，且保持原有顺序
+        assert_eq!(order, ["1.0.0.3", "1.0.0.1", "1.0.0.2"]);
+    }
+}
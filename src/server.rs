@@ -0,0 +1,228 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use crate::csv::ResultRecord;
+use crate::summary::{self, Summary};
+use crate::types::Config;
+use crate::{metrics, scan};
+
+// 由 POST /scan 的请求体生成本次测速的配置，请求体为参数名到值的 JSON 对象，如 {"dn": 5, "httping": true}
+// 配置无效时返回错误信息
+pub type ConfigBuilder = Arc<dyn Fn(&Map<String, Value>) -> Result<Config, String> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    #[default]
+    Idle,
+    Running,
+    Finished,
+    Failed,
+}
+
+// GET /status 的内容
+#[derive(Debug, Clone, Default, Serialize)]
+struct Status {
+    state: State,
+    id: u64,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    phase: String,
+    done: u64,
+    total: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished_at: Option<u64>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    error: String,
+}
+
+// GET /results 的内容：最近一次完成的测速结果
+#[derive(Debug, Clone, Serialize)]
+struct Results {
+    id: u64,
+    finished_at: u64,
+    summary: Summary,
+    results: Vec<ResultRecord>,
+}
+
+lazy_static! {
+    static ref STATUS: Mutex<Status> = Mutex::new(Status::default());
+    static ref RESULTS: Mutex<Option<Results>> = Mutex::new(None);
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// 是否以 [-serve] 运行，进度条据此上报进度
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// 测速进入新阶段
+pub fn begin_phase(title: &str, total: u64) {
+    let mut status = STATUS.lock().unwrap();
+    status.phase = title.to_string();
+    status.done = 0;
+    status.total = total;
+}
+
+pub fn advance(n: u64) {
+    STATUS.lock().unwrap().done += n;
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json; charset=utf-8")
+        .body(Body::from(serde_json::to_string_pretty(body).unwrap_or_default()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &json!({ "error": message }))
+}
+
+// 请求体为空时使用启动时的配置
+async fn start_scan(req: Request<Body>, builder: &ConfigBuilder, queue: &mpsc::Sender<(u64, Config)>) -> Response<Body> {
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let overrides = if body.iter().all(u8::is_ascii_whitespace) {
+        Map::new()
+    } else {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Object(map)) => map,
+            Ok(_) => return error_response(StatusCode::BAD_REQUEST, "请求体必须是 JSON 对象"),
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("JSON 解析失败：{}", e)),
+        }
+    };
+    if let Some((key, _)) = overrides.iter().find(|(_, v)| v.is_array() || v.is_object()) {
+        return error_response(StatusCode::BAD_REQUEST, &format!("参数 {} 的值必须是字符串、数字或布尔值", key));
+    }
+    let config = match builder(&overrides) {
+        Ok(config) => config,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let id = {
+        let mut status = STATUS.lock().unwrap();
+        if status.state == State::Running {
+            return error_response(StatusCode::CONFLICT, "已有测速正在进行");
+        }
+        *status = Status {
+            state: State::Running,
+            id: status.id + 1,
+            started_at: Some(now()),
+            ..Status::default()
+        };
+        status.id
+    };
+    if queue.send((id, config)).await.is_err() {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "服务正在退出");
+    }
+    json_response(StatusCode::ACCEPTED, &json!({ "id": id }))
+}
+
+async fn handle(req: Request<Body>, builder: ConfigBuilder, queue: mpsc::Sender<(u64, Config)>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::POST, "/scan") => start_scan(req, &builder, &queue).await,
+        (&Method::GET, "/status") => json_response(StatusCode::OK, &*STATUS.lock().unwrap()),
+        (&Method::GET, "/results") => match RESULTS.lock().unwrap().as_ref() {
+            Some(results) => json_response(StatusCode::OK, results),
+            None => error_response(StatusCode::NOT_FOUND, "还没有完成的测速"),
+        },
+        _ => error_response(StatusCode::NOT_FOUND, "未知的接口，可用 POST /scan、GET /status、GET /results"),
+    };
+    Ok(response)
+}
+
+// 执行一次测速；与命令行单次运行相同，会写入 [-o]、更新 DNS 等
+async fn execute(id: u64, mut config: Config) {
+    println!("\n[服务] 第 {} 次测速开始", id);
+    let result = scan::run_pipeline(&mut config).await;
+    let mut status = match result {
+        Ok(mut speed_data) => {
+            scan::record_history(&config, &speed_data);
+            if let Err(e) = scan::publish_results(&config, &mut speed_data).await {
+                println!("[错误] 输出结果失败：{:#}", e);
+            }
+            let summary = summary::report(&config, &speed_data);
+            metrics::update(&speed_data);
+            let finished_at = now();
+            *RESULTS.lock().unwrap() = Some(Results {
+                id,
+                finished_at,
+                summary,
+                results: speed_data.iter().map(|d| ResultRecord::new(d, finished_at)).collect(),
+            });
+            let mut status = STATUS.lock().unwrap();
+            status.state = State::Finished;
+            status.clone()
+        }
+        Err(e) => {
+            println!("[服务] 第 {} 次测速失败：{:#}", id, e);
+            let mut status = STATUS.lock().unwrap();
+            status.state = State::Failed;
+            status.error = format!("{:#}", e);
+            status.clone()
+        }
+    };
+    status.finished_at = Some(now());
+    STATUS.lock().unwrap().finished_at = status.finished_at;
+    println!("[服务] 第 {} 次测速结束", id);
+}
+
+// [-serve]：提供 HTTP 接口，由 POST /scan 触发测速，同一时间只进行一次测速，Ctrl+C 退出
+pub async fn run(config: &Config, builder: ConfigBuilder) -> Result<()> {
+    let addr: SocketAddr = config.serve_addr.parse().with_context(|| format!("无效的监听地址 {}", config.serve_addr))?;
+    let (queue, mut scans) = mpsc::channel::<(u64, Config)>(1);
+    let make_svc = make_service_fn(move |_conn| {
+        let builder = builder.clone();
+        let queue = queue.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(req, builder.clone(), queue.clone())))
+        }
+    });
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("监听 {} 失败", addr))?
+        .serve(make_svc);
+    if !config.metrics_addr.is_empty() {
+        metrics::serve(&config.metrics_addr)?;
+    }
+
+    ACTIVE.store(true, Ordering::Relaxed);
+    println!("[服务] 接口地址：http://{}（POST /scan、GET /status、GET /results）", addr);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            println!("[错误] 接口服务异常退出：{}", e);
+        }
+    });
+
+    // 测速在当前任务中依次执行
+    loop {
+        tokio::select! {
+            scan = scans.recv() => match scan {
+                Some((id, config)) => execute(id, config).await,
+                None => return Ok(()),
+            },
+            _ = tokio::signal::ctrl_c() => {
+                println!("\n[服务] 收到退出信号，停止服务");
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+use crate::ip::IPWithPort;
+use crate::progress::Bar;
+use crate::tcping::Ping;
+use crate::types::{Config, DownloadSpeedSet, SoakStats};
+use crate::{cancel, tui};
+
+// 单个 IP 的逐次探测结果，None 为失败
+struct Probes {
+    delays: Vec<Option<Duration>>,
+}
+
+impl Probes {
+    fn stats(&self) -> SoakStats {
+        let probes = self.delays.len() as u32;
+        let ok: Vec<f64> = self.delays.iter().flatten().map(|d| d.as_secs_f64() * 1000.0).collect();
+        let mean = |v: &[f64]| if v.is_empty() { 0.0 } else { v.iter().sum::<f64>() / v.len() as f64 };
+
+        // 延迟漂移：后半段平均延迟减去前半段
+        let (first, second) = ok.split_at(ok.len() / 2);
+        let drift_ms = if first.is_empty() { 0.0 } else { mean(second) - mean(first) };
+
+        // 连续失败视为一次断连，记录最长的一次
+        let mut burst = 0;
+        let mut max_burst = 0;
+        let mut disconnects = 0;
+        for delay in &self.delays {
+            if delay.is_some() {
+                burst = 0;
+                continue;
+            }
+            if burst == 0 {
+                disconnects += 1;
+            }
+            burst += 1;
+            max_burst = max_burst.max(burst);
+        }
+
+        SoakStats {
+            probes,
+            received: ok.len() as u32,
+            latency_ms: mean(&ok),
+            drift_ms,
+            max_burst,
+            disconnects,
+        }
+    }
+}
+
+// [-soak]：对排名前 [-soak-n] 的 IP 每隔 [-soak-interval] 探测一次，持续 [-soak] 时长，
+// 按期间的丢包率、断连次数与平均延迟重新排序，原有的测速结果不变
+pub async fn run(config: &Config, data: &mut DownloadSpeedSet) {
+    if config.soak.is_zero() || config.warp || data.is_empty() {
+        return;
+    }
+    let count = (config.soak_count as usize).min(data.len());
+    let interval = config.soak_interval.max(Duration::from_millis(100));
+    let rounds = (config.soak.as_secs_f64() / interval.as_secs_f64()).ceil().max(1.0) as u64;
+
+    println!("\n开始稳定性测试（IP 数量：{}，时长：{:?}，间隔：{:?}）", count, config.soak, interval);
+    let bar = Bar::new(rounds * count as u64, "", "").phase("稳定性测试");
+
+    // 每轮只探测一次
+    let mut probe_config = config.clone();
+    probe_config.ping_times = 1;
+    let targets: Vec<IPWithPort> = data[..count]
+        .iter()
+        .map(|d| IPWithPort { ip: d.ping_data.ip, port: Some(d.ping_data.port) })
+        .collect();
+    let mut probes: Vec<Probes> = (0..count).map(|_| Probes { delays: Vec::new() }).collect();
+
+    let start = Instant::now();
+    for round in 0..rounds {
+        if tui::abort_requested() || cancel::requested() {
+            break;
+        }
+        tokio::time::sleep_until((start + interval * round as u32).into()).await;
+        let results = futures::future::join_all(targets.iter().map(|target| Ping::tcping_handler(target, &probe_config))).await;
+        let mut failed = 0;
+        for (probe, result) in probes.iter_mut().zip(results) {
+            let delay = result.ok().map(|ping| ping.delay);
+            failed += delay.is_none() as usize;
+            probe.delays.push(delay);
+        }
+        bar.grow(count as u64, &format!("第 {}/{} 轮，失败 {}", round + 1, rounds, failed));
+    }
+    bar.done();
+
+    for (ip_data, probe) in data.iter_mut().zip(&probes) {
+        ip_data.soak = Some(probe.stats());
+    }
+    // 测试过的 IP 排在前面，其余保持原顺序
+    data[..count].sort_by(|a, b| {
+        let (a, b) = (a.soak.as_ref().unwrap(), b.soak.as_ref().unwrap());
+        a.loss_rate()
+            .total_cmp(&b.loss_rate())
+            .then(a.disconnects.cmp(&b.disconnects))
+            .then(a.latency_ms.total_cmp(&b.latency_ms))
+    });
+
+    let best = &data[0];
+    if let Some(stats) = &best.soak {
+        println!(
+            "[信息] 稳定性测试最优 IP：{}（丢包率 {:.2}，平均延迟 {:.2} ms，漂移 {:+.2} ms，断连 {} 次）",
+            best.ping_data.ip, stats.loss_rate(), stats.latency_ms, stats.drift_ms, stats.disconnects
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probes(delays: &[Option<u64>]) -> Probes {
+        Probes { delays: delays.iter().map(|d| d.map(Duration::from_millis)).collect() }
+    }
+
+    #[test]
+    fn stats_counts_bursts() {
+        let stats = probes(&[Some(10), None, None, Some(20), None, Some(30), Some(40)]).stats();
+        assert_eq!((stats.probes, stats.received), (7, 4));
+        assert_eq!(stats.latency_ms, 25.0);
+        // 两段连续失败，最长 2 次
+        assert_eq!((stats.disconnects, stats.max_burst), (2, 2));
+        // 后半段 (30, 40) 减去前半段 (10, 20)
+        assert_eq!(stats.drift_ms, 20.0);
+    }
+
+    #[test]
+    fn stats_without_samples() {
+        let stats = probes(&[None, None, None]).stats();
+        assert_eq!((stats.probes, stats.received), (3, 0));
+        assert_eq!((stats.latency_ms, stats.drift_ms), (0.0, 0.0));
+        assert_eq!((stats.disconnects, stats.max_burst), (1, 3));
+    }
+
+    #[test]
+    fn stats_single_sample_has_no_drift() {
+        let stats = probes(&[Some(15)]).stats();
+        assert_eq!(stats.latency_ms, 15.0);
+        assert_eq!(stats.drift_ms, 0.0);
+        assert_eq!(stats.disconnects, 0);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use crate::failure;
+use crate::history::percentile;
+use crate::types::{CloudflareIPData, Config, DownloadSpeedSet, PingDelaySet};
+
+// 延迟分位数 (ms)
+#[derive(Debug, Clone, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+// 下载速度分布 (MB/s)
+#[derive(Debug, Clone, Serialize)]
+pub struct Distribution {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+}
+
+// [-dual-stack] 时单一地址族的最优结果
+#[derive(Debug, Clone, Serialize)]
+pub struct FamilyBest {
+    pub results: usize,
+    pub best_latency_ip: String,
+    pub best_latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_speed_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_speed_mb: Option<f64>,
+}
+
+impl FamilyBest {
+    fn from_results<'a>(results: impl Iterator<Item = &'a CloudflareIPData> + Clone) -> Option<Self> {
+        let fastest = results.clone().min_by_key(|d| d.ping_data.delay)?;
+        let best_speed = results.clone()
+            .filter(|d| d.download_speed > 0.0)
+            .max_by(|a, b| a.download_speed.total_cmp(&b.download_speed));
+        Some(Self {
+            results: results.count(),
+            best_latency_ip: fastest.ping_data.ip.to_string(),
+            best_latency_ms: fastest.ping_data.delay.as_secs_f64() * 1000.0,
+            best_speed_ip: best_speed.map(|d| d.ping_data.ip.to_string()),
+            best_speed_mb: best_speed.map(|d| d.download_speed / 1024.0 / 1024.0),
+        })
+    }
+
+    fn describe(&self) -> String {
+        let mut text = format!("{} 个，最低延迟 {:.2} ms ({})", self.results, self.best_latency_ms, self.best_latency_ip);
+        if let (Some(ip), Some(speed)) = (&self.best_speed_ip, self.best_speed_mb) {
+            text += &format!("，最高速度 {:.2} MB/s ({})", speed, ip);
+        }
+        text
+    }
+}
+
+// 单轮测速的汇总统计，JSON 字段名固定为英文
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    pub timestamp: u64,
+    pub candidates: usize,      // 候选 IP 数量
+    pub responded: usize,       // 延迟测速有响应的数量
+    pub qualified: usize,       // 满足延迟/丢包/抖动条件的数量
+    pub download_tested: usize, // 下载测速数量
+    pub download_failed: usize, // 下载测速失败数量
+    pub results: usize,         // 最终结果数量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<Percentiles>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_mb: Option<Distribution>,
+    pub colos: BTreeMap<String, usize>,
+    pub failures: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<FamilyBest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<FamilyBest>,
+    #[serde(skip)]
+    latencies: Vec<f64>,
+}
+
+lazy_static! {
+    static ref CURRENT: Mutex<Summary> = Mutex::new(Summary::default());
+}
+
+// 每轮测速开始时清空统计
+pub fn reset() {
+    *CURRENT.lock().unwrap() = Summary::default();
+}
+
+// 记录延迟测速结果（过滤前）
+pub fn record_ping(candidates: usize, responded: &PingDelaySet) {
+    let mut summary = CURRENT.lock().unwrap();
+    summary.candidates = candidates;
+    summary.responded = responded.len();
+    summary.latencies = responded.iter()
+        .map(|d| d.ping_data.delay.as_secs_f64() * 1000.0)
+        .collect();
+}
+
+// 记录满足延迟/丢包/抖动条件的数量
+pub fn record_qualified(qualified: usize) {
+    CURRENT.lock().unwrap().qualified = qualified;
+}
+
+// 记录单个 IP 的下载测速结果
+pub fn record_download(failed: bool) {
+    let mut summary = CURRENT.lock().unwrap();
+    summary.download_tested += 1;
+    if failed {
+        summary.download_failed += 1;
+    }
+}
+
+// 汇总本轮统计
+pub fn finish(speed_data: &DownloadSpeedSet) -> Summary {
+    let mut summary = CURRENT.lock().unwrap().clone();
+    summary.timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    summary.results = speed_data.len();
+
+    let mut latencies = std::mem::take(&mut summary.latencies);
+    latencies.sort_by(f64::total_cmp);
+    if !latencies.is_empty() {
+        summary.latency_ms = Some(Percentiles {
+            p50: percentile(&latencies, 0.50),
+            p90: percentile(&latencies, 0.90),
+            p99: percentile(&latencies, 0.99),
+        });
+    }
+
+    let mut speeds: Vec<f64> = speed_data.iter()
+        .map(|d| d.download_speed / 1024.0 / 1024.0)
+        .filter(|&s| s > 0.0)
+        .collect();
+    speeds.sort_by(f64::total_cmp);
+    if let (Some(&min), Some(&max)) = (speeds.first(), speeds.last()) {
+        summary.speed_mb = Some(Distribution {
+            min,
+            p50: percentile(&speeds, 0.50),
+            p90: percentile(&speeds, 0.90),
+            max,
+        });
+    }
+
+    for ip_data in speed_data {
+        let colo = if ip_data.colo.is_empty() { "unknown" } else { &ip_data.colo };
+        *summary.colos.entry(colo.to_string()).or_insert(0) += 1;
+    }
+
+    // 延迟测速与下载测速的失败按原因分类，另计不满足过滤条件的数量
+    for (kind, count) in failure::counts() {
+        summary.failures.insert(kind.to_string(), count);
+    }
+    let filtered = summary.responded.saturating_sub(summary.qualified);
+    if filtered > 0 {
+        summary.failures.insert("filtered".to_string(), filtered);
+    }
+    summary
+}
+
+fn failure_label(reason: &str) -> &str {
+    match reason {
+        "filtered" => "不满足条件",
+        other => failure::kind_label(other),
+    }
+}
+
+impl Summary {
+    pub fn print(&self) {
+        println!(
+            "\n测速统计：候选 {}，有响应 {}，满足条件 {}，下载测速 {} (失败 {})，结果 {}",
+            self.candidates, self.responded, self.qualified, self.download_tested, self.download_failed, self.results
+        );
+        if let Some(l) = &self.latency_ms {
+            println!("延迟 (ms)：P50 {:.2}，P90 {:.2}，P99 {:.2}", l.p50, l.p90, l.p99);
+        }
+        if let Some(s) = &self.speed_mb {
+            println!("下载速度 (MB/s)：最低 {:.2}，P50 {:.2}，P90 {:.2}，最高 {:.2}", s.min, s.p50, s.p90, s.max);
+        }
+        if !self.colos.is_empty() {
+            let colos: Vec<String> = self.colos.iter().map(|(c, n)| format!("{} {}", c, n)).collect();
+            println!("数据中心：{}", colos.join("，"));
+        }
+        if !self.failures.is_empty() {
+            let failures: Vec<String> = self.failures.iter()
+                .map(|(r, n)| format!("{} {}", failure_label(r), n))
+                .collect();
+            println!("失败原因：{}", failures.join("，"));
+        }
+        self.print_families();
+    }
+
+    // [-dual-stack] 的 IPv4 与 IPv6 对比
+    fn print_families(&self) {
+        if self.ipv4.is_none() && self.ipv6.is_none() {
+            return;
+        }
+        let describe = |best: &Option<FamilyBest>| best.as_ref().map(FamilyBest::describe).unwrap_or_else(|| "无结果".to_string());
+        println!("\nIPv4：{}", describe(&self.ipv4));
+        println!("IPv6：{}", describe(&self.ipv6));
+        let (Some(v4), Some(v6)) = (&self.ipv4, &self.ipv6) else { return };
+        let latency = if v4.best_latency_ms <= v6.best_latency_ms { "IPv4" } else { "IPv6" };
+        let mut verdict = format!("延迟更低：{} (相差 {:.2} ms)", latency, (v4.best_latency_ms - v6.best_latency_ms).abs());
+        if let (Some(s4), Some(s6)) = (v4.best_speed_mb, v6.best_speed_mb) {
+            let speed = if s4 >= s6 { "IPv4" } else { "IPv6" };
+            verdict += &format!("，速度更快：{} (相差 {:.2} MB/s)", speed, (s4 - s6).abs());
+        }
+        println!("{}", verdict);
+    }
+}
+
+// 打印本轮统计，指定 [-summary] 时写入 JSON 文件
+pub fn report(config: &Config, speed_data: &DownloadSpeedSet) -> Summary {
+    failure::flush();
+    let mut summary = finish(speed_data);
+    if config.dual_stack {
+        summary.ipv4 = FamilyBest::from_results(speed_data.iter().filter(|d| d.ping_data.ip.is_ipv4()));
+        summary.ipv6 = FamilyBest::from_results(speed_data.iter().filter(|d| d.ping_data.ip.is_ipv6()));
+    }
+    summary.print();
+    if let Err(e) = write(&config.summary_file, &summary) {
+        println!("[错误] 写入统计文件失败：{:#}", e);
+    }
+    summary
+}
+
+fn write(path: &str, summary: &Summary) -> Result<()> {
+    if path.is_empty() {
+        return Ok(());
+    }
+    let json = serde_json::to_string_pretty(summary)?;
+    std::fs::write(path, json + "\n").with_context(|| format!("无法写入 {}", path))?;
+    Ok(())
+}
@@ -0,0 +1,698 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::types::Config;
+
+// 原始套接字只打开一次，打开失败的原因同样保留，避免重复提示
+static SESSION: OnceLock<Result<imp::Session, String>> = OnceLock::new();
+
+// 开始 TCP 延迟测速前调用：首次调用时打开原始套接字，不可用时提示并改用 connect() 测速
+pub fn start(config: &Config) -> bool {
+    let mut first = false;
+    let session = SESSION.get_or_init(|| {
+        first = true;
+        imp::Session::open(config)
+    });
+    match session {
+        Ok(_) => true,
+        Err(e) => {
+            if first {
+                println!("[信息] 无法使用 SYN 测速（{}），改用 connect() 测速", e);
+            }
+            false
+        }
+    }
+}
+
+// [-syn]：发送一个 SYN，返回收到 SYN-ACK 的往返时间，收到 RST 时为拒绝连接；
+// 未启用或该地址类型不可用时返回 None，由调用方改用 connect()，超时由调用方控制
+pub async fn probe(addr: SocketAddr, config: &Config) -> Option<io::Result<Duration>> {
+    if !config.syn {
+        return None;
+    }
+    SESSION.get()?.as_ref().ok()?.probe(addr, config).await
+}
+
+// Linux 与 Windows 实现共用：等待回应的探测与 SYN 报文
+#[cfg(any(target_os = "linux", all(windows, feature = "windivert")))]
+mod common {
+    use std::collections::HashMap;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use rand::Rng;
+    use tokio::sync::oneshot;
+    use crate::interface;
+    use crate::types::Config;
+
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const ACK: u8 = 0x10;
+    // 本地端口取自 Linux 默认临时端口范围 (32768 ~ 60999) 之外，避免与正常连接冲突；
+    // Windows 的临时端口范围 (49152 ~ 65535) 与之重叠，回应按确认号匹配，不会误收正常连接的报文
+    pub const LOCAL_PORTS: std::ops::Range<u16> = 61000..65535;
+    pub const TCP_PROTOCOL: u8 = 6;
+
+    // 收到的回应：收包时间与是否为 RST
+    struct Reply {
+        received: Duration,
+        refused: bool,
+    }
+
+    // 等待回应的探测，按 (目标地址, 本地端口) 查找
+    struct Waiter {
+        seq: u32,
+        reply: oneshot::Sender<Reply>,
+    }
+
+    #[derive(Clone, Default)]
+    pub struct Pending(Arc<Mutex<HashMap<(SocketAddr, u16), Waiter>>>);
+
+    impl Pending {
+        // 登记一次探测，分配未被占用的本地端口与随机序列号
+        pub fn register(&self, addr: SocketAddr) -> Probe<'_> {
+            let seq = rand::random::<u32>();
+            let (sender, reply) = oneshot::channel();
+            let mut pending = self.0.lock().unwrap();
+            let mut rng = rand::thread_rng();
+            let port = loop {
+                let port = rng.gen_range(LOCAL_PORTS);
+                if !pending.contains_key(&(addr, port)) {
+                    break port;
+                }
+            };
+            pending.insert((addr, port), Waiter { seq, reply: sender });
+            Probe { pending: self, key: (addr, port), seq, reply }
+        }
+
+        // 收到的 TCP 报文交给对应的等待中的探测，只接受确认号匹配的 SYN-ACK 或 RST
+        pub fn dispatch(&self, from: IpAddr, segment: &[u8], received: Duration) {
+            if segment.len() < 20 {
+                return;
+            }
+            let remote = u16::from_be_bytes([segment[0], segment[1]]);
+            let local = u16::from_be_bytes([segment[2], segment[3]]);
+            let ack = u32::from_be_bytes([segment[8], segment[9], segment[10], segment[11]]);
+            let flags = segment[13];
+
+            let key = (SocketAddr::new(from, remote), local);
+            let mut pending = self.0.lock().unwrap();
+            let Some(waiter) = pending.get(&key) else { return };
+            let matched = flags & ACK != 0 && ack == waiter.seq.wrapping_add(1);
+            if matched && (flags & SYN != 0 || flags & RST != 0) {
+                let waiter = pending.remove(&key).unwrap();
+                let _ = waiter.reply.send(Reply { received, refused: flags & RST != 0 });
+            }
+        }
+    }
+
+    // 一次探测，超时或出错时移除等待记录
+    pub struct Probe<'a> {
+        pending: &'a Pending,
+        key: (SocketAddr, u16),
+        seq: u32,
+        reply: oneshot::Receiver<Reply>,
+    }
+
+    impl Probe<'_> {
+        pub fn port(&self) -> u16 {
+            self.key.1
+        }
+
+        pub fn seq(&self) -> u32 {
+            self.seq
+        }
+
+        // 等待回应，返回自 sent 起的往返时间，sent 与收包时间使用同一时钟
+        pub async fn wait(mut self, sent: Duration) -> io::Result<Duration> {
+            let reply = (&mut self.reply).await.map_err(|_| io::Error::other("接收线程已退出"))?;
+            if reply.refused {
+                return Err(io::ErrorKind::ConnectionRefused.into());
+            }
+            Ok(reply.received.saturating_sub(sent))
+        }
+    }
+
+    impl Drop for Probe<'_> {
+        fn drop(&mut self) {
+            self.pending.0.lock().unwrap().remove(&self.key);
+        }
+    }
+
+    // 与 connect() 相同的源地址：指定 [-interface] 时使用该网卡的地址，否则由路由决定
+    pub fn source_address(addr: SocketAddr, config: &Config) -> io::Result<IpAddr> {
+        if let Some(ip) = interface::local_address(config, addr.ip()) {
+            return Ok(ip);
+        }
+        let bind: SocketAddr = if addr.is_ipv4() {
+            (IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).into()
+        } else {
+            (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0).into()
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(addr)?;
+        Ok(socket.local_addr()?.ip())
+    }
+
+    // 带 MSS 选项的 SYN 报文 (24 字节)，不含 IP 头
+    pub fn syn_packet(source: IpAddr, addr: SocketAddr, port: u16, seq: u32) -> [u8; 24] {
+        let mut packet = [0u8; 24];
+        packet[0..2].copy_from_slice(&port.to_be_bytes());
+        packet[2..4].copy_from_slice(&addr.port().to_be_bytes());
+        packet[4..8].copy_from_slice(&seq.to_be_bytes());
+        packet[12] = 6 << 4; // 首部长度 6 个 32 位字
+        packet[13] = SYN;
+        packet[14..16].copy_from_slice(&64240u16.to_be_bytes());
+        packet[20..24].copy_from_slice(&[2, 4, 0x05, 0xb4]); // MSS 1460
+        let sum = checksum(source, addr.ip(), &packet);
+        packet[16..18].copy_from_slice(&sum.to_be_bytes());
+        packet
+    }
+
+    // TCP 校验和，包含 IPv4 / IPv6 伪首部
+    pub fn checksum(source: IpAddr, dest: IpAddr, segment: &[u8]) -> u16 {
+        let mut sum = 0u32;
+        match (source, dest) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                add_words(&mut sum, &s.octets());
+                add_words(&mut sum, &d.octets());
+            }
+            (s, d) => {
+                add_words(&mut sum, &to_v6(s).octets());
+                add_words(&mut sum, &to_v6(d).octets());
+            }
+        }
+        add_words(&mut sum, &[0, TCP_PROTOCOL]);
+        add_words(&mut sum, &(segment.len() as u16).to_be_bytes());
+        add_words(&mut sum, segment);
+        fold(sum)
+    }
+
+    // IPv4 首部校验和
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn header_checksum(header: &[u8]) -> u16 {
+        let mut sum = 0u32;
+        add_words(&mut sum, header);
+        fold(sum)
+    }
+
+    fn fold(mut sum: u32) -> u16 {
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    pub fn to_v6(ip: IpAddr) -> Ipv6Addr {
+        match ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        }
+    }
+
+    fn add_words(sum: &mut u32, bytes: &[u8]) {
+        for chunk in bytes.chunks(2) {
+            *sum += u16::from_be_bytes([chunk[0], chunk.get(1).copied().unwrap_or(0)]) as u32;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // 校验和正确时，对含校验和的报文再次求和结果为 0
+        fn verifies(source: IpAddr, dest: IpAddr, segment: &[u8]) -> bool {
+            checksum(source, dest, segment) == 0
+        }
+
+        fn segment(port: u16, remote: u16, ack: u32, flags: u8) -> [u8; 20] {
+            let mut segment = [0u8; 20];
+            segment[0..2].copy_from_slice(&remote.to_be_bytes());
+            segment[2..4].copy_from_slice(&port.to_be_bytes());
+            segment[8..12].copy_from_slice(&ack.to_be_bytes());
+            segment[13] = flags;
+            segment
+        }
+
+        #[test]
+        fn syn_packet_fields() {
+            let source: IpAddr = "192.0.2.1".parse().unwrap();
+            let addr: SocketAddr = "198.51.100.7:443".parse().unwrap();
+            let packet = syn_packet(source, addr, 61000, 0x01020304);
+            assert_eq!(&packet[0..2], &61000u16.to_be_bytes());
+            assert_eq!(&packet[2..4], &443u16.to_be_bytes());
+            assert_eq!(&packet[4..8], &[1, 2, 3, 4]);
+            assert_eq!(packet[12] >> 4, 6);
+            assert_eq!(packet[13], SYN);
+            assert_eq!(&packet[20..24], &[2, 4, 0x05, 0xb4]);
+            assert!(verifies(source, addr.ip(), &packet));
+        }
+
+        #[test]
+        fn syn_packet_checksum_v6() {
+            let source: IpAddr = "2001:db8::1".parse().unwrap();
+            let addr: SocketAddr = "[2606:4700::1]:8443".parse().unwrap();
+            let packet = syn_packet(source, addr, 65000, 7);
+            assert!(verifies(source, addr.ip(), &packet));
+            // 校验和覆盖伪首部，换一个源地址即不再正确
+            assert!(!verifies("2001:db8::2".parse().unwrap(), addr.ip(), &packet));
+        }
+
+        #[test]
+        fn header_checksum_verifies() {
+            let mut header = [0x45, 0, 0, 44, 0, 1, 0x40, 0, 64, 6, 0, 0, 192, 0, 2, 1, 198, 51, 100, 7];
+            let sum = header_checksum(&header);
+            header[10..12].copy_from_slice(&sum.to_be_bytes());
+            assert_eq!(header_checksum(&header), 0);
+        }
+
+        #[tokio::test]
+        async fn dispatch_matches_ack() {
+            let pending = Pending::default();
+            let addr: SocketAddr = "198.51.100.7:443".parse().unwrap();
+            let probe = pending.register(addr);
+            assert!(LOCAL_PORTS.contains(&probe.port()));
+            let (port, seq) = (probe.port(), probe.seq());
+
+            // 确认号不匹配、来源端口不同与不带 ACK 的报文被忽略
+            pending.dispatch(addr.ip(), &segment(port, 443, seq, SYN | ACK), Duration::from_millis(30));
+            pending.dispatch(addr.ip(), &segment(port, 80, seq.wrapping_add(1), SYN | ACK), Duration::from_millis(30));
+            pending.dispatch(addr.ip(), &segment(port, 443, seq.wrapping_add(1), SYN), Duration::from_millis(30));
+            pending.dispatch(addr.ip(), &segment(port, 443, seq.wrapping_add(1), SYN | ACK), Duration::from_millis(40));
+            assert_eq!(probe.wait(Duration::from_millis(10)).await.unwrap(), Duration::from_millis(30));
+            assert!(pending.0.lock().unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn dispatch_rst_is_refused() {
+            let pending = Pending::default();
+            let addr: SocketAddr = "[2606:4700::1]:443".parse().unwrap();
+            let probe = pending.register(addr);
+            pending.dispatch(addr.ip(), &segment(probe.port(), 443, probe.seq().wrapping_add(1), RST | ACK), Duration::ZERO);
+            let err = probe.wait(Duration::ZERO).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        }
+
+        #[test]
+        fn dropped_probe_is_removed() {
+            let pending = Pending::default();
+            drop(pending.register("198.51.100.7:443".parse().unwrap()));
+            assert!(pending.0.lock().unwrap().is_empty());
+        }
+    }
+}
+
+// Linux：IPPROTO_TCP 原始套接字发送 SYN，内核为收到的 SYN-ACK 回复 RST，不会建立连接；
+// 回应由后台线程接收，以内核收包时间戳 (SO_TIMESTAMPNS) 计时
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use socket2::{Domain, Protocol, Socket, Type};
+    use super::common::{self, Pending, LOCAL_PORTS, RST, SYN};
+    use crate::types::Config;
+
+    const RECV_BUFFER: usize = 4 * 1024 * 1024;
+
+    // 一种地址类型的原始套接字
+    struct Family {
+        socket: Socket,
+        pending: Pending,
+    }
+
+    pub struct Session {
+        v4: Option<Family>,
+        v6: Option<Family>,
+    }
+
+    impl Session {
+        // 两种地址类型都无法打开时返回原因，常见为缺少 root 权限或 CAP_NET_RAW
+        pub fn open(config: &Config) -> Result<Self, String> {
+            let v4 = Family::open(Domain::IPV4, config);
+            let v6 = Family::open(Domain::IPV6, config);
+            match (v4, v6) {
+                (Err(e), Err(_)) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    Err("需要 root 权限或 CAP_NET_RAW".to_string())
+                }
+                (Err(e), Err(_)) => Err(format!("无法打开原始套接字：{}", e)),
+                (v4, v6) => Ok(Self { v4: v4.ok(), v6: v6.ok() }),
+            }
+        }
+
+        pub async fn probe(&self, addr: SocketAddr, config: &Config) -> Option<io::Result<Duration>> {
+            let family = if addr.is_ipv4() { self.v4.as_ref()? } else { self.v6.as_ref()? };
+            Some(family.probe(addr, config).await)
+        }
+    }
+
+    impl Family {
+        fn open(domain: Domain, config: &Config) -> io::Result<Self> {
+            let socket = Socket::new(domain, Type::RAW, Some(Protocol::TCP))?;
+            let ipv4 = domain == Domain::IPV4;
+            socket.attach_filter(&filter(ipv4))?;
+            if !config.interface.is_empty() {
+                socket.bind_device(Some(config.interface.as_bytes()))?;
+            }
+            let _ = socket.set_recv_buffer_size(RECV_BUFFER);
+            enable_timestamps(&socket)?;
+
+            let pending = Pending::default();
+            let receiver = socket.try_clone()?;
+            let waiters = pending.clone();
+            std::thread::Builder::new()
+                .name("syn-recv".to_string())
+                .spawn(move || receive(receiver, ipv4, waiters))?;
+            Ok(Self { socket, pending })
+        }
+
+        async fn probe(&self, addr: SocketAddr, config: &Config) -> io::Result<Duration> {
+            let source = common::source_address(addr, config)?;
+            let probe = self.pending.register(addr);
+            let packet = common::syn_packet(source, addr, probe.port(), probe.seq());
+            let sent = now();
+            self.socket.send_to(&packet, &SocketAddr::new(addr.ip(), 0).into())?;
+            probe.wait(sent).await
+        }
+    }
+
+    fn now() -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    // 原始套接字会收到本机的全部 TCP 报文，内核中只放行发往本地端口范围的 SYN / RST，
+    // 其余报文不进入接收队列；IPv4 报文含 IP 头，按首部长度取 TCP 头，IPv6 直接从 TCP 头开始
+    fn filter(ipv4: bool) -> Vec<libc::sock_filter> {
+        let op = |code: u32, jt: u8, jf: u8, k: u32| libc::sock_filter { code: code as u16, jt, jf, k };
+        let mode = if ipv4 { libc::BPF_IND } else { libc::BPF_ABS };
+        let mut program = Vec::new();
+        if ipv4 {
+            program.push(op(libc::BPF_LDX | libc::BPF_B | libc::BPF_MSH, 0, 0, 0)); // X = IP 首部长度
+        }
+        program.extend([
+            op(libc::BPF_LD | libc::BPF_H | mode, 0, 0, 2), // 目标端口
+            op(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, 0, 4, LOCAL_PORTS.start as u32),
+            op(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, 3, 0, LOCAL_PORTS.end as u32),
+            op(libc::BPF_LD | libc::BPF_B | mode, 0, 0, 13), // TCP 标志
+            op(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, 0, 1, (SYN | RST) as u32),
+            op(libc::BPF_RET | libc::BPF_K, 0, 0, u32::MAX),
+            op(libc::BPF_RET | libc::BPF_K, 0, 0, 0),
+        ]);
+        program
+    }
+
+    fn enable_timestamps(socket: &Socket) -> io::Result<()> {
+        let on: libc::c_int = 1;
+        // SAFETY: fd 为有效的套接字，选项值为 c_int
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_TIMESTAMPNS,
+                &on as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    // 后台线程：接收该地址类型通过过滤的 TCP 报文，交给对应的等待中的探测
+    fn receive(socket: Socket, ipv4: bool, pending: Pending) {
+        let mut data = [0u8; 128];
+        loop {
+            let (len, from, received) = match recv(&socket, &mut data) {
+                Ok(packet) => packet,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return,
+            };
+            // IPv4 原始套接字收到的数据包含 IP 头，IPv6 不包含
+            let offset = if ipv4 { (data[0] & 0x0f) as usize * 4 } else { 0 };
+            if let Some(segment) = data.get(offset..len) {
+                pending.dispatch(from, segment, received);
+            }
+        }
+    }
+
+    // 接收一个报文，返回长度、来源地址与内核收包时间
+    fn recv(socket: &Socket, data: &mut [u8]) -> io::Result<(usize, IpAddr, Duration)> {
+        let mut control = [0u64; 16]; // 按 cmsghdr 对齐
+        let mut iov = libc::iovec { iov_base: data.as_mut_ptr() as *mut libc::c_void, iov_len: data.len() };
+        // SAFETY: msghdr 全部字段可为零，缓冲区在调用期间有效
+        unsafe {
+            let mut from: libc::sockaddr_storage = std::mem::zeroed();
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_name = &mut from as *mut libc::sockaddr_storage as *mut libc::c_void;
+            msg.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = std::mem::size_of_val(&control) as _;
+            let len = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            // 没有内核时间戳时使用当前时间
+            let mut received = now();
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while let Some(hdr) = cmsg.as_ref() {
+                if hdr.cmsg_level == libc::SOL_SOCKET && hdr.cmsg_type == libc::SCM_TIMESTAMPNS {
+                    let ts = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                    received = Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32);
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+
+            let ip = match i32::from(from.ss_family) {
+                libc::AF_INET => {
+                    let sa = std::ptr::read_unaligned(&from as *const _ as *const libc::sockaddr_in);
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)))
+                }
+                _ => {
+                    let sa = std::ptr::read_unaligned(&from as *const _ as *const libc::sockaddr_in6);
+                    IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr))
+                }
+            };
+            Ok((len as usize, ip, received))
+        }
+    }
+}
+
+// Windows：系统禁止通过原始套接字发送 TCP 报文 (自 XP SP2 起)，改由 WinDivert 驱动注入 SYN 并旁路接收回应；
+// 运行时加载 WinDivert.dll，需要管理员权限，系统协议栈照常为收到的 SYN-ACK 回复 RST，以驱动收包时间计时
+#[cfg(all(windows, feature = "windivert"))]
+mod imp {
+    use std::ffi::c_void;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{GetLastError, ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_INSUFFICIENT_BUFFER, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+    use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+    use super::common::{self, Pending, TCP_PROTOCOL};
+    use crate::types::Config;
+
+    // 只接收发往本地端口范围的 SYN-ACK 与 RST，与 common::LOCAL_PORTS 一致
+    const FILTER: &[u8] = b"inbound and tcp.DstPort >= 61000 and tcp.DstPort < 65535 and tcp.Ack and (tcp.Syn or tcp.Rst)\0";
+    const LAYER_NETWORK: u32 = 0;
+    const FLAG_SNIFF: u64 = 0x0001;
+    // WINDIVERT_ADDRESS 的位域：Outbound、IPv6、IPChecksum、TCPChecksum
+    const ADDRESS_OUTBOUND: u32 = 1 << 17;
+    const ADDRESS_IPV6: u32 = 1 << 20;
+    const ADDRESS_CHECKSUMS: u32 = (1 << 21) | (1 << 22);
+    const TTL: u8 = 64;
+
+    // WINDIVERT_ADDRESS (80 字节)，网络层只用到时间戳与位域
+    #[repr(C)]
+    struct Address {
+        timestamp: i64,
+        flags: u32,
+        reserved: u32,
+        union: [u8; 64],
+    }
+    const _: () = assert!(std::mem::size_of::<Address>() == 80);
+
+    type OpenFn = unsafe extern "C" fn(*const u8, u32, i16, u64) -> HANDLE;
+    type RecvFn = unsafe extern "C" fn(HANDLE, *mut c_void, u32, *mut u32, *mut Address) -> i32;
+    type SendFn = unsafe extern "C" fn(HANDLE, *const c_void, u32, *mut u32, *const Address) -> i32;
+
+    pub struct Session {
+        handle: usize,
+        send: SendFn,
+        pending: Pending,
+        frequency: i64,
+    }
+
+    impl Session {
+        // 找不到 WinDivert.dll、不是管理员或驱动无法加载时返回原因
+        pub fn open(_config: &Config) -> Result<Self, String> {
+            let name: Vec<u16> = "WinDivert.dll".encode_utf16().chain(Some(0)).collect();
+            // SAFETY: 名称均以 0 结尾，取得的函数按 windivert.h 的声明转换
+            let (handle, recv, send) = unsafe {
+                let library = LoadLibraryW(name.as_ptr());
+                if library.is_null() {
+                    return Err("未找到 WinDivert.dll，请从 https://reqrypt.org/windivert.html 下载并放在程序目录".to_string());
+                }
+                let symbol = |name: &[u8]| GetProcAddress(library, name.as_ptr());
+                let (Some(open), Some(recv), Some(send)) =
+                    (symbol(b"WinDivertOpen\0"), symbol(b"WinDivertRecv\0"), symbol(b"WinDivertSend\0"))
+                else {
+                    return Err("WinDivert.dll 版本不支持，需要 2.x".to_string());
+                };
+                let open: OpenFn = std::mem::transmute(open);
+                let recv: RecvFn = std::mem::transmute(recv);
+                let send: SendFn = std::mem::transmute(send);
+                let handle = open(FILTER.as_ptr(), LAYER_NETWORK, 0, FLAG_SNIFF);
+                if handle == INVALID_HANDLE_VALUE {
+                    return Err(match GetLastError() {
+                        ERROR_ACCESS_DENIED => "需要管理员权限".to_string(),
+                        ERROR_FILE_NOT_FOUND => "未找到 WinDivert 驱动 (WinDivert64.sys)".to_string(),
+                        e => format!("无法打开 WinDivert：{}", io::Error::from_raw_os_error(e as i32)),
+                    });
+                }
+                (handle as usize, recv, send)
+            };
+
+            let mut frequency = 0;
+            // SAFETY: 参数为有效的 i64 指针
+            unsafe { QueryPerformanceFrequency(&mut frequency) };
+            let pending = Pending::default();
+            let waiters = pending.clone();
+            std::thread::Builder::new()
+                .name("syn-recv".to_string())
+                .spawn(move || receive(handle, recv, frequency, waiters))
+                .map_err(|e| format!("无法启动接收线程：{}", e))?;
+            Ok(Self { handle, send, pending, frequency })
+        }
+
+        pub async fn probe(&self, addr: SocketAddr, config: &Config) -> Option<io::Result<Duration>> {
+            Some(self.send_probe(addr, config).await)
+        }
+
+        async fn send_probe(&self, addr: SocketAddr, config: &Config) -> io::Result<Duration> {
+            let source = common::source_address(addr, config)?;
+            let probe = self.pending.register(addr);
+            let segment = common::syn_packet(source, addr, probe.port(), probe.seq());
+            let packet = ip_packet(source, addr.ip(), &segment);
+
+            let mut address = Address { timestamp: 0, flags: ADDRESS_OUTBOUND | ADDRESS_CHECKSUMS, reserved: 0, union: [0; 64] };
+            if addr.is_ipv6() {
+                address.flags |= ADDRESS_IPV6;
+            }
+            let sent = now(self.frequency);
+            // SAFETY: 句柄在进程退出前保持打开，报文与地址在调用期间有效
+            let ok = unsafe {
+                (self.send)(self.handle as HANDLE, packet.as_ptr() as *const c_void, packet.len() as u32, std::ptr::null_mut(), &address)
+            };
+            if ok == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            probe.wait(sent).await
+        }
+    }
+
+    // WinDivert 在网络层收发完整的 IP 报文，IP 头需自行填写
+    fn ip_packet(source: IpAddr, dest: IpAddr, segment: &[u8]) -> Vec<u8> {
+        match (source, dest) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                let mut packet = vec![0x45, 0];
+                packet.extend(((20 + segment.len()) as u16).to_be_bytes());
+                packet.extend(rand::random::<u16>().to_be_bytes());
+                packet.extend([0x40, 0, TTL, TCP_PROTOCOL, 0, 0]); // 不分片
+                packet.extend(s.octets());
+                packet.extend(d.octets());
+                let sum = common::header_checksum(&packet);
+                packet[10..12].copy_from_slice(&sum.to_be_bytes());
+                packet.extend(segment);
+                packet
+            }
+            (s, d) => {
+                let mut packet = vec![0x60, 0, 0, 0];
+                packet.extend((segment.len() as u16).to_be_bytes());
+                packet.extend([TCP_PROTOCOL, TTL]);
+                packet.extend(common::to_v6(s).octets());
+                packet.extend(common::to_v6(d).octets());
+                packet.extend(segment);
+                packet
+            }
+        }
+    }
+
+    // 与 WINDIVERT_ADDRESS 时间戳相同的时钟 (QueryPerformanceCounter)
+    fn now(frequency: i64) -> Duration {
+        let mut counter = 0;
+        // SAFETY: 参数为有效的 i64 指针
+        unsafe { QueryPerformanceCounter(&mut counter) };
+        ticks(counter, frequency)
+    }
+
+    fn ticks(counter: i64, frequency: i64) -> Duration {
+        Duration::from_nanos((counter.max(0) as u128 * 1_000_000_000 / frequency.max(1) as u128) as u64)
+    }
+
+    // 后台线程：接收通过过滤的报文，交给对应的等待中的探测
+    fn receive(handle: usize, recv: RecvFn, frequency: i64, pending: Pending) {
+        let mut data = [0u8; 1500];
+        loop {
+            let mut len = 0u32;
+            let mut address = Address { timestamp: 0, flags: 0, reserved: 0, union: [0; 64] };
+            // SAFETY: 句柄在进程退出前保持打开，缓冲区与地址在调用期间有效
+            let ok = unsafe {
+                recv(handle as HANDLE, data.as_mut_ptr() as *mut c_void, data.len() as u32, &mut len, &mut address)
+            };
+            if ok == 0 {
+                // SAFETY: 无参数
+                match unsafe { GetLastError() } {
+                    ERROR_INSUFFICIENT_BUFFER => continue,
+                    _ => return,
+                }
+            }
+            let packet = &data[..len as usize];
+            let received = ticks(address.timestamp, frequency);
+            let (from, segment) = match packet.first().map(|b| b >> 4) {
+                Some(4) if packet.len() >= 20 => {
+                    let offset = (packet[0] & 0x0f) as usize * 4;
+                    let from = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+                    (IpAddr::V4(from), packet.get(offset..))
+                }
+                // 过滤条件已限定 TCP，带扩展首部的报文忽略
+                Some(6) if packet.len() >= 40 && packet[6] == TCP_PROTOCOL => {
+                    let from: [u8; 16] = packet[8..24].try_into().unwrap();
+                    (IpAddr::V6(Ipv6Addr::from(from)), packet.get(40..))
+                }
+                _ => continue,
+            };
+            if let Some(segment) = segment {
+                pending.dispatch(from, segment, received);
+            }
+        }
+    }
+}
+
+// 其他系统不支持：Windows 自 XP SP2 起禁止通过原始套接字发送 TCP 报文，需以 windivert 功能编译
+#[cfg(not(any(target_os = "linux", all(windows, feature = "windivert"))))]
+mod imp {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use crate::types::Config;
+
+    pub enum Session {}
+
+    impl Session {
+        pub fn open(_config: &Config) -> Result<Self, String> {
+            if cfg!(windows) {
+                return Err("未以 windivert 功能编译，Windows 不允许通过原始套接字发送 TCP 报文".to_string());
+            }
+            Err("当前系统不允许通过原始套接字发送 TCP 报文".to_string())
+        }
+
+        pub async fn probe(&self, _addr: SocketAddr, _config: &Config) -> Option<io::Result<Duration>> {
+            match *self {}
+        }
+    }
+}
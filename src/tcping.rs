@@ -1,217 +1,270 @@
-use std::net::IpAddr;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::types::{
-    Config, PingDelaySet, CloudflareIPData, PingData
-};
-use crate::httping::{self, HttpPing};
-use crate::progress::Bar;
-use crate::ip::{self, IPWithPort};
-use tokio::net::TcpStream;
-use std::sync::Mutex;
-use std::io;
-use crate::threadpool::GLOBAL_POOL;
-use hyper::{Client, Body};
-use hyper::client::HttpConnector;
-use hyper_tls::HttpsConnector;
-
-const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
-
-type HandlerResult = Option<PingData>;
-
-#[derive(Debug)]
-pub struct Ping {
-    m: Arc<Mutex<()>>,
-    ips: Vec<IpAddr>,
-    csv: PingDelaySet,
-    config: Config,
-    bar: Bar,
-    available_count: Arc<AtomicUsize>,
-}
-
-impl Ping {
-    fn check_ping_default(&mut self) {
-        if self.config.tcp_port <= 0 || self.config.tcp_port >= 65535 {
-            self.config.tcp_port = Config::default().tcp_port;
-        }
-        if self.config.ping_times <= 0 {
-            self.config.ping_times = Config::default().ping_times;
-        }
-    }
-
-    pub async fn run(mut self) -> anyhow::Result<PingDelaySet> {
-        self.check_ping_default();
-        
-        if self.ips.is_empty() {
-            return Ok(self.csv);
-        }
-
-        println!(
-            "开始延迟测速（模式：{}, 端口：{}, 范围：{} ~ {} ms, 丢包：{:.2}）",
-            if self.config.httping { "HTTP" } else { "TCP" },
-            self.config.tcp_port,
-            self.config.min_delay.as_millis(),
-            self.config.max_delay.as_millis(),
-            self.config.max_loss_rate
-        );
-
-        let results = Arc::new(Mutex::new(Vec::new()));
-        let mut handles = Vec::new();
-        
-        for ip in self.ips {
-            let config = self.config.clone();
-            let bar = self.bar.clone();
-            let available_count = self.available_count.clone();
-            let m = self.m.clone();
-            let results = Arc::clone(&results);
-            
-            let handle = tokio::spawn(async move {
-                // 使用 GLOBAL_POOL 控制并发
-                let permit = GLOBAL_POOL.acquire().await;
-                
-                let result = Self::tcping_handler(ip, &config).await;
-                if result.is_some() {
-                    available_count.fetch_add(1, Ordering::Relaxed);
-                }
-
-                if let Some(ping_data) = result {
-                    let _lock = m.lock().unwrap();
-                    let mut ip_data = CloudflareIPData::new(ping_data);
-                    ip_data.config = config;
-                    let mut results = results.lock().unwrap();
-                    results.push(ip_data);
-                    let now_able = results.len();
-                    bar.grow(1, &now_able.to_string());
-                } else {
-                    let results = results.lock().unwrap();
-                    bar.grow(1, &results.len().to_string());
-                }
-                
-                drop(permit);
-            });
-
-            handles.push(handle);
-        }
-
-        // 等待所有任务完成
-        futures::future::join_all(handles).await;
-
-        // 获取结果
-        let mut results = results.lock().unwrap();
-        self.csv = results.drain(..).collect();
-        drop(results);
-
-        self.bar.done();
-        self.csv.sort();
-        Ok(self.csv)
-    }
-
-    pub async fn tcping_handler(ip: IpAddr, config: &Config) -> HandlerResult {
-        let ip_with_port = IPWithPort { 
-            ip, 
-            port: None 
-        };
-
-        if config.httping {
-            let http_ping = HttpPing::new(config.clone(), Some(&config.httping_cf_colo));
-            let mut http = HttpConnector::new();
-            http.set_local_address(Some(ip));
-            let https = HttpsConnector::new_with_connector(http);
-            let _client = Client::builder().build::<_, Body>(https);
-            
-            if !http_ping.check_connection(&_client, &config.url).await? {
-                return None;
-            }
-            httping::http_ping(config, ip).await
-        } else {
-            Self::check_connection(&ip_with_port, config).await
-        }
-    }
-
-    pub async fn check_connection(ip_with_port: &IPWithPort, config: &Config) -> Option<PingData> {
-        let mut http = HttpConnector::new();
-        http.set_local_address(Some(ip_with_port.ip));
-        let https = HttpsConnector::new_with_connector(http);
-        let _client = Client::builder().build::<_, Body>(https);
-
-        let mut received = 0;
-        let mut total_delay = Duration::ZERO;
-        let mut delays = Vec::with_capacity(config.ping_times as usize);
-
-        // 收集所有成功的延迟测量
-        for _ in 0..config.ping_times {
-            if let Some(delay) = tcping(ip_with_port, config).await {
-                received += 1;
-                total_delay += delay;
-                delays.push(delay);
-            }
-        }
-
-        if received == 0 {
-            return None;
-        }
-
-        // 计算平均延迟，保持精确度
-        let avg_delay = if delays.len() > 2 {
-            // 去掉最高和最低值后取平均
-            delays.sort();
-            let valid_delays = &delays[1..delays.len()-1];
-            let sum: Duration = valid_delays.iter().sum();
-            sum / valid_delays.len() as u32
-        } else {
-            total_delay / received as u32
-        };
-
-        Some(PingData::new(
-            ip_with_port.ip,
-            config.ping_times,
-            received,
-            avg_delay,
-        ))
-    }
-}
-
-pub async fn new_ping(config: Config) -> io::Result<Ping> {
-    let ips = ip::load_ip_ranges_concurrent(&config).await?;
-    Ok(Ping {
-        m: Arc::new(Mutex::new(())),
-        ips: ips.clone(),
-        csv: Vec::new(),
-        config,
-        bar: Bar::new(ips.len() as u64, "可用:", ""),
-        available_count: Arc::new(AtomicUsize::new(0)),
-    })
-}
-
-pub async fn tcping(ip_with_port: &IPWithPort, config: &Config) -> Option<Duration> {
-    let task_id = rand::random::<usize>();
-    GLOBAL_POOL.start_task(task_id);
-
-    let port = ip_with_port.get_port(config.tcp_port);
-    let addr = if ip_with_port.ip.is_ipv4() {
-        format!("{}:{}", ip_with_port.ip, port)
-    } else {
-        format!("[{}]:{}", ip_with_port.ip, port)
-    };
-
-    let start = Instant::now();
-    
-    let result = match tokio::time::timeout(
-        TCP_CONNECT_TIMEOUT,
-        TcpStream::connect(&addr)
-    ).await {
-        Ok(Ok(stream)) => {
-            // 只有成功建立连接才记录进展
-            GLOBAL_POOL.record_progress(task_id);
-            let duration = start.elapsed();
-            drop(stream);
-            Some(duration)
-        },
-        Ok(Err(_)) | Err(_) => None
-    };
-
-    GLOBAL_POOL.end_task(task_id);
-    result
-}
-
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::types::{
+    Config, PingDelaySet, CloudflareIPData, PingData
+};
+use crate::httping;
+use crate::progress::Bar;
+use crate::ip::{self, IPWithPort, IpStream};
+use tokio::task::JoinSet;
+use std::sync::Mutex;
+use std::io;
+use crate::threadpool::{GLOBAL_POOL, Outcome};
+use crate::checkpoint::Checkpoint;
+use crate::failure::{self, ProbeError};
+use crate::{aggregate, cancel, csv, interface, pipeline, ratelimit, synping, tui, warp};
+
+
+type HandlerResult = Result<PingData, ProbeError>;
+
+#[derive(Debug)]
+pub struct Ping {
+    ips: IpStream,
+    csv: PingDelaySet,
+    config: Config,
+    bar: Bar,
+    checkpoint: Checkpoint,
+}
+
+impl Ping {
+    fn check_ping_default(&mut self) {
+        if self.config.tcp_port == 0 || self.config.tcp_port == u16::MAX {
+            self.config.tcp_port = Config::default().tcp_port;
+        }
+        if self.config.ping_times == 0 {
+            self.config.ping_times = Config::default().ping_times;
+        }
+    }
+
+    // 待测速的 IP 数量
+    pub fn ip_count(&self) -> usize {
+        self.ips.total()
+    }
+
+    pub async fn run(mut self) -> anyhow::Result<PingDelaySet> {
+        self.check_ping_default();
+        if self.ips.is_empty() {
+            self.csv = resumed_results(&self.config, &self.checkpoint, &AtomicUsize::new(0));
+            self.csv.sort();
+            return Ok(self.csv);
+        }
+
+        // [-syn] 只用于 TCP 延迟测速，原始套接字不可用时改用 connect()
+        let syn = self.config.syn && !self.config.warp && !self.config.httping && synping::start(&self.config);
+        println!(
+            "开始延迟测速（模式：{}, 端口：{}, 范围：{} ~ {} ms, 丢包：{:.2}）",
+            if self.config.warp { "WARP" } else if self.config.httping { "HTTP" } else if syn { "TCP SYN" } else { "TCP" },
+            if self.config.ports.is_empty() { self.config.tcp_port.to_string() } else { self.config.ports.clone() },
+            self.config.min_delay.as_millis(),
+            self.config.max_delay.as_millis(),
+            self.config.max_loss_rate
+        );
+
+        let ips = std::mem::take(&mut self.ips);
+        self.csv = ping_all(&self.config, ips, &self.checkpoint, &self.bar, |ip_with_port, config| async move {
+            Self::tcping_handler(&ip_with_port, &config).await
+        })
+        .await;
+        Ok(self.csv)
+    }
+
+    pub async fn tcping_handler(ip_with_port: &IPWithPort, config: &Config) -> HandlerResult {
+        let ip = ip_with_port.ip;
+        if config.warp {
+            warp::check_connection(ip_with_port, config).await
+        } else if config.httping {
+            // 状态码与 [-cfcolo] 由 http_ping 的第一次请求检查
+            httping::http_ping(config, ip, ip_with_port.get_port(config.tcp_port)).await
+        } else {
+            Self::check_connection(ip_with_port, config).await
+        }
+    }
+
+    pub async fn check_connection(ip_with_port: &IPWithPort, config: &Config) -> HandlerResult {
+        let mut delays = Vec::with_capacity(config.ping_times as usize);
+        let mut last_error = ProbeError::Timeout;
+
+        // 收集所有成功的延迟测量，全部失败时返回最后一次的原因
+        for _ in 0..config.ping_times {
+            match tcping(ip_with_port, config).await {
+                Ok(delay) => delays.push(delay),
+                Err(e) => last_error = e,
+            }
+        }
+
+        let port = ip_with_port.get_port(config.tcp_port);
+        let ping = PingData::from_delays(ip_with_port.ip, port, config.ping_times, delays).ok_or(last_error)?;
+        if config.expects_body() {
+            httping::verify_body(config, ip_with_port.ip, port).await?;
+        }
+        Ok(ping)
+    }
+}
+
+// 延迟测速的调度：按需生成候选 IP，获取到并发许可后才创建任务，处理 [-stop-after]、中断与检查点；
+// probe 测量单个候选 (TCPing、HTTPing 或 WARP)，返回的任务在后台执行
+pub async fn ping_all<P, F>(config: &Config, mut ips: IpStream, checkpoint: &Checkpoint, bar: &Bar, probe: P) -> PingDelaySet
+where
+    P: Fn(IPWithPort, Arc<Config>) -> F,
+    F: Future<Output = HandlerResult> + Send + 'static,
+{
+    let qualified = Arc::new(AtomicUsize::new(0));
+    let results = Arc::new(Mutex::new(resumed_results(config, checkpoint, &qualified)));
+    let shared = Arc::new(config.clone());
+    let mut tasks = JoinSet::new();
+
+    let mut scanned = None;
+    while let Some(ip_with_port) = ips.next() {
+        let position = ips.position() - 1;
+        let permit = GLOBAL_POOL.acquire().await;
+        if reached_stop_after(config, &qualified) || tui::abort_requested() {
+            tasks.abort_all();
+            scanned = Some(position);
+            break;
+        }
+        // 收到中断信号时等待进行中的测速结束，保留已完成的结果
+        if cancel::requested() {
+            cancel::drain(&mut tasks).await;
+            scanned = Some(position);
+            break;
+        }
+        checkpoint.begin(position);
+        let ip = ip_with_port.ip;
+        let port = ip_with_port.get_port(config.tcp_port);
+        let probing = probe(ip_with_port, shared.clone());
+        let task_checkpoint = checkpoint.clone();
+        let qualified = qualified.clone();
+        let config = shared.clone();
+        let results = Arc::clone(&results);
+        let bar = bar.clone();
+
+        tasks.spawn(async move {
+            let result = probing.await;
+            task_checkpoint.complete(position, result.as_ref().ok());
+            match result {
+                Ok(ping_data) => {
+                    tui::record_ping(&ping_data);
+                    let mut ip_data = CloudflareIPData::new(ping_data);
+                    let passed = ip_data.meets_ping_filters(&config);
+                    if passed {
+                        qualified.fetch_add(1, Ordering::Relaxed);
+                    }
+                    ip_data.config = (*config).clone();
+                    if passed {
+                        csv::stream_record(&ip_data);
+                        pipeline::offer(&ip_data);
+                    }
+                    let mut results = results.lock().unwrap();
+                    results.push(ip_data);
+                    let now_able = results.len();
+                    bar.grow(1, &now_able.to_string());
+                }
+                Err(e) => {
+                    failure::record(ip, port, "ping", &e);
+                    aggregate::record_failed(ip);
+                    let results = results.lock().unwrap();
+                    bar.grow(1, &results.len().to_string());
+                }
+            }
+            drop(permit);
+        });
+
+        // 回收已完成的任务
+        while tasks.try_join_next().is_some() {}
+        checkpoint.save_if_due(ips.position());
+    }
+
+    // 等待所有任务完成
+    checkpoint.join_all(&mut tasks, scanned.unwrap_or(ips.position())).await;
+
+    let mut ping_data: PingDelaySet = results.lock().unwrap().drain(..).collect();
+    bar.done();
+    ping_data.sort();
+    ping_data
+}
+
+// 满足条件的 IP 达到 [-stop-after] 时提前结束延迟测速
+pub fn reached_stop_after(config: &Config, qualified: &AtomicUsize) -> bool {
+    if config.stop_after == 0 || qualified.load(Ordering::Relaxed) < config.stop_after {
+        return false;
+    }
+    println!("\n[信息] 已找到 {} 个满足条件的 IP，提前结束延迟测速", config.stop_after);
+    true
+}
+
+// 从检查点恢复的结果直接计入，满足条件的同样计入 [-stop-after]
+pub fn resumed_results(config: &Config, checkpoint: &Checkpoint, qualified: &AtomicUsize) -> PingDelaySet {
+    checkpoint.resumed()
+        .into_iter()
+        .map(|ping_data| {
+            let mut ip_data = CloudflareIPData::new(ping_data);
+            let passed = ip_data.meets_ping_filters(config);
+            if passed {
+                qualified.fetch_add(1, Ordering::Relaxed);
+            }
+            ip_data.config = config.clone();
+            if passed {
+                csv::stream_record(&ip_data);
+                pipeline::offer(&ip_data);
+            }
+            ip_data
+        })
+        .collect()
+}
+
+pub async fn new_ping(config: Config) -> io::Result<Ping> {
+    let ips = ip::ip_stream(&config).await?;
+    Ok(ping_with(config, ips, Checkpoint::default()))
+}
+
+// 使用已生成的候选流，[-resume] 时由检查点生成
+pub fn ping_with(config: Config, ips: IpStream, checkpoint: Checkpoint) -> Ping {
+    Ping {
+        bar: Bar::new(ips.total() as u64, "可用:", "").phase("延迟测速"),
+        ips,
+        csv: Vec::new(),
+        config,
+        checkpoint,
+    }
+}
+
+pub async fn tcping(ip_with_port: &IPWithPort, config: &Config) -> Result<Duration, ProbeError> {
+    let task = GLOBAL_POOL.start_task();
+
+    let port = ip_with_port.get_port(config.tcp_port);
+    let addr = SocketAddr::new(ip_with_port.ip, port);
+
+    ratelimit::acquire().await;
+    let start = Instant::now();
+    // [-syn] 可用时只发送 SYN 计时，否则以 connect() 完成握手计时
+    let connected = async {
+        if let Some(result) = synping::probe(addr, config).await {
+            return result;
+        }
+        let stream = interface::connect(addr, config).await?;
+        let duration = start.elapsed();
+        drop(stream);
+        Ok(duration)
+    };
+
+    match tokio::time::timeout(config.connect_timeout, connected).await {
+        Ok(Ok(duration)) => {
+            // 只有成功建立连接才记录进展
+            task.record_progress();
+            GLOBAL_POOL.record_outcome(Outcome::Success);
+            Ok(duration)
+        },
+        Ok(Err(e)) => {
+            GLOBAL_POOL.record_outcome(Outcome::from_io_error(&e));
+            Err(ProbeError::from_io_error(&e))
+        }
+        Err(_) => {
+            GLOBAL_POOL.record_outcome(Outcome::Timeout);
+            Err(ProbeError::Timeout)
+        }
+    }
+}
+
@@ -1,124 +1,286 @@
-use crate::debug_log;
-use std::sync::Arc;
-use tokio::sync::{Semaphore, OwnedSemaphorePermit};
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
-use std::collections::HashMap;
-
-pub struct DynamicThreadPool {
-    semaphore: Arc<Semaphore>,
-    stats: Arc<Mutex<ThreadStats>>,
-    cpu_count: usize,
-}
-
-struct ThreadStats {
-    threads_per_core: usize,
-    stalled_tasks: usize,
-    active_tasks: usize,
-    last_adjust: Instant,
-    last_progress: Arc<Mutex<HashMap<usize, Instant>>>,
-}
-
-impl DynamicThreadPool {
-    pub fn new() -> Self {
-        let cpu_count = num_cpus::get();
-        let initial_threads = cpu_count * 64;
-        
-        debug_log!("初始化线程池: CPU核心数={}, 初始线程数={}", cpu_count, initial_threads);
-        
-        Self {
-            semaphore: Arc::new(Semaphore::new(initial_threads)),
-            stats: Arc::new(Mutex::new(ThreadStats {
-                threads_per_core: 64,
-                stalled_tasks: 0,
-                active_tasks: 0,
-                last_adjust: Instant::now(),
-                last_progress: Arc::new(Mutex::new(HashMap::new())),
-            })),
-            cpu_count,
-        }
-    }
-
-    pub async fn acquire(&self) -> OwnedSemaphorePermit {
-        self.adjust_threads();
-        self.semaphore.clone().acquire_owned().await.unwrap()
-    }
-
-    pub fn record_progress(&self, task_id: usize) {
-        let stats = self.stats.lock().unwrap();
-        stats.last_progress.lock().unwrap().insert(task_id, Instant::now());
-    }
-
-    pub fn start_task(&self, task_id: usize) {
-        let mut stats = self.stats.lock().unwrap();
-        stats.active_tasks += 1;
-        stats.last_progress.lock().unwrap().insert(task_id, Instant::now());
-    }
-
-    pub fn end_task(&self, task_id: usize) {
-        let mut stats = self.stats.lock().unwrap();
-        stats.active_tasks -= 1;
-        stats.last_progress.lock().unwrap().remove(&task_id);
-    }
-
-    fn adjust_threads(&self) {
-        let now = Instant::now();
-        
-        let adjust_needed = {
-            let stats = self.stats.lock().unwrap();
-            now.duration_since(stats.last_adjust) >= Duration::from_secs(1)
-        };
-
-        if !adjust_needed {
-            return;
-        }
-
-        let (stalled, active_tasks, current_threads) = {
-            let stats = self.stats.lock().unwrap();
-            let progress_map = stats.last_progress.lock().unwrap();
-            let stalled = progress_map.iter()
-                .filter(|(_, last_time)| now.duration_since(**last_time) > Duration::from_secs(2))
-                .count();
-            (stalled, stats.active_tasks, stats.threads_per_core)
-        };
-
-        if active_tasks > 0 {
-            let stall_ratio = stalled as f64 / active_tasks as f64;
-            debug_log!("线程状态: 活跃任务={}, 卡顿任务={}, 卡顿比例={:.2}%, 当前每核心线程数={}", 
-                active_tasks, stalled, stall_ratio * 100.0, current_threads);
-
-            let mut stats = self.stats.lock().unwrap();
-            stats.stalled_tasks = stalled;
-
-            let new_threads_per_core = if stall_ratio > 0.2 {
-                ((current_threads as f64 * 0.75) as usize).max(32)
-            } else if stall_ratio < 0.05 {
-                ((current_threads as f64 * 1.25) as usize)
-                    .min(128)
-                    .min(1024 / self.cpu_count)
-            } else {
-                current_threads
-            };
-
-            if new_threads_per_core != current_threads {
-                let new_total = new_threads_per_core * self.cpu_count;
-                let current_total = current_threads * self.cpu_count;
-                
-                if new_total > current_total {
-                    debug_log!("增加线程数: 每核心 {} -> {}", current_threads, new_threads_per_core);
-                    self.semaphore.add_permits(new_total - current_total);
-                } else {
-                    debug_log!("减少线程数: 每核心 {} -> {}", current_threads, new_threads_per_core);
-                }
-                
-                let mut stats = self.stats.lock().unwrap();
-                stats.threads_per_core = new_threads_per_core;
-                stats.last_adjust = now;
-            }
-        }
-    }
-}
-
-lazy_static::lazy_static! {
-    pub static ref GLOBAL_POOL: DynamicThreadPool = DynamicThreadPool::new();
-} 
\ No newline at end of file
+use crate::debug_log;
+use std::sync::Arc;
+use tokio::sync::{Semaphore, OwnedSemaphorePermit};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+
+pub const DEFAULT_MAX_CONCURRENCY: usize = 1024; // 默认并发上限
+
+const ADAPTIVE_INITIAL: usize = 64;       // 自适应模式初始并发
+const ADAPTIVE_MIN: usize = 8;            // 自适应模式最低并发
+const ADAPTIVE_STEP: usize = 32;          // 加性增大步长
+const ADAPTIVE_DECREASE: f64 = 0.7;       // 乘性减小系数
+const ADAPTIVE_INTERVAL: Duration = Duration::from_millis(500);
+const ADAPTIVE_MIN_SAMPLES: u64 = 20;     // 每个窗口最少样本数
+const TIMEOUT_TOLERANCE: f64 = 0.1;       // 超时率高于基线该值时视为拥塞
+
+// 单次探测的结果，用于自适应并发控制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,       // 收到对端响应（含拒绝连接）
+    Timeout,       // 超时
+    LocalPressure, // 本地资源不足（文件描述符、端口、发送缓冲区耗尽等）
+}
+
+impl Outcome {
+    // 根据连接错误判断是否为本地资源不足
+    pub fn from_io_error(e: &std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        match e.kind() {
+            ErrorKind::TimedOut => return Outcome::Timeout,
+            ErrorKind::AddrNotAvailable | ErrorKind::OutOfMemory | ErrorKind::WouldBlock => {
+                return Outcome::LocalPressure;
+            }
+            _ => {}
+        }
+        // 各系统的错误码不同，按 libc 常量匹配
+        #[cfg(unix)]
+        if matches!(
+            e.raw_os_error(),
+            Some(libc::EAGAIN | libc::ENFILE | libc::EMFILE | libc::EADDRNOTAVAIL | libc::ENOBUFS)
+        ) {
+            return Outcome::LocalPressure;
+        }
+        Outcome::Success
+    }
+}
+
+pub struct DynamicThreadPool {
+    semaphore: Arc<Semaphore>,
+    stats: Arc<Mutex<ThreadStats>>,
+    cpu_count: usize,
+}
+
+struct ThreadStats {
+    limit: usize,            // 当前并发数
+    debt: usize,             // 减小并发时尚未收回的许可数
+    max_concurrency: usize,  // 并发上限
+    adaptive: bool,          // 是否按超时率自适应调整
+    stalled_tasks: usize,
+    active_tasks: usize,
+    last_adjust: Instant,
+    last_progress: HashMap<usize, Instant>,
+    // 自适应模式的当前窗口统计
+    successes: u64,
+    timeouts: u64,
+    pressure: u64,
+    baseline_timeout_rate: Option<f64>,
+}
+
+impl DynamicThreadPool {
+    pub fn new() -> Self {
+        let cpu_count = num_cpus::get();
+        let initial_threads = (cpu_count * 64).min(DEFAULT_MAX_CONCURRENCY);
+
+        debug_log!("初始化线程池: CPU核心数={}, 初始线程数={}", cpu_count, initial_threads);
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(initial_threads)),
+            stats: Arc::new(Mutex::new(ThreadStats {
+                limit: initial_threads,
+                debt: 0,
+                max_concurrency: DEFAULT_MAX_CONCURRENCY,
+                adaptive: false,
+                stalled_tasks: 0,
+                active_tasks: 0,
+                last_adjust: Instant::now(),
+                last_progress: HashMap::new(),
+                successes: 0,
+                timeouts: 0,
+                pressure: 0,
+                baseline_timeout_rate: None,
+            })),
+            cpu_count,
+        }
+    }
+
+    // 设置调度模式与并发上限，在测速开始前调用
+    pub fn configure(&self, adaptive: bool, max_concurrency: usize) {
+        let max_concurrency = max_concurrency.max(1);
+        let mut stats = self.stats.lock().unwrap();
+        stats.adaptive = adaptive;
+        stats.max_concurrency = max_concurrency;
+        stats.successes = 0;
+        stats.timeouts = 0;
+        stats.pressure = 0;
+        stats.baseline_timeout_rate = None;
+        stats.last_adjust = Instant::now();
+
+        let target = if adaptive {
+            ADAPTIVE_INITIAL.min(max_concurrency)
+        } else {
+            stats.limit.min(max_concurrency)
+        };
+        debug_log!("线程池配置: 自适应={}, 并发上限={}, 当前并发={}", adaptive, max_concurrency, target);
+        self.resize(&mut stats, target);
+    }
+
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.adjust_threads();
+        self.semaphore.clone().acquire_owned().await.unwrap()
+    }
+
+    fn record_progress(&self, task_id: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.last_progress.insert(task_id, Instant::now());
+    }
+
+    // 记录一次探测结果，供自适应模式计算超时率
+    pub fn record_outcome(&self, outcome: Outcome) {
+        let mut stats = self.stats.lock().unwrap();
+        match outcome {
+            Outcome::Success => stats.successes += 1,
+            Outcome::Timeout => stats.timeouts += 1,
+            Outcome::LocalPressure => stats.pressure += 1,
+        }
+    }
+
+    // 开始一个测速任务，返回的 TaskGuard 离开作用域时结束任务
+    pub fn start_task(&'static self) -> TaskGuard {
+        let task_id = rand::random::<usize>();
+        let mut stats = self.stats.lock().unwrap();
+        stats.active_tasks += 1;
+        stats.last_progress.insert(task_id, Instant::now());
+        TaskGuard { pool: self, task_id }
+    }
+
+    fn end_task(&self, task_id: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.active_tasks = stats.active_tasks.saturating_sub(1);
+        stats.last_progress.remove(&task_id);
+    }
+
+    // 调整许可总数：增加时直接添加，减少时收回空闲许可，不足部分记为欠账稍后收回
+    fn resize(&self, stats: &mut ThreadStats, target: usize) {
+        if stats.debt > 0 {
+            let forgotten = self.semaphore.forget_permits(stats.debt);
+            stats.debt -= forgotten;
+        }
+        if target > stats.limit {
+            let mut add = target - stats.limit;
+            let repaid = add.min(stats.debt);
+            stats.debt -= repaid;
+            add -= repaid;
+            self.semaphore.add_permits(add);
+        } else if target < stats.limit {
+            let remove = stats.limit - target;
+            let forgotten = self.semaphore.forget_permits(remove);
+            stats.debt += remove - forgotten;
+        }
+        stats.limit = target;
+    }
+
+    fn adjust_threads(&self) {
+        let now = Instant::now();
+        let mut stats = self.stats.lock().unwrap();
+
+        if stats.adaptive {
+            if now.duration_since(stats.last_adjust) >= ADAPTIVE_INTERVAL {
+                self.adjust_adaptive(&mut stats, now);
+            }
+            return;
+        }
+
+        if now.duration_since(stats.last_adjust) < Duration::from_secs(1) {
+            return;
+        }
+
+        let stalled = stats.last_progress.values()
+            .filter(|last_time| now.duration_since(**last_time) > Duration::from_secs(2))
+            .count();
+        let active_tasks = stats.active_tasks;
+        // [-max-concurrency] 小于 CPU 核心数时每核心至少按 1 个计算
+        let current_threads = (stats.limit / self.cpu_count).max(1);
+
+        if active_tasks > 0 {
+            let stall_ratio = stalled as f64 / active_tasks as f64;
+            debug_log!("线程状态: 活跃任务={}, 卡顿任务={}, 卡顿比例={:.2}%, 当前每核心线程数={}",
+                active_tasks, stalled, stall_ratio * 100.0, current_threads);
+
+            stats.stalled_tasks = stalled;
+
+            let new_threads_per_core = if stall_ratio > 0.2 {
+                ((current_threads as f64 * 0.75) as usize).max(32)
+            } else if stall_ratio < 0.05 {
+                ((current_threads as f64 * 1.25) as usize)
+                    .min(128)
+                    .min((DEFAULT_MAX_CONCURRENCY / self.cpu_count).max(1))
+            } else {
+                current_threads
+            };
+
+            let new_total = (new_threads_per_core * self.cpu_count).min(stats.max_concurrency).max(1);
+            if new_total != stats.limit {
+                if new_total > stats.limit {
+                    debug_log!("增加线程数: 每核心 {} -> {}", current_threads, new_threads_per_core);
+                } else {
+                    debug_log!("减少线程数: 每核心 {} -> {}", current_threads, new_threads_per_core);
+                }
+                self.resize(&mut stats, new_total);
+                stats.last_adjust = now;
+            }
+        }
+    }
+
+    // AIMD：超时率明显高于基线或出现本地资源不足时乘性减小，否则加性增大
+    fn adjust_adaptive(&self, stats: &mut ThreadStats, now: Instant) {
+        let samples = stats.successes + stats.timeouts;
+        if samples < ADAPTIVE_MIN_SAMPLES && stats.pressure == 0 {
+            return;
+        }
+
+        let timeout_rate = if samples > 0 { stats.timeouts as f64 / samples as f64 } else { 0.0 };
+        // 基线为观测到的最低超时率（无响应的 IP 带来的固有超时），缓慢向上跟随
+        let baseline = match stats.baseline_timeout_rate {
+            Some(b) if timeout_rate < b => timeout_rate,
+            Some(b) => b + (timeout_rate - b) * 0.05,
+            None => timeout_rate,
+        };
+        stats.baseline_timeout_rate = Some(baseline);
+
+        let current = stats.limit;
+        let target = if stats.pressure > 0 || timeout_rate > baseline + TIMEOUT_TOLERANCE {
+            ((current as f64 * ADAPTIVE_DECREASE) as usize).max(ADAPTIVE_MIN)
+        } else {
+            (current + ADAPTIVE_STEP).min(stats.max_concurrency)
+        };
+
+        debug_log!("自适应并发: 样本={}, 超时率={:.2}%, 基线={:.2}%, 本地压力={}, 并发 {} -> {}",
+            samples, timeout_rate * 100.0, baseline * 100.0, stats.pressure, current, target);
+
+        stats.successes = 0;
+        stats.timeouts = 0;
+        stats.pressure = 0;
+        stats.last_adjust = now;
+        self.resize(stats, target);
+    }
+}
+
+// 进行中的测速任务；提前返回或任务被取消 (abort_all) 时同样会结束，避免统计残留到下一轮测速
+pub struct TaskGuard {
+    pool: &'static DynamicThreadPool,
+    task_id: usize,
+}
+
+impl TaskGuard {
+    pub fn record_progress(&self) {
+        self.pool.record_progress(self.task_id);
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.pool.end_task(self.task_id);
+    }
+}
+
+impl Default for DynamicThreadPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref GLOBAL_POOL: DynamicThreadPool = DynamicThreadPool::new();
+}
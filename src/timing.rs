@@ -0,0 +1,140 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+use hyper::header::{HeaderMap, HeaderValue, ACCEPT, CONNECTION, HOST, USER_AGENT};
+use crate::types::{self, CloudflareIPData, Config, Timing};
+use crate::progress::Bar;
+use crate::{interface, ratelimit, tls};
+use crate::debug_log;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const TIMING_CONCURRENCY: usize = 64;
+
+struct Target {
+    sni: String,
+    headers: String, // 已拼接好的请求头
+    path: String,
+    https: bool,
+}
+
+impl Target {
+    fn parse(config: &Config) -> Option<Self> {
+        let url = reqwest::Url::parse(&config.request_url()).ok()?;
+        let path = match url.query() {
+            Some(q) => format!("{}?{}", url.path(), q),
+            None => url.path().to_string(),
+        };
+        let sni = url.host_str()?.to_string();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_str(config.host_header().unwrap_or(&sni)).ok()?);
+        headers.insert(USER_AGENT, HeaderValue::from_static(types::USER_AGENT));
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        config.apply_headers(&mut headers);
+        headers.insert(CONNECTION, HeaderValue::from_static("close"));
+        let headers = headers.iter()
+            .filter_map(|(name, value)| Some(format!("{}: {}\r\n", name, value.to_str().ok()?)))
+            .collect();
+
+        Some(Self {
+            headers,
+            sni,
+            path,
+            https: url.scheme() == "https",
+        })
+    }
+}
+
+// 发送 HEAD 请求并等待响应的第一个字节
+async fn first_byte<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, target: &Target) -> Option<Duration> {
+    let request = format!(
+        "HEAD {} HTTP/1.1\r\n{}\r\n",
+        target.path, target.headers
+    );
+    let start = Instant::now();
+    stream.write_all(request.as_bytes()).await.ok()?;
+    let mut buf = [0u8; 1];
+    match stream.read(&mut buf).await {
+        Ok(n) if n > 0 => Some(start.elapsed()),
+        _ => None,
+    }
+}
+
+// 直连指定 IP，分别记录 TCP 连接、TLS 握手和首字节时间
+async fn probe(ip: IpAddr, port: u16, config: &Config, target: &Target, tls: &tokio_native_tls::TlsConnector) -> Option<Timing> {
+    let addr = SocketAddr::new(ip, port);
+
+    ratelimit::acquire().await;
+    let start = Instant::now();
+    let stream = timeout(PROBE_TIMEOUT.max(config.connect_timeout), interface::connect(addr, config)).await.ok()?.ok()?;
+    let connect = start.elapsed();
+
+    if target.https {
+        let start = Instant::now();
+        let mut stream = timeout(PROBE_TIMEOUT, tls.connect(&target.sni, stream)).await.ok()?.ok()?;
+        let tls_handshake = start.elapsed();
+        let ttfb = timeout(PROBE_TIMEOUT, first_byte(&mut stream, target)).await.ok()??;
+        Some(Timing { connect, tls_handshake, ttfb })
+    } else {
+        let mut stream = stream;
+        let ttfb = timeout(PROBE_TIMEOUT, first_byte(&mut stream, target)).await.ok()??;
+        Some(Timing { connect, tls_handshake: Duration::ZERO, ttfb })
+    }
+}
+
+// 多次测量取平均值
+async fn measure(ip: IpAddr, port: u16, config: &Config, target: &Target, tls: &tokio_native_tls::TlsConnector) -> Option<Timing> {
+    let mut samples = Vec::new();
+    for _ in 0..config.ping_times.max(1) {
+        if let Some(t) = probe(ip, port, config, target, tls).await {
+            samples.push(t);
+        }
+    }
+    if samples.is_empty() {
+        debug_log!("耗时测量失败: {}", ip);
+        return None;
+    }
+
+    let n = samples.len() as u32;
+    Some(Timing {
+        connect: samples.iter().map(|t| t.connect).sum::<Duration>() / n,
+        tls_handshake: samples.iter().map(|t| t.tls_handshake).sum::<Duration>() / n,
+        ttfb: samples.iter().map(|t| t.ttfb).sum::<Duration>() / n,
+    })
+}
+
+// 对延迟测速结果分阶段测量连接、TLS 握手、首字节耗时
+pub async fn measure_timing(config: &Config, data: &mut [CloudflareIPData]) {
+    if !config.timing || data.is_empty() {
+        return;
+    }
+    let target = match Target::parse(config) {
+        Some(t) => t,
+        None => return,
+    };
+    let tls = match tls::native_connector(config) {
+        Ok(c) => tokio_native_tls::TlsConnector::from(c),
+        Err(_) => return,
+    };
+
+    println!("开始分阶段耗时测量（数量：{}）", data.len());
+    let bar = Bar::new(data.len() as u64, "", "").phase("耗时测量");
+
+    futures::stream::iter(data.iter_mut())
+        .for_each_concurrent(TIMING_CONCURRENCY, |ip_data| {
+            let target = &target;
+            let tls = &tls;
+            let bar = &bar;
+            async move {
+                if let Some(timing) = measure(ip_data.ping_data.ip, ip_data.ping_data.port, config, target, tls).await {
+                    ip_data.timing = timing;
+                }
+                bar.grow(1, "");
+            }
+        })
+        .await;
+
+    bar.done();
+}
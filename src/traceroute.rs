@@ -0,0 +1,174 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use tokio::time::timeout;
+use crate::progress::Bar;
+use crate::types::{Config, DownloadSpeedSet, PathInfo};
+use crate::{cancel, geoip, interface, ratelimit};
+use crate::debug_log;
+
+const TRACE_CONCURRENCY: usize = 8;
+// 单个探测等待 ICMP 超时报文或连接建立的时长
+const HOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 单个 TTL 的探测结果
+enum Probe {
+    Reached,                       // 到达目标 (连接建立或被目标拒绝)
+    Hop(Option<IpAddr>, Duration), // 途经的路由器返回 ICMP 超时
+    Lost,                          // 没有回应
+}
+
+// 以指定 TTL 发起 TCP 连接 (SYN)：TTL 不足时由途经的路由器返回 ICMP 超时，
+// Linux 通过 IP_RECVERR 读取返回该报文的路由器地址，其他系统只能得到跳数
+async fn probe(addr: SocketAddr, ttl: u32, config: &Config) -> Probe {
+    let Ok(socket) = interface::tcp_socket(addr, config) else { return Probe::Lost };
+    let sock = socket2::SockRef::from(&socket);
+    let set = if addr.is_ipv4() { sock.set_ttl(ttl) } else { sock.set_unicast_hops_v6(ttl) };
+    if set.is_err() {
+        return Probe::Lost;
+    }
+    #[cfg(target_os = "linux")]
+    let errqueue = recverr::enable(&socket, addr.is_ipv4());
+
+    ratelimit::acquire().await;
+    let start = Instant::now();
+    match timeout(HOP_TIMEOUT.max(config.connect_timeout), socket.connect(addr)).await {
+        Ok(Ok(_)) => Probe::Reached,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => Probe::Reached,
+        Ok(Err(_)) => {
+            let rtt = start.elapsed();
+            #[cfg(target_os = "linux")]
+            if let Some(fd) = &errqueue {
+                return Probe::Hop(recverr::offender(fd), rtt);
+            }
+            Probe::Hop(None, rtt)
+        }
+        Err(_) => Probe::Lost,
+    }
+}
+
+// 同时发出 TTL 1..=max_ttl 的探测，最小的到达 TTL 即跳数
+async fn trace(ip: IpAddr, port: u16, config: &Config, asn_db: Option<&geoip::GeoDb>) -> PathInfo {
+    let addr = SocketAddr::new(ip, port);
+    let probes = futures::future::join_all((1..=config.trace_max_ttl as u32).map(|ttl| probe(addr, ttl, config))).await;
+
+    let hops = probes.iter().position(|p| matches!(p, Probe::Reached)).map(|i| i as u8 + 1);
+    let limit = hops.map(|h| h as usize - 1).unwrap_or(probes.len());
+    let routers: Vec<(IpAddr, Duration)> = probes[..limit]
+        .iter()
+        .filter_map(|p| match p {
+            Probe::Hop(Some(hop), rtt) if *hop != ip => Some((*hop, *rtt)),
+            _ => None,
+        })
+        .collect();
+    debug_log!("路径 {}: 跳数 {:?}，途经 {:?}", ip, hops, routers);
+
+    // 指定了 ASN 数据库时跳过与目标同一 ASN (CDN 内部) 的路由器
+    let target_asn = asn_db.map(|db| db.lookup(ip).asn).filter(|&asn| asn != 0);
+    let isp_hop = routers.iter().rev().find(|(hop, _)| match (target_asn, asn_db) {
+        (Some(asn), Some(db)) => db.lookup(*hop).asn != asn,
+        _ => true,
+    });
+    PathInfo {
+        hops,
+        isp_hop: isp_hop.map(|(hop, _)| *hop),
+        isp_hop_ms: isp_hop.map(|(_, rtt)| rtt.as_secs_f64() * 1000.0),
+    }
+}
+
+// [-trace]：对排名前 [-trace-n] 的 IP 探测路径，记录跳数与最后一个运营商路由器的延迟
+pub async fn run(config: &Config, data: &mut DownloadSpeedSet) {
+    if !config.trace || config.warp || data.is_empty() || cancel::requested() {
+        return;
+    }
+    let count = (config.trace_count as usize).min(data.len());
+    let db = geoip::database(config);
+
+    println!("\n开始路径探测（IP 数量：{}，最大跳数：{}）", count, config.trace_max_ttl);
+    let bar = Bar::new(count as u64, "", "").phase("路径探测");
+    futures::stream::iter(data[..count].iter_mut())
+        .for_each_concurrent(TRACE_CONCURRENCY, |ip_data| {
+            let bar = &bar;
+            let db = db.as_deref();
+            async move {
+                ip_data.path = Some(trace(ip_data.ping_data.ip, ip_data.ping_data.port, config, db).await);
+                bar.grow(1, "");
+            }
+        })
+        .await;
+    bar.done();
+}
+
+// Linux 下通过套接字错误队列读取 ICMP 报文的来源地址
+#[cfg(target_os = "linux")]
+mod recverr {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+
+    // 开启 IP_RECVERR / IPV6_RECVERR，返回复制的描述符，连接失败后套接字被关闭时仍可读取错误队列
+    pub fn enable(socket: &impl AsFd, ipv4: bool) -> Option<OwnedFd> {
+        let fd = socket.as_fd();
+        let on: libc::c_int = 1;
+        let (level, name) = if ipv4 { (libc::SOL_IP, libc::IP_RECVERR) } else { (libc::SOL_IPV6, libc::IPV6_RECVERR) };
+        // SAFETY: fd 为有效的套接字，选项值为 c_int
+        let ret = unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(), level, name,
+                &on as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return None;
+        }
+        fd.try_clone_to_owned().ok()
+    }
+
+    // 从错误队列取出一条 ICMP 错误，返回发送该报文的路由器地址
+    pub fn offender(fd: &OwnedFd) -> Option<IpAddr> {
+        let mut data = [0u8; 64];
+        let mut control = [0u64; 64]; // 按 cmsghdr 对齐
+        let mut iov = libc::iovec { iov_base: data.as_mut_ptr() as *mut libc::c_void, iov_len: data.len() };
+        // SAFETY: msghdr 全部字段可为零，缓冲区在调用期间有效
+        unsafe {
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = std::mem::size_of_val(&control) as _;
+            if libc::recvmsg(fd.as_raw_fd(), &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) < 0 {
+                return None;
+            }
+
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while let Some(hdr) = cmsg.as_ref() {
+                let is_error = (hdr.cmsg_level == libc::SOL_IP && hdr.cmsg_type == libc::IP_RECVERR)
+                    || (hdr.cmsg_level == libc::SOL_IPV6 && hdr.cmsg_type == libc::IPV6_RECVERR);
+                if is_error {
+                    let err = libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err;
+                    let origin = (*err).ee_origin;
+                    if origin == libc::SO_EE_ORIGIN_ICMP || origin == libc::SO_EE_ORIGIN_ICMP6 {
+                        return sockaddr_ip(libc::SO_EE_OFFENDER(err));
+                    }
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        None
+    }
+
+    // SAFETY: 调用方保证 addr 指向错误消息中的 sockaddr
+    unsafe fn sockaddr_ip(addr: *const libc::sockaddr) -> Option<IpAddr> {
+        match i32::from((*addr).sa_family) {
+            libc::AF_INET => {
+                let sa = std::ptr::read_unaligned(addr as *const libc::sockaddr_in);
+                Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr))))
+            }
+            libc::AF_INET6 => {
+                let sa = std::ptr::read_unaligned(addr as *const libc::sockaddr_in6);
+                Some(IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr)))
+            }
+            _ => None,
+        }
+    }
+}
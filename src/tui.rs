@@ -0,0 +1,533 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use lazy_static::lazy_static;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::{cursor, execute, terminal};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use tokio::runtime::Handle;
+use crate::cancel;
+use crate::ip::IPWithPort;
+use crate::tcping::Ping;
+use crate::types::{CloudflareIPData, Config, PingData};
+
+const LOG_LINES: usize = 200;
+const TICK: Duration = Duration::from_millis(150);
+
+type Output = Box<dyn Write + Send>;
+
+// 一个测速阶段的进度
+struct Phase {
+    title: String,
+    total: u64,
+    pos: u64,
+    msg: String,
+    done: bool,
+}
+
+type Key = (IpAddr, u16);
+
+// 结果表中的一行
+struct ResultRow {
+    sended: u32,
+    received: u32,
+    delay: f64,          // 平均延迟 (ms)
+    speed: Option<f64>,  // 下载速度 (字节/秒)
+    colo: String,
+    retesting: bool,
+}
+
+impl ResultRow {
+    fn loss_rate(&self) -> f64 {
+        if self.sended == 0 {
+            return 1.0;
+        }
+        1.0 - self.received as f64 / self.sended as f64
+    }
+}
+
+#[derive(Default)]
+struct State {
+    config: Option<Config>,
+    phases: Vec<Phase>,
+    rows: HashMap<Key, ResultRow>,
+    order: Vec<Key>, // 排序后的显示顺序
+    sorted: bool,
+    selected: Option<Key>,
+    log: VecDeque<String>,
+    finished: bool,
+}
+
+impl State {
+    // 有下载速度时按速度从高到低，否则按丢包率、延迟从低到高
+    fn sort(&mut self) {
+        if self.sorted {
+            return;
+        }
+        let rows = &self.rows;
+        self.order = rows.keys().copied().collect();
+        self.order.sort_by(|a, b| {
+            let (a, b) = (&rows[a], &rows[b]);
+            b.speed.unwrap_or(-1.0).total_cmp(&a.speed.unwrap_or(-1.0))
+                .then(a.loss_rate().total_cmp(&b.loss_rate()))
+                .then(a.delay.total_cmp(&b.delay))
+        });
+        self.sorted = true;
+    }
+
+    fn row_mut(&mut self, ip: IpAddr, port: u16) -> Option<&mut ResultRow> {
+        self.rows.get_mut(&(ip, port))
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        let selected = self.selected?;
+        self.order.iter().position(|&key| key == selected)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.order.is_empty() {
+            return;
+        }
+        let index = match self.selected_index() {
+            Some(i) => (i as isize + delta).clamp(0, self.order.len() as isize - 1) as usize,
+            None => 0,
+        };
+        self.selected = Some(self.order[index]);
+    }
+
+    fn push_log(&mut self, line: String) {
+        if self.log.len() >= LOG_LINES {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static ABORT: AtomicBool = AtomicBool::new(false);
+static ENTERED: AtomicBool = AtomicBool::new(false); // 是否已进入备用屏幕
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+    static ref UI_THREAD: Mutex<Option<std::thread::JoinHandle<()>>> = Mutex::new(None);
+    static ref CAPTURE: Mutex<Option<capture::Capture>> = Mutex::new(None);
+}
+
+// 是否正在使用交互界面 [-tui]
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// 进入交互界面：切换到备用屏幕，测速过程中的输出显示在界面的日志区域
+pub fn start(config: &Config) -> io::Result<()> {
+    let output = capture::start()?;
+    ACTIVE.store(true, Ordering::Relaxed);
+    let terminal = match init_terminal(output) {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            restore();
+            return Err(e);
+        }
+    };
+
+    // 发生 panic 时先恢复终端，避免终端停留在原始模式
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+    }));
+
+    STATE.lock().unwrap().config = Some(config.clone());
+    let handle = Handle::current();
+    *UI_THREAD.lock().unwrap() = Some(std::thread::spawn(move || ui_loop(terminal, handle)));
+    Ok(())
+}
+
+fn init_terminal(output: Output) -> io::Result<Terminal<CrosstermBackend<Output>>> {
+    terminal::enable_raw_mode()?;
+    let mut backend = CrosstermBackend::new(output);
+    execute!(backend, terminal::EnterAlternateScreen, cursor::Hide)?;
+    ENTERED.store(true, Ordering::Relaxed);
+    Terminal::new(backend)
+}
+
+// 测速结束：显示最终结果并等待用户关闭界面，之后恢复终端正常输出
+pub async fn finish(data: &[CloudflareIPData]) {
+    if !active() {
+        return;
+    }
+    {
+        let mut state = STATE.lock().unwrap();
+        for ip_data in data {
+            let ping = &ip_data.ping_data;
+            upsert(&mut state, ping);
+            if let Some(row) = state.row_mut(ping.ip, ping.port) {
+                row.colo = ip_data.colo.clone();
+                if ip_data.download_speed > 0.0 {
+                    row.speed = Some(ip_data.download_speed);
+                }
+            }
+        }
+        state.finished = true;
+    }
+    let ui = UI_THREAD.lock().unwrap().take();
+    if let Some(ui) = ui {
+        tokio::task::spawn_blocking(move || ui.join()).await.ok();
+    }
+}
+
+// 恢复终端，可重复调用
+fn restore() {
+    if !ACTIVE.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    terminal::disable_raw_mode().ok();
+    // 先恢复标准输出，再在进入备用屏幕的同一输出上离开
+    if let Some(capture) = CAPTURE.lock().unwrap().take() {
+        capture.restore();
+    }
+    if ENTERED.swap(false, Ordering::Relaxed) {
+        execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show).ok();
+    }
+}
+
+// 开始新的阶段，返回阶段序号
+pub fn begin_phase(title: &str, total: u64) -> usize {
+    ABORT.store(false, Ordering::Relaxed);
+    let mut state = STATE.lock().unwrap();
+    state.phases.push(Phase {
+        title: title.to_string(),
+        total,
+        pos: 0,
+        msg: String::new(),
+        done: false,
+    });
+    state.phases.len() - 1
+}
+
+pub fn advance(phase: usize, num: u64, msg: &str) {
+    if let Some(p) = STATE.lock().unwrap().phases.get_mut(phase) {
+        p.pos += num;
+        p.msg = msg.to_string();
+    }
+}
+
+pub fn end_phase(phase: usize) {
+    if let Some(p) = STATE.lock().unwrap().phases.get_mut(phase) {
+        p.done = true;
+    }
+}
+
+// 用户是否要求提前结束当前阶段
+pub fn abort_requested() -> bool {
+    active() && ABORT.load(Ordering::Relaxed)
+}
+
+fn upsert(state: &mut State, ping: &PingData) {
+    let delay = ping.delay.as_secs_f64() * 1000.0;
+    match state.row_mut(ping.ip, ping.port) {
+        Some(row) => {
+            row.sended = ping.sended;
+            row.received = ping.received;
+            row.delay = delay;
+            row.retesting = false;
+        }
+        None => {
+            state.rows.insert((ping.ip, ping.port), ResultRow {
+                sended: ping.sended,
+                received: ping.received,
+                delay,
+                speed: None,
+                colo: String::new(),
+                retesting: false,
+            });
+        }
+    }
+    state.sorted = false;
+}
+
+// 记录延迟测速结果
+pub fn record_ping(ping: &PingData) {
+    if active() {
+        upsert(&mut STATE.lock().unwrap(), ping);
+    }
+}
+
+// 记录下载测速结果
+pub fn record_speed(ip: IpAddr, port: u16, speed: f64) {
+    if !active() {
+        return;
+    }
+    let mut state = STATE.lock().unwrap();
+    if let Some(row) = state.row_mut(ip, port) {
+        row.speed = Some(speed);
+        state.sorted = false;
+    }
+}
+
+fn log(line: String) {
+    STATE.lock().unwrap().push_log(line);
+}
+
+// 界面线程：定时重绘并处理按键
+fn ui_loop(mut terminal: Terminal<CrosstermBackend<Output>>, handle: Handle) {
+    loop {
+        if terminal.draw(draw).is_err() {
+            break;
+        }
+        match event::poll(TICK) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => break,
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(_) => break,
+        };
+        if !handle_key(key, &handle) {
+            break;
+        }
+    }
+    restore();
+}
+
+// 处理按键，返回 false 时关闭界面
+fn handle_key(key: KeyEvent, handle: &Handle) -> bool {
+    let finished = STATE.lock().unwrap().finished;
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) && finished => return false,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => interrupt(),
+        KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter if finished => return false,
+        KeyCode::Char('q') | KeyCode::Esc => interrupt(),
+        KeyCode::Up | KeyCode::Char('k') => STATE.lock().unwrap().move_selection(-1),
+        KeyCode::Down | KeyCode::Char('j') => STATE.lock().unwrap().move_selection(1),
+        KeyCode::PageUp => STATE.lock().unwrap().move_selection(-10),
+        KeyCode::PageDown => STATE.lock().unwrap().move_selection(10),
+        KeyCode::Char('s') if !finished => {
+            ABORT.store(true, Ordering::Relaxed);
+            log("[信息] 已请求提前结束当前阶段".to_string());
+        }
+        KeyCode::Char('r') => retest_selected(handle),
+        _ => {}
+    }
+    true
+}
+
+// 测速进行中按 Ctrl-C / q / Esc：界面处于原始模式，Ctrl-C 不产生信号，与中断信号同样处理，
+// 第一次停止新的测速并在界面中显示部分结果，再次按下立即退出
+fn interrupt() {
+    if cancel::trigger() {
+        restore();
+        std::process::exit(cancel::EXIT_INTERRUPTED);
+    }
+}
+
+// 对选中的 IP 重新进行延迟测速
+fn retest_selected(handle: &Handle) {
+    let (target, config) = {
+        let mut state = STATE.lock().unwrap();
+        let Some((ip, port)) = state.selected else { return };
+        let Some(config) = state.config.clone() else { return };
+        if let Some(row) = state.row_mut(ip, port) {
+            if row.retesting {
+                return;
+            }
+            row.retesting = true;
+        }
+        (IPWithPort { ip, port: Some(port) }, config)
+    };
+    handle.spawn(async move {
+        match Ping::tcping_handler(&target, &config).await {
+            Ok(ping) => record_ping(&ping),
+            Err(e) => {
+                let mut state = STATE.lock().unwrap();
+                if let Some(row) = state.row_mut(target.ip, target.get_port(config.tcp_port)) {
+                    row.retesting = false;
+                }
+                state.push_log(format!("[信息] 重新测速 {} 失败：{}", target.ip, e));
+            }
+        }
+    });
+}
+
+fn draw(frame: &mut Frame) {
+    let mut state = STATE.lock().unwrap();
+    state.sort();
+    if state.selected.is_none() {
+        state.selected = state.order.first().copied();
+    }
+
+    let phase_height = state.phases.len().max(1) as u16 + 2;
+    let [phases_area, table_area, log_area, help_area] = Layout::vertical([
+        Constraint::Length(phase_height),
+        Constraint::Min(5),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    draw_phases(frame, &state, phases_area);
+    draw_table(frame, &state, table_area);
+
+    let lines = log_area.height.saturating_sub(2) as usize;
+    let log: Vec<&str> = state.log.iter().skip(state.log.len().saturating_sub(lines)).map(|s| s.as_str()).collect();
+    frame.render_widget(
+        Paragraph::new(log.join("\n")).block(Block::default().borders(Borders::ALL).title(" 输出 ")),
+        log_area,
+    );
+
+    let help = if state.finished {
+        "测速完成  ↑/↓ 选择  r 重新测速选中 IP  Enter/q 关闭界面并输出结果"
+    } else {
+        "↑/↓ 选择  r 重新测速选中 IP  s 提前结束当前阶段  q 中断测速 (再次按下立即退出)"
+    };
+    frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), help_area);
+}
+
+fn draw_phases(frame: &mut Frame, state: &State, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(" 进度 ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::vertical(vec![Constraint::Length(1); state.phases.len()]).split(inner);
+    for (phase, row) in state.phases.iter().zip(rows.iter()) {
+        let ratio = if phase.done || phase.total == 0 {
+            1.0
+        } else {
+            (phase.pos as f64 / phase.total as f64).min(1.0)
+        };
+        let label = format!("{} {}/{} {}", phase.title, phase.pos, phase.total, phase.msg);
+        let color = if phase.done { Color::Green } else { Color::Cyan };
+        frame.render_widget(Gauge::default().gauge_style(Style::default().fg(color)).ratio(ratio).label(label), *row);
+    }
+}
+
+fn draw_table(frame: &mut Frame, state: &State, area: Rect) {
+    let visible = area.height.saturating_sub(3) as usize;
+    let selected = state.selected_index();
+    // 只构建可见的行，结果很多时也能流畅刷新
+    let offset = match selected {
+        Some(i) if visible > 0 && i >= visible => i + 1 - visible,
+        _ => 0,
+    };
+
+    let rows = state.order.iter().skip(offset).take(visible).map(|key| {
+        let r = &state.rows[key];
+        let speed = match r.speed {
+            Some(speed) => format!("{:.2}", speed / 1024.0 / 1024.0),
+            None => "-".to_string(),
+        };
+        let delay = if r.retesting { "测速中".to_string() } else { format!("{:.2}", r.delay) };
+        Row::new(vec![
+            key.0.to_string(),
+            key.1.to_string(),
+            r.sended.to_string(),
+            r.received.to_string(),
+            format!("{:.2}", r.loss_rate()),
+            delay,
+            speed,
+            r.colo.clone(),
+        ])
+    });
+
+    let header = Row::new(["IP 地址", "端口", "已发送", "已接收", "丢包率", "平均延迟", "下载速度 (MB/s)", "地区码"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let widths = [
+        Constraint::Min(16),
+        Constraint::Length(6),
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Length(9),
+        Constraint::Length(16),
+        Constraint::Length(7),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title(format!(" 结果 ({}) ", state.rows.len())))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut table_state = TableState::default().with_selected(selected.map(|i| i - offset));
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
+// 界面运行期间把标准输出重定向到管道，读取的内容显示在日志区域，界面本身绘制到原来的标准输出
+#[cfg(unix)]
+mod capture {
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::io::FromRawFd;
+    use super::{Output, CAPTURE};
+
+    pub struct Capture {
+        saved: libc::c_int,
+    }
+
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret) }
+    }
+
+    pub fn start() -> io::Result<Output> {
+        io::stdout().flush()?;
+        let mut fds = [0 as libc::c_int; 2];
+        // SAFETY: 只操作本进程的文件描述符，失败时返回错误
+        unsafe {
+            let saved = check(libc::dup(libc::STDOUT_FILENO))?;
+            let terminal = check(libc::dup(saved))?;
+            check(libc::pipe(fds.as_mut_ptr()))?;
+            check(libc::dup2(fds[1], libc::STDOUT_FILENO))?;
+            libc::close(fds[1]);
+
+            let reader = File::from_raw_fd(fds[0]);
+            std::thread::spawn(move || {
+                for line in BufReader::new(reader).lines() {
+                    match line {
+                        Ok(line) if !line.trim().is_empty() => super::log(line),
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+            });
+
+            *CAPTURE.lock().unwrap() = Some(Capture { saved });
+            Ok(Box::new(File::from_raw_fd(terminal)))
+        }
+    }
+
+    impl Capture {
+        // 恢复标准输出，管道写端随之关闭，读取线程自然结束
+        pub fn restore(self) {
+            io::stdout().flush().ok();
+            // SAFETY: saved 为 start 中复制的原标准输出
+            unsafe {
+                libc::dup2(self.saved, libc::STDOUT_FILENO);
+                libc::close(self.saved);
+            }
+        }
+    }
+}
+
+// 其他平台不捕获输出，直接绘制到标准输出
+#[cfg(not(unix))]
+mod capture {
+    use std::io;
+    use super::Output;
+
+    pub struct Capture;
+
+    impl Capture {
+        pub fn restore(self) {}
+    }
+
+    pub fn start() -> io::Result<Output> {
+        Ok(Box::new(io::stdout()))
+    }
+}
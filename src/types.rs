@@ -1,258 +1,870 @@
-use std::net::IpAddr;
-use std::time::Duration;
-use std::cmp::Ordering;
-use thiserror::Error;
-use tokio::sync::AcquireError;
-
-#[derive(Error, Debug)]
-pub enum SpeedTestError {
-    #[error("IO error: {0}")]
-    IoError(#[from] std::io::Error),
-
-    #[error("Request error: {0}")]
-    RequestError(#[from] reqwest::Error),
-
-    #[error("CSV error: {0}")]
-    CsvError(#[from] csv::Error),
-
-    #[error("Thread control error: {0}")]
-    ThreadError(String),
-}
-
-#[derive(Debug, Clone)]
-pub struct Config {
-    pub ping_times: u32,          // 延迟测速次数
-    pub test_count: u32,         // 下载测速数量
-    pub download_time: Duration, // 下载测速时间
-    pub tcp_port: u16,          // 测速端口
-    pub url: String,            // 测速URL
-    
-    pub httping: bool,                // 是否使用HTTP测速
-    pub httping_status_code: u16,     // HTTP状态码
-    pub httping_cf_colo: String,      // 匹配指定地区
-    
-    pub max_delay: Duration,    // 平均延迟上限
-    pub min_delay: Duration,    // 平均延迟下限
-    pub max_loss_rate: f32,     // 丢包率上限
-    pub min_speed: f64,         // 下载速度下限
-    
-    pub print_num: u32,         // 显示结果数量
-    pub ip_file: String,        // IP段数据文件
-    pub ip_text: String,        // 指定IP段数据
-    pub output: String,         // 输出文件
-    
-    pub disable_download: bool, // 禁用下载测速
-    pub test_all: bool,        // 测试全部IP
-    pub ipv4_amount: Option<u32>,  // IPv4 测试数量
-    pub ipv6_amount: Option<u32>,  // IPv6 测试数量
-    pub ipv6_num_mode: Option<String>, // IPv6 数量模式
-    pub ipv4_num_mode: Option<String>, // IPv4 数量模式
-    pub max_ip_count: usize,  // 添加 IP 总量上限参数
-}
-
-impl Config {
-    pub fn is_test_all(&self) -> bool {
-        self.test_all
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct PingData {
-    pub ip: IpAddr,
-    pub sended: u32,
-    pub received: u32,
-    pub delay: Duration,
-}
-
-impl PingData {
-    pub fn new(ip: IpAddr, sended: u32, received: u32, delay: Duration) -> Self {
-        Self {
-            ip,
-            sended,
-            received,
-            delay,
-        }
-    }
-
-    pub fn loss_rate(&self) -> f32 {
-        if self.sended == 0 {
-            return 1.0;
-        }
-        (self.sended - self.received) as f32 / self.sended as f32
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct CloudflareIPData {
-    pub ping_data: PingData,
-    pub loss_rate: f32,
-    pub download_speed: f64,
-    pub config: Config,
-    pub colo: String,
-}
-
-impl CloudflareIPData {
-    pub fn new(ping_data: PingData) -> Self {
-        let loss_rate = ping_data.loss_rate();
-        Self {
-            ping_data,
-            loss_rate,
-            download_speed: 0.0,
-            config: Config::default(),
-            colo: String::new(),
-        }
-    }
-
-    pub fn to_string_vec(&self) -> Vec<String> {
-        vec![
-            self.ping_data.ip.to_string(),
-            self.ping_data.sended.to_string(),
-            self.ping_data.received.to_string(),
-            format!("{:.2}", self.loss_rate),
-            format!("{:.2}", self.ping_data.delay.as_secs_f64() * 1000.0),
-            format!("{:.2}", self.download_speed / 1024.0 / 1024.0),
-            self.colo.clone(),
-        ]
-    }
-}
-
-// 实现排序特性
-impl Ord for CloudflareIPData {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let self_rate = self.loss_rate;
-        let other_rate = other.loss_rate;
-        
-        if self_rate != other_rate {
-            self_rate.partial_cmp(&other_rate)
-                .unwrap_or(Ordering::Equal)
-        } else {
-            self.ping_data.delay.cmp(&other.ping_data.delay)
-        }
-    }
-}
-
-impl PartialOrd for CloudflareIPData {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl PartialEq for CloudflareIPData {
-    fn eq(&self, other: &Self) -> bool {
-        self.download_speed == other.download_speed &&
-        self.ping_data.delay == other.ping_data.delay
-    }
-}
-
-impl Eq for CloudflareIPData {}
-
-pub type PingDelaySet = Vec<CloudflareIPData>;
-pub type DownloadSpeedSet = Vec<CloudflareIPData>;
-
-pub trait DelayFilter {
-    fn filter_delay(self) -> Self;
-    fn filter_loss_rate(self) -> Self;
-}
-
-impl DelayFilter for PingDelaySet {
-    fn filter_delay(self) -> Self {
-        unsafe {
-            if INPUT_MAX_DELAY > MAX_DELAY || INPUT_MIN_DELAY < MIN_DELAY {
-                return self;
-            }
-            if INPUT_MAX_DELAY == MAX_DELAY && INPUT_MIN_DELAY == MIN_DELAY {
-                return self;
-            }
-            
-            let mut filtered = Vec::new();
-            for ip_data in self {
-                if ip_data.ping_data.delay > INPUT_MAX_DELAY {
-                    break;
-                }
-                if ip_data.ping_data.delay < INPUT_MIN_DELAY {
-                    continue;
-                }
-                filtered.push(ip_data);
-            }
-            filtered
-        }
-    }
-
-    fn filter_loss_rate(self) -> Self {
-        unsafe {
-            if INPUT_MAX_LOSS_RATE >= MAX_LOSS_RATE {
-                return self;
-            }
-            
-            let mut filtered = Vec::new();
-            for ip_data in self {
-                if ip_data.loss_rate > INPUT_MAX_LOSS_RATE {
-                    break;
-                }
-                filtered.push(ip_data);
-            }
-            filtered
-        }
-    }
-}
-
-// 这些常量和静态变量应该保留在 types.rs 中
-pub const MAX_DELAY: Duration = Duration::from_millis(9999);
-pub const MIN_DELAY: Duration = Duration::ZERO;
-pub const MAX_LOSS_RATE: f32 = 1.0;
-
-// 修改静态变量命名并添加 unsafe 块
-pub static mut INPUT_MAX_DELAY: Duration = MAX_DELAY;
-pub static mut INPUT_MIN_DELAY: Duration = MIN_DELAY;
-pub static mut INPUT_MAX_LOSS_RATE: f32 = MAX_LOSS_RATE;
-
-impl From<AcquireError> for SpeedTestError {
-    fn from(err: AcquireError) -> Self {
-        SpeedTestError::ThreadError(format!("线程控制失败: {}", err))
-    }
-} 
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            ping_times: 4,          // -t 4
-            test_count: 10,         // -dn 10
-            download_time: Duration::from_secs(10),  // -dt 10
-            tcp_port: 443,          // -tp 443
-            url: String::from("https://cf.xiu2.xyz/url"),  // -url
-            httping: false,         // -httping
-            httping_status_code: 200,  // -httping-code
-            httping_cf_colo: String::new(),  // -cfcolo (默认空)
-            max_delay: Duration::from_millis(9999),  // -tl 9999
-            min_delay: Duration::from_millis(0),     // -tll 0
-            max_loss_rate: 1.0,     // -tlr 1.00
-            min_speed: 0.0,         // -sl 0.00
-            print_num: 10,          // -p 10
-            ip_file: String::from("ip.txt"),  // -f ip.txt
-            ip_text: String::new(),  // -ip (默认空)
-            output: String::from("result.csv"),  // -o result.csv
-            disable_download: false,  // -dd (默认启用)
-            test_all: false,         // -all4 (默认否)
-            ipv4_amount: None,       // -v4 (默认无)
-            ipv6_amount: None,       // -v6 (默认无)
-            ipv6_num_mode: None,     // -more6/-lots6/-many6/-some6 (默认无)
-            ipv4_num_mode: None,     // -many4 (默认无)
-            max_ip_count: 500_000,  // 默认50万
-        }
-    }
-}
-
-// 解析测试数量，只接受单个数字
-pub fn parse_test_amount(expr: &str, is_v4: bool) -> u32 {
-    let max_amount = if is_v4 { 
-        2u32.pow(16) // IPv4 最大值 2^16
-    } else {
-        2u32.pow(20) // IPv6 最大值 2^20
-    };
-
-    // 直接解析数字，解析失败返回0
-    let amount = expr.parse::<u32>().unwrap_or(0);
-    
-    // 确保不超过最大值
-    amount.min(max_amount)
-}
\ No newline at end of file
+use std::net::IpAddr;
+use std::time::Duration;
+use std::cmp::Ordering;
+use thiserror::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::AcquireError;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, COOKIE};
+use crate::geoip::GeoInfo;
+
+// HTTPing、分阶段耗时、复用延迟与下载测速请求使用的 User-Agent
+pub const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_12_6) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/98.0.4758.80 Safari/537.36";
+
+#[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum SpeedTestError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Request error: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("CSV error: {0}")]
+    CsvError(#[from] csv::Error),
+
+    #[error("Thread control error: {0}")]
+    ThreadError(String),
+}
+
+// 结果文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+    #[serde(alias = "jsonl")]
+    Ndjson,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("未知的输出格式: {}", s)),
+        }
+    }
+}
+
+// 日志格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("未知的日志格式: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub ping_times: u32,          // 延迟测速次数
+    pub test_count: u32,         // 下载测速数量
+    pub pipeline: bool,          // 延迟测速进行中即开始下载测速
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub download_time: Duration, // 下载测速时间
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub connect_timeout: Duration, // TCP 连接超时，下载测速不低于 5s
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub httping_timeout: Duration, // HTTPing 单次请求等待响应的超时
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub download_timeout: Duration, // 下载测速时持续未收到数据的超时
+    pub download_connections: usize, // 每个 IP 同时下载的连接数
+    pub download_converge: f64,  // 速度样本的相对标准差低于该值时提前结束下载测速，0 为不启用
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub download_min_time: Duration, // 提前结束前至少下载的时长
+    #[serde(deserialize_with = "deserialize_size")]
+    pub download_budget: u64,    // 每个 IP 的下载流量上限 (字节)，0 为不限制
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub soak: Duration,          // 稳定性测试时长，0 为不启用
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub soak_interval: Duration, // 稳定性测试的探测间隔
+    pub soak_count: u32,         // 稳定性测试的 IP 数量
+    pub tcp_port: u16,          // 测速端口
+    pub syn: bool,              // TCP 延迟测速通过原始套接字只发送 SYN 计时
+    pub ports: String,          // 多端口测速的端口列表，逗号分隔，为空时只测 tcp_port
+    pub url: String,            // 测速URL，可为逗号分隔的多个地址或地址列表文件
+    pub sni: String,            // TLS SNI 域名，替换测速地址中的域名
+    pub host_header: String,    // 请求头 Host
+    pub ca_cert: String,        // 自定义根证书 (PEM)
+    pub client_cert: String,    // 客户端证书 (PEM)，用于 mTLS
+    pub client_key: String,     // 客户端私钥 (PKCS#8 PEM)，为空时从证书文件读取
+    pub insecure: bool,         // 跳过证书校验
+    pub headers: Vec<String>,   // 自定义请求头，每项为 "Name: value"
+    pub resolve: Vec<String>,   // 自定义解析，每项为 "域名:端口:IP"
+    pub cookie: String,         // 请求 Cookie
+    
+    pub httping: bool,                // 是否使用HTTP测速
+    pub warp: bool,                   // WARP 模式：通过 WireGuard 握手测速
+    pub warp_private_key: String,     // WARP 私钥 (base64)，为空时随机生成
+    pub warp_public_key: String,      // WARP 服务端公钥 (base64)
+    pub warp_reserved: String,        // WARP reserved 字段，如 "1,2,3"
+    pub httping_status_code: u16,     // HTTP状态码
+    pub allowed_status: String,       // 有效状态码列表，可含范围，如 "200,204,301-308"
+    pub expect_body_sha256: String,   // 检查连接时响应体应有的 SHA-256 (小写十六进制)
+    pub expect_body_contains: String, // 检查连接时响应体应包含的文本
+    pub httping_cf_colo: String,      // 匹配指定地区
+    pub cdn_provider: String,         // 识别节点代码所用的 CDN，auto 为自动识别
+    pub cf_trace: bool,               // 通过 /cdn-cgi/trace 获取节点信息
+    pub trace: bool,                  // 对排名靠前的 IP 探测路径
+    pub trace_count: u32,             // 探测路径的 IP 数量
+    pub trace_max_ttl: u8,            // 路径探测的最大跳数
+    pub timing: bool,                 // 分阶段测量连接、TLS 握手、首字节耗时
+    pub h2_latency: bool,             // 测量 HTTP/2 连接建立后的复用请求延迟
+    
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_delay: Duration,    // 平均延迟上限
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub min_delay: Duration,    // 平均延迟下限
+    pub max_loss_rate: f32,     // 丢包率上限
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub max_jitter: Duration,   // 抖动上限
+    pub min_speed: f64,         // 下载速度下限
+    
+    pub print_num: u32,         // 显示结果数量
+    pub per_colo: u32,          // 每个数据中心保留的 IP 数量，0 为不按数据中心选取
+    pub ip_file: String,        // IP段数据文件
+    pub ip_file_v6: String,     // [-dual-stack] 时额外读取的 IPv6 段数据文件
+    pub dual_stack: bool,       // 同时测速 IPv4 与 IPv6，并分别对比两者的最优结果
+    pub ip_text: String,        // 指定IP段数据
+    pub cf_official: bool,      // 使用 Cloudflare 官方 IP 段
+    pub exclude: String,        // 排除的 IP/IP 段，逗号分隔
+    pub exclude_file: String,   // 排除列表文件
+    pub auto_exclude: u32,      // 连续下载失败多少次后自动加入排除文件，0 为不启用
+    pub geoip_db: String,       // MaxMind DB 格式的地理位置/ASN 数据库，逗号分隔多个
+    pub ip_country: String,     // 只测速属于这些国家的 IP，逗号分隔
+    pub asn: String,            // 只测速属于这些 ASN 的 IP，逗号分隔
+    pub exclude_asn: String,    // 不测速属于这些 ASN 的 IP，逗号分隔
+    pub output: String,         // 输出文件
+    pub output_format: OutputFormat, // 输出文件格式
+    pub outputs: Vec<String>,   // [-output] 指定的输出目标，如 csv=result.csv、table，为空时使用 [-o] 与表格
+    pub stream_output: bool,    // 边测速边写入结果文件
+    pub columns: String,        // 结果文件的列及顺序，逗号分隔
+    pub english_header: bool,   // CSV 表头使用英文字段名
+    pub log_level: String,      // 日志级别，为空时不输出
+    pub log_file: String,       // 日志文件，为空时输出到标准错误
+    pub log_format: LogFormat,  // 日志格式
+    pub summary_file: String,   // 汇总统计 JSON 文件，为空时不写入
+    pub debug_failures: String, // 失败记录 CSV 文件，为空时不写入
+    pub compare: String,        // 与之对比的上次结果文件，为空时不对比
+    pub tui: bool,              // 交互界面
+    
+    pub disable_download: bool, // 禁用下载测速
+    pub score: String,          // 结果排序的评分公式或预设名称，为空时使用默认排序
+    pub upload_test: bool,      // 启用上传测速
+    pub upload_url: String,     // 上传测速地址
+    pub worker_account: String, // gen-worker 部署 Worker 的账户 ID
+    pub worker_name: String,    // gen-worker 部署的 Worker 名称
+    pub upload_size: u64,       // 上传数据量（字节）
+    pub test_all: bool,        // 测试全部IP
+    pub ipv4_amount: Option<u32>,  // IPv4 测试数量
+    pub ipv6_amount: Option<u32>,  // IPv6 测试数量
+    pub ipv6_num_mode: Option<String>, // IPv6 数量模式
+    pub ipv4_num_mode: Option<String>, // IPv4 数量模式
+    pub ipv6_sample_per_64: u32,  // 每个 IPv6 /64 子网抽取的地址数量，0 为不按子网抽样
+    pub seed: Option<u64>,        // 随机种子，指定后抽样结果可复现
+    pub max_ip_count: usize,  // 添加 IP 总量上限参数
+    pub stop_after: usize,    // 满足条件的 IP 达到该数量后结束延迟测速，0 为测完全部
+    pub checkpoint: String,   // 延迟测速检查点文件，为空时不保存
+    pub resume: bool,         // 从检查点继续
+    pub adaptive_concurrency: bool, // 按超时率自适应调整并发
+    pub max_concurrency: usize,     // 并发上限
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub rate_limit: f64,            // 每秒探测次数上限，0 为不限制
+    #[serde(deserialize_with = "deserialize_bandwidth")]
+    pub download_cap: f64,          // 下载测速的总带宽上限 (字节/秒)，0 为不限制
+    pub interface: String,          // 测速使用的网卡，为空时由系统路由决定
+    pub proxy: String,              // HTTPing 与下载测速使用的代理，为空时直连
+
+    pub dns_zone_id: String,    // Cloudflare 区域 ID
+    pub dns_api_token: String,  // Cloudflare API 令牌
+    pub dns_records: String,    // 要更新的记录名，逗号分隔
+    pub dns_top_n: u32,         // 写入记录的 IP 数量
+    pub dns_dry_run: bool,      // 仅打印计划，不实际修改
+    pub output_hosts: String,   // hosts 格式输出文件，为空时不写入
+    pub output_dnsmasq: String, // dnsmasq 格式输出文件，为空时不写入
+    pub hosts_domains: String,  // 写入 hosts / dnsmasq 的域名，逗号分隔
+    pub hosts_top_n: u32,       // 每个域名写入的 IP 数量
+    pub aggregate: String,      // 合并网段输出文件，为空时不合并
+    pub aggregate_ratio: f64,   // 网段中通过的 IP 比例不低于该值时整体输出
+
+    pub daemon: bool,             // 持续监控模式
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub daemon_interval: Duration, // 每轮测速间隔
+    pub degrade_threshold: f64,   // 最优 IP 劣化阈值（比例）
+    pub metrics_addr: String,     // Prometheus 指标监听地址，为空时不启用
+    pub quiet: bool,              // 安静模式，只输出最优 IP
+    pub serve_addr: String,       // HTTP 接口监听地址，为空时不启用
+
+    pub db: String,               // SQLite 历史数据库路径，为空时不记录
+
+    pub notify_webhook: String,   // 通用 Webhook 地址（POST JSON）
+    pub telegram_token: String,   // Telegram 机器人令牌
+    pub telegram_chat_id: String, // Telegram 会话 ID
+    pub discord_webhook: String,  // Discord Webhook 地址
+    pub notify_top_n: u32,        // 通知中包含的 IP 数量
+    pub notify_on_change: bool,   // 仅在最优 IP 变化时通知
+}
+
+impl Config {
+    pub fn is_test_all(&self) -> bool {
+        self.test_all
+    }
+
+    // 实际请求的地址：多个测速地址时轮询选取，指定 SNI 时替换其中的域名
+    pub fn request_url(&self) -> String {
+        crate::urls::next_url(self)
+    }
+
+    // 自定义请求头 [-header] 与 [-cookie]，格式无效的项忽略
+    pub fn extra_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for header in &self.headers {
+            if let Some((name, value)) = parse_header(header) {
+                headers.append(name, value);
+            }
+        }
+        if let Ok(cookie) = HeaderValue::from_str(self.cookie.trim()) {
+            if !cookie.is_empty() {
+                headers.insert(COOKIE, cookie);
+            }
+        }
+        headers
+    }
+
+    // 将自定义请求头写入请求，同名的默认请求头 (如 User-Agent) 被替换
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        let extra = self.extra_headers();
+        for name in extra.keys() {
+            headers.remove(name);
+        }
+        for (name, value) in extra.iter() {
+            headers.append(name, value.clone());
+        }
+    }
+
+    // 是否需要校验响应体，校验时检查连接改用 GET 请求
+    pub fn expects_body(&self) -> bool {
+        !self.expect_body_sha256.is_empty() || !self.expect_body_contains.is_empty()
+    }
+
+    // 需要显式设置的 Host 请求头
+    pub fn host_header(&self) -> Option<&str> {
+        if self.host_header.is_empty() {
+            None
+        } else {
+            Some(&self.host_header)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingData {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub sended: u32,
+    pub received: u32,
+    pub delay: Duration,     // 平均延迟
+    pub min_delay: Duration, // 最小延迟
+    pub max_delay: Duration, // 最大延迟
+    pub jitter: Duration,    // 抖动（延迟标准差）
+}
+
+impl PingData {
+    pub fn new(ip: IpAddr, port: u16, sended: u32, received: u32, delay: Duration) -> Self {
+        Self {
+            ip,
+            port,
+            sended,
+            received,
+            delay,
+            min_delay: delay,
+            max_delay: delay,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    // 根据每次成功测速的延迟计算统计数据，全部失败时返回 None
+    pub fn from_delays(ip: IpAddr, port: u16, sended: u32, mut delays: Vec<Duration>) -> Option<Self> {
+        if delays.is_empty() {
+            return None;
+        }
+        let received = delays.len() as u32;
+        delays.sort();
+
+        let min_delay = delays[0];
+        let max_delay = delays[delays.len() - 1];
+
+        // 计算平均延迟，样本多于 2 个时去掉最高和最低值
+        let valid_delays = if delays.len() > 2 {
+            &delays[1..delays.len() - 1]
+        } else {
+            &delays[..]
+        };
+        let avg_delay = valid_delays.iter().sum::<Duration>() / valid_delays.len() as u32;
+
+        // 抖动取全部样本的标准差
+        let mean = delays.iter().map(|d| d.as_secs_f64()).sum::<f64>() / delays.len() as f64;
+        let variance = delays.iter()
+            .map(|d| (d.as_secs_f64() - mean).powi(2))
+            .sum::<f64>() / delays.len() as f64;
+
+        Some(Self {
+            ip,
+            port,
+            sended,
+            received,
+            delay: avg_delay,
+            min_delay,
+            max_delay,
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+
+    pub fn loss_rate(&self) -> f32 {
+        if self.sended == 0 {
+            return 1.0;
+        }
+        (self.sended - self.received) as f32 / self.sended as f32
+    }
+}
+
+// 分阶段耗时：TCP 连接、TLS 握手、首字节
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timing {
+    pub connect: Duration,
+    pub tls_handshake: Duration,
+    pub ttfb: Duration,
+}
+
+// 稳定性测试期间的统计
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SoakStats {
+    pub probes: u32,
+    pub received: u32,
+    pub latency_ms: f64,  // 成功探测的平均延迟
+    pub drift_ms: f64,    // 后半段平均延迟减去前半段，正数为变慢
+    pub max_burst: u32,   // 最长连续失败次数
+    pub disconnects: u32, // 连续失败的段数
+}
+
+impl SoakStats {
+    pub fn loss_rate(&self) -> f64 {
+        if self.probes == 0 { 0.0 } else { 1.0 - self.received as f64 / self.probes as f64 }
+    }
+}
+
+// [-trace] 路径探测结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathInfo {
+    pub hops: Option<u8>,         // 到达目标的跳数，最大跳数内未到达时为空
+    pub isp_hop: Option<IpAddr>,  // 最后一个运营商路由器 (目标之前最后一个有回应的路由器)
+    pub isp_hop_ms: Option<f64>,  // 到该路由器的延迟
+}
+
+// /cdn-cgi/trace 返回的节点信息
+#[derive(Debug, Clone, Default)]
+pub struct TraceInfo {
+    pub colo: String,
+    pub warp: String,
+    pub http: String,
+    pub tls: String,
+    pub sgroup: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CloudflareIPData {
+    pub ping_data: PingData,
+    pub loss_rate: f32,
+    pub download_speed: f64,
+    pub download_capped: bool,        // 下载测速受 [-download-cap] 限制，实际速度不低于记录值
+    pub upload_speed: f64,
+    pub config: Config,
+    pub colo: String,
+    pub trace: TraceInfo,
+    pub timing: Timing,
+    pub h2_latency: Option<Duration>, // HTTP/2 复用延迟，未测量或失败时为空
+    pub geo: GeoInfo,                 // IP 本身的国家、城市与 ASN
+    pub soak: Option<SoakStats>,      // [-soak] 稳定性测试结果，未参与时为空
+    pub path: Option<PathInfo>,       // [-trace] 路径探测结果，未参与时为空
+}
+
+impl CloudflareIPData {
+    pub fn new(ping_data: PingData) -> Self {
+        let loss_rate = ping_data.loss_rate();
+        Self {
+            ping_data,
+            loss_rate,
+            download_speed: 0.0,
+            download_capped: false,
+            upload_speed: 0.0,
+            config: Config::default(),
+            colo: String::new(),
+            trace: TraceInfo::default(),
+            timing: Timing::default(),
+            h2_latency: None,
+            geo: GeoInfo::default(),
+            soak: None,
+            path: None,
+        }
+    }
+
+    // 是否满足延迟、丢包率、抖动条件，规则与 DelayFilter 一致；地区在延迟测速时尚未获取，[-cfcolo] 由 HTTPing 检查连接时过滤
+    pub fn meets_ping_filters(&self, config: &Config) -> bool {
+        let delay_ok = config.max_delay > MAX_DELAY
+            || (self.ping_data.delay <= config.max_delay && self.ping_data.delay >= config.min_delay);
+        let loss_ok = config.max_loss_rate >= MAX_LOSS_RATE || self.loss_rate <= config.max_loss_rate;
+        let jitter_ok = config.max_jitter >= MAX_DELAY || self.ping_data.jitter <= config.max_jitter;
+        delay_ok && loss_ok && jitter_ok
+    }
+
+    // 是否满足下载速度下限 [-sl]；没有 IP 达到下限时下载测速会保留全部结果，需要另行判断；
+    // 启用下载测速时速度为 0 (未测速或失败) 的 IP 即使 [-sl 0] 也不满足
+    pub fn meets_speed_filter(&self, config: &Config) -> bool {
+        config.disable_download
+            || (self.download_speed > 0.0 && self.download_speed >= config.min_speed * 1024.0 * 1024.0)
+    }
+}
+
+// 实现排序特性
+impl Ord for CloudflareIPData {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let self_rate = self.loss_rate;
+        let other_rate = other.loss_rate;
+        
+        if self_rate != other_rate {
+            self_rate.partial_cmp(&other_rate)
+                .unwrap_or(Ordering::Equal)
+        } else {
+            self.ping_data.delay.cmp(&other.ping_data.delay)
+        }
+    }
+}
+
+impl PartialOrd for CloudflareIPData {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CloudflareIPData {
+    fn eq(&self, other: &Self) -> bool {
+        self.download_speed == other.download_speed &&
+        self.ping_data.delay == other.ping_data.delay
+    }
+}
+
+impl Eq for CloudflareIPData {}
+
+pub type PingDelaySet = Vec<CloudflareIPData>;
+pub type DownloadSpeedSet = Vec<CloudflareIPData>;
+
+pub trait DelayFilter {
+    fn filter_delay(self, config: &Config) -> Self;
+    fn filter_loss_rate(self, config: &Config) -> Self;
+    fn filter_jitter(self, config: &Config) -> Self;
+}
+
+impl DelayFilter for PingDelaySet {
+    fn filter_delay(self, config: &Config) -> Self {
+        if config.max_delay > MAX_DELAY || config.min_delay < MIN_DELAY {
+            return self;
+        }
+        if config.max_delay == MAX_DELAY && config.min_delay == MIN_DELAY {
+            return self;
+        }
+
+        // 结果优先按丢包率排序，延迟并不单调，不能提前结束
+        self.into_iter()
+            .filter(|ip_data| {
+                ip_data.ping_data.delay <= config.max_delay
+                    && ip_data.ping_data.delay >= config.min_delay
+            })
+            .collect()
+    }
+
+    fn filter_loss_rate(self, config: &Config) -> Self {
+        if config.max_loss_rate >= MAX_LOSS_RATE {
+            return self;
+        }
+
+        self.into_iter()
+            .filter(|ip_data| ip_data.loss_rate <= config.max_loss_rate)
+            .collect()
+    }
+
+    fn filter_jitter(self, config: &Config) -> Self {
+        if config.max_jitter >= MAX_DELAY {
+            return self;
+        }
+
+        self.into_iter()
+            .filter(|ip_data| ip_data.ping_data.jitter <= config.max_jitter)
+            .collect()
+    }
+}
+
+pub const MAX_DELAY: Duration = Duration::from_millis(9999);
+pub const MIN_DELAY: Duration = Duration::ZERO;
+pub const MAX_LOSS_RATE: f32 = 1.0;
+
+impl From<AcquireError> for SpeedTestError {
+    fn from(err: AcquireError) -> Self {
+        SpeedTestError::ThreadError(format!("线程控制失败: {}", err))
+    }
+} 
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ping_times: 4,          // -t 4
+            test_count: 10,         // -dn 10
+            pipeline: false,        // -pipeline (默认禁用)
+            download_time: Duration::from_secs(10),  // -dt 10
+            connect_timeout: Duration::from_secs(1),  // -connect-timeout 1s
+            httping_timeout: Duration::from_secs(10),  // -httping-timeout 10s
+            download_timeout: Duration::from_secs(2),  // -download-timeout 2s
+            download_connections: 1,  // -download-connections 1
+            download_converge: 0.0,   // -dt-converge (默认不启用)
+            download_min_time: Duration::from_secs(2),  // -dt-min 2s
+            download_budget: 0,       // -download-budget (默认不限制)
+            soak: Duration::ZERO,     // -soak (默认不启用)
+            soak_interval: Duration::from_secs(10),  // -soak-interval 10s
+            soak_count: 5,            // -soak-n 5
+            tcp_port: 443,          // -tp 443
+            syn: false,             // -syn (默认禁用)
+            ports: String::new(),   // -ports (默认空，使用 -tp)
+            url: String::from("https://cf.xiu2.xyz/url"),  // -url
+            sni: String::new(),     // -sni (默认使用测速地址的域名)
+            host_header: String::new(),  // -host-header (默认同 SNI)
+            ca_cert: String::new(),      // -ca-cert (默认系统根证书)
+            client_cert: String::new(),  // -client-cert (默认空)
+            client_key: String::new(),   // -client-key (默认空)
+            insecure: false,             // -insecure
+            headers: Vec::new(),         // -header (可重复指定)
+            resolve: Vec::new(),         // -resolve (可重复指定)
+            cookie: String::new(),       // -cookie (默认空)
+            httping: false,         // -httping
+            warp: false,            // -warp
+            warp_private_key: String::new(),  // -warp-key (默认随机生成)
+            warp_public_key: crate::warp::WARP_PUBLIC_KEY.to_string(),  // -warp-peer
+            warp_reserved: String::new(),     // -warp-reserved (默认 0,0,0)
+            httping_status_code: 200,  // -httping-code
+            allowed_status: String::new(),  // -allowed-status (默认空，使用 -httping-code)
+            expect_body_sha256: String::new(),    // -expect-body-sha256 (默认空，不校验)
+            expect_body_contains: String::new(),  // -expect-body-contains (默认空，不校验)
+            httping_cf_colo: String::new(),  // -cfcolo (默认空)
+            cdn_provider: "auto".to_string(),  // -cdn-provider (默认 auto)
+            cf_trace: false,        // -cf-trace
+            trace: false,           // -trace
+            trace_count: 5,         // -trace-n 5
+            trace_max_ttl: 30,      // -trace-max-ttl 30
+            timing: false,          // -timing
+            h2_latency: false,      // -h2-latency
+            max_delay: Duration::from_millis(9999),  // -tl 9999
+            min_delay: Duration::from_millis(0),     // -tll 0
+            max_loss_rate: 1.0,     // -tlr 1.00
+            max_jitter: Duration::from_millis(9999),  // -max-jitter 9999
+            min_speed: 0.0,         // -sl 0.00
+            print_num: 10,          // -p 10
+            per_colo: 0,            // -per-colo 0
+            ip_file: String::from("ip.txt"),  // -f ip.txt
+            ip_file_v6: String::from("ipv6.txt"),  // -f6 ipv6.txt
+            dual_stack: false,      // -dual-stack (默认禁用)
+            ip_text: String::new(),  // -ip (默认空)
+            cf_official: false,      // -cf-official
+            exclude: String::new(),      // -exclude (默认空)
+            exclude_file: String::new(), // -exclude-file (默认空)
+            auto_exclude: 0,             // -auto-exclude (默认不启用)
+            geoip_db: String::new(),     // -geoip-db (默认空)
+            ip_country: String::new(),   // -ip-country (默认空)
+            asn: String::new(),          // -asn (默认空)
+            exclude_asn: String::new(),  // -exclude-asn (默认空)
+            output: String::from("result.csv"),  // -o result.csv
+            output_format: OutputFormat::Csv,    // -output-format csv
+            outputs: Vec::new(),    // -output (默认空)
+            stream_output: false,                // -stream-output (默认禁用)
+            columns: String::new(),              // -columns (默认空，使用默认列)
+            english_header: false,               // -english-header (默认禁用)
+            log_level: String::new(),            // -log-level (默认空，不输出)
+            log_file: String::new(),             // -log-file (默认空，输出到标准错误)
+            log_format: LogFormat::Text,         // -log-format text
+            summary_file: String::new(),         // -summary (默认空，不写入)
+            debug_failures: String::new(),       // -debug-failures (默认空，不写入)
+            compare: String::new(),              // -compare (默认空，不对比)
+            tui: false,                          // -tui (默认禁用)
+            disable_download: false,  // -dd (默认启用)
+            score: String::new(),     // -score (默认空，使用默认排序)
+            upload_test: false,      // -upload-test (默认否)
+            upload_url: String::from("https://speed.cloudflare.com/__up"),  // -upload-url
+            worker_account: String::new(),  // -worker-account (默认空)
+            worker_name: String::from("cfst-speed"),  // -worker-name cfst-speed
+            upload_size: 10 * 1024 * 1024,  // -upload-size 10 (MB)
+            test_all: false,         // -all4 (默认否)
+            ipv4_amount: None,       // -v4 (默认无)
+            ipv6_amount: None,       // -v6 (默认无)
+            ipv6_num_mode: None,     // -more6/-lots6/-many6/-some6 (默认无)
+            ipv4_num_mode: None,     // -many4 (默认无)
+            ipv6_sample_per_64: 0,   // -ipv6-sample-per-64 (默认不按子网抽样)
+            seed: None,              // -seed (默认随机)
+            max_ip_count: 500_000,  // 默认50万
+            stop_after: 0,          // -stop-after (默认测完全部)
+            checkpoint: String::new(), // -checkpoint / -resume (默认空，不保存)
+            resume: false,
+            adaptive_concurrency: false,  // -adaptive
+            max_concurrency: crate::threadpool::DEFAULT_MAX_CONCURRENCY,  // -max-concurrency 1024
+            rate_limit: 0.0,              // -rate-limit (默认不限制)
+            download_cap: 0.0,            // -download-cap (默认不限制)
+            interface: String::new(),     // -interface (默认空，由系统路由决定)
+            proxy: String::new(),         // -proxy (默认空，直连)
+            dns_zone_id: String::new(),    // -dns-zone (默认空)
+            dns_api_token: String::new(),  // -dns-token (默认读取 CF_API_TOKEN)
+            dns_records: String::new(),    // -dns-records (默认空)
+            dns_top_n: 1,                  // -dns-top 1
+            dns_dry_run: false,            // -dns-dry-run
+            output_hosts: String::new(),   // -output-hosts (默认空)
+            output_dnsmasq: String::new(), // -output-dnsmasq (默认空)
+            hosts_domains: String::new(),  // -hosts-domains (默认空)
+            hosts_top_n: 1,                // -hosts-top 1
+            aggregate: String::new(),      // -aggregate (默认空)
+            aggregate_ratio: 0.8,          // -aggregate-ratio 0.8
+            daemon: false,                 // -daemon
+            daemon_interval: Duration::from_secs(30 * 60),  // -interval 30m
+            degrade_threshold: 0.2,        // -degrade 0.2
+            metrics_addr: String::new(),   // -metrics (默认空，不启用)
+            quiet: false,                  // -quiet
+            serve_addr: String::new(),     // -serve (默认空，不启用)
+            db: String::new(),             // -db (默认空，不记录)
+            notify_webhook: String::new(),   // -notify-webhook (默认空)
+            telegram_token: String::new(),   // -tg-token (默认空)
+            telegram_chat_id: String::new(), // -tg-chat (默认空)
+            discord_webhook: String::new(),  // -discord-webhook (默认空)
+            notify_top_n: 3,                 // -notify-top 3
+            notify_on_change: false,         // -notify-on-change
+        }
+    }
+}
+
+// 解析测试数量，只接受单个数字
+pub fn parse_test_amount(expr: &str, is_v4: bool) -> u32 {
+    let max_amount = if is_v4 { 
+        2u32.pow(16) // IPv4 最大值 2^16
+    } else {
+        2u32.pow(20) // IPv6 最大值 2^20
+    };
+
+    // 直接解析数字，解析失败返回0
+    let amount = expr.parse::<u32>().unwrap_or(0);
+    
+    // 确保不超过最大值
+    amount.min(max_amount)
+}
+// 解析 "Name: value" 格式的请求头
+pub fn parse_header(expr: &str) -> Option<(HeaderName, HeaderValue)> {
+    let (name, value) = expr.split_once(':')?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).ok()?;
+    let value = HeaderValue::from_str(value.trim()).ok()?;
+    Some((name, value))
+}
+
+// 解析时长，支持 ms/s/m/h 后缀，不带单位时按秒处理，如 "800ms"、"30m"
+pub fn parse_duration(expr: &str) -> Option<Duration> {
+    let expr = expr.trim();
+    let split = expr.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(expr.len());
+    let (num, unit) = expr.split_at(split);
+    let value: f64 = num.parse().ok()?;
+    if value < 0.0 {
+        return None;
+    }
+
+    let secs = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+    Some(Duration::from_secs_f64(secs))
+}
+
+// 解析速率，如 "500/s"、"30000/m"，不带单位时按每秒处理，返回每秒次数
+pub fn parse_rate(expr: &str) -> Option<f64> {
+    let expr = expr.trim();
+    let (num, unit) = expr.split_once('/').unwrap_or((expr, "s"));
+    let value: f64 = num.trim().parse().ok()?;
+    if value < 0.0 {
+        return None;
+    }
+    let per_secs = match unit.trim() {
+        "ms" => 0.001,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    Some(value / per_secs)
+}
+
+// 解析带宽，返回字节/秒：bps/kbps/mbps/gbps 为比特 (按 1000 进位)，B/s、KB/s、MB/s、GB/s 为字节 (按 1024 进位)，
+// 不带单位时按 MB/s 处理，与 [-sl] 一致
+pub fn parse_bandwidth(expr: &str) -> Option<f64> {
+    let expr = expr.trim();
+    let split = expr.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(expr.len());
+    let (num, unit) = expr.split_at(split);
+    let unit = unit.trim();
+    let Some(prefix) = unit.strip_suffix("bps").or_else(|| unit.strip_suffix("b/s")) else {
+        return parse_size(&format!("{}{}", num, unit.trim_end_matches("/s")));
+    };
+    let scale = match prefix.to_ascii_lowercase().as_str() {
+        "" => 1.0,
+        "k" => 1e3,
+        "m" => 1e6,
+        "g" => 1e9,
+        _ => return None,
+    };
+    let value: f64 = num.parse().ok()?;
+    Some(value * scale / 8.0)
+}
+
+// 解析字节数，如 "512KB"、"100MB"、"1GB" (按 1024 进位)，不带单位时按 MB 处理
+pub fn parse_size(expr: &str) -> Option<f64> {
+    let expr = expr.trim();
+    let split = expr.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(expr.len());
+    let (num, unit) = expr.split_at(split);
+    let value: f64 = num.parse().ok()?;
+    let bytes_per_unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "" | "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some(value * bytes_per_unit)
+}
+
+// 配置文件中的字节数，可写作字符串 "100MB" 或数字 (MB)
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawSize {
+        Number(f64),
+        Text(String),
+    }
+
+    match RawSize::deserialize(deserializer)? {
+        RawSize::Number(mb) if mb >= 0.0 => Ok((mb * 1024.0 * 1024.0) as u64),
+        RawSize::Number(mb) => Err(serde::de::Error::custom(format!("无效的大小: {}", mb))),
+        RawSize::Text(text) => parse_size(&text)
+            .map(|size| size as u64)
+            .ok_or_else(|| serde::de::Error::custom(format!("无效的大小: {}", text))),
+    }
+}
+
+// 配置文件中的带宽，可写作字符串 "50mbps" 或数字 (MB/s)
+fn deserialize_bandwidth<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawBandwidth {
+        Number(f64),
+        Text(String),
+    }
+
+    match RawBandwidth::deserialize(deserializer)? {
+        RawBandwidth::Number(mb) if mb >= 0.0 => Ok(mb * 1024.0 * 1024.0),
+        RawBandwidth::Number(mb) => Err(serde::de::Error::custom(format!("无效的带宽: {}", mb))),
+        RawBandwidth::Text(text) => parse_bandwidth(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("无效的带宽: {}", text))),
+    }
+}
+
+// 配置文件中的速率，可写作字符串 "500/s" 或数字（每秒）
+fn deserialize_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawRate {
+        Number(f64),
+        Text(String),
+    }
+
+    match RawRate::deserialize(deserializer)? {
+        RawRate::Number(rate) if rate >= 0.0 => Ok(rate),
+        RawRate::Number(rate) => Err(serde::de::Error::custom(format!("无效的速率: {}", rate))),
+        RawRate::Text(text) => parse_rate(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("无效的速率: {}", text))),
+    }
+}
+
+// 配置文件中的时长，可写作字符串 "800ms"、"30m" 或数字（秒）
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawDuration {
+        Number(f64),
+        Text(String),
+    }
+
+    match RawDuration::deserialize(deserializer)? {
+        RawDuration::Number(secs) if secs >= 0.0 => Ok(Duration::from_secs_f64(secs)),
+        RawDuration::Number(secs) => Err(serde::de::Error::custom(format!("无效的时长: {}", secs))),
+        RawDuration::Text(text) => parse_duration(&text)
+            .ok_or_else(|| serde::de::Error::custom(format!("无效的时长: {}", text))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("800ms"), Some(Duration::from_millis(800)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        // 不带单位时按秒处理
+        assert_eq!(parse_duration(" 10 "), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("10d"), None);
+        assert_eq!(parse_duration("ms"), None);
+    }
+
+    #[test]
+    fn parse_bandwidth_bits() {
+        assert_eq!(parse_bandwidth("8bps"), Some(1.0));
+        assert_eq!(parse_bandwidth("100Mbps"), Some(12_500_000.0));
+        assert_eq!(parse_bandwidth("1gbps"), Some(125_000_000.0));
+        assert_eq!(parse_bandwidth("8kb/s"), Some(1000.0));
+        assert_eq!(parse_bandwidth("1tbps"), None);
+    }
+
+    #[test]
+    fn parse_bandwidth_bytes() {
+        assert_eq!(parse_bandwidth("512KB/s"), Some(512.0 * 1024.0));
+        assert_eq!(parse_bandwidth("2MB/s"), Some(2.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_bandwidth("1GB/s"), Some(1024.0 * 1024.0 * 1024.0));
+        // 不带单位时按 MB/s 处理
+        assert_eq!(parse_bandwidth("10"), Some(10.0 * 1024.0 * 1024.0));
+        assert_eq!(parse_bandwidth("fast"), None);
+    }
+}
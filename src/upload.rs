@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use futures::stream;
+use reqwest::Body;
+use bytes::Bytes;
+use crate::types::{Config, DownloadSpeedSet};
+use crate::download::build_client;
+use crate::{cancel, tui, urls};
+use crate::progress::Bar;
+use crate::debug_log;
+
+const CHUNK_SIZE: usize = 64 * 1024; // 每块 64KB
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5); // 数据发送完后等待响应的时间
+
+// 生成上传数据流，超过测速时间后不再产生数据，已发送字节数记录在 sent_bytes
+fn payload_stream(total: u64, deadline: Instant, sent_bytes: Arc<AtomicU64>) -> Body {
+    let chunk = Bytes::from(vec![0u8; CHUNK_SIZE]);
+    let stream = stream::unfold(0u64, move |sent| {
+        let chunk = chunk.clone();
+        let sent_bytes = sent_bytes.clone();
+        async move {
+            if sent >= total || Instant::now() >= deadline {
+                return None;
+            }
+            let len = (total - sent).min(CHUNK_SIZE as u64) as usize;
+            sent_bytes.fetch_add(len as u64, Ordering::Relaxed);
+            Some((Ok::<_, std::io::Error>(chunk.slice(..len)), sent + len as u64))
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+// 单个 IP 上传测速，返回 字节/秒
+async fn upload_handler(ip: &std::net::IpAddr, port: u16, config: &Config) -> Option<f64> {
+    let client = build_client(ip, port, config).await.ok()?;
+    let start = Instant::now();
+    let deadline = start + config.download_time;
+
+    let sent_bytes = Arc::new(AtomicU64::new(0));
+    let response = client
+        .post(urls::with_port(&config.upload_url, port))
+        .header("Content-Type", "application/octet-stream")
+        .headers(config.extra_headers())
+        .timeout(config.download_time + RESPONSE_TIMEOUT)
+        .body(payload_stream(config.upload_size, deadline, sent_bytes.clone()))
+        .send()
+        .await;
+
+    let elapsed = start.elapsed().min(config.download_time);
+    let sent = sent_bytes.load(Ordering::Relaxed);
+    match response {
+        Ok(resp) if resp.status().is_success() => {
+            debug_log!("上传完成: IP={}, 数据量={} bytes, 用时={:?}", ip, sent, elapsed);
+            if elapsed == Duration::ZERO {
+                return None;
+            }
+            Some(sent as f64 / elapsed.as_secs_f64())
+        }
+        Ok(_resp) => {
+            debug_log!("上传失败: IP={}, 状态码={}", ip, _resp.status());
+            None
+        }
+        Err(_e) => {
+            debug_log!("上传失败: IP={}, 错误={}", ip, _e);
+            None
+        }
+    }
+}
+
+// 对下载测速结果进行上传测速，逐个进行以免互相争抢上行带宽
+pub async fn test_upload_speed(config: &Config, data: &mut DownloadSpeedSet) {
+    if !config.upload_test || data.is_empty() {
+        return;
+    }
+
+    println!(
+        "开始上传测速（数据量：{:.2} MB, 数量：{}）",
+        config.upload_size as f64 / 1024.0 / 1024.0,
+        data.len()
+    );
+
+    let bar = Bar::new(data.len() as u64, "", "").phase("上传测速");
+    for ip_data in data.iter_mut() {
+        if tui::abort_requested() || cancel::requested() {
+            break;
+        }
+        let speed = upload_handler(&ip_data.ping_data.ip, ip_data.ping_data.port, config).await.unwrap_or(0.0);
+        ip_data.upload_speed = speed;
+        bar.grow(1, &format!("{:.2} MB/s", speed / 1024.0 / 1024.0));
+    }
+    bar.done();
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use reqwest::ClientBuilder;
+use crate::types::Config;
+use crate::debug_log;
+
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(60); // 出错的地址暂停使用的时间
+
+lazy_static::lazy_static! {
+    // 按 (测速地址参数, SNI) 缓存解析后的地址列表
+    static ref URL_LISTS: Mutex<HashMap<String, Arc<Vec<String>>>> = Mutex::new(HashMap::new());
+    // 出错地址的恢复时间
+    static ref FAILED_UNTIL: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+// 解析测速地址参数：英文逗号分隔，不含 :// 的项视为地址列表文件（每行一个地址）；无法解析的地址被跳过
+pub fn parse_url_list(expr: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for item in expr.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if item.contains("://") {
+            urls.push(item.to_string());
+            continue;
+        }
+        match std::fs::read_to_string(item) {
+            Ok(content) => urls.extend(
+                content.lines()
+                    .map(|l| l.trim())
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(|l| l.to_string()),
+            ),
+            Err(_e) => {
+                debug_log!("读取测速地址文件 {} 失败: {}", item, _e);
+            }
+        }
+    }
+    urls.retain(|url| match reqwest::Url::parse(url) {
+        Ok(_) => true,
+        Err(_e) => {
+            debug_log!("跳过无效的测速地址 {}: {}", url, _e);
+            false
+        }
+    });
+    urls
+}
+
+// 检查 [-url] 能否得到至少一个有效地址，用于启动时提前报错
+pub fn validate(config: &Config) -> Result<(), String> {
+    if config.url.is_empty() || !parse_url_list(&config.url).is_empty() {
+        return Ok(());
+    }
+    Err(format!("[-url] 中没有有效的测速地址 (也不是可读取的地址列表文件)：{}", config.url))
+}
+
+// 指定 SNI 时替换地址中的域名
+fn apply_sni(url: &str, sni: &str) -> String {
+    if sni.is_empty() {
+        return url.to_string();
+    }
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) => match parsed.set_host(Some(sni)) {
+            Ok(()) => parsed.to_string(),
+            Err(_) => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+fn url_list(config: &Config) -> Arc<Vec<String>> {
+    let key = format!("{}|{}", config.url, config.sni);
+    let mut lists = URL_LISTS.lock().unwrap();
+    lists.entry(key)
+        .or_insert_with(|| {
+            let urls: Vec<String> = parse_url_list(&config.url)
+                .iter()
+                .map(|u| apply_sni(u, &config.sni))
+                .collect();
+            // 没有有效地址时 (启动时已报错) 保留原参数，请求时按无效地址处理
+            Arc::new(if urls.is_empty() { vec![config.url.clone()] } else { urls })
+        })
+        .clone()
+}
+
+// 轮询选取下一个可用的测速地址，全部出错时选取最早恢复的地址
+pub fn next_url(config: &Config) -> String {
+    let urls = url_list(config);
+    if urls.len() == 1 {
+        return urls[0].clone();
+    }
+
+    let now = Instant::now();
+    let failed = FAILED_UNTIL.lock().unwrap();
+    let start = NEXT_INDEX.fetch_add(1, Ordering::Relaxed);
+    for offset in 0..urls.len() {
+        let url = &urls[(start + offset) % urls.len()];
+        if failed.get(&cooldown_key(url)).is_none_or(|until| *until <= now) {
+            return url.clone();
+        }
+    }
+
+    urls.iter()
+        .min_by_key(|u| failed.get(&cooldown_key(u)).copied())
+        .cloned()
+        .unwrap_or_default()
+}
+
+// 出错记录的键：请求地址经 with_port 带上了待测端口，去掉端口后才能与地址列表中的项对应
+fn cooldown_key(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) => match parsed.set_port(None) {
+            Ok(()) => parsed.to_string(),
+            Err(()) => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+// 设置地址的端口，端口为协议默认端口时省略
+pub fn with_port(url: &str, port: u16) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) => match parsed.set_port(Some(port)) {
+            Ok(()) => parsed.to_string(),
+            Err(()) => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+// [-resolve] 指定的解析，格式与 curl --resolve 相同："域名:端口:IP"，IPv6 可写作 [::1]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveEntry {
+    pub host: String,
+    pub port: u16,
+    pub ip: IpAddr,
+}
+
+pub fn parse_resolve(expr: &str) -> Option<ResolveEntry> {
+    let (host, rest) = expr.trim().split_once(':')?;
+    let (port, ip) = rest.split_once(':')?;
+    let ip = ip.trim().trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some(ResolveEntry {
+        host: host.to_ascii_lowercase(),
+        port: port.parse().ok()?,
+        ip: ip.parse().ok()?,
+    })
+}
+
+// 为 reqwest 客户端应用 [-resolve]；reqwest 只能按域名覆盖解析，连接时仍使用请求地址中的端口
+pub fn apply_resolve(mut builder: ClientBuilder, config: &Config) -> ClientBuilder {
+    for entry in config.resolve.iter().filter_map(|e| parse_resolve(e)) {
+        builder = builder.resolve(&entry.host, SocketAddr::new(entry.ip, entry.port));
+    }
+    builder
+}
+
+// 测速地址与上传地址中的全部域名，用于将请求固定解析到待测 IP
+pub fn hosts(config: &Config) -> Vec<String> {
+    let mut hosts: Vec<String> = url_list(config)
+        .iter()
+        .map(|u| u.as_str())
+        .chain(std::iter::once(config.upload_url.as_str()))
+        .filter_map(|u| reqwest::Url::parse(u).ok()?.host_str().map(|h| h.to_string()))
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+    hosts
+}
+
+// 记录请求结果：限速 (429) 或服务端错误时暂停使用该地址
+pub fn report_status(url: &str, status: u16) {
+    if status == 429 || status == 404 || status >= 500 {
+        debug_log!("测速地址 {} 返回 {}，暂停使用 {:?}", url, status, FAILURE_COOLDOWN);
+        FAILED_UNTIL.lock().unwrap().insert(cooldown_key(url), Instant::now() + FAILURE_COOLDOWN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_url_list_mixes_urls_and_files() {
+        let path = std::env::temp_dir().join(format!("cfst-urls-{}.txt", std::process::id()));
+        std::fs::write(&path, "# 注释\n\nhttps://b.example.com/file\n  https://c.example.com/file  \nnot a url\n").unwrap();
+        let expr = format!("https://a.example.com/file, {} ,,missing-file.txt", path.display());
+        let urls = parse_url_list(&expr);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(urls, ["https://a.example.com/file", "https://b.example.com/file", "https://c.example.com/file"]);
+
+        assert!(parse_url_list("").is_empty());
+        assert!(parse_url_list("http://").is_empty());
+    }
+
+    #[test]
+    fn next_url_skips_failed_url() {
+        let config = Config {
+            url: "https://ok.next-url.test/a,https://bad.next-url.test/b".to_string(),
+            ..Config::default()
+        };
+        // 下载、HTTPing 上报的是带待测端口的请求地址
+        report_status(&with_port("https://bad.next-url.test/b", 2053), 429);
+        for _ in 0..4 {
+            assert_eq!(next_url(&config), "https://ok.next-url.test/a");
+        }
+        // 200 等正常状态不影响轮询
+        report_status("https://ok.next-url.test:8443/a", 200);
+        assert_eq!(next_url(&config), "https://ok.next-url.test/a");
+    }
+
+    #[test]
+    fn next_url_falls_back_to_earliest_recovery() {
+        let config = Config {
+            url: "https://x.all-failed.test/,https://y.all-failed.test/".to_string(),
+            ..Config::default()
+        };
+        report_status("https://x.all-failed.test:2083/", 503);
+        std::thread::sleep(Duration::from_millis(5));
+        report_status("https://y.all-failed.test/", 404);
+        for _ in 0..3 {
+            assert_eq!(next_url(&config), "https://x.all-failed.test/");
+        }
+    }
+
+    #[test]
+    fn parse_resolve_entries() {
+        let v4 = parse_resolve(" Speed.Example.com:443:1.1.1.1 ").unwrap();
+        assert_eq!(v4, ResolveEntry { host: "speed.example.com".to_string(), port: 443, ip: "1.1.1.1".parse().unwrap() });
+        let v6 = parse_resolve("speed.example.com:8443:[2606:4700::1]").unwrap();
+        assert_eq!((v6.port, v6.ip), (8443, "2606:4700::1".parse().unwrap()));
+        let bare_v6 = parse_resolve("speed.example.com:80:::1").unwrap();
+        assert_eq!(bare_v6.ip, "::1".parse::<IpAddr>().unwrap());
+
+        for bad in ["", "speed.example.com", "speed.example.com:443", ":443:1.1.1.1", "a.com:x:1.1.1.1", "a.com:70000:1.1.1.1", "a.com:443:host"] {
+            assert!(parse_resolve(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn apply_resolve_pins_host() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf);
+            stream.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").ok();
+        });
+
+        // 无法解析的项被跳过，不影响其余项
+        let config = Config {
+            resolve: vec!["broken".to_string(), format!("resolve.test:{}:127.0.0.1", port)],
+            ..Config::default()
+        };
+        let client = apply_resolve(reqwest::Client::builder().no_proxy(), &config).build().unwrap();
+        let resp = client.get(format!("http://resolve.test:{}/", port)).send().await.unwrap();
+        assert_eq!(resp.status().as_u16(), 204);
+    }
+
+    #[test]
+    fn cooldown_key_ignores_port() {
+        assert_eq!(cooldown_key("https://a.test:2053/x"), cooldown_key("https://a.test/x"));
+        assert_eq!(cooldown_key(&with_port("http://a.test/x", 8080)), "http://a.test/x");
+        assert_eq!(cooldown_key("not a url"), "not a url");
+    }
+}
@@ -0,0 +1,246 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use blake2::{Blake2s256, Blake2sMac, Digest};
+use blake2::digest::Mac;
+use blake2::digest::consts::U16;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use hmac::SimpleHmac;
+use x25519_dalek::{PublicKey, StaticSecret};
+use crate::failure::ProbeError;
+use crate::ip::IPWithPort;
+use crate::types::{Config, PingData};
+use crate::threadpool::{GLOBAL_POOL, Outcome};
+use crate::{interface, ratelimit};
+
+// Cloudflare WARP 服务端公钥
+pub const WARP_PUBLIC_KEY: &str = "bmXOC+F1FxEMF9dyiK2H5/1SUtzH0JuVo51h2wPfgyo=";
+
+// 未指定 [-ports] 时测速的 WARP 端口
+pub const DEFAULT_PORTS: &str = "2408,500,1701,4500";
+
+// 未指定 IP 数据且 IP 文件不存在时使用的 WARP 接入点 IP 段
+pub const DEFAULT_RANGES: &str = "162.159.192.0/24
+162.159.193.0/24
+162.159.195.0/24
+188.114.96.0/24
+188.114.97.0/24
+188.114.98.0/24
+188.114.99.0/24
+2606:4700:d0::/48
+2606:4700:d1::/48";
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+const INITIATION_LEN: usize = 148;
+const RESPONSE_LEN: usize = 92;
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <SimpleHmac<Blake2s256> as Mac>::new_from_slice(key).expect("HMAC 支持任意长度的密钥");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+// WireGuard 的 KDF，返回前两个输出
+fn kdf2(key: &[u8; 32], input: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let t0 = hmac(key, &[input]);
+    let t1 = hmac(&t0, &[&[1]]);
+    let t2 = hmac(&t0, &[&t1, &[2]]);
+    (t1, t2)
+}
+
+fn aead(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(&Nonce::default(), Payload { msg: plaintext, aad })
+        .expect("ChaCha20Poly1305 加密不会失败")
+}
+
+// TAI64N 时间戳
+fn tai64n() -> [u8; 12] {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut out = [0u8; 12];
+    out[..8].copy_from_slice(&(now.as_secs() + (1u64 << 62)).to_be_bytes());
+    out[8..].copy_from_slice(&now.subsec_nanos().to_be_bytes());
+    out
+}
+
+fn parse_key(kind: &str, expr: &str) -> Result<[u8; 32], String> {
+    BASE64.decode(expr.trim())
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .ok_or_else(|| format!("无效的{}：{}", kind, expr))
+}
+
+// 握手发起方的密钥
+pub struct Handshake {
+    secret: StaticSecret,
+    public: PublicKey,
+    peer: PublicKey,
+    reserved: [u8; 3], // WARP 使用的 reserved 字段（客户端 ID）
+}
+
+impl Handshake {
+    // 由 [-warp-key]、[-warp-peer]、[-warp-reserved] 构建，未指定私钥时随机生成
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let secret = if config.warp_private_key.is_empty() {
+            StaticSecret::random_from_rng(rand::rngs::OsRng)
+        } else {
+            StaticSecret::from(parse_key("WARP 私钥", &config.warp_private_key)?)
+        };
+        let peer = PublicKey::from(parse_key("WARP 公钥", &config.warp_public_key)?);
+
+        let mut reserved = [0u8; 3];
+        if !config.warp_reserved.is_empty() {
+            let values: Vec<u8> = config.warp_reserved
+                .split(',')
+                .map(|v| v.trim().parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("无效的 reserved：{}", config.warp_reserved))?;
+            reserved = values.try_into()
+                .map_err(|_| format!("reserved 应为 3 个数字：{}", config.warp_reserved))?;
+        }
+
+        Ok(Self {
+            public: PublicKey::from(&secret),
+            secret,
+            peer,
+            reserved,
+        })
+    }
+
+    // 构造握手发起消息 (Noise IKpsk2)
+    fn initiation(&self, sender: u32) -> [u8; INITIATION_LEN] {
+        let ephemeral = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+
+        let chaining = hash(&[CONSTRUCTION]);
+        let h = hash(&[&chaining, IDENTIFIER]);
+        let h = hash(&[&h, self.peer.as_bytes()]);
+
+        let (chaining, _) = kdf2(&chaining, ephemeral_public.as_bytes());
+        let h = hash(&[&h, ephemeral_public.as_bytes()]);
+
+        let (chaining, key) = kdf2(&chaining, ephemeral.diffie_hellman(&self.peer).as_bytes());
+        let encrypted_static = aead(&key, self.public.as_bytes(), &h);
+        let h = hash(&[&h, &encrypted_static]);
+
+        let (_, key) = kdf2(&chaining, self.secret.diffie_hellman(&self.peer).as_bytes());
+        let encrypted_timestamp = aead(&key, &tai64n(), &h);
+
+        let mut msg = [0u8; INITIATION_LEN];
+        msg[0] = 1;
+        msg[1..4].copy_from_slice(&self.reserved);
+        msg[4..8].copy_from_slice(&sender.to_le_bytes());
+        msg[8..40].copy_from_slice(ephemeral_public.as_bytes());
+        msg[40..88].copy_from_slice(&encrypted_static);
+        msg[88..116].copy_from_slice(&encrypted_timestamp);
+
+        // mac1 使用服务端公钥派生的密钥，mac2 仅在服务端要求 cookie 时使用，保持为 0
+        let mac1_key = hash(&[LABEL_MAC1, self.peer.as_bytes()]);
+        let mut mac = <Blake2sMac<U16> as Mac>::new_from_slice(&mac1_key).expect("mac1 密钥长度为 32 字节");
+        mac.update(&msg[..116]);
+        msg[116..132].copy_from_slice(&mac.finalize().into_bytes());
+        msg
+    }
+}
+
+// 发送一次握手发起消息，收到对应的握手响应时返回往返时间
+pub async fn handshake(hs: &Handshake, addr: SocketAddr, config: &Config) -> Result<Duration, ProbeError> {
+    let socket = interface::udp_socket(addr, config).await.map_err(|e| ProbeError::from_io_error(&e))?;
+
+    let sender = rand::random::<u32>();
+    let msg = hs.initiation(sender);
+
+    ratelimit::acquire().await;
+    let start = Instant::now();
+    if let Err(e) = socket.send(&msg).await {
+        GLOBAL_POOL.record_outcome(Outcome::from_io_error(&e));
+        return Err(ProbeError::from_io_error(&e));
+    }
+
+    let mut buf = [0u8; 256];
+    let deadline = start + HANDSHAKE_TIMEOUT;
+    loop {
+        match tokio::time::timeout_at(deadline.into(), socket.recv(&mut buf)).await {
+            // 握手响应：类型 2，receiver 为本次的 sender
+            Ok(Ok(n)) if n == RESPONSE_LEN && buf[0] == 2 && buf[8..12] == sender.to_le_bytes() => {
+                GLOBAL_POOL.record_outcome(Outcome::Success);
+                return Ok(start.elapsed());
+            }
+            Ok(Ok(_)) => continue,
+            // 对端端口关闭时会收到 ICMP 不可达，表现为连接被拒绝
+            Ok(Err(e)) => {
+                GLOBAL_POOL.record_outcome(Outcome::from_io_error(&e));
+                return Err(ProbeError::from_io_error(&e));
+            }
+            Err(_) => {
+                GLOBAL_POOL.record_outcome(Outcome::Timeout);
+                return Err(ProbeError::Timeout);
+            }
+        }
+    }
+}
+
+// 本次测速共用的握手密钥，按 WARP 参数缓存，参数变化（如 /scan 覆盖）时重新构建
+static HANDSHAKE: Mutex<Option<(String, Arc<Handshake>)>> = Mutex::new(None);
+
+fn shared_handshake(config: &Config) -> Result<Arc<Handshake>, String> {
+    let key = format!("{}|{}|{}", config.warp_private_key, config.warp_public_key, config.warp_reserved);
+    let mut cached = HANDSHAKE.lock().unwrap();
+    if let Some((cached_key, hs)) = cached.as_ref() {
+        if *cached_key == key {
+            return Ok(hs.clone());
+        }
+    }
+    let hs = Arc::new(Handshake::from_config(config)?);
+    *cached = Some((key, hs.clone()));
+    Ok(hs)
+}
+
+// 检查 WARP 相关参数，并拒绝不适用于 WARP 模式的参数组合
+pub fn validate(config: &Config) -> Result<(), String> {
+    if !config.warp {
+        return Ok(());
+    }
+    if config.httping {
+        return Err("[-warp] 通过 WireGuard 握手测速，不能与 [-httping] 同时使用".to_string());
+    }
+    shared_handshake(config).map(|_| ())
+}
+
+// WARP 模式的延迟测速：每次发送新的握手，统计成功率与往返时间
+pub async fn check_connection(ip_with_port: &IPWithPort, config: &Config) -> Result<PingData, ProbeError> {
+    let hs = shared_handshake(config).map_err(ProbeError::Other)?;
+    let port = ip_with_port.get_port(config.tcp_port);
+    let addr = SocketAddr::new(ip_with_port.ip, port);
+
+    let task = GLOBAL_POOL.start_task();
+    let mut delays = Vec::with_capacity(config.ping_times as usize);
+    let mut last_error = ProbeError::Timeout;
+    for _ in 0..config.ping_times {
+        match handshake(&hs, addr, config).await {
+            Ok(delay) => {
+                task.record_progress();
+                delays.push(delay);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    PingData::from_delays(ip_with_port.ip, port, config.ping_times, delays).ok_or(last_error)
+}
@@ -0,0 +1,153 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use crate::dns_update::{self, API_BASE};
+use crate::types::Config;
+
+// gen-worker 未指定文件名时写入的脚本
+const DEFAULT_FILE: &str = "cfst-worker.js";
+// [-worker] 的下载地址请求的数据量，实际下载量由 [-dt] 与 [-download-budget] 限制
+const DOWNLOAD_BYTES: u64 = 1 << 30;
+const MULTIPART_BOUNDARY: &str = "cfst-worker-boundary";
+
+// 测速用的 Cloudflare Worker (ES 模块)：
+// /ping 用于检查可用，/down?bytes=N 返回 N 字节数据 (支持 HEAD 与 Range，HTTPing 使用 HEAD)，/up 接收上传并返回字节数
+const SCRIPT: &str = r#"// CloudflareST-Rust 测速 Worker，由 gen-worker 生成
+const CHUNK = new Uint8Array(64 * 1024);
+const MAX_BYTES = 10 * 1024 * 1024 * 1024;
+const NO_CACHE = { "Cache-Control": "no-store" };
+
+function download(request, url, ctx) {
+  const total = Math.min(Number.parseInt(url.searchParams.get("bytes") ?? "", 10) || 100 * 1024 * 1024, MAX_BYTES);
+  let start = 0;
+  let end = total - 1;
+  let status = 200;
+  const headers = { ...NO_CACHE, "Content-Type": "application/octet-stream", "Accept-Ranges": "bytes" };
+
+  const range = /^bytes=(\d+)-(\d*)$/.exec(request.headers.get("Range") ?? "");
+  if (range) {
+    start = Number(range[1]);
+    end = range[2] ? Math.min(Number(range[2]), total - 1) : total - 1;
+    if (start > end) {
+      return new Response(null, { status: 416, headers: { "Content-Range": `bytes */${total}` } });
+    }
+    status = 206;
+    headers["Content-Range"] = `bytes ${start}-${end}/${total}`;
+  }
+  const length = end - start + 1;
+  headers["Content-Length"] = String(length);
+  if (request.method === "HEAD") {
+    return new Response(null, { status, headers });
+  }
+
+  const { readable, writable } = new FixedLengthStream(length);
+  ctx.waitUntil((async () => {
+    const writer = writable.getWriter();
+    for (let remaining = length; remaining > 0; remaining -= CHUNK.length) {
+      await writer.write(remaining >= CHUNK.length ? CHUNK : CHUNK.subarray(0, remaining));
+    }
+    await writer.close();
+  })());
+  return new Response(readable, { status, headers });
+}
+
+async function upload(request) {
+  if (request.method !== "POST") {
+    return new Response("method not allowed", { status: 405 });
+  }
+  let bytes = 0;
+  if (request.body) {
+    const reader = request.body.getReader();
+    for (;;) {
+      const { done, value } = await reader.read();
+      if (done) break;
+      bytes += value.byteLength;
+    }
+  }
+  return Response.json({ bytes }, { headers: NO_CACHE });
+}
+
+export default {
+  async fetch(request, env, ctx) {
+    const url = new URL(request.url);
+    switch (url.pathname) {
+      case "/ping":
+        return new Response("ok", { headers: NO_CACHE });
+      case "/down":
+        return download(request, url, ctx);
+      case "/up":
+        return upload(request);
+      default:
+        return new Response("not found", { status: 404 });
+    }
+  },
+};
+"#;
+
+#[derive(Deserialize)]
+struct Subdomain {
+    subdomain: String,
+}
+
+// [-worker]：使用 gen-worker 部署的 Worker 作为下载与上传测速地址
+pub fn download_url(base: &str) -> String {
+    format!("{}/down?bytes={}", base.trim_end_matches('/'), DOWNLOAD_BYTES)
+}
+
+pub fn upload_url(base: &str) -> String {
+    format!("{}/up", base.trim_end_matches('/'))
+}
+
+// gen-worker：写入 Worker 脚本，指定 [-worker-account] 时通过 API 部署并启用 workers.dev 地址
+pub async fn generate(config: &Config, path: Option<&str>) -> Result<()> {
+    let path = path.unwrap_or(DEFAULT_FILE);
+    std::fs::write(path, SCRIPT).with_context(|| format!("无法写入 {}", path))?;
+    println!("已写入 Worker 脚本到 {}", path);
+
+    if config.worker_account.is_empty() {
+        println!("可在 Cloudflare 控制台新建 Worker 并粘贴该脚本，或指定 [-worker-account] 与 [-dns-token] 自动部署；");
+        println!("部署后使用 -worker https://<名称>.<子域>.workers.dev 测速");
+        return Ok(());
+    }
+
+    let base = deploy(config).await?;
+    println!("已部署 Worker {}：{}", config.worker_name, base);
+    println!("使用 -worker {} 测速，相当于：", base);
+    println!("    -url {} -upload-url {}", download_url(&base), upload_url(&base));
+    Ok(())
+}
+
+// 上传脚本并启用 workers.dev 地址，返回 Worker 的地址；令牌需要 Workers 脚本编辑权限
+async fn deploy(config: &Config) -> Result<String> {
+    let token = dns_update::api_token(config)?;
+    let client = dns_update::api_client(config)?;
+    let account = &config.worker_account;
+    let name = &config.worker_name;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        bail!("无效的 Worker 名称：{}，只能包含字母、数字与 -", name);
+    }
+    let script_url = format!("{}/accounts/{}/workers/scripts/{}", API_BASE, account, name);
+
+    let metadata = json!({ "main_module": "worker.js", "compatibility_date": "2024-09-23" });
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"metadata\"\r\nContent-Type: application/json\r\n\r\n{}\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"worker.js\"; filename=\"worker.js\"\r\nContent-Type: application/javascript+module\r\n\r\n{}\r\n\
+         --{b}--\r\n",
+        metadata, SCRIPT, b = MULTIPART_BOUNDARY
+    );
+    let req = client
+        .put(&script_url)
+        .header("Content-Type", format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY))
+        .body(body);
+    dns_update::send_api::<serde_json::Value>(req, &token).await.context("上传 Worker 脚本失败")?;
+
+    let req = client.post(format!("{}/subdomain", script_url)).json(&json!({ "enabled": true }));
+    dns_update::send_api::<serde_json::Value>(req, &token).await.context("启用 workers.dev 地址失败")?;
+
+    let req = client.get(format!("{}/accounts/{}/workers/subdomain", API_BASE, account));
+    let subdomain = dns_update::send_api::<Subdomain>(req, &token)
+        .await
+        .context("获取 workers.dev 子域失败")?
+        .context("账户尚未设置 workers.dev 子域")?;
+    Ok(format!("https://{}.{}.workers.dev", name, subdomain.subdomain))
+}